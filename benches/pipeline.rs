@@ -0,0 +1,110 @@
+//! Throughput of `video-sam3 --mask-to-json` vs. `--json-out` with the full display path, and of
+//! `--save-video` with annotation on worker threads vs. `--sync-annotate`.
+//!
+//! Encodes a synthetic 1080p clip and times the runs end to end. Needs `ffmpeg`/`ffprobe` in
+//! PATH and the SAM3 weights. Without `DISPLAY`/`WAYLAND_DISPLAY` the `--json-out` run falls back
+//! to `--no-display`, which still annotates every inference frame:
+//!
+//! ```bash
+//! cargo bench --bench pipeline
+//! ```
+
+use sam3_card_detector::color::ColorMatrix;
+use sam3_card_detector::ffmpeg_log::FfmpegLogLevel;
+use sam3_card_detector::output::FfmpegVideoWriter;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Instant;
+
+const FRAMES: usize = 120;
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("sam3-bench-{}-{name}", std::process::id()))
+}
+
+fn write_synthetic_video(path: &Path) {
+    let mut writer = FfmpegVideoWriter::spawn(
+        path,
+        WIDTH,
+        HEIGHT,
+        30.0,
+        ColorMatrix::default(),
+        FfmpegLogLevel::default(),
+    )
+    .expect("spawn encoder");
+    for i in 0..FRAMES as u32 {
+        let x0 = 200 + i * 8;
+        let frame = image::RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            if (x0..x0 + 400).contains(&x) && (340..740).contains(&y) {
+                image::Rgb([255, 0, 0])
+            } else {
+                image::Rgb([255, 255, 255])
+            }
+        });
+        writer
+            .write_frame(&usls::Image::from(frame))
+            .expect("write frame");
+    }
+    writer.finish().expect("finish encoder");
+}
+
+/// Run `video-sam3` on `input` with `extra` args; returns frames per second.
+fn run(input: &Path, extra: &[&str], stdout: Stdio) -> f64 {
+    let start = Instant::now();
+    let status = Command::new(env!("CARGO_BIN_EXE_video-sam3"))
+        .arg(input)
+        .args(["-p", "red square", "--infer-every", "1"])
+        .args(extra)
+        .stdout(stdout)
+        .status()
+        .expect("run video-sam3");
+    assert!(status.success(), "video-sam3 exited with {status}");
+    FRAMES as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let input = temp_path("input.mp4");
+    let json_out = temp_path("detections.jsonl");
+    let saved = temp_path("annotated.mp4");
+    write_synthetic_video(&input);
+
+    let has_display =
+        std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some();
+    let json_out_str = json_out.to_string_lossy().into_owned();
+    let mut full = vec!["--json-out", json_out_str.as_str()];
+    if !has_display {
+        full.push("--no-display");
+    }
+
+    let geometry_fps = run(&input, &["--mask-to-json"], Stdio::null());
+    let full_fps = run(&input, &full, Stdio::inherit());
+    let saved_str = saved.to_string_lossy().into_owned();
+    let async_fps = run(&input, &["--save-video", &saved_str], Stdio::null());
+    let sync_fps = run(
+        &input,
+        &["--save-video", &saved_str, "--sync-annotate"],
+        Stdio::null(),
+    );
+
+    let full_label = if has_display {
+        "--json-out + display"
+    } else {
+        "--json-out --no-display"
+    };
+    println!("{FRAMES} frames at {WIDTH}x{HEIGHT}");
+    println!("  --mask-to-json          {geometry_fps:>7.2} fps");
+    println!("  {full_label:<23} {full_fps:>7.2} fps");
+    println!(
+        "  speedup                 {:>7.2}x",
+        geometry_fps / full_fps
+    );
+    println!("  --save-video            {async_fps:>7.2} fps");
+    println!("  --save-video --sync-annotate {sync_fps:>7.2} fps");
+    println!("  speedup                 {:>7.2}x", async_fps / sync_fps);
+
+    for path in [&input, &json_out, &saved] {
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,85 @@
+//! Cost of the `--gamma`/`--contrast`/`--brightness` LUT, of `--auto-levels` and of the
+//! `--preprocess-infer-only` split on a 1080p frame. Needs nothing but the crate:
+//!
+//! ```bash
+//! cargo bench --bench tone
+//! ```
+
+use sam3_card_detector::preprocess::{
+    ToneAdjust, ToneParams, ToneTarget, apply_lut, apply_lut_into, build_lut,
+};
+use std::time::Instant;
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+const RUNS: u32 = 200;
+
+/// Mean milliseconds per call of `f` over [`RUNS`] calls, after a warm-up call.
+fn time_ms(mut f: impl FnMut()) -> f64 {
+    f();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        f();
+    }
+    start.elapsed().as_secs_f64() * 1000.0 / f64::from(RUNS)
+}
+
+fn main() {
+    let frame = image::RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        image::Rgb([(x % 256) as u8, (y % 256) as u8, ((x + y) % 128) as u8])
+    });
+    let mut img = usls::Image::from(frame);
+    let params = ToneParams {
+        gamma: 2.2,
+        brightness: 0.05,
+        contrast: 1.2,
+        auto_levels: false,
+    };
+
+    let copy_ms = time_ms(|| {
+        std::hint::black_box(img.as_raw().to_vec());
+    });
+    let lut = build_lut(&params, (0, 255));
+    let mut buf = img.as_raw().to_vec();
+    let lut_ms = time_ms(|| {
+        apply_lut(&lut, std::hint::black_box(&mut buf));
+    });
+    let into_ms = time_ms(|| {
+        apply_lut_into(&lut, img.as_raw(), std::hint::black_box(&mut buf));
+    });
+    let build_ms = time_ms(|| {
+        std::hint::black_box(build_lut(&params, (16, 235)));
+    });
+    let mut levels = ToneAdjust::new(
+        ToneParams {
+            auto_levels: true,
+            ..params
+        },
+        ToneTarget::Both,
+    )
+    .expect("valid parameters")
+    .expect("not the identity");
+    let auto_ms = time_ms(|| {
+        levels.apply(std::hint::black_box(&mut img));
+    });
+    let mut infer_only = ToneAdjust::new(params, ToneTarget::InferOnly)
+        .expect("valid parameters")
+        .expect("not the identity");
+    let mut frame = Some(img);
+    let split_ms = time_ms(|| {
+        let (shown, input) = infer_only.split(frame.take().expect("frame"), true);
+        std::hint::black_box(input);
+        frame = Some(shown);
+    });
+
+    println!(
+        "{WIDTH}x{HEIGHT} RGB24, mean of {RUNS} runs on {} thread(s)",
+        rayon::current_num_threads()
+    );
+    println!("  plain copy (reference)  {copy_ms:>7.3} ms");
+    println!("  LUT apply in place      {lut_ms:>7.3} ms");
+    println!("  LUT apply into a buffer {into_ms:>7.3} ms");
+    println!("  LUT rebuild             {build_ms:>7.3} ms");
+    println!("  --auto-levels + apply   {auto_ms:>7.3} ms");
+    println!("  infer-only split        {split_ms:>7.3} ms");
+}
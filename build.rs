@@ -0,0 +1,35 @@
+//! Injects the git revision and rustc version shown by `--version` (see `src/build_info.rs`) and
+//! the pinned usls revision recorded in run summaries (see `src/repro.rs`).
+
+use std::process::Command;
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).output().ok()?;
+    let text = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (out.status.success() && !text.is_empty()).then_some(text)
+}
+
+/// `rev = "..."` of the `usls` dependency line in `Cargo.toml`.
+fn usls_rev() -> Option<String> {
+    let manifest = std::fs::read_to_string("Cargo.toml").ok()?;
+    let line = manifest
+        .lines()
+        .find(|l| l.trim_start().starts_with("usls ="))?;
+    let rest = &line[line.find("rev = \"")? + "rev = \"".len()..];
+    Some(rest[..rest.find('"')?].to_string())
+}
+
+fn main() {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+    let git_hash =
+        output("git", &["rev-parse", "--short", "HEAD"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=SAM3_RUSTC_VERSION={rustc_version}");
+    println!("cargo:rustc-env=SAM3_GIT_HASH={git_hash}");
+    let usls_rev = usls_rev().unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=SAM3_USLS_REV={usls_rev}");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+}
@@ -0,0 +1,312 @@
+//! Drawing annotations off the inference loop (`video-sam3` with `--save-video`, unless
+//! `--sync-annotate`).
+//!
+//! Annotating a frame (mask blending, outlines, labels) costs about as much as decoding it, and
+//! it only depends on the frame and its detections. [`AnnotationWorker`] runs it on a couple of
+//! threads fed through a bounded channel; [`OrderedOutput`] hands frames to the encoder in input
+//! order as their annotations complete, so the loop can start the next inference meanwhile and
+//! only waits when the workers fall too far behind.
+
+use anyhow::{Context, Result};
+use crossbeam_channel::{Receiver, Sender, bounded, unbounded};
+use std::collections::{BTreeMap, VecDeque};
+use std::thread::JoinHandle;
+use usls::{Annotator, models::Sam3Prompt};
+
+use crate::groups::PromptGroups;
+
+/// Annotation threads; more rarely help since the encoder consumes frames one at a time.
+const THREADS: usize = 2;
+
+/// Items numbered `0, 1, 2, ...` arriving in any order, released in order.
+pub struct ReorderBuffer<T> {
+    next: u64,
+    items: BTreeMap<u64, T>,
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            items: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> ReorderBuffer<T> {
+    pub fn push(&mut self, seq: u64, item: T) {
+        debug_assert!(seq >= self.next, "item {seq} was already released");
+        self.items.insert(seq, item);
+    }
+
+    /// The next item in sequence, if it has arrived.
+    pub fn pop(&mut self) -> Option<T> {
+        let item = self.items.remove(&self.next)?;
+        self.next += 1;
+        Some(item)
+    }
+
+    /// Items waiting for an earlier one.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+struct Job {
+    seq: u64,
+    img: usls::Image,
+    y: usls::Y,
+    prompts: Vec<Sam3Prompt>,
+}
+
+/// Threads running [`PromptGroups::annotate`] on submitted frames. Results come back tagged with
+/// the sequence number of their job, in whatever order they finish.
+pub struct AnnotationWorker {
+    jobs: Option<Sender<Job>>,
+    done: Receiver<(u64, Result<usls::Image>)>,
+    threads: Vec<JoinHandle<()>>,
+    submitted: u64,
+}
+
+impl AnnotationWorker {
+    /// At most `max_pending` jobs wait for a thread; [`Self::submit`] blocks beyond that.
+    pub fn spawn(annotator: &Annotator, groups: &PromptGroups, max_pending: usize) -> Result<Self> {
+        let (job_tx, job_rx) = bounded::<Job>(max_pending.max(1));
+        let (done_tx, done_rx) = unbounded();
+        let threads = (0..THREADS)
+            .map(|i| {
+                let (jobs, done) = (job_rx.clone(), done_tx.clone());
+                let (annotator, groups) = (annotator.clone(), groups.clone());
+                std::thread::Builder::new()
+                    .name(format!("annotate-{i}"))
+                    .spawn(move || {
+                        for job in jobs {
+                            let annotated =
+                                groups.annotate(&annotator, &job.img, &job.y, &job.prompts);
+                            if done.send((job.seq, annotated)).is_err() {
+                                break;
+                            }
+                        }
+                    })
+                    .context("failed to spawn an annotation thread")
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            jobs: Some(job_tx),
+            done: done_rx,
+            threads,
+            submitted: 0,
+        })
+    }
+
+    /// Queue `img` for annotation; returns the job's sequence number.
+    pub fn submit(&mut self, img: usls::Image, y: usls::Y, prompts: &[Sam3Prompt]) -> Result<u64> {
+        let seq = self.submitted;
+        let job = Job {
+            seq,
+            img,
+            y,
+            prompts: prompts.to_vec(),
+        };
+        self.jobs
+            .as_ref()
+            .context("annotation worker already finished")?
+            .send(job)
+            .ok()
+            .context("annotation threads exited unexpectedly")?;
+        self.submitted += 1;
+        Ok(seq)
+    }
+
+    /// A finished annotation, without waiting.
+    fn try_recv(&self) -> Option<(u64, Result<usls::Image>)> {
+        self.done.try_recv().ok()
+    }
+
+    /// A finished annotation, waiting for one.
+    fn recv(&self) -> Result<(u64, Result<usls::Image>)> {
+        self.done
+            .recv()
+            .ok()
+            .context("annotation threads exited unexpectedly")
+    }
+
+    /// No more jobs; the threads exit once the queue is empty.
+    fn close(&mut self) {
+        self.jobs = None;
+    }
+
+    fn join(&mut self) {
+        self.close();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for AnnotationWorker {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// What an output frame shows once it is its turn.
+enum Pending {
+    /// The annotation of the next job; jobs are submitted in frame order.
+    Annotated,
+    /// A frame without inference: the last annotated frame, as the synchronous path shows.
+    Repeat,
+    /// A frame before the first inference, shown as decoded.
+    Raw(usls::Image),
+}
+
+/// Output frames in input order, annotated by an [`AnnotationWorker`].
+pub struct OrderedOutput {
+    worker: AnnotationWorker,
+    queue: VecDeque<(u64, Pending)>,
+    finished: ReorderBuffer<usls::Image>,
+    last: Option<usls::Image>,
+    submitted_any: bool,
+}
+
+impl OrderedOutput {
+    pub fn new(worker: AnnotationWorker) -> Self {
+        Self {
+            worker,
+            queue: VecDeque::new(),
+            finished: ReorderBuffer::default(),
+            last: None,
+            submitted_any: false,
+        }
+    }
+
+    /// Frame `frame_idx` with detections `y`, annotated on the worker.
+    pub fn push_inferred(
+        &mut self,
+        frame_idx: u64,
+        img: usls::Image,
+        y: usls::Y,
+        prompts: &[Sam3Prompt],
+    ) -> Result<()> {
+        self.worker.submit(img, y, prompts)?;
+        self.queue.push_back((frame_idx, Pending::Annotated));
+        self.submitted_any = true;
+        Ok(())
+    }
+
+    /// Frame `frame_idx`, which had no inference.
+    pub fn push_repeat(&mut self, frame_idx: u64, img: &usls::Image) {
+        let pending = if self.submitted_any {
+            Pending::Repeat
+        } else {
+            Pending::Raw(img.clone())
+        };
+        self.queue.push_back((frame_idx, pending));
+    }
+
+    /// Pass every frame whose annotation is ready to `write`, in order, without waiting.
+    pub fn drain(&mut self, write: impl FnMut(u64, &usls::Image) -> Result<()>) -> Result<()> {
+        while let Some((seq, annotated)) = self.worker.try_recv() {
+            self.finished.push(seq, annotated?);
+        }
+        self.emit_ready(write)
+    }
+
+    /// Wait for the remaining annotations and pass every queued frame to `write`.
+    pub fn finish(mut self, mut write: impl FnMut(u64, &usls::Image) -> Result<()>) -> Result<()> {
+        self.worker.close();
+        loop {
+            self.emit_ready(&mut write)?;
+            if self.queue.is_empty() {
+                break;
+            }
+            let (seq, annotated) = self.worker.recv()?;
+            self.finished.push(seq, annotated?);
+        }
+        self.worker.join();
+        Ok(())
+    }
+
+    fn emit_ready(&mut self, mut write: impl FnMut(u64, &usls::Image) -> Result<()>) -> Result<()> {
+        while let Some((frame_idx, pending)) = self.queue.front() {
+            let frame_idx = *frame_idx;
+            match pending {
+                Pending::Annotated => match self.finished.pop() {
+                    Some(img) => {
+                        write(frame_idx, &img)?;
+                        self.last = Some(img);
+                    }
+                    None => break,
+                },
+                Pending::Repeat => {
+                    let last = self
+                        .last
+                        .as_ref()
+                        .context("repeated frame before any annotation")?;
+                    write(frame_idx, last)?;
+                }
+                Pending::Raw(img) => write(frame_idx, img)?,
+            }
+            self.queue.pop_front();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releases_items_in_sequence() {
+        let mut buf = ReorderBuffer::default();
+        buf.push(2, "c");
+        buf.push(1, "b");
+        assert_eq!(buf.pop(), None);
+        buf.push(0, "a");
+        assert_eq!(buf.pop(), Some("a"));
+        assert_eq!(buf.pop(), Some("b"));
+        assert_eq!(buf.pop(), Some("c"));
+        assert_eq!(buf.pop(), None);
+        assert!(buf.is_empty());
+        buf.push(4, "e");
+        assert_eq!((buf.pop(), buf.len()), (None, 1));
+        buf.push(3, "d");
+        assert_eq!(buf.pop(), Some("d"));
+        assert_eq!(buf.pop(), Some("e"));
+    }
+
+    #[test]
+    fn keeps_frame_order_across_threads() {
+        let groups = PromptGroups::new(&[], &[]);
+        let worker = AnnotationWorker::spawn(&Annotator::default(), &groups, 4).unwrap();
+        let mut out = OrderedOutput::new(worker);
+        let frame =
+            |v: u8| usls::Image::from(image::RgbImage::from_pixel(4, 4, image::Rgb([v; 3])));
+        out.push_repeat(1, &frame(1));
+        for i in 2..=9u8 {
+            if i % 3 == 0 {
+                out.push_repeat(u64::from(i), &frame(i));
+            } else {
+                out.push_inferred(u64::from(i), frame(i), usls::Y::default(), &[])
+                    .unwrap();
+            }
+        }
+        let mut written = Vec::new();
+        let mut record = |idx: u64, img: &usls::Image| {
+            written.push((idx, img.as_raw()[0]));
+            Ok(())
+        };
+        out.drain(&mut record).unwrap();
+        out.finish(&mut record).unwrap();
+        let idx: Vec<u64> = written.iter().map(|(i, _)| *i).collect();
+        assert_eq!(idx, (1..=9).collect::<Vec<_>>());
+        // Frames without inference repeat the previous annotated frame.
+        let pixels: Vec<u8> = written.iter().map(|(_, p)| *p).collect();
+        assert_eq!(pixels, [1, 2, 2, 4, 5, 5, 7, 8, 8]);
+    }
+}
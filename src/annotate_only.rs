@@ -0,0 +1,199 @@
+//! `video-sam3 --annotate-only <detections.jsonl>`: redraw a `--json-out` file over its source
+//! video with the current annotation settings, without loading SAM3.
+//!
+//! The file is read as it is written: one [`FrameRecord`](crate::output::FrameRecord) per line in
+//! frame order. A record without inference repeats the last annotated frame, as the live run
+//! showed it; a frame with no record at all (dropped by `--jsonl-dedup`) is drawn with the
+//! detections of the record before it. Masks come back from `--mask-encoding polygon` or `rle`;
+//! with the default `none` only boxes are drawn.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+
+use crate::output::{Detection, Segmentation};
+use crate::rle;
+
+/// The fields of a `--json-out` line that drawing needs; the rest are ignored.
+#[derive(Debug, Deserialize)]
+struct LoggedFrame {
+    frame_idx: u64,
+    inferred: bool,
+    #[serde(default)]
+    detections: Vec<Detection>,
+}
+
+/// What to draw on one frame of the video.
+#[derive(Debug, PartialEq)]
+pub enum Overlay {
+    /// This frame's detections.
+    Detections(Vec<Detection>),
+    /// The last annotated frame, for a frame between inferences.
+    Repeat,
+    /// No record for the frame: the detections of the previous one.
+    Previous,
+}
+
+/// Reads a `--json-out` file in step with the decoded frames.
+pub struct DetectionLog {
+    lines: Lines<BufReader<File>>,
+    line_no: usize,
+    /// Read but not reached yet.
+    next: Option<LoggedFrame>,
+    last_idx: Option<u64>,
+}
+
+impl DetectionLog {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("failed to open detections file {}", path.display()))?;
+        Ok(Self {
+            lines: BufReader::new(file).lines(),
+            line_no: 0,
+            next: None,
+            last_idx: None,
+        })
+    }
+
+    fn read(&mut self) -> Result<Option<LoggedFrame>> {
+        for line in self.lines.by_ref() {
+            self.line_no += 1;
+            let line = line.context("failed to read detections file")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let frame: LoggedFrame = serde_json::from_str(&line)
+                .with_context(|| format!("invalid record on line {}", self.line_no))?;
+            if let Some(last) = self.last_idx {
+                anyhow::ensure!(
+                    frame.frame_idx > last,
+                    "line {}: frame {} follows frame {last}; records must be in frame order",
+                    self.line_no,
+                    frame.frame_idx
+                );
+            }
+            self.last_idx = Some(frame.frame_idx);
+            return Ok(Some(frame));
+        }
+        Ok(None)
+    }
+
+    /// The overlay of frame `frame_idx`; frames must be asked for in increasing order.
+    pub fn overlay(&mut self, frame_idx: u64) -> Result<Overlay> {
+        if self.next.is_none() {
+            self.next = self.read()?;
+        }
+        match self.next.take_if(|f| f.frame_idx == frame_idx) {
+            Some(frame) if frame.inferred => Ok(Overlay::Detections(frame.detections)),
+            Some(_) => Ok(Overlay::Repeat),
+            None => Ok(Overlay::Previous),
+        }
+    }
+
+    /// Records left for frames past the end of the video.
+    pub fn remaining(&mut self) -> Result<usize> {
+        let mut n = usize::from(self.next.take().is_some());
+        while self.read()?.is_some() {
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+/// `detections` as model output for the annotator.
+pub fn y_from_detections(detections: &[Detection]) -> Result<usls::Y> {
+    let mut hbbs = Vec::new();
+    let mut polygons = Vec::new();
+    let mut masks = Vec::new();
+    for d in detections {
+        let [x, y, w, h] = d.bbox;
+        let mut hbb = usls::Hbb::from_xywh(x, y, w, h);
+        let mut polygon = None;
+        let mut mask = None;
+        match &d.segmentation {
+            Some(Segmentation::Polygon(points)) => {
+                polygon = Some(usls::Polygon::from_xys(points));
+            }
+            Some(Segmentation::Rle(r)) => {
+                let decoded = rle::decode(r)?;
+                let (width, height) = decoded.dimensions();
+                mask = Some(usls::Mask::new(&decoded.into_raw(), width, height)?);
+            }
+            None => {}
+        }
+        if let Some(id) = d.class_id {
+            hbb = hbb.with_id(id);
+            polygon = polygon.map(|p| p.with_id(id));
+            mask = mask.map(|m| m.with_id(id));
+        }
+        if let Some(label) = &d.label {
+            hbb = hbb.with_name(label);
+            polygon = polygon.map(|p| p.with_name(label));
+            mask = mask.map(|m| m.with_name(label));
+        }
+        if let Some(conf) = d.confidence {
+            hbb = hbb.with_confidence(conf);
+            polygon = polygon.map(|p| p.with_confidence(conf));
+            mask = mask.map(|m| m.with_confidence(conf));
+        }
+        hbbs.push(hbb);
+        polygons.extend(polygon);
+        masks.extend(mask);
+    }
+    let mut y = usls::Y::default().with_hbbs(&hbbs);
+    if !polygons.is_empty() {
+        y = y.with_polygons(&polygons);
+    }
+    if !masks.is_empty() {
+        y = y.with_masks(&masks);
+    }
+    Ok(y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn log(lines: &[&str]) -> DetectionLog {
+        let dir = TempDir::new("annotate-only");
+        let path = dir.join("detections.jsonl");
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        DetectionLog::open(&path).unwrap()
+    }
+
+    #[test]
+    fn follows_the_frames() {
+        let mut log = log(&[
+            r#"{"frame_idx":1,"inferred":true,"detections":[{"label":"cat","class_id":0,"confidence":0.9,"bbox":[1,2,3,4]}]}"#,
+            r#"{"frame_idx":2,"inferred":false,"detections":[]}"#,
+            "",
+            r#"{"frame_idx":4,"inferred":true,"detections":[],"stages":[],"latency_ms":3.5}"#,
+            r#"{"frame_idx":7,"inferred":false,"detections":[]}"#,
+        ]);
+        let Overlay::Detections(first) = log.overlay(1).unwrap() else {
+            panic!("frame 1 was inferred");
+        };
+        assert_eq!(first[0].label.as_deref(), Some("cat"));
+        assert_eq!(first[0].bbox, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(log.overlay(2).unwrap(), Overlay::Repeat);
+        assert_eq!(log.overlay(3).unwrap(), Overlay::Previous);
+        assert_eq!(log.overlay(4).unwrap(), Overlay::Detections(Vec::new()));
+        assert_eq!(log.overlay(5).unwrap(), Overlay::Previous);
+        assert_eq!(log.remaining().unwrap(), 1);
+    }
+
+    #[test]
+    fn rejects_out_of_order_records() {
+        let mut log = log(&[
+            r#"{"frame_idx":3,"inferred":true,"detections":[]}"#,
+            r#"{"frame_idx":2,"inferred":true,"detections":[]}"#,
+        ]);
+        assert!(log.overlay(1).is_ok());
+        log.overlay(3).unwrap();
+        let err = log.overlay(4).unwrap_err();
+        assert!(err.to_string().contains("frame order"), "{err}");
+    }
+}
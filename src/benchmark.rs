@@ -0,0 +1,250 @@
+//! `--benchmark-matrix`: time every device x dtype combination on the same decoded frames.
+//!
+//! Each combination gets a freshly built model. Detection-count agreement with the fp32 run on
+//! the same device is reported as a cheap accuracy proxy for reduced-precision dtypes.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::Instant;
+use usls::models::Sam3Prompt;
+
+use crate::common::ModelOptions;
+
+/// Leading frames per combination that are run but not timed (session/allocator warm-up).
+const WARMUP_FRAMES: usize = 3;
+/// dtype whose detection counts the others are compared against.
+const REFERENCE_DTYPE: &str = "fp32";
+
+/// Outcome of one device x dtype combination.
+pub struct BenchResult {
+    pub device: String,
+    pub dtype: String,
+    pub load_s: f64,
+    /// Per timed frame, in milliseconds.
+    pub latencies_ms: Vec<f64>,
+    /// Detections per frame (all frames, warm-up included).
+    pub counts: Vec<usize>,
+    /// Model build or inference error; the other fields are empty.
+    pub error: Option<String>,
+}
+
+impl BenchResult {
+    fn mean_ms(&self) -> Option<f64> {
+        (!self.latencies_ms.is_empty())
+            .then(|| self.latencies_ms.iter().sum::<f64>() / self.latencies_ms.len() as f64)
+    }
+
+    fn percentile_ms(&self, p: f64) -> Option<f64> {
+        if self.latencies_ms.is_empty() {
+            return None;
+        }
+        let mut sorted = self.latencies_ms.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+        Some(sorted[idx])
+    }
+}
+
+/// Split a comma-separated `--benchmark-*` list, dropping empty entries.
+pub fn parse_list(s: &str) -> Vec<String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Run every combination on `frames`; failures are recorded, not fatal.
+pub fn run_matrix(
+    base: &ModelOptions,
+    devices: &[String],
+    dtypes: &[String],
+    frames: &[usls::Image],
+    prompts: &[Sam3Prompt],
+) -> Vec<BenchResult> {
+    let mut results = Vec::new();
+    for device in devices {
+        for dtype in dtypes {
+            tracing::info!("Benchmark: {device} / {dtype}");
+            let mut result = BenchResult {
+                device: device.clone(),
+                dtype: dtype.clone(),
+                load_s: 0.0,
+                latencies_ms: Vec::new(),
+                counts: Vec::new(),
+                error: None,
+            };
+            if let Err(e) = run_one(base, &mut result, frames, prompts) {
+                tracing::warn!("Benchmark {device} / {dtype} failed: {e:#}");
+                result.latencies_ms.clear();
+                result.counts.clear();
+                result.error = Some(format!("{e:#}"));
+            }
+            results.push(result);
+        }
+    }
+    results
+}
+
+fn run_one(
+    base: &ModelOptions,
+    result: &mut BenchResult,
+    frames: &[usls::Image],
+    prompts: &[Sam3Prompt],
+) -> Result<()> {
+    let opts = ModelOptions {
+        device: result.device.clone(),
+        dtype: result.dtype.clone(),
+        ..base.clone()
+    };
+    let started = Instant::now();
+    let mut model = opts.load_model(opts.build_config()?)?;
+    result.load_s = started.elapsed().as_secs_f64();
+
+    let warmup = if frames.len() > WARMUP_FRAMES {
+        WARMUP_FRAMES
+    } else {
+        0
+    };
+    for (i, frame) in frames.iter().enumerate() {
+        let started = Instant::now();
+        let ys = model.forward(std::slice::from_ref(frame), prompts)?;
+        let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+        if i >= warmup {
+            result.latencies_ms.push(elapsed_ms);
+        }
+        result.counts.push(
+            ys.first()
+                .map_or(0, |y| y.hbbs().len().max(y.masks().len())),
+        );
+    }
+    Ok(())
+}
+
+/// Share of frames whose detection count matches the fp32 run on the same device.
+fn agreement(result: &BenchResult, results: &[BenchResult]) -> Option<f64> {
+    let reference = results
+        .iter()
+        .find(|r| r.device == result.device && r.dtype == REFERENCE_DTYPE && r.error.is_none())?;
+    if result.error.is_some() || reference.counts.len() != result.counts.len() {
+        return None;
+    }
+    let total = result.counts.len().max(1);
+    let same = result
+        .counts
+        .iter()
+        .zip(&reference.counts)
+        .filter(|(a, b)| a == b)
+        .count();
+    Some(same as f64 / total as f64)
+}
+
+const HEADER: [&str; 9] = [
+    "device",
+    "dtype",
+    "load_s",
+    "mean_ms",
+    "p50_ms",
+    "p95_ms",
+    "fps",
+    "agreement_vs_fp32",
+    "error",
+];
+
+fn rows(results: &[BenchResult]) -> Vec<[String; 9]> {
+    let fmt = |v: Option<f64>, prec: usize| v.map_or(String::new(), |v| format!("{v:.prec$}"));
+    results
+        .iter()
+        .map(|r| {
+            let mean = r.mean_ms();
+            [
+                r.device.clone(),
+                r.dtype.clone(),
+                fmt(r.error.is_none().then_some(r.load_s), 2),
+                fmt(mean, 1),
+                fmt(r.percentile_ms(0.5), 1),
+                fmt(r.percentile_ms(0.95), 1),
+                fmt(mean.filter(|m| *m > 0.0).map(|m| 1000.0 / m), 2),
+                fmt(agreement(r, results).map(|a| a * 100.0), 1),
+                r.error.clone().unwrap_or_default(),
+            ]
+        })
+        .collect()
+}
+
+pub fn to_markdown(results: &[BenchResult]) -> String {
+    let mut out = format!("| {} |\n", HEADER.join(" | "));
+    out.push_str(&format!("|{}\n", "---|".repeat(HEADER.len())));
+    for row in rows(results) {
+        out.push_str(&format!("| {} |\n", row.join(" | ")));
+    }
+    out
+}
+
+pub fn to_csv(results: &[BenchResult]) -> String {
+    let quote = |s: &str| {
+        if s.contains([',', '"', '\n']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_string()
+        }
+    };
+    let mut out = HEADER.join(",") + "\n";
+    for row in rows(results) {
+        let cells: Vec<String> = row.iter().map(|c| quote(c)).collect();
+        out.push_str(&(cells.join(",") + "\n"));
+    }
+    out
+}
+
+/// Print the markdown table and write `out` as CSV (`.csv`) or markdown (anything else).
+pub fn report(results: &[BenchResult], out: Option<&Path>) -> Result<()> {
+    println!("{}", to_markdown(results));
+    if let Some(path) = out {
+        let text = if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+        {
+            to_csv(results)
+        } else {
+            to_markdown(results)
+        };
+        std::fs::write(path, text)
+            .with_context(|| format!("failed to write benchmark table: {}", path.display()))?;
+        tracing::info!("Benchmark table: {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(dtype: &str, latencies_ms: &[f64], counts: &[usize]) -> BenchResult {
+        BenchResult {
+            device: "cuda:0".to_string(),
+            dtype: dtype.to_string(),
+            load_s: 1.0,
+            latencies_ms: latencies_ms.to_vec(),
+            counts: counts.to_vec(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn table_reports_fps_and_agreement() {
+        let mut failed = result("q4f16", &[], &[]);
+        failed.error = Some("no kernel, \"int4\"".to_string());
+        let results = vec![
+            result("fp32", &[20.0, 20.0], &[1, 2, 2, 1]),
+            result("fp16", &[10.0, 10.0], &[1, 2, 3, 1]),
+            failed,
+        ];
+        let csv = to_csv(&results);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[1], "cuda:0,fp32,1.00,20.0,20.0,20.0,50.00,100.0,");
+        assert_eq!(lines[2], "cuda:0,fp16,1.00,10.0,10.0,10.0,100.00,75.0,");
+        assert_eq!(lines[3], "cuda:0,q4f16,,,,,,,\"no kernel, \"\"int4\"\"\"");
+        assert!(to_markdown(&results).starts_with("| device | dtype |"));
+    }
+}
@@ -0,0 +1,155 @@
+//! `--save-best`: the top-K frames per prompt by detection confidence.
+//!
+//! Each prompt keeps its K best `(confidence, frame)` entries; an annotated frame is held only while
+//! some prompt ranks it, and frames ranked by several prompts are shared. Everything is written when
+//! the run ends, so this works the same for files and live cameras.
+
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+struct Entry {
+    confidence: f32,
+    frame_idx: u64,
+    frame: Rc<usls::Image>,
+}
+
+pub struct BestFrames {
+    dir: PathBuf,
+    k: usize,
+    /// Per prompt label, best first.
+    ranked: BTreeMap<String, Vec<Entry>>,
+}
+
+impl BestFrames {
+    pub fn new(dir: &Path, k: usize) -> Result<Self> {
+        if k == 0 {
+            anyhow::bail!("--best-k must be >= 1");
+        }
+        std::fs::create_dir_all(dir).with_context(|| {
+            format!("failed to create best-frames directory: {}", dir.display())
+        })?;
+        tracing::info!(
+            "Keeping the top {k} frame(s) per prompt for: {}",
+            dir.display()
+        );
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            k,
+            ranked: BTreeMap::new(),
+        })
+    }
+
+    /// Whether `confidence` would enter `label`'s top K.
+    fn qualifies(&self, label: &str, confidence: f32) -> bool {
+        self.ranked
+            .get(label)
+            .is_none_or(|r| r.len() < self.k || r.last().is_some_and(|e| confidence > e.confidence))
+    }
+
+    /// Rank `frame_idx` by each label's best confidence in it; `frame` is only built (and kept) if
+    /// it enters some prompt's top K.
+    fn offer_scores(
+        &mut self,
+        frame_idx: u64,
+        scores: BTreeMap<String, f32>,
+        frame: impl FnOnce() -> usls::Image,
+    ) {
+        let mut frame = Some(frame);
+        let mut shared: Option<Rc<usls::Image>> = None;
+        for (label, confidence) in scores {
+            if !self.qualifies(&label, confidence) {
+                continue;
+            }
+            let frame = shared
+                .get_or_insert_with(|| Rc::new(frame.take().expect("built once")()))
+                .clone();
+            let ranked = self.ranked.entry(label).or_default();
+            let at = ranked.partition_point(|e| e.confidence >= confidence);
+            ranked.insert(
+                at,
+                Entry {
+                    confidence,
+                    frame_idx,
+                    frame,
+                },
+            );
+            ranked.truncate(self.k);
+        }
+    }
+
+    /// Offer one inference result and its annotated frame.
+    pub fn offer(&mut self, frame_idx: u64, y: &usls::Y, annotated: &usls::Image) {
+        let mut scores: BTreeMap<String, f32> = BTreeMap::new();
+        for hbb in y.hbbs() {
+            let (Some(name), Some(conf)) = (hbb.name(), hbb.confidence()) else {
+                continue;
+            };
+            let best = scores.entry(name.to_string()).or_insert(conf);
+            *best = best.max(conf);
+        }
+        self.offer_scores(frame_idx, scores, || annotated.clone());
+    }
+
+    /// Write `<rank>_<prompt>_<confidence>_frame<idx>.jpg` for every ranked frame.
+    pub fn finish(self) -> Result<()> {
+        let mut written = 0;
+        for (label, ranked) in &self.ranked {
+            let safe: String = label
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect();
+            for (rank, e) in ranked.iter().enumerate() {
+                let path = self.dir.join(format!(
+                    "{:02}_{safe}_{:.3}_frame{:06}.jpg",
+                    rank + 1,
+                    e.confidence,
+                    e.frame_idx
+                ));
+                e.frame
+                    .save(&path)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+                written += 1;
+            }
+        }
+        tracing::info!(
+            "Saved {written} best frame(s) for {} prompt(s) to {}",
+            self.ranked.len(),
+            self.dir.display()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame() -> usls::Image {
+        usls::Image::from(image::RgbImage::new(2, 2))
+    }
+
+    #[test]
+    fn keeps_top_k_per_prompt_and_drops_unranked_frames() {
+        let mut best = BestFrames {
+            dir: PathBuf::new(),
+            k: 2,
+            ranked: BTreeMap::new(),
+        };
+        let scores = |v: &[(&str, f32)]| v.iter().map(|(l, c)| (l.to_string(), *c)).collect();
+        best.offer_scores(0, scores(&[("cat", 0.5), ("dog", 0.9)]), frame);
+        best.offer_scores(1, scores(&[("cat", 0.7)]), frame);
+        best.offer_scores(2, scores(&[("cat", 0.6)]), frame);
+        let mut built = false;
+        best.offer_scores(3, scores(&[("cat", 0.55)]), || {
+            built = true;
+            frame()
+        });
+        assert!(!built, "a frame outside the top K is never cloned");
+        let cats: Vec<u64> = best.ranked["cat"].iter().map(|e| e.frame_idx).collect();
+        assert_eq!(cats, [1, 2]);
+        // Frame 0 is still held for "dog" only.
+        assert_eq!(Rc::strong_count(&best.ranked["dog"][0].frame), 1);
+    }
+}
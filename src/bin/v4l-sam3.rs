@@ -1,3 +1,3 @@
 fn main() -> anyhow::Result<()> {
-    sam3_card_detector::v4l_sam3::run()
+    sam3_card_detector::watchdog::exit_on_fire(sam3_card_detector::v4l_sam3::run())
 }
@@ -0,0 +1,48 @@
+//! `--version`: crate, `usls`, rustc and git revision, the last two injected by `build.rs`.
+
+use crate::repro::USLS_REV;
+
+pub const RUSTC_VERSION: &str = env!("SAM3_RUSTC_VERSION");
+/// Short commit hash, `unknown` when built outside a git checkout.
+pub const GIT_HASH: &str = env!("SAM3_GIT_HASH");
+
+/// Print the version and return `true` if the first argument is `--version`. Checked by hand
+/// before argh runs: argh only handles `--help`, and would reject a bare `--version` for
+/// binaries with required arguments.
+pub fn handle_version_flag(bin: &str) -> bool {
+    let requested = version_requested(std::env::args().skip(1));
+    if requested {
+        print_version(bin);
+    }
+    requested
+}
+
+/// Only the first argument counts, so `--version` as an option value (`-p --version`) is left to
+/// argh.
+fn version_requested(mut args: impl Iterator<Item = String>) -> bool {
+    args.next().is_some_and(|a| a == "--version")
+}
+
+pub fn print_version(bin: &str) {
+    println!("{bin} {}", env!("CARGO_PKG_VERSION"));
+    println!("usls:   git {USLS_REV}");
+    println!("rustc:  {RUSTC_VERSION}");
+    println!("commit: {GIT_HASH}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requested(args: &[&str]) -> bool {
+        version_requested(args.iter().map(|a| a.to_string()))
+    }
+
+    #[test]
+    fn only_a_leading_version_flag_counts() {
+        assert!(requested(&["--version"]));
+        assert!(!requested(&[]));
+        assert!(!requested(&["input.mp4", "-p", "--version"]));
+        assert!(!requested(&["--", "--version"]));
+    }
+}
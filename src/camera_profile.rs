@@ -0,0 +1,309 @@
+//! `hikvision-sam3 --profile <file.toml>`: which camera to open and the GenICam node values to
+//! give it, so per-camera settings live in one file instead of a shell script per camera.
+//!
+//! ```toml
+//! # Abort when a node cannot be set (default: warn and continue).
+//! strict = true
+//!
+//! [camera]            # exactly one of name, serial, ip
+//! serial = "DA1234567"
+//!
+//! [[nodes]]
+//! name = "ExposureAuto"
+//! enum = "Off"
+//!
+//! [[nodes]]
+//! name = "ExposureTime"
+//! float = 8000.0
+//!
+//! [[nodes]]
+//! name = "GevSCPSPacketSize"
+//! int = 8164
+//! ```
+//!
+//! Each node has a `name` and exactly one of `int`, `float`, `enum` (the entry's symbolic name),
+//! `bool` or `string`. Nodes are set in file order, since some depend on others (e.g.
+//! `ExposureAuto` before `ExposureTime`, `Width` before `OffsetX`).
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    #[serde(default)]
+    strict: bool,
+    camera: SelectorSpec,
+    #[serde(default)]
+    nodes: Vec<NodeSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SelectorSpec {
+    name: Option<String>,
+    serial: Option<String>,
+    ip: Option<Ipv4Addr>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NodeSpec {
+    name: String,
+    int: Option<i64>,
+    float: Option<f64>,
+    #[serde(rename = "enum")]
+    enumeration: Option<String>,
+    bool: Option<bool>,
+    string: Option<String>,
+}
+
+/// The camera a profile is for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CameraSelector {
+    /// User-defined name (`DeviceUserID`).
+    Name(String),
+    Serial(String),
+    /// Current address of a GigE camera.
+    Ip(Ipv4Addr),
+}
+
+impl CameraSelector {
+    /// Whether an enumerated camera is the one selected; `ip` is `None` for USB3 cameras.
+    pub fn matches(&self, name: &str, serial: &str, ip: Option<Ipv4Addr>) -> bool {
+        match self {
+            Self::Name(n) => n == name,
+            Self::Serial(s) => s == serial,
+            Self::Ip(addr) => ip == Some(*addr),
+        }
+    }
+}
+
+impl fmt::Display for CameraSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "name {name}"),
+            Self::Serial(serial) => write!(f, "serial {serial}"),
+            Self::Ip(ip) => write!(f, "ip {ip}"),
+        }
+    }
+}
+
+/// A typed GenICam node value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeValue {
+    Int(i64),
+    Float(f64),
+    /// Symbolic name of an enumeration entry.
+    Enum(String),
+    Bool(bool),
+    String(String),
+}
+
+impl fmt::Display for NodeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(v) => write!(f, "{v}"),
+            Self::Float(v) => write!(f, "{v}"),
+            Self::Enum(v) => write!(f, "{v}"),
+            Self::Bool(v) => write!(f, "{v}"),
+            Self::String(v) => write!(f, "{v:?}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeAssignment {
+    pub name: String,
+    pub value: NodeValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraProfile {
+    pub camera: CameraSelector,
+    /// In the order to set them.
+    pub nodes: Vec<NodeAssignment>,
+    /// Abort on the first node that cannot be set instead of warning.
+    pub strict: bool,
+}
+
+impl CameraProfile {
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: ProfileFile = toml::from_str(text)?;
+        let camera = match file.camera {
+            SelectorSpec {
+                name: Some(name),
+                serial: None,
+                ip: None,
+            } => CameraSelector::Name(name),
+            SelectorSpec {
+                name: None,
+                serial: Some(serial),
+                ip: None,
+            } => CameraSelector::Serial(serial),
+            SelectorSpec {
+                name: None,
+                serial: None,
+                ip: Some(ip),
+            } => CameraSelector::Ip(ip),
+            _ => anyhow::bail!("[camera] needs exactly one of `name`, `serial` or `ip`"),
+        };
+        if let CameraSelector::Name(s) | CameraSelector::Serial(s) = &camera
+            && s.trim().is_empty()
+        {
+            anyhow::bail!("[camera]: empty {camera}");
+        }
+        let nodes = file
+            .nodes
+            .into_iter()
+            .enumerate()
+            .map(|(i, node)| {
+                if node.name.trim().is_empty() || node.name.contains('\0') {
+                    anyhow::bail!("nodes[{i}]: invalid node name `{}`", node.name);
+                }
+                let values = [
+                    node.int.map(NodeValue::Int),
+                    node.float.map(NodeValue::Float),
+                    node.enumeration.map(NodeValue::Enum),
+                    node.bool.map(NodeValue::Bool),
+                    node.string.map(NodeValue::String),
+                ];
+                let mut values = values.into_iter().flatten();
+                match (values.next(), values.next()) {
+                    (Some(value), None) => Ok(NodeAssignment {
+                        name: node.name,
+                        value,
+                    }),
+                    _ => anyhow::bail!(
+                        "nodes[{i}] (`{}`): needs exactly one of `int`, `float`, `enum`, `bool` or `string`",
+                        node.name
+                    ),
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            camera,
+            nodes,
+            strict: file.strict,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read profile: {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("invalid profile: {}", path.display()))
+    }
+}
+
+/// Every `*.toml` profile in `dir`, sorted by file name; files that fail to parse are skipped
+/// with a warning.
+pub fn load_dir(dir: &Path) -> Result<Vec<(PathBuf, CameraProfile)>> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("failed to read profile directory: {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| match CameraProfile::load(&path) {
+            Ok(profile) => Some((path, profile)),
+            Err(e) => {
+                tracing::warn!("{e:#}");
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        format!("{:#}", CameraProfile::parse(text).unwrap_err())
+    }
+
+    #[test]
+    fn parses_typed_nodes_in_order() {
+        let profile = CameraProfile::parse(
+            r#"
+strict = true
+
+[camera]
+ip = "192.168.1.64"
+
+[[nodes]]
+name = "ExposureAuto"
+enum = "Off"
+
+[[nodes]]
+name = "ExposureTime"
+float = 8000
+
+[[nodes]]
+name = "OffsetX"
+int = 0
+
+[[nodes]]
+name = "ReverseX"
+bool = true
+
+[[nodes]]
+name = "DeviceUserID"
+string = "line-1"
+"#,
+        )
+        .unwrap();
+        assert!(profile.strict);
+        assert_eq!(
+            profile.camera,
+            CameraSelector::Ip(Ipv4Addr::new(192, 168, 1, 64))
+        );
+        let values: Vec<(&str, &NodeValue)> = profile
+            .nodes
+            .iter()
+            .map(|n| (n.name.as_str(), &n.value))
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("ExposureAuto", &NodeValue::Enum("Off".into())),
+                ("ExposureTime", &NodeValue::Float(8000.0)),
+                ("OffsetX", &NodeValue::Int(0)),
+                ("ReverseX", &NodeValue::Bool(true)),
+                ("DeviceUserID", &NodeValue::String("line-1".into())),
+            ]
+        );
+        assert!(
+            profile
+                .camera
+                .matches("", "", Some(Ipv4Addr::new(192, 168, 1, 64)))
+        );
+        assert!(!profile.camera.matches("", "", None));
+
+        let minimal = CameraProfile::parse("[camera]\nserial = \"DA1\"\n").unwrap();
+        assert!(!minimal.strict && minimal.nodes.is_empty());
+        assert!(minimal.camera.matches("line-1", "DA1", None));
+    }
+
+    #[test]
+    fn validates_the_schema() {
+        assert!(error("nodes = []\n").contains("missing field `camera`"));
+        assert!(error("[camera]\nname = \"a\"\nserial = \"b\"\n").contains("exactly one of"));
+        assert!(error("[camera]\n").contains("exactly one of"));
+        assert!(error("[camera]\nname = \" \"\n").contains("empty name"));
+        assert!(error("[camera]\nip = \"192.168.1\"\n").contains("invalid"));
+        assert!(error("[camera]\nname = \"a\"\nmodel = \"b\"\n").contains("unknown field `model`"));
+        assert!(error("strikt = true\n[camera]\nname = \"a\"\n").contains("unknown field"));
+        let node = |body: &str| error(&format!("[camera]\nname = \"a\"\n[[nodes]]\n{body}"));
+        assert!(node("name = \"Gain\"\n").contains("nodes[0] (`Gain`): needs exactly one"));
+        assert!(node("name = \"Gain\"\nint = 1\nfloat = 1.0\n").contains("needs exactly one"));
+        assert!(node("name = \"\"\nint = 1\n").contains("invalid node name"));
+        assert!(node("name = \"Gain\"\nfloat = \"high\"\n").contains("invalid type"));
+        assert!(node("name = \"Gain\"\nvalue = 1\n").contains("unknown field `value`"));
+    }
+}
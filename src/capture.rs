@@ -0,0 +1,121 @@
+//! Reading raw RGB24 frames from the `ffmpeg` CLI decoder, inline or ahead of the loop
+//! (`--prefetch-depth`).
+//!
+//! `ffmpeg` writes frames in bursts (keyframes, B-frame reordering), so a synchronous read in the
+//! inference loop stalls whenever the next frame is not out yet. [`FfmpegRawRgb24Prefetch`] moves
+//! the reads to a thread that keeps a bounded queue filled; the loop only waits when the decoder
+//! falls behind.
+
+use anyhow::{Context, Result};
+use crossbeam_channel::{Receiver, TryRecvError, bounded};
+use std::io::Read;
+use std::process::{Child, ChildStdout};
+use std::thread::JoinHandle;
+
+/// Read one `width` x `height` RGB24 frame from `ffmpeg`'s stdout; `None` at the end of the
+/// stream.
+pub fn read_raw_frame(
+    stdout: &mut impl Read,
+    width: u32,
+    height: u32,
+) -> Result<Option<image::RgbImage>> {
+    let frame_size = width
+        .checked_mul(height)
+        .and_then(|px| px.checked_mul(3))
+        .context("width*height overflow")?;
+    let mut buf = vec![0u8; frame_size as usize];
+    match stdout.read_exact(&mut buf) {
+        Ok(()) => {
+            let img = image::RgbImage::from_raw(width, height, buf)
+                .context("failed to construct RgbImage")?;
+            Ok(Some(img))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e).context("failed to read frame bytes from ffmpeg"),
+    }
+}
+
+/// Reader thread for the stdout of a [`crate::video_sam3::FfmpegRawRgb24`] decoder. Frames go
+/// through a queue of `depth`, ending with `None` at the end of the stream; a read error goes
+/// through a separate channel and ends the stream.
+pub struct FfmpegRawRgb24Prefetch {
+    frames: Option<Receiver<Option<image::RgbImage>>>,
+    errors: Receiver<anyhow::Error>,
+    thread: Option<JoinHandle<()>>,
+    ended: bool,
+}
+
+impl FfmpegRawRgb24Prefetch {
+    pub fn spawn(mut stdout: ChildStdout, width: u32, height: u32, depth: usize) -> Result<Self> {
+        let (frame_tx, frame_rx) = bounded(depth);
+        let (error_tx, error_rx) = bounded(1);
+        let thread = std::thread::Builder::new()
+            .name("ffmpeg-prefetch".to_string())
+            .spawn(move || {
+                loop {
+                    match read_raw_frame(&mut stdout, width, height) {
+                        // Sending fails once the receiver is dropped, i.e. the run stopped early.
+                        Ok(Some(img)) => {
+                            if frame_tx.send(Some(img)).is_err() {
+                                break;
+                            }
+                        }
+                        Ok(None) => {
+                            let _ = frame_tx.send(None);
+                            break;
+                        }
+                        Err(e) => {
+                            let _ = error_tx.send(e);
+                            break;
+                        }
+                    }
+                }
+            })
+            .context("failed to spawn the frame prefetch thread")?;
+        Ok(Self {
+            frames: Some(frame_rx),
+            errors: error_rx,
+            thread: Some(thread),
+            ended: false,
+        })
+    }
+
+    /// The next frame: a queued one right away, otherwise the reader's error if it failed, or a
+    /// wait for the decoder. `None` at the end of the stream.
+    pub fn next_frame(&mut self) -> Result<Option<image::RgbImage>> {
+        let Some(frames) = self.frames.as_ref().filter(|_| !self.ended) else {
+            return Ok(None);
+        };
+        let frame = match frames.try_recv() {
+            Ok(frame) => Ok(frame),
+            Err(TryRecvError::Empty) => match self.errors.try_recv() {
+                Ok(e) => Err(e),
+                Err(_) => frames.recv().map_err(|_| self.reader_exited()),
+            },
+            Err(TryRecvError::Disconnected) => Err(self.reader_exited()),
+        };
+        if !matches!(frame, Ok(Some(_))) {
+            self.ended = true;
+        }
+        frame
+    }
+
+    /// Why the frame queue closed without an end-of-stream marker.
+    fn reader_exited(&self) -> anyhow::Error {
+        self.errors
+            .try_recv()
+            .unwrap_or_else(|_| anyhow::anyhow!("frame prefetch thread exited unexpectedly"))
+    }
+
+    /// Stop and join the reader. Dropping the receiver unblocks a send into a full queue;
+    /// killing `child` unblocks a read from a pipe `ffmpeg` is still writing to.
+    pub fn stop(&mut self, child: Option<&mut Child>) {
+        self.frames = None;
+        if let Some(child) = child {
+            let _ = child.kill();
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
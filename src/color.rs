@@ -0,0 +1,194 @@
+//! YUV <-> RGB matrices for the `ffmpeg` decoder and [`crate::output::FfmpegVideoWriter`].
+//!
+//! swscale converts with limited-range BT.601 unless told otherwise, so an HD (BT.709) source
+//! decoded to rgb24 and re-encoded to yuv420p comes out with shifted, washed-out colors. The
+//! decoder is given the source's matrix and range from ffprobe, and the encoder converts with the
+//! `--out-color` matrix (the source's by default) and tags the stream with it.
+
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorMatrix {
+    /// Also what untagged sources are assumed to use, as swscale does.
+    #[default]
+    Bt601,
+    Bt709,
+}
+
+impl ColorMatrix {
+    /// Name for the `scale` filter's `in_color_matrix`/`out_color_matrix`.
+    fn swscale_name(self) -> &'static str {
+        match self {
+            Self::Bt601 => "bt601",
+            Self::Bt709 => "bt709",
+        }
+    }
+
+    /// Name for the `-colorspace`, `-color_primaries` and `-color_trc` stream tags.
+    fn tag_name(self) -> &'static str {
+        match self {
+            Self::Bt601 => "smpte170m",
+            Self::Bt709 => "bt709",
+        }
+    }
+
+    /// Encoder filter converting rgb24 to limited-range yuv420p with this matrix.
+    pub fn encode_filter(self) -> String {
+        format!(
+            "scale=out_color_matrix={}:out_range=tv,format=yuv420p",
+            self.swscale_name()
+        )
+    }
+
+    /// Encoder options tagging the output stream with this matrix, limited range.
+    pub fn encode_tags(self) -> [&'static str; 8] {
+        let name = self.tag_name();
+        [
+            "-colorspace",
+            name,
+            "-color_primaries",
+            name,
+            "-color_trc",
+            name,
+            "-color_range",
+            "tv",
+        ]
+    }
+}
+
+/// Color properties of an input's video stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SourceColor {
+    pub matrix: ColorMatrix,
+    /// `color_range=pc` (full-range, JPEG-style) rather than the usual limited range.
+    pub full_range: bool,
+}
+
+impl SourceColor {
+    /// Parse ffprobe's `key=value` lines for `stream=color_space,color_range`. Missing and
+    /// `unknown` values, and matrices other than BT.601/BT.709, keep the defaults.
+    pub fn from_ffprobe(text: &str) -> Self {
+        let mut color = Self::default();
+        for (key, value) in text.lines().filter_map(|l| l.trim().split_once('=')) {
+            match (key, value) {
+                ("color_space", "bt709") => color.matrix = ColorMatrix::Bt709,
+                ("color_range", "pc" | "jpeg") => color.full_range = true,
+                _ => {}
+            }
+        }
+        color
+    }
+
+    /// Decoder filter converting to rgb24 with this source's matrix and range, optionally resized
+    /// to `size`. The trailing `format` keeps ffmpeg from inserting a second, default-matrix
+    /// conversion after it.
+    pub fn decode_filter(self, size: Option<(u32, u32)>) -> String {
+        let size = size.map_or(String::new(), |(w, h)| format!("{w}:{h}:"));
+        format!(
+            "scale={size}in_color_matrix={}:in_range={},format=rgb24",
+            self.matrix.swscale_name(),
+            if self.full_range { "pc" } else { "tv" }
+        )
+    }
+}
+
+/// `--out-color`: matrix of the written video.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutColor {
+    /// Same as the (first) input.
+    #[default]
+    Auto,
+    Fixed(ColorMatrix),
+}
+
+impl OutColor {
+    pub fn resolve(self, source: SourceColor) -> ColorMatrix {
+        match self {
+            Self::Auto => source.matrix,
+            Self::Fixed(matrix) => matrix,
+        }
+    }
+}
+
+impl FromStr for OutColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "bt601" => Ok(Self::Fixed(ColorMatrix::Bt601)),
+            "bt709" => Ok(Self::Fixed(ColorMatrix::Bt709)),
+            other => Err(format!(
+                "invalid --out-color `{other}` (expected bt601, bt709 or auto)"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ffprobe_color_tags() {
+        let hd = SourceColor::from_ffprobe(
+            "color_range=tv\ncolor_space=bt709\ncolor_transfer=bt709\ncolor_primaries=bt709\n",
+        );
+        assert_eq!(
+            hd,
+            SourceColor {
+                matrix: ColorMatrix::Bt709,
+                full_range: false,
+            }
+        );
+        let mjpeg = SourceColor::from_ffprobe("color_range=pc\ncolor_space=bt470bg\n");
+        assert_eq!(mjpeg.matrix, ColorMatrix::Bt601);
+        assert!(mjpeg.full_range);
+        assert_eq!(
+            SourceColor::from_ffprobe("color_range=unknown\ncolor_space=unknown\n"),
+            SourceColor::default()
+        );
+    }
+
+    #[test]
+    fn builds_filters_and_tags() {
+        let hd = SourceColor {
+            matrix: ColorMatrix::Bt709,
+            full_range: false,
+        };
+        assert_eq!(
+            hd.decode_filter(Some((640, 360))),
+            "scale=640:360:in_color_matrix=bt709:in_range=tv,format=rgb24"
+        );
+        let full = SourceColor {
+            matrix: ColorMatrix::Bt601,
+            full_range: true,
+        };
+        assert_eq!(
+            full.decode_filter(None),
+            "scale=in_color_matrix=bt601:in_range=pc,format=rgb24"
+        );
+        assert_eq!(
+            ColorMatrix::Bt709.encode_filter(),
+            "scale=out_color_matrix=bt709:out_range=tv,format=yuv420p"
+        );
+        assert_eq!(ColorMatrix::Bt601.encode_tags()[1], "smpte170m");
+    }
+
+    #[test]
+    fn out_color_resolves_against_the_source() {
+        let hd = SourceColor {
+            matrix: ColorMatrix::Bt709,
+            full_range: false,
+        };
+        assert_eq!(
+            "auto".parse::<OutColor>().unwrap().resolve(hd),
+            ColorMatrix::Bt709
+        );
+        assert_eq!(
+            "BT601".parse::<OutColor>().unwrap().resolve(hd),
+            ColorMatrix::Bt601
+        );
+        assert!("bt2020".parse::<OutColor>().is_err());
+    }
+}
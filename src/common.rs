@@ -0,0 +1,1035 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use usls::{
+    Annotator, Config, Task,
+    models::{SAM3, Sam3Prompt},
+};
+
+use crate::device::ExecutionProvider;
+use crate::preprocess::{OutputSize, TemporalAverager, ToneAdjust, fit_to_size};
+use crate::redact::scrub_credentials;
+use crate::undistort::Undistorter;
+use crate::viz::{prompt_palette, to_rgb};
+
+/// SAM3's native (square) input size; `Config::sam3_image()` defaults to it.
+pub const SAM3_IMGSZ: u32 = 1008;
+/// ViT patch size: the input side has to be a whole number of patches.
+const SAM3_PATCH: u32 = 14;
+/// Smallest accepted `--imgsz` (a 24x24 patch grid).
+const SAM3_MIN_IMGSZ: u32 = 336;
+
+/// Per-channel RGB normalization values (`--image-mean`, `--image-std`), e.g. `0.5,0.5,0.5`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelValues(pub [f32; 3]);
+
+impl std::str::FromStr for ChannelValues {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let err = || format!("invalid channel values `{s}` (expected R,G,B, e.g. 0.5,0.5,0.5)");
+        let vals: Vec<f32> = s
+            .split(',')
+            .map(|v| v.trim().parse::<f32>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| err())?;
+        match vals[..] {
+            [r, g, b] if vals.iter().all(|v| v.is_finite()) => Ok(Self([r, g, b])),
+            _ => Err(err()),
+        }
+    }
+}
+
+/// Check `--imgsz` against what the SAM3 image encoder accepts.
+fn validate_imgsz(imgsz: u32) -> Result<()> {
+    if !(SAM3_MIN_IMGSZ..=SAM3_IMGSZ).contains(&imgsz) || !imgsz.is_multiple_of(SAM3_PATCH) {
+        anyhow::bail!(
+            "invalid --imgsz {imgsz}: must be a multiple of {SAM3_PATCH} between {SAM3_MIN_IMGSZ} and {SAM3_IMGSZ} (e.g. 504, 672, 840, 1008)"
+        );
+    }
+    Ok(())
+}
+
+/// `--conf` when neither the command line nor a `--session` sets it.
+pub const DEFAULT_CONF: f32 = 0.5;
+
+/// Model construction flags shared by all binaries.
+#[derive(Clone, Debug)]
+pub struct ModelOptions {
+    pub task: String,
+    pub device: String,
+    pub dtype: String,
+    pub conf: f32,
+    pub trt_fp16: bool,
+    pub trt_engine_cache: bool,
+    pub trt_timing_cache: bool,
+    /// Square model input side; `None` keeps [`SAM3_IMGSZ`].
+    pub imgsz: Option<u32>,
+    pub image_mean: Option<ChannelValues>,
+    pub image_std: Option<ChannelValues>,
+    /// Extra attempts for transient (download) failures in [`ModelOptions::load_model`].
+    pub retry_init: u32,
+}
+
+impl ModelOptions {
+    /// `task` on `device` at `dtype` and `conf`, everything else at the binaries' defaults.
+    pub fn new(task: &str, device: &str, dtype: &str, conf: f32) -> Self {
+        Self {
+            task: task.to_string(),
+            device: device.to_string(),
+            dtype: dtype.to_string(),
+            conf,
+            trt_fp16: true,
+            trt_engine_cache: true,
+            trt_timing_cache: true,
+            imgsz: None,
+            image_mean: None,
+            image_std: None,
+            retry_init: 0,
+        }
+    }
+
+    pub fn build_config(&self) -> Result<Config> {
+        let mut config = match self.task.parse()? {
+            Task::Sam3Image => Config::sam3_image(),
+            Task::Sam3Tracker => Config::sam3_tracker(),
+            _ => anyhow::bail!(
+                "Sam3 Task now only support: {}, {}",
+                Task::Sam3Image,
+                Task::Sam3Tracker
+            ),
+        }
+        .with_dtype_all(self.dtype.parse()?)
+        .with_class_confs(&[self.conf])
+        .with_device_all(self.device.parse()?);
+        // The TensorRT options are meaningless (and noisy in the logs) for other providers.
+        if ExecutionProvider::from_device(&self.device) == Some(ExecutionProvider::TensorRt) {
+            config = config
+                .with_tensorrt_fp16_all(self.trt_fp16)
+                .with_tensorrt_engine_cache_all(self.trt_engine_cache)
+                .with_tensorrt_timing_cache_all(self.trt_timing_cache);
+        }
+
+        let imgsz = self.imgsz.unwrap_or(SAM3_IMGSZ);
+        validate_imgsz(imgsz)?;
+        if self.imgsz.is_some() {
+            config = config.with_image_width(imgsz).with_image_height(imgsz);
+        }
+        if let Some(ChannelValues(mean)) = self.image_mean {
+            config = config.with_image_mean(&mean);
+        }
+        if let Some(ChannelValues(std)) = self.image_std {
+            if std.iter().any(|v| *v <= 0.0) {
+                anyhow::bail!("invalid --image-std {std:?}: values must be > 0");
+            }
+            config = config.with_image_std(&std);
+        }
+        tracing::info!("Model input size: {imgsz}x{imgsz}");
+
+        let config = config.commit()?;
+        Ok(config)
+    }
+
+    /// Whether the task keeps state across frames (`sam3-tracker`).
+    pub fn is_tracker(&self) -> bool {
+        matches!(self.task.parse(), Ok(Task::Sam3Tracker))
+    }
+
+    /// The ONNX files [`ModelOptions::load_model`] loads for these options, as `commit` resolved
+    /// them (downloaded into the usls cache when needed).
+    pub fn model_files(&self) -> Result<Vec<PathBuf>> {
+        Ok(onnx_files(&format!("{:?}", self.build_config()?)))
+    }
+
+    /// `SAM3::new` with a diagnosis of what went wrong and retries for failed weight downloads.
+    ///
+    /// Retries rebuild the config from `self` and back off 1s, 2s, 4s, ... (capped at 30s).
+    pub fn load_model(&self, config: Config) -> Result<SAM3> {
+        let mut config = Some(config);
+        let mut attempt = 0;
+        loop {
+            let config = match config.take() {
+                Some(c) => c,
+                None => self.build_config()?,
+            };
+            let err = match SAM3::new(config) {
+                Ok(model) => return Ok(model),
+                Err(e) => e,
+            };
+            let failure = InitFailure::classify(&err);
+            if failure == InitFailure::Weights && attempt < self.retry_init {
+                let delay = std::time::Duration::from_secs((1u64 << attempt.min(5)).min(30));
+                attempt += 1;
+                tracing::warn!(
+                    "Model init failed ({}), retrying in {}s ({attempt}/{}): {err:#}",
+                    failure.summary(),
+                    delay.as_secs(),
+                    self.retry_init
+                );
+                std::thread::sleep(delay);
+                continue;
+            }
+            return Err(err.context(format!(
+                "failed to initialize SAM3: {}\n  hint: {}",
+                failure.summary(),
+                failure.hint(self)
+            )));
+        }
+    }
+}
+
+/// Coarse cause of a `SAM3::new` failure, inferred from the error chain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum InitFailure {
+    /// Weights missing locally and the download failed (often transient).
+    Weights,
+    /// Requested execution device/provider is not available.
+    Device,
+    /// TensorRT (or another EP) failed to build an engine for the model.
+    Engine,
+    Other,
+}
+
+/// Every `file: "<path>.onnx"` in a `Config`'s `Debug` text, sorted. usls keeps one ORT config per
+/// model part in fields that differ between tasks, so the text is the one place listing them all.
+fn onnx_files(config_debug: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = config_debug
+        .split("file: \"")
+        .skip(1)
+        .filter_map(|rest| rest.split('"').next())
+        .filter(|file| file.ends_with(".onnx"))
+        .map(PathBuf::from)
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Phrases of TensorRT engine build/load failures, as onnxruntime's TensorRT EP words them.
+const ENGINE_ERRORS: [&str; 6] = [
+    "tensorrt ep",
+    "[tensorrt]",
+    "nvinfer",
+    "build engine",
+    "engine build",
+    "deserialize engine",
+];
+
+/// Phrases of a missing or broken execution provider.
+const DEVICE_ERRORS: [&str; 9] = [
+    "cuda error",
+    "cudnn",
+    "cublas",
+    "no cuda-capable device",
+    "cuda driver version is insufficient",
+    "execution provider",
+    "coreml",
+    "libonnxruntime",
+    "libcudart",
+];
+
+/// Phrases of a failed weights download or a missing weights file.
+const WEIGHTS_ERRORS: [&str; 9] = [
+    "failed to download",
+    "http status",
+    "error sending request",
+    "connection refused",
+    "connection reset",
+    "operation timed out",
+    "dns error",
+    "failed to lookup address",
+    "no such file or directory",
+];
+
+impl InitFailure {
+    /// Device and engine phrases are checked first: a missing provider library is reported as
+    /// "no such file or directory" too.
+    fn classify(err: &anyhow::Error) -> Self {
+        let chain = format!("{err:#}").to_ascii_lowercase();
+        let has = |needles: &[&str]| needles.iter().any(|n| chain.contains(n));
+        if has(&ENGINE_ERRORS) {
+            Self::Engine
+        } else if has(&DEVICE_ERRORS) {
+            Self::Device
+        } else if has(&WEIGHTS_ERRORS) {
+            Self::Weights
+        } else {
+            Self::Other
+        }
+    }
+
+    fn summary(self) -> &'static str {
+        match self {
+            Self::Weights => "model weights not found and download failed",
+            Self::Device => "execution device unavailable",
+            Self::Engine => "inference engine build failed",
+            Self::Other => "model construction failed",
+        }
+    }
+
+    fn hint(self, opts: &ModelOptions) -> String {
+        match self {
+            Self::Weights => format!(
+                "check network access (weights are fetched on first run), then retry with `--retry-init <n>`{}",
+                if opts.retry_init > 0 {
+                    format!(" (gave up after {} retries)", opts.retry_init)
+                } else {
+                    String::new()
+                }
+            ),
+            Self::Device => format!(
+                "`--device {}` could not be initialized; check drivers/onnxruntime providers or try `--device cpu`",
+                opts.device
+            ),
+            Self::Engine => "the TensorRT engine could not be built; try `--trt-fp16 false`, clear the engine cache, or use `--device cuda:0`".to_string(),
+            Self::Other => "run with `--check` to see which init step fails".to_string(),
+        }
+    }
+}
+
+/// Overlay density for annotated frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnnotationStyle {
+    /// Polygon outline only (no mask fill, no labels).
+    Minimal,
+    /// Polygon, mask and label text.
+    #[default]
+    Full,
+    /// Pass-through: frames are shown/saved as captured.
+    ///
+    /// `full` rasterizes every mask, extracts the largest polygon, renders
+    /// label text and allocates a fresh annotated image per inference frame;
+    /// `none` skips all of that, so the loop only pays for capture and
+    /// `model.forward`. Use it to measure pure inference throughput.
+    None,
+}
+
+impl std::str::FromStr for AnnotationStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "minimal" => Ok(Self::Minimal),
+            "full" => Ok(Self::Full),
+            "none" => Ok(Self::None),
+            other => Err(format!(
+                "invalid annotation style `{other}` (expected minimal, full or none)"
+            )),
+        }
+    }
+}
+
+/// Box style in [`PROMPT_PALETTE`](crate::viz::PROMPT_PALETTE) colors.
+pub(crate) fn hbb_style() -> usls::HbbStyle {
+    usls::HbbStyle::default().with_palette(&prompt_palette())
+}
+
+/// Mask style in [`PROMPT_PALETTE`](crate::viz::PROMPT_PALETTE) colors.
+pub(crate) fn mask_style() -> usls::MaskStyle {
+    usls::MaskStyle::default().with_palette(&prompt_palette())
+}
+
+/// Polygon style in [`PROMPT_PALETTE`](crate::viz::PROMPT_PALETTE) colors.
+pub(crate) fn polygon_style() -> usls::PolygonStyle {
+    usls::PolygonStyle::default().with_palette(&prompt_palette())
+}
+
+/// An annotator with default styles in the legend's colors.
+pub fn palette_annotator() -> Annotator {
+    Annotator::default()
+        .with_hbb_style(hbb_style())
+        .with_mask_style(mask_style())
+        .with_polygon_style(polygon_style())
+}
+
+/// Build the annotator for `style`; `None` means annotation is skipped entirely.
+pub fn build_annotator(style: AnnotationStyle, show_mask: bool) -> Option<Annotator> {
+    match style {
+        AnnotationStyle::Full => Some(
+            palette_annotator()
+                .with_mask_style(
+                    mask_style()
+                        .with_visible(show_mask)
+                        .with_cutout(true)
+                        .with_draw_polygon_largest(true),
+                )
+                .with_polygon_style(polygon_style().with_thickness(2)),
+        ),
+        AnnotationStyle::Minimal => Some(
+            palette_annotator()
+                .with_mask_style(
+                    mask_style()
+                        .with_visible(false)
+                        .with_draw_polygon_largest(true),
+                )
+                .with_polygon_style(polygon_style().with_thickness(1).with_text_visible(false)),
+        ),
+        AnnotationStyle::None => None,
+    }
+}
+
+/// Overlay presets the viewer cycles through with the `M` key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnnotationPreset {
+    Boxes,
+    BoxesMasks,
+    CutoutPolygons,
+    Polygons,
+}
+
+impl AnnotationPreset {
+    pub const ALL: [Self; 4] = [
+        Self::Boxes,
+        Self::BoxesMasks,
+        Self::CutoutPolygons,
+        Self::Polygons,
+    ];
+
+    /// The preset whose [`AnnotationPreset::name`] is `name`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Boxes => "boxes",
+            Self::BoxesMasks => "boxes + masks",
+            Self::CutoutPolygons => "cutout + polygons",
+            Self::Polygons => "polygons",
+        }
+    }
+
+    pub fn annotator(self) -> Annotator {
+        let boxes = matches!(self, Self::Boxes | Self::BoxesMasks);
+        let masks = matches!(self, Self::BoxesMasks | Self::CutoutPolygons);
+        let polygons = matches!(self, Self::CutoutPolygons | Self::Polygons);
+        Annotator::default()
+            .with_hbb_style(hbb_style().with_visible(boxes))
+            .with_mask_style(
+                mask_style()
+                    .with_visible(masks)
+                    .with_cutout(self == Self::CutoutPolygons)
+                    .with_draw_polygon_largest(polygons),
+            )
+            .with_polygon_style(polygon_style().with_visible(polygons).with_thickness(2))
+    }
+}
+
+/// Pre-built annotators for every [`AnnotationPreset`], so switching costs nothing mid-stream.
+pub struct AnnotationPresets {
+    annotators: Vec<Annotator>,
+    /// `None` until the first switch: the `--annotation-style` annotator is in use.
+    current: Option<usize>,
+}
+
+impl Default for AnnotationPresets {
+    fn default() -> Self {
+        Self {
+            annotators: AnnotationPreset::ALL
+                .map(AnnotationPreset::annotator)
+                .to_vec(),
+            current: None,
+        }
+    }
+}
+
+impl AnnotationPresets {
+    /// Advance to the next preset (wrapping) and return it with its annotator.
+    pub fn cycle(&mut self) -> (AnnotationPreset, &Annotator) {
+        let next = self.current.map_or(0, |i| (i + 1) % self.annotators.len());
+        self.current = Some(next);
+        (AnnotationPreset::ALL[next], &self.annotators[next])
+    }
+
+    /// The preset switched to last; `None` while `--annotation-style` is in use.
+    pub fn current(&self) -> Option<AnnotationPreset> {
+        self.current.map(|i| AnnotationPreset::ALL[i])
+    }
+
+    /// Switch straight to `preset` and return its annotator.
+    pub fn select(&mut self, preset: AnnotationPreset) -> &Annotator {
+        let i = AnnotationPreset::ALL
+            .iter()
+            .position(|p| *p == preset)
+            .unwrap_or_default();
+        self.current = Some(i);
+        &self.annotators[i]
+    }
+}
+
+/// Draw the inference result plus the prompt boxes/points on top of `img`.
+pub fn annotate_frame(
+    annotator: &Annotator,
+    img: &usls::Image,
+    y: &usls::Y,
+    prompts: &[Sam3Prompt],
+) -> Result<usls::Image> {
+    annotate_prompts(annotator, &annotator.annotate(img, y)?, prompts)
+}
+
+/// Draw only the prompt boxes/points on top of `img`.
+pub fn annotate_prompts(
+    annotator: &Annotator,
+    img: &usls::Image,
+    prompts: &[Sam3Prompt],
+) -> Result<usls::Image> {
+    let mut annotated = img.clone();
+    for prompt in prompts {
+        annotated = annotator.annotate(&annotated, &prompt.boxes)?;
+        annotated = annotator.annotate(&annotated, &prompt.points)?;
+    }
+    Ok(annotated)
+}
+
+/// `--visualize-points-only` needs an annotator even with `--annotation-style none`.
+pub fn prompt_only_annotator(annotator: Option<Annotator>, points_only: bool) -> Option<Annotator> {
+    if points_only {
+        tracing::info!("Running in prompt-visualization mode (no inference)");
+        return annotator.or_else(|| Some(palette_annotator()));
+    }
+    annotator
+}
+
+/// Warn about box prompts that fall outside a `width`x`height` frame (prompts are in frame pixels).
+pub fn warn_prompts_outside(prompts: &[Sam3Prompt], width: u32, height: u32) {
+    for hbb in prompts.iter().flat_map(|p| p.boxes.iter()) {
+        if hbb.xmin() < 0.0
+            || hbb.ymin() < 0.0
+            || hbb.xmax() > width as f32
+            || hbb.ymax() > height as f32
+        {
+            tracing::warn!(
+                "Box prompt ({:.0},{:.0},{:.0},{:.0}) lies outside the {width}x{height} frame",
+                hbb.xmin(),
+                hbb.ymin(),
+                hbb.width(),
+                hbb.height()
+            );
+        }
+    }
+}
+
+/// Exact-match fingerprint for `--dedup-frames`: xxh3 over a 4x-subsampled luma copy.
+///
+/// Sampling every 4th pixel on every 4th row keeps this well under a millisecond at 1080p;
+/// repeated strobe frames are byte-identical, so no perceptual tolerance is needed.
+pub fn frame_hash(img: &usls::Image) -> u64 {
+    const STEP: usize = 4;
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let raw = img.as_raw();
+    let mut luma = Vec::with_capacity(w.div_ceil(STEP) * h.div_ceil(STEP) + 8);
+    for row in raw.chunks_exact(w * 3).step_by(STEP) {
+        for px in row.chunks_exact(3).step_by(STEP) {
+            luma.push(((px[0] as u16 + 2 * px[1] as u16 + px[2] as u16) / 4) as u8);
+        }
+    }
+    luma.extend_from_slice(&(w as u32).to_le_bytes());
+    luma.extend_from_slice(&(h as u32).to_le_bytes());
+    xxhash_rust::xxh3::xxh3_64(&luma)
+}
+
+/// A stream of RGB frames: the ffmpeg decoder, a V4L2 device or a Hikvision camera.
+pub trait VideoSource {
+    /// Next frame; `None` once the stream has ended (cameras never end).
+    fn next_frame(&mut self) -> Result<Option<usls::Image>>;
+    /// Frame size as delivered, before any `--output-size` rescale.
+    fn size(&self) -> (u32, u32);
+    /// Nominal frame rate, if the source has one.
+    fn fps(&self) -> Option<f32>;
+}
+
+impl<S: VideoSource + ?Sized> VideoSource for Box<S> {
+    fn next_frame(&mut self) -> Result<Option<usls::Image>> {
+        (**self).next_frame()
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (**self).size()
+    }
+
+    fn fps(&self) -> Option<f32> {
+        (**self).fps()
+    }
+}
+
+/// Capture stage shared by all binaries: pulls frames from a [`VideoSource`], applies the
+/// per-frame input transforms (`--calibration`, `--output-size`) and numbers the frames from 1.
+///
+/// Transforms added here apply to every input kind at once.
+pub struct Capture<S> {
+    source: S,
+    undistort: Option<Undistorter>,
+    output_size: Option<OutputSize>,
+    letterbox: bool,
+    average: Option<TemporalAverager>,
+    tone: Option<ToneAdjust>,
+    frame_idx: u64,
+}
+
+impl<S: VideoSource> Capture<S> {
+    pub fn new(source: S) -> Self {
+        Self {
+            source,
+            undistort: None,
+            output_size: None,
+            letterbox: false,
+            average: None,
+            tone: None,
+            frame_idx: 0,
+        }
+    }
+
+    pub fn with_output_size(mut self, size: Option<OutputSize>, letterbox: bool) -> Self {
+        self.output_size = size;
+        self.letterbox = letterbox;
+        self
+    }
+
+    /// Undistort every frame at the source resolution, before any other transform.
+    pub fn with_undistort(mut self, undistort: Option<Undistorter>) -> Self {
+        self.undistort = undistort;
+        self
+    }
+
+    /// Average every frame with the ones before it (`--motion-blur-kernel`), after resizing.
+    pub fn with_temporal_average(mut self, average: Option<TemporalAverager>) -> Self {
+        self.average = average;
+        self
+    }
+
+    /// Adjust gamma, brightness and contrast with [`Capture::split_tone`].
+    pub fn with_tone(mut self, tone: Option<ToneAdjust>) -> Self {
+        self.tone = tone;
+        self
+    }
+
+    /// The frame to show and save, and the model input when it differs from that (see
+    /// [`ToneAdjust::split`]); `img` as is without tone adjustments.
+    pub fn split_tone(
+        &mut self,
+        img: usls::Image,
+        infer: bool,
+    ) -> (usls::Image, Option<&usls::Image>) {
+        match self.tone.as_mut() {
+            Some(tone) => tone.split(img, infer),
+            None => (img, None),
+        }
+    }
+
+    /// The undistortion stage, e.g. to toggle it for comparison.
+    pub fn undistort_mut(&mut self) -> Option<&mut Undistorter> {
+        self.undistort.as_mut()
+    }
+
+    /// Next frame and its index; `None` at end of stream. Failed grabs don't consume an index.
+    pub fn next_frame(&mut self) -> Result<Option<(u64, usls::Image)>> {
+        let Some(img) = self.source.next_frame()? else {
+            return Ok(None);
+        };
+        let img = match self.undistort.as_mut() {
+            Some(undistort) => undistort.apply(img),
+            None => img,
+        };
+        let img = match self.output_size {
+            Some(size) if (img.width(), img.height()) != (size.width, size.height) => {
+                let rgb = to_rgb(&img)?;
+                usls::Image::from(fit_to_size(rgb, size, self.letterbox))
+            }
+            _ => img,
+        };
+        let img = match self.average.as_mut() {
+            Some(average) => {
+                let rgb = to_rgb(&img)?;
+                usls::Image::from(average.push(rgb))
+            }
+            None => img,
+        };
+        self.frame_idx += 1;
+        Ok(Some((self.frame_idx, img)))
+    }
+
+    /// Size of the frames [`Capture::next_frame`] returns.
+    pub fn size(&self) -> (u32, u32) {
+        self.output_size
+            .map_or_else(|| self.source.size(), |s| (s.width, s.height))
+    }
+
+    /// Index of the last frame returned by [`Capture::next_frame`] (0 before the first).
+    pub fn frame_idx(&self) -> u64 {
+        self.frame_idx
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
+
+    pub fn into_source(self) -> S {
+        self.source
+    }
+}
+
+/// Hard-cut detector for `--detect-scene-cut`: chi-square distance between luma histograms.
+pub struct SceneCutDetector {
+    threshold: f32,
+    prev: Option<[f32; Self::BINS]>,
+}
+
+impl SceneCutDetector {
+    const BINS: usize = 64;
+
+    pub fn new(threshold: f32) -> Result<Self> {
+        if !(threshold > 0.0 && threshold <= 1.0) {
+            anyhow::bail!("--detect-scene-cut must be in (0, 1], got {threshold}");
+        }
+        Ok(Self {
+            threshold,
+            prev: None,
+        })
+    }
+
+    /// Compare `img` with the frame passed on the previous call; `true` on a cut.
+    ///
+    /// The distance is `0.5 * sum((a - b)^2 / (a + b))` over normalized histograms, so it lies in
+    /// `[0, 1]`: 0 for identical brightness distributions, 1 for disjoint ones.
+    pub fn is_cut(&mut self, img: &usls::Image) -> bool {
+        let mut hist = [0f32; Self::BINS];
+        let mut n = 0f32;
+        for px in img.as_raw().chunks_exact(3).step_by(2) {
+            let luma = (px[0] as u16 * 77 + px[1] as u16 * 150 + px[2] as u16 * 29) >> 8;
+            hist[luma as usize * Self::BINS / 256] += 1.0;
+            n += 1.0;
+        }
+        if n > 0.0 {
+            hist.iter_mut().for_each(|v| *v /= n);
+        }
+        let Some(prev) = self.prev.replace(hist) else {
+            return false;
+        };
+        let dist: f32 = prev
+            .iter()
+            .zip(&hist)
+            .filter(|(a, b)| **a + **b > 0.0)
+            .map(|(a, b)| (a - b).powi(2) / (a + b))
+            .sum::<f32>()
+            * 0.5;
+        dist > self.threshold
+    }
+}
+
+/// Where `--mask-to-json` streams its JSON Lines.
+pub const MASK_TO_JSON_PATH: &str = "/dev/stdout";
+
+/// Log destination: stdout as usual, stderr when stdout carries data (`--mask-to-json`).
+pub fn log_writer(stdout_is_data: bool) -> tracing_subscriber::fmt::writer::BoxMakeWriter {
+    if stdout_is_data {
+        tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stderr)
+    } else {
+        tracing_subscriber::fmt::writer::BoxMakeWriter::new(std::io::stdout)
+    }
+}
+
+/// Reject `--mask-to-json` combined with flags that need rendered frames.
+pub fn check_mask_to_json(enabled: bool, conflicts: &[(&str, bool)]) -> Result<()> {
+    if !enabled {
+        return Ok(());
+    }
+    if let Some((flag, _)) = conflicts.iter().find(|(_, set)| *set) {
+        anyhow::bail!("--mask-to-json cannot be combined with {flag}");
+    }
+    Ok(())
+}
+
+/// Run one forward pass on a black `width`x`height` frame.
+pub fn dummy_forward(
+    model: &mut SAM3,
+    prompts: &[Sam3Prompt],
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    let img = usls::Image::from(image::RgbImage::new(width.max(1), height.max(1)));
+    model.forward(&[img], prompts)?;
+    Ok(())
+}
+
+/// `--warmup-image`: run `runs` forwards on a still image so ONNX Runtime has allocated and tuned
+/// its kernels before the input is even opened. Empty `prompts` fall back to `"object"`.
+pub fn warmup_with_image(
+    model: &mut SAM3,
+    path: &Path,
+    prompts: &[Sam3Prompt],
+    runs: u32,
+) -> Result<()> {
+    let img = image::open(path)
+        .with_context(|| format!("failed to read warm-up image: {}", path.display()))?;
+    let img = usls::Image::from(img.to_rgb8());
+    let fallback: Vec<Sam3Prompt>;
+    let prompts = if prompts.is_empty() {
+        fallback = vec!["object".parse().map_err(|e| anyhow::anyhow!("{e}"))?];
+        &fallback
+    } else {
+        prompts
+    };
+    let started = std::time::Instant::now();
+    let mut first_ms = 0.0;
+    let mut last_ms = 0.0;
+    for i in 0..runs {
+        let run_started = std::time::Instant::now();
+        model.forward(std::slice::from_ref(&img), prompts)?;
+        last_ms = run_started.elapsed().as_secs_f64() * 1000.0;
+        if i == 0 {
+            first_ms = last_ms;
+        }
+    }
+    tracing::info!(
+        "Warm-up: {runs} run(s) on {} in {:.2}s (first {first_ms:.0} ms, last {last_ms:.0} ms)",
+        path.display(),
+        started.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+/// Default save directory (`./runs/<model-spec>/`), created on demand.
+pub fn default_save_base(spec: &str, create: bool) -> Result<PathBuf> {
+    if create {
+        return usls::Dir::Current.base_dir_with_subs(&["runs", spec]);
+    }
+    Ok(std::env::current_dir()?.join("runs").join(spec))
+}
+
+/// Check that `dir` is (or could be created as) a writable directory by creating and removing a
+/// probe file in its nearest existing ancestor; nothing else is created.
+///
+/// The permission bits alone miss ACLs, read-only mounts and directories owned by another user.
+pub fn check_dir_writable(dir: &Path) -> Result<()> {
+    let mut probe = dir;
+    loop {
+        if probe.exists() {
+            let meta = std::fs::metadata(probe)?;
+            if !meta.is_dir() {
+                anyhow::bail!("{} exists but is not a directory", probe.display());
+            }
+            let file = probe.join(format!(".sam3-write-check-{}", std::process::id()));
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&file)
+                .with_context(|| format!("{} is not writable", probe.display()))?;
+            std::fs::remove_file(&file)
+                .with_context(|| format!("failed to remove {}", file.display()))?;
+            return Ok(());
+        }
+        match probe.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => probe = parent,
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Step table for `--check`: records each initialization step and prints a summary.
+pub struct CheckReport {
+    enabled: bool,
+    rows: Vec<(String, bool, String)>,
+}
+
+impl CheckReport {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Record the outcome of `step`; on failure the table printed so far ends with it.
+    pub fn record<T>(&mut self, step: &str, result: Result<T>) -> Result<T> {
+        match result {
+            Ok(v) => {
+                self.rows.push((step.to_string(), true, String::new()));
+                Ok(v)
+            }
+            Err(e) => {
+                if !self.enabled {
+                    return Err(e);
+                }
+                self.rows.push((
+                    step.to_string(),
+                    false,
+                    scrub_credentials(&format!("{e:#}")),
+                ));
+                self.print();
+                Err(e.context(format!("check failed at `{step}`")))
+            }
+        }
+    }
+
+    /// Attach a detail string to the most recently recorded step.
+    pub fn detail(&mut self, detail: impl Into<String>) {
+        if let Some(row) = self.rows.last_mut() {
+            row.2 = scrub_credentials(&detail.into());
+        }
+    }
+
+    pub fn print(&self) {
+        if !self.enabled {
+            return;
+        }
+        for line in self.lines() {
+            println!("{line}");
+        }
+    }
+
+    /// The summary table, one string per line (details are already credential-scrubbed).
+    pub fn lines(&self) -> Vec<String> {
+        let width = self.rows.iter().map(|r| r.0.len()).max().unwrap_or(4).max(4);
+        let mut lines = vec![format!("{:<width$}  {:<6}  detail", "step", "status")];
+        for (step, ok, detail) in &self.rows {
+            let status = if *ok { "ok" } else { "FAILED" };
+            lines.push(format!("{step:<width$}  {status:<6}  {detail}"));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn writable_dir_is_probed_without_leftovers() {
+        let dir = TempDir::new("writable");
+        check_dir_writable(&dir.join("runs/new")).unwrap();
+        assert!(!dir.join("runs").exists());
+        assert_eq!(std::fs::read_dir(dir.join("")).unwrap().count(), 0);
+        std::fs::write(dir.join("file"), b"").unwrap();
+        assert!(check_dir_writable(&dir.join("file/sub")).is_err());
+    }
+
+    #[test]
+    fn onnx_files_come_from_the_config() {
+        let debug = r#"Config { name: "sam3-image", visual_encoder: ORTConfig { file: "/c/usls/sam3/vision-encoder-q4f16.onnx", device: Cpu(0) }, textual_encoder: ORTConfig { file: "/c/usls/sam3/text-encoder-q4f16.onnx" }, decoder: ORTConfig { file: "" }, tokenizer_file: Some("/c/usls/sam3/tokenizer.json"), model: ORTConfig { file: "/c/usls/sam3/vision-encoder-q4f16.onnx" } }"#;
+        assert_eq!(
+            onnx_files(debug),
+            [
+                PathBuf::from("/c/usls/sam3/text-encoder-q4f16.onnx"),
+                PathBuf::from("/c/usls/sam3/vision-encoder-q4f16.onnx"),
+            ]
+        );
+    }
+
+    #[test]
+    fn classifies_init_failures() {
+        let classify = |msg: &str| InitFailure::classify(&anyhow::anyhow!(msg.to_string()));
+        assert_eq!(
+            classify(
+                "[ONNXRuntimeError] : 1 : FAIL : TensorRT EP failed to create engine from network"
+            ),
+            InitFailure::Engine
+        );
+        assert_eq!(
+            classify("CUDA error cudaErrorNoDevice:no CUDA-capable device is detected"),
+            InitFailure::Device
+        );
+        assert_eq!(
+            classify("libonnxruntime_providers_cuda.so: No such file or directory"),
+            InitFailure::Device
+        );
+        assert_eq!(
+            classify("failed to download sam3-encoder.onnx: error sending request for url"),
+            InitFailure::Weights
+        );
+        assert_eq!(
+            classify("./weights/sam3.onnx: No such file or directory (os error 2)"),
+            InitFailure::Weights
+        );
+        // Words that merely appear in unrelated errors.
+        assert_eq!(
+            classify("failed to serialize config: invalid device string `gpu`"),
+            InitFailure::Other
+        );
+        assert_eq!(
+            classify("out of memory: /usr/local/cuda/lib64 not found"),
+            InitFailure::Other
+        );
+    }
+
+    /// `frames` solid frames whose red channel is the 0-based frame number.
+    struct SyntheticSource {
+        frames: u8,
+        sent: u8,
+        width: u32,
+        height: u32,
+    }
+
+    impl VideoSource for SyntheticSource {
+        fn next_frame(&mut self) -> Result<Option<usls::Image>> {
+            if self.sent == self.frames {
+                return Ok(None);
+            }
+            let value = self.sent;
+            self.sent += 1;
+            let rgb =
+                image::RgbImage::from_pixel(self.width, self.height, image::Rgb([value, 0, 0]));
+            Ok(Some(usls::Image::from(rgb)))
+        }
+
+        fn size(&self) -> (u32, u32) {
+            (self.width, self.height)
+        }
+
+        fn fps(&self) -> Option<f32> {
+            Some(30.0)
+        }
+    }
+
+    #[test]
+    fn capture_numbers_and_resizes_frames() {
+        let source = SyntheticSource {
+            frames: 3,
+            sent: 0,
+            width: 64,
+            height: 32,
+        };
+        let size = OutputSize {
+            width: 32,
+            height: 32,
+        };
+        let mut capture = Capture::new(source).with_output_size(Some(size), true);
+        assert_eq!(capture.source().size(), (64, 32));
+        assert_eq!(capture.source().fps(), Some(30.0));
+
+        for expected in 1..=3u64 {
+            let (idx, img) = capture.next_frame().unwrap().expect("frame");
+            assert_eq!(idx, expected);
+            assert_eq!((img.width(), img.height()), (32, 32));
+            // Letterboxed: black bars above and below, the frame in the middle.
+            let center = (16 * 32 + 16) * 3;
+            assert_eq!(img.as_raw()[center], (expected - 1) as u8);
+            assert_eq!(img.as_raw()[0], 0);
+        }
+        assert!(capture.next_frame().unwrap().is_none());
+        assert_eq!(capture.frame_idx(), 3);
+    }
+
+    #[test]
+    fn presets_cycle_from_the_first_and_wrap() {
+        let mut presets = AnnotationPresets::default();
+        assert_eq!(presets.current(), None);
+        let seen: Vec<_> = (0..AnnotationPreset::ALL.len() + 1)
+            .map(|_| presets.cycle().0)
+            .collect();
+        assert_eq!(seen[..AnnotationPreset::ALL.len()], AnnotationPreset::ALL);
+        assert_eq!(seen.last(), Some(&AnnotationPreset::Boxes));
+        assert_eq!(presets.current(), Some(AnnotationPreset::Boxes));
+
+        presets.select(AnnotationPreset::CutoutPolygons);
+        assert_eq!(presets.cycle().0, AnnotationPreset::Polygons);
+        assert_eq!(presets.cycle().0, AnnotationPreset::Boxes);
+    }
+}
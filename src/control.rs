@@ -0,0 +1,218 @@
+//! `--control-socket`: runtime commands over a Unix socket, one per line.
+//!
+//! ```text
+//! prompt <p>[|<p>...]   replace prompts (same syntax as `-p`)
+//! conf <0..1>           change the confidence threshold, not below the startup `--conf`
+//! infer-every <n>       run inference every N frames (0 disables)
+//! snapshot              save the next displayed frame to the save directory
+//! exclude-zone x,y,w,h  add an exclude zone (saved to `--zones-file` on exit)
+//! clear-zones           remove all exclude zones
+//! ping                  liveness check
+//! ```
+//!
+//! Every command gets exactly one reply line: `ok[ <detail>]` or `error <message>`.
+
+use crate::zones::Zone;
+
+/// A parsed control command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlCommand {
+    /// Raw prompt strings, parsed by the binary like `-p`.
+    Prompt(Vec<String>),
+    Conf(f32),
+    InferEvery(u32),
+    Snapshot,
+    ExcludeZone(Zone),
+    ClearZones,
+    Ping,
+}
+
+impl std::str::FromStr for ControlCommand {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim();
+        let (verb, rest) = s
+            .split_once(char::is_whitespace)
+            .map(|(v, r)| (v, r.trim()))
+            .unwrap_or((s, ""));
+        let no_args = |cmd: Self| {
+            if rest.is_empty() {
+                Ok(cmd)
+            } else {
+                Err(format!("`{verb}` takes no arguments"))
+            }
+        };
+        match verb.to_ascii_lowercase().as_str() {
+            "prompt" => {
+                let parts: Vec<String> = rest
+                    .split('|')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .map(|p| p.to_string())
+                    .collect();
+                if parts.is_empty() {
+                    return Err("usage: prompt <p>[|<p>...]".to_string());
+                }
+                Ok(Self::Prompt(parts))
+            }
+            "conf" => match rest.parse::<f32>() {
+                Ok(c) if (0.0..=1.0).contains(&c) => Ok(Self::Conf(c)),
+                _ => Err(format!("invalid conf `{rest}` (expected 0..1)")),
+            },
+            "infer-every" => rest
+                .parse()
+                .map(Self::InferEvery)
+                .map_err(|_| format!("invalid infer-every `{rest}` (expected integer >= 0)")),
+            "snapshot" => no_args(Self::Snapshot),
+            "exclude-zone" => rest.parse().map(Self::ExcludeZone),
+            "clear-zones" => no_args(Self::ClearZones),
+            "ping" => no_args(Self::Ping),
+            other => Err(format!(
+                "unknown command `{other}` (expected prompt, conf, infer-every, snapshot, exclude-zone, clear-zones or ping)"
+            )),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use unix::ControlSocket;
+
+#[cfg(unix)]
+mod unix {
+    use super::ControlCommand;
+    use anyhow::{Context, Result};
+    use std::io::{ErrorKind, Read, Write};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+
+    /// Longest accepted command line; clients sending more are disconnected.
+    const MAX_LINE: usize = 64 * 1024;
+
+    struct Client {
+        stream: UnixStream,
+        buf: Vec<u8>,
+        closed: bool,
+    }
+
+    /// Non-blocking listener polled once per frame by the capture loop.
+    pub struct ControlSocket {
+        path: PathBuf,
+        listener: UnixListener,
+        clients: Vec<Client>,
+    }
+
+    impl ControlSocket {
+        pub fn bind(path: &Path) -> Result<Self> {
+            if let Ok(meta) = std::fs::symlink_metadata(path) {
+                if !meta.file_type().is_socket() {
+                    anyhow::bail!("{} exists and is not a socket", path.display());
+                }
+                // Stale socket left behind by a previous run.
+                std::fs::remove_file(path)
+                    .with_context(|| format!("failed to remove {}", path.display()))?;
+            }
+            let listener = UnixListener::bind(path)
+                .with_context(|| format!("failed to bind control socket: {}", path.display()))?;
+            listener.set_nonblocking(true)?;
+            tracing::info!("Control socket listening on: {}", path.display());
+            Ok(Self {
+                path: path.to_path_buf(),
+                listener,
+                clients: Vec::new(),
+            })
+        }
+
+        /// Accept pending connections and run `handle` on every complete command line.
+        pub fn poll(&mut self, mut handle: impl FnMut(ControlCommand) -> Result<String>) {
+            loop {
+                match self.listener.accept() {
+                    Ok((stream, _)) => {
+                        if stream.set_nonblocking(true).is_ok() {
+                            self.clients.push(Client {
+                                stream,
+                                buf: Vec::new(),
+                                closed: false,
+                            });
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        tracing::warn!("Control socket accept failed: {e}");
+                        break;
+                    }
+                }
+            }
+
+            for client in &mut self.clients {
+                let mut chunk = [0u8; 1024];
+                loop {
+                    match client.stream.read(&mut chunk) {
+                        Ok(0) => {
+                            client.closed = true;
+                            break;
+                        }
+                        Ok(n) => client.buf.extend_from_slice(&chunk[..n]),
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                        Err(_) => {
+                            client.closed = true;
+                            break;
+                        }
+                    }
+                }
+
+                while let Some(pos) = client.buf.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = client.buf.drain(..=pos).collect();
+                    let line = String::from_utf8_lossy(&line);
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let reply = match line.parse::<ControlCommand>() {
+                        Ok(cmd) => {
+                            tracing::debug!("Control command: {cmd:?}");
+                            handle(cmd).map_err(|e| format!("{e:#}"))
+                        }
+                        Err(e) => Err(e),
+                    };
+                    let reply = match reply {
+                        Ok(detail) if detail.is_empty() => "ok\n".to_string(),
+                        Ok(detail) => format!("ok {detail}\n"),
+                        Err(e) => format!("error {}\n", e.replace('\n', " ")),
+                    };
+                    // Replies are tiny; a client that stops reading is simply dropped.
+                    if client.stream.write_all(reply.as_bytes()).is_err() {
+                        client.closed = true;
+                        break;
+                    }
+                }
+
+                if client.buf.len() > MAX_LINE {
+                    let _ = client.stream.write_all(b"error line too long\n");
+                    client.closed = true;
+                }
+            }
+            self.clients.retain(|c| !c.closed);
+        }
+    }
+
+    impl Drop for ControlSocket {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub struct ControlSocket;
+
+#[cfg(not(unix))]
+impl ControlSocket {
+    pub fn bind(_path: &std::path::Path) -> anyhow::Result<Self> {
+        anyhow::bail!("`--control-socket` requires a Unix platform")
+    }
+
+    pub fn poll(&mut self, _handle: impl FnMut(ControlCommand) -> anyhow::Result<String>) {}
+}
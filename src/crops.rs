@@ -0,0 +1,147 @@
+//! `--save-crops`: every detected instance as its own image, for training secondary classifiers.
+//!
+//! Each box is cut from the unannotated frame (grown by `--crop-pad` pixels, clamped to the frame)
+//! and saved as `frame<idx>_inst<j>.jpg`, under a per-prompt subdirectory with `--split-by-prompt`.
+//! `crops.jsonl` gets one line per crop with its label, confidence, source frame and boxes. Crop
+//! names repeat across runs, so a run overwrites the previous run's crops and starts a new index.
+
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::output::prompt_dir_name;
+use crate::viz::to_rgb;
+
+/// `(x, y, w, h)` of the `(x0, y0, x1, y1)` box grown by `pad` and clamped to a `width` x `height`
+/// frame; `None` when nothing is left.
+pub(crate) fn crop_rect(
+    (x0, y0, x1, y1): (f32, f32, f32, f32),
+    pad: u32,
+    width: u32,
+    height: u32,
+) -> Option<(u32, u32, u32, u32)> {
+    let pad = pad as f32;
+    let cx0 = (x0 - pad).floor().clamp(0.0, width as f32) as u32;
+    let cy0 = (y0 - pad).floor().clamp(0.0, height as f32) as u32;
+    let cx1 = (x1 + pad).ceil().clamp(0.0, width as f32) as u32;
+    let cy1 = (y1 + pad).ceil().clamp(0.0, height as f32) as u32;
+    (cx1 > cx0 && cy1 > cy0).then(|| (cx0, cy0, cx1 - cx0, cy1 - cy0))
+}
+
+pub struct CropWriter {
+    dir: PathBuf,
+    pad: u32,
+    split_by_prompt: bool,
+    index: BufWriter<File>,
+    written: u64,
+}
+
+impl CropWriter {
+    pub fn new(dir: &Path, pad: u32, split_by_prompt: bool) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create crops directory: {}", dir.display()))?;
+        let path = dir.join("crops.jsonl");
+        let index = File::options()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        tracing::info!("Saving instance crops to: {}", dir.display());
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            pad,
+            split_by_prompt,
+            index: BufWriter::new(index),
+            written: 0,
+        })
+    }
+
+    /// Crop every box of `y` out of `img` (the unannotated frame).
+    pub fn write(&mut self, frame_idx: u64, img: &usls::Image, y: &usls::Y) -> Result<()> {
+        if y.hbbs().is_empty() {
+            return Ok(());
+        }
+        let frame = to_rgb(img)?;
+        for (j, hbb) in y.hbbs().iter().enumerate() {
+            let Some((x, y, w, h)) = crop_rect(hbb.xyxy(), self.pad, frame.width(), frame.height())
+            else {
+                continue;
+            };
+            let label = hbb.name().unwrap_or("object");
+            let name = format!("frame{frame_idx:06}_inst{j}.jpg");
+            let file = if self.split_by_prompt {
+                let safe = prompt_dir_name(hbb.id().unwrap_or(0), label);
+                std::fs::create_dir_all(self.dir.join(&safe))?;
+                format!("{safe}/{name}")
+            } else {
+                name
+            };
+            let crop = image::imageops::crop_imm(&frame, x, y, w, h).to_image();
+            let path = self.dir.join(&file);
+            crop.save(&path)
+                .with_context(|| format!("failed to write crop: {}", path.display()))?;
+            let (x0, y0, x1, y1) = hbb.xyxy();
+            let line = serde_json::json!({
+                "file": file,
+                "label": label,
+                "confidence": hbb.confidence(),
+                "frame_idx": frame_idx,
+                "instance": j,
+                "bbox": [x0, y0, x1, y1],
+                "crop": [x, y, w, h],
+            });
+            writeln!(self.index, "{line}").context("failed to write crops.jsonl")?;
+            self.written += 1;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.index.flush().context("failed to flush crops.jsonl")?;
+        tracing::info!("Saved {} crop(s) to {}", self.written, self.dir.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crops_are_padded_and_clamped() {
+        assert_eq!(
+            crop_rect((10.2, 20.0, 30.7, 40.0), 0, 100, 100),
+            Some((10, 20, 21, 20))
+        );
+        assert_eq!(
+            crop_rect((5.0, 5.0, 95.0, 50.0), 10, 100, 60),
+            Some((0, 0, 100, 60))
+        );
+        assert_eq!(crop_rect((120.0, 5.0, 150.0, 50.0), 4, 100, 60), None);
+    }
+
+    #[test]
+    fn each_run_starts_a_new_index() {
+        let dir = crate::test_util::TempDir::new("crops");
+        let img = usls::Image::from(image::RgbImage::new(64, 48));
+        let boxes = |n: usize| {
+            let hbbs: Vec<usls::Hbb> = (0..n)
+                .map(|i| usls::Hbb::from_xywh(10.0 * i as f32, 0.0, 8.0, 8.0))
+                .collect();
+            usls::Y::default().with_hbbs(&hbbs)
+        };
+        for n in [3, 1] {
+            let mut crops = CropWriter::new(&dir.join("crops"), 0, false).unwrap();
+            crops.write(7, &img, &boxes(n)).unwrap();
+            crops.finish().unwrap();
+        }
+        let index = std::fs::read_to_string(dir.join("crops/crops.jsonl")).unwrap();
+        let files: Vec<String> = index
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["file"].to_string())
+            .collect();
+        assert_eq!(files, ["\"frame000007_inst0.jpg\""]);
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use usls::models::Sam3Prompt;
+
+use crate::common::{ChannelValues, ModelOptions, SAM3_IMGSZ};
+use crate::viz::visualize_prompts;
+
+/// The model stage whose input [`dump_input_tensor`] writes; the `{model_stage}` of its file name.
+pub const VISION_ENCODER_STAGE: &str = "vision-encoder";
+/// `Config::sam3_image()` normalization when `--image-mean` / `--image-std` are not given.
+const SAM3_IMAGE_MEAN: [f32; 3] = [0.5, 0.5, 0.5];
+const SAM3_IMAGE_STD: [f32; 3] = [0.5, 0.5, 0.5];
+
+/// Write `data` as a little-endian float32 NPY (format v1.0) array of `shape`.
+pub fn save_npy_f32(path: &Path, data: &[f32], shape: &[usize]) -> Result<()> {
+    let expected: usize = shape.iter().product();
+    if data.len() != expected {
+        anyhow::bail!(
+            "NPY shape {:?} needs {} values, got {}",
+            shape,
+            expected,
+            data.len()
+        );
+    }
+
+    let dims = match shape {
+        [n] => format!("({n},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {dims}, }}");
+    // magic (6) + version (2) + header length (2) + header, padded to a multiple of 64, ending in '\n'.
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    header.push('\n');
+    let header_len = u16::try_from(header.len()).context("NPY header too long")?;
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("failed to create NPY file: {}", path.display()))?;
+    let mut out = BufWriter::new(file);
+    out.write_all(b"\x93NUMPY\x01\x00")?;
+    out.write_all(&header_len.to_le_bytes())?;
+    out.write_all(header.as_bytes())?;
+    for v in data {
+        out.write_all(&v.to_le_bytes())?;
+    }
+    out.flush()
+        .with_context(|| format!("failed to write NPY file: {}", path.display()))?;
+    Ok(())
+}
+
+/// The vision encoder input for an RGB frame, built the way the `usls` SAM3 processor does:
+/// stretched to `imgsz` x `imgsz` (bilinear), scaled to `[0, 1]`, then `(x - mean) / std` per
+/// channel. CHW order, `3 * imgsz * imgsz` values.
+pub fn encoder_input(
+    rgb: &[u8],
+    (width, height): (u32, u32),
+    imgsz: u32,
+    mean: [f32; 3],
+    std: [f32; 3],
+) -> Result<Vec<f32>> {
+    let src = image::ImageBuffer::<image::Rgb<u8>, &[u8]>::from_raw(width, height, rgb)
+        .context("frame buffer does not match its size")?;
+    let resized = image::imageops::resize(&src, imgsz, imgsz, image::imageops::FilterType::Triangle);
+    let plane = (imgsz * imgsz) as usize;
+    let mut chw = vec![0f32; plane * 3];
+    for (i, px) in resized.pixels().enumerate() {
+        for c in 0..3 {
+            chw[c * plane + i] = (px[c] as f32 / 255.0 - mean[c]) / std[c];
+        }
+    }
+    Ok(chw)
+}
+
+/// Dump an approximation of what the SAM3 vision encoder receives for `img` as
+/// `<frame>_vision-encoder-approx-input.npy`, a `[1, 3, imgsz, imgsz]` float32 tensor with
+/// `model`'s size and normalization.
+///
+/// `usls` exposes no hook into its processor at the pinned revision, so [`encoder_input`]
+/// rebuilds the tensor from the same configuration; its resampling may differ from usls' in the
+/// last bits.
+pub fn dump_input_tensor(
+    dir: &Path,
+    frame_idx: u64,
+    img: &usls::Image,
+    model: &ModelOptions,
+) -> Result<()> {
+    let imgsz = model.imgsz.unwrap_or(SAM3_IMGSZ);
+    let mean = model.image_mean.map_or(SAM3_IMAGE_MEAN, |ChannelValues(v)| v);
+    let std = model.image_std.map_or(SAM3_IMAGE_STD, |ChannelValues(v)| v);
+    let tensor = encoder_input(img.as_raw(), (img.width(), img.height()), imgsz, mean, std)?;
+    let side = imgsz as usize;
+    let path = dir.join(dump_file_name(frame_idx));
+    save_npy_f32(&path, &tensor, &[1, 3, side, side])
+}
+
+/// The [`dump_input_tensor`] file of `frame_idx`; `approx` because it is rebuilt, not read back
+/// from the session.
+fn dump_file_name(frame_idx: u64) -> String {
+    format!("{frame_idx:08}_{VISION_ENCODER_STAGE}-approx-input.npy")
+}
+
+/// Save [`visualize_prompts`] for the frame about to be inferred as `<frame>_prompt_<i>.jpg`.
+pub fn export_prompt_vis(
+    dir: &Path,
+    frame_idx: u64,
+    img: &usls::Image,
+    prompts: &[Sam3Prompt],
+) -> Result<()> {
+    for (i, vis) in visualize_prompts(img, prompts)?.iter().enumerate() {
+        let path = dir.join(format!("{frame_idx:08}_prompt_{i}.jpg"));
+        vis.save(&path)
+            .with_context(|| format!("failed to save prompt visualization: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// xxh3 over the pixel bytes; `--verify-seek-accuracy` logs it to tell identical frames apart
+/// at a glance.
+pub fn frame_checksum(img: &image::RgbImage) -> u64 {
+    xxhash_rust::xxh3::xxh3_64(img.as_raw())
+}
+
+/// Pixels where any channel of `a` and `b` differs by more than `threshold`.
+pub fn differing_pixels(a: &image::RgbImage, b: &image::RgbImage, threshold: u8) -> u64 {
+    if a.dimensions() != b.dimensions() {
+        return u64::from(a.width()) * u64::from(a.height());
+    }
+    a.pixels()
+        .zip(b.pixels())
+        .filter(|(p, q)| (0..3).any(|c| p[c].abs_diff(q[c]) > threshold))
+        .count() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_and_pixel_diff() {
+        let a = image::RgbImage::from_fn(8, 8, |x, y| image::Rgb([x as u8 * 30, y as u8, 7]));
+        let mut b = a.clone();
+        assert_eq!(frame_checksum(&a), frame_checksum(&b));
+        b.put_pixel(1, 1, image::Rgb([a.get_pixel(1, 1)[0] + 2, 1, 7]));
+        assert_ne!(frame_checksum(&a), frame_checksum(&b));
+        assert_eq!(differing_pixels(&a, &b, 2), 0);
+        b.put_pixel(2, 2, image::Rgb([200, 2, 7]));
+        assert_eq!(differing_pixels(&a, &b, 2), 1);
+        assert_eq!(differing_pixels(&a, &image::RgbImage::new(4, 4), 2), 64);
+    }
+
+    #[test]
+    fn encoder_input_is_resized_and_normalized() {
+        let rgb = [255u8, 0, 51].repeat(6 * 4);
+        let t = encoder_input(&rgb, (6, 4), 8, [0.5; 3], [0.5; 3]).unwrap();
+        assert_eq!(t.len(), 3 * 8 * 8);
+        let plane = 64;
+        assert!(t[..plane].iter().all(|v| (v - 1.0).abs() < 1e-6));
+        assert!(t[plane..2 * plane].iter().all(|v| (v + 1.0).abs() < 1e-6));
+        assert!(t[2 * plane..].iter().all(|v| (v + 0.6).abs() < 1e-6));
+        assert!(encoder_input(&rgb[1..], (6, 4), 8, [0.5; 3], [0.5; 3]).is_err());
+    }
+
+    #[test]
+    fn encoder_input_reconstruction_is_pinned() {
+        // A 4x1 ramp upscaled to 8x8: pins the bilinear (Triangle) resample and the channel order.
+        let rgb: Vec<u8> = [0u8, 85, 170, 255]
+            .iter()
+            .flat_map(|&v| [v, 255 - v, 128])
+            .collect();
+        let t = encoder_input(&rgb, (4, 1), 8, [0.5; 3], [0.5; 3]).unwrap();
+        let red = [0u8, 21, 64, 106, 149, 191, 234, 255];
+        let normalized = |v: u8| (v as f32 / 255.0 - 0.5) / 0.5;
+        let plane = 64;
+        for row in 0..8 {
+            for (x, &r) in red.iter().enumerate() {
+                let i = row * 8 + x;
+                assert!((t[i] - normalized(r)).abs() < 1e-6, "R at {i}: {}", t[i]);
+                assert!((t[plane + i] - normalized(255 - r)).abs() < 1e-6, "G at {i}");
+                assert!((t[2 * plane + i] - normalized(128)).abs() < 1e-6, "B at {i}");
+            }
+        }
+        assert_eq!(dump_file_name(3), "00000003_vision-encoder-approx-input.npy");
+    }
+}
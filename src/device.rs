@@ -0,0 +1,265 @@
+//! `--execution-provider` and `--device auto`.
+//!
+//! Every provider maps onto a usls device string, so model construction only ever sees a
+//! device. Without `--device` the model runs on `cpu:0`; `--device auto` picks a provider for the
+//! machine, never one that was not compiled in (cargo features).
+
+/// ONNX Runtime execution provider.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecutionProvider {
+    Cpu,
+    Cuda,
+    TensorRt,
+    DirectMl,
+    CoreMl,
+    OpenVino,
+}
+
+impl std::str::FromStr for ExecutionProvider {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cpu" => Ok(Self::Cpu),
+            "cuda" => Ok(Self::Cuda),
+            "tensorrt" | "trt" => Ok(Self::TensorRt),
+            "directml" | "dml" => Ok(Self::DirectMl),
+            "coreml" => Ok(Self::CoreMl),
+            "openvino" => Ok(Self::OpenVino),
+            _ => Err(format!(
+                "unknown execution provider `{s}` (expected cpu, cuda, tensorrt, directml, coreml or openvino)"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ExecutionProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Cpu => "cpu",
+            Self::Cuda => "cuda",
+            Self::TensorRt => "tensorrt",
+            Self::DirectMl => "directml",
+            Self::CoreMl => "coreml",
+            Self::OpenVino => "openvino",
+        })
+    }
+}
+
+impl ExecutionProvider {
+    /// The provider a `--device` string (`cuda:0`, `cpu`, ...) selects.
+    pub fn from_device(device: &str) -> Option<Self> {
+        device.split(':').next()?.parse().ok()
+    }
+
+    /// usls device string for device `index`.
+    pub fn device(self, index: usize) -> String {
+        match self {
+            Self::CoreMl => "coreml".to_string(),
+            Self::OpenVino => "openvino:CPU".to_string(),
+            ep => format!("{ep}:{index}"),
+        }
+    }
+
+    /// Whether this binary was built with the provider's cargo feature.
+    pub fn is_built(self) -> bool {
+        match self {
+            Self::Cpu => true,
+            Self::Cuda => cfg!(feature = "cuda"),
+            Self::TensorRt => cfg!(feature = "tensorrt"),
+            Self::DirectMl => cfg!(feature = "directml"),
+            Self::CoreMl => cfg!(feature = "coreml"),
+            Self::OpenVino => cfg!(feature = "openvino"),
+        }
+    }
+}
+
+/// `--device` without a value.
+pub const DEFAULT_DEVICE: &str = "cpu:0";
+
+/// At least one GPU the CUDA driver library (`libcuda.so.1`) can initialize. This is what the
+/// CUDA provider needs at run time; a toolkit (`nvcc`) is neither needed nor enough.
+#[cfg(target_os = "linux")]
+fn has_cuda() -> bool {
+    type CuInit = unsafe extern "C" fn(u32) -> i32;
+    type CuDeviceGetCount = unsafe extern "C" fn(*mut i32) -> i32;
+    // SAFETY: the symbols are looked up by their documented driver API names and called with
+    // matching signatures; the library stays loaded for the rest of the process.
+    unsafe {
+        let lib = libc::dlopen(c"libcuda.so.1".as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if lib.is_null() {
+            return false;
+        }
+        let init = libc::dlsym(lib, c"cuInit".as_ptr());
+        let count = libc::dlsym(lib, c"cuDeviceGetCount".as_ptr());
+        if init.is_null() || count.is_null() {
+            return false;
+        }
+        let init: CuInit = std::mem::transmute(init);
+        let count: CuDeviceGetCount = std::mem::transmute(count);
+        let mut n = 0;
+        init(0) == 0 && count(&mut n) == 0 && n > 0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn has_cuda() -> bool {
+    false
+}
+
+/// `(intel, amx)` from `/proc/cpuinfo`; both false off Linux.
+fn intel_cpu() -> (bool, bool) {
+    let Ok(info) = std::fs::read_to_string("/proc/cpuinfo") else {
+        return (false, false);
+    };
+    let intel = info
+        .lines()
+        .any(|l| l.starts_with("vendor_id") && l.contains("GenuineIntel"));
+    let amx = info
+        .lines()
+        .any(|l| l.starts_with("flags") && l.split_whitespace().any(|f| f == "amx_tile"));
+    (intel, amx)
+}
+
+/// Provider for `--device auto`, with the reason.
+fn auto_provider() -> (ExecutionProvider, &'static str) {
+    use ExecutionProvider::*;
+    if Cuda.is_built() && has_cuda() {
+        return (Cuda, "CUDA available");
+    }
+    if cfg!(target_os = "windows") && DirectMl.is_built() {
+        return (DirectMl, "Windows without CUDA");
+    }
+    if cfg!(target_os = "macos") && CoreMl.is_built() {
+        return (CoreMl, "macOS");
+    }
+    if cfg!(target_os = "linux") && OpenVino.is_built() {
+        match intel_cpu() {
+            (true, true) => return (OpenVino, "Intel CPU with AMX"),
+            (true, false) => return (OpenVino, "Intel CPU"),
+            _ => {}
+        }
+    }
+    (Cpu, "no accelerated provider built or available")
+}
+
+/// `--ep`, the short form of `--execution-provider`; giving both with different values is an
+/// error.
+pub fn ep_flag(
+    ep: Option<ExecutionProvider>,
+    execution_provider: Option<ExecutionProvider>,
+) -> anyhow::Result<Option<ExecutionProvider>> {
+    match (ep, execution_provider) {
+        (Some(a), Some(b)) if a != b => {
+            anyhow::bail!("--ep {a} conflicts with --execution-provider {b}")
+        }
+        (ep, execution_provider) => Ok(ep.or(execution_provider)),
+    }
+}
+
+/// Resolve `--device` / `--execution-provider` into the usls device string and its provider.
+///
+/// An explicit `--execution-provider` wins (keeping `--device`'s index); a bare `--device` is used
+/// as-is, `auto` picking the provider for this machine; with neither, [`DEFAULT_DEVICE`]. The
+/// choice is logged. A provider chosen by either flag but not compiled in is an error rather than
+/// a failure at model load.
+pub fn auto_select_device_and_ep(
+    device: Option<&str>,
+    ep: Option<ExecutionProvider>,
+) -> anyhow::Result<(String, ExecutionProvider)> {
+    let (device, ep, reason) = resolve(device, ep);
+    if !ep.is_built() {
+        anyhow::bail!(
+            "the `{ep}` execution provider ({reason}) is not compiled into this binary; rebuild with `--features {ep}` or pick another with --ep"
+        );
+    }
+    tracing::info!("Execution provider: {ep} on {device} ({reason})");
+    Ok((device, ep))
+}
+
+fn resolve(
+    device: Option<&str>,
+    ep: Option<ExecutionProvider>,
+) -> (String, ExecutionProvider, &'static str) {
+    match (device, ep) {
+        (Some("auto"), None) => {
+            let (ep, reason) = auto_provider();
+            (ep.device(0), ep, reason)
+        }
+        (Some("auto"), Some(ep)) => (ep.device(0), ep, "--execution-provider"),
+        (Some(device), None) => {
+            let ep = ExecutionProvider::from_device(device).unwrap_or(ExecutionProvider::Cpu);
+            (device.to_string(), ep, "--device")
+        }
+        // Already a device of that provider, e.g. `--ep openvino --device openvino:GPU`.
+        (Some(device), Some(ep)) if ExecutionProvider::from_device(device) == Some(ep) => {
+            (device.to_string(), ep, "--execution-provider")
+        }
+        (device, Some(ep)) => {
+            let index = device
+                .and_then(|d| d.split_once(':'))
+                .and_then(|(_, i)| i.parse().ok())
+                .unwrap_or(0);
+            let resolved = ep.device(index);
+            if let Some(d) = device
+                && ExecutionProvider::from_device(d) != Some(ep)
+            {
+                tracing::warn!("--device {d} overridden by --execution-provider {ep} ({resolved})");
+            }
+            (resolved, ep, "--execution-provider")
+        }
+        (None, None) => (
+            DEFAULT_DEVICE.to_string(),
+            ExecutionProvider::Cpu,
+            "default",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn providers_map_to_device_strings() {
+        use ExecutionProvider::*;
+        assert_eq!("DML".parse::<ExecutionProvider>(), Ok(DirectMl));
+        assert!("rocm".parse::<ExecutionProvider>().is_err());
+        assert_eq!(ExecutionProvider::from_device("tensorrt:1"), Some(TensorRt));
+        let resolved = |device, ep| {
+            let (device, ep, _) = resolve(device, ep);
+            (device, ep)
+        };
+        assert_eq!(
+            resolved(Some("cuda:1"), Some(DirectMl)),
+            ("directml:1".to_string(), DirectMl)
+        );
+        assert_eq!(resolved(Some("cuda:0"), None), ("cuda:0".to_string(), Cuda));
+        assert_eq!(
+            resolved(Some("openvino:GPU"), Some(OpenVino)),
+            ("openvino:GPU".to_string(), OpenVino)
+        );
+        assert_eq!(OpenVino.device(3), "openvino:CPU");
+        assert_eq!(resolved(None, None), ("cpu:0".to_string(), Cpu));
+        assert_eq!(
+            resolved(Some("auto"), Some(TensorRt)),
+            ("tensorrt:0".to_string(), TensorRt)
+        );
+        assert!(resolved(Some("auto"), None).1.is_built());
+    }
+
+    #[test]
+    fn explicit_providers_must_be_built() {
+        use ExecutionProvider::*;
+        assert!(auto_select_device_and_ep(Some("cpu:0"), None).is_ok());
+        for ep in [Cuda, TensorRt, DirectMl, CoreMl, OpenVino] {
+            assert_eq!(
+                auto_select_device_and_ep(None, Some(ep)).is_ok(),
+                ep.is_built()
+            );
+        }
+        assert_eq!(ep_flag(Some(Cpu), None).unwrap(), Some(Cpu));
+        assert_eq!(ep_flag(None, Some(Cuda)).unwrap(), Some(Cuda));
+        assert!(ep_flag(Some(Cpu), Some(Cuda)).is_err());
+    }
+}
@@ -0,0 +1,488 @@
+//! `--zone name=x,y,w,h`: how long each tracked object stays inside named zones (queue analysis).
+//!
+//! Boxes of consecutive inferences are linked into tracks by greedy IoU matching per label; a track
+//! ends after [`MAX_MISSES`] inferences without a match. A track is inside a zone when its box center
+//! is or, with `--dwell-overlap <f>`, when at least that fraction of its box is covered. Crossing a
+//! zone edge emits a `zone-enter`/`zone-exit` [`ZoneEvent`]; at the end every track, including those
+//! that never touched a zone, is summarized with its first/last sighting and dwell per zone.
+//!
+//! With `--kalman` each track's box is a [`KalmanBox`] estimate: tracks are matched against their
+//! predicted boxes, and [`DwellTracker::predict`] moves them along on frames without inference so
+//! zone crossings are timed between inferences too.
+
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+use crate::geometry::{Rect, iou};
+use crate::tracking::kalman::KalmanBox;
+use crate::zones::Zone;
+
+/// Minimum IoU for a box to continue a track.
+const MIN_IOU: f32 = 0.3;
+/// Inferences a track may go unmatched before it ends.
+pub const MAX_MISSES: u32 = 5;
+
+/// A `--zone name=x,y,w,h`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedZone {
+    pub name: String,
+    pub zone: Zone,
+}
+
+impl std::str::FromStr for NamedZone {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, rect) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid zone `{s}` (expected name=x,y,w,h)"))?;
+        let name = name.trim();
+        if name.is_empty() || name.contains(',') {
+            return Err(format!("invalid zone name in `{s}`"));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            zone: rect.parse()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ZoneEventKind {
+    ZoneEnter,
+    ZoneExit,
+}
+
+/// A track crossing a zone edge, at `t_s` seconds into the run.
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct ZoneEvent {
+    pub event: ZoneEventKind,
+    pub zone: String,
+    pub track_id: u64,
+    pub label: String,
+    pub t_s: f64,
+}
+
+struct Track {
+    id: u64,
+    label: String,
+    rect: Rect,
+    first_seen: f64,
+    last_seen: f64,
+    misses: u32,
+    /// With `--kalman`: the box estimate, and when it was last predicted.
+    filter: Option<(KalmanBox, f64)>,
+    /// Per zone: when the current stay began.
+    inside_since: Vec<Option<f64>>,
+    /// Per zone: seconds of completed stays.
+    dwell: Vec<f64>,
+}
+
+/// Move a `--kalman` track's box to its predicted position at `t_s`.
+fn predict_to(track: &mut Track, t_s: f64) {
+    if let Some((filter, predicted_at)) = &mut track.filter {
+        filter.predict((t_s - *predicted_at) as f32);
+        *predicted_at = t_s;
+        track.rect = filter.rect();
+    }
+}
+
+fn log_events(events: &[ZoneEvent]) {
+    for e in events {
+        tracing::info!(
+            "{} {}: track {} ({}) at {:.2}s",
+            match e.event {
+                ZoneEventKind::ZoneEnter => "Entered",
+                ZoneEventKind::ZoneExit => "Left",
+            },
+            e.zone,
+            e.track_id,
+            e.label,
+            e.t_s
+        );
+    }
+}
+
+pub struct DwellTracker {
+    zones: Vec<NamedZone>,
+    min_overlap: Option<f32>,
+    kalman: bool,
+    active: Vec<Track>,
+    ended: Vec<Track>,
+    next_id: u64,
+}
+
+impl DwellTracker {
+    pub fn new(zones: Vec<NamedZone>, min_overlap: Option<f32>, kalman: bool) -> Result<Self> {
+        if let Some(f) = min_overlap
+            && !(f > 0.0 && f <= 1.0)
+        {
+            anyhow::bail!("--dwell-overlap must be in (0, 1], got {f}");
+        }
+        let names: Vec<&str> = zones.iter().map(|z| z.name.as_str()).collect();
+        tracing::info!("Dwell zones: {}", names.join(", "));
+        Ok(Self {
+            zones,
+            min_overlap,
+            kalman,
+            active: Vec::new(),
+            ended: Vec::new(),
+            next_id: 1,
+        })
+    }
+
+    fn inside(&self, zone: &Zone, rect: Rect) -> bool {
+        match self.min_overlap {
+            Some(f) => zone.coverage(rect) >= f,
+            None => zone.contains((rect.0 + rect.2) / 2.0, (rect.1 + rect.3) / 2.0),
+        }
+    }
+
+    /// Feed the boxes of one inference at `t_s`; returns the zone crossings.
+    pub fn update(&mut self, t_s: f64, y: &usls::Y) -> Vec<ZoneEvent> {
+        let boxes: Vec<(String, Rect)> = y
+            .hbbs()
+            .iter()
+            .map(|h| (h.name().unwrap_or("object").to_string(), h.xyxy()))
+            .collect();
+        self.update_boxes(t_s, &boxes)
+    }
+
+    /// With `--kalman`, move the tracks to their predicted boxes at `t_s` (a frame without
+    /// inference); returns the zone crossings. A no-op without `--kalman`.
+    pub fn predict(&mut self, t_s: f64) -> Vec<ZoneEvent> {
+        let mut events = Vec::new();
+        let mut active = std::mem::take(&mut self.active);
+        for track in active.iter_mut().filter(|t| t.filter.is_some()) {
+            predict_to(track, t_s);
+            self.step_zones(track, t_s, &mut events);
+        }
+        self.active = active;
+        log_events(&events);
+        events
+    }
+
+    fn update_boxes(&mut self, t_s: f64, boxes: &[(String, Rect)]) -> Vec<ZoneEvent> {
+        for track in &mut self.active {
+            predict_to(track, t_s);
+        }
+        let mut pairs: Vec<(f32, usize, usize)> = Vec::new();
+        for (ti, track) in self.active.iter().enumerate() {
+            for (bi, (label, rect)) in boxes.iter().enumerate() {
+                let overlap = iou(track.rect, *rect);
+                if *label == track.label && overlap >= MIN_IOU {
+                    pairs.push((overlap, ti, bi));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut track_box: Vec<Option<usize>> = vec![None; self.active.len()];
+        let mut box_taken = vec![false; boxes.len()];
+        for (_, ti, bi) in pairs {
+            if track_box[ti].is_none() && !box_taken[bi] {
+                track_box[ti] = Some(bi);
+                box_taken[bi] = true;
+            }
+        }
+
+        let mut events = Vec::new();
+        let mut active = std::mem::take(&mut self.active);
+        for (track, matched) in active.iter_mut().zip(&track_box) {
+            match matched {
+                Some(bi) => {
+                    track.rect = match &mut track.filter {
+                        Some((filter, _)) => {
+                            filter.correct(boxes[*bi].1);
+                            filter.rect()
+                        }
+                        None => boxes[*bi].1,
+                    };
+                    track.last_seen = t_s;
+                    track.misses = 0;
+                    self.step_zones(track, t_s, &mut events);
+                }
+                None => {
+                    track.misses += 1;
+                    // A `--kalman` track coasts on its prediction.
+                    if track.filter.is_some() {
+                        self.step_zones(track, t_s, &mut events);
+                    }
+                }
+            }
+        }
+        for (bi, (label, rect)) in boxes.iter().enumerate() {
+            if box_taken[bi] {
+                continue;
+            }
+            let mut track = Track {
+                id: self.next_id,
+                label: label.clone(),
+                rect: *rect,
+                first_seen: t_s,
+                last_seen: t_s,
+                misses: 0,
+                filter: self.kalman.then(|| (KalmanBox::new(*rect), t_s)),
+                inside_since: vec![None; self.zones.len()],
+                dwell: vec![0.0; self.zones.len()],
+            };
+            self.next_id += 1;
+            self.step_zones(&mut track, t_s, &mut events);
+            active.push(track);
+        }
+        let (lost, kept): (Vec<Track>, Vec<Track>) =
+            active.into_iter().partition(|t| t.misses > MAX_MISSES);
+        self.active = kept;
+        for mut track in lost {
+            let at = track.last_seen;
+            self.leave_all(&mut track, at, &mut events);
+            self.ended.push(track);
+        }
+        log_events(&events);
+        events
+    }
+
+    fn event(&self, kind: ZoneEventKind, zi: usize, track: &Track, t_s: f64) -> ZoneEvent {
+        ZoneEvent {
+            event: kind,
+            zone: self.zones[zi].name.clone(),
+            track_id: track.id,
+            label: track.label.clone(),
+            t_s,
+        }
+    }
+
+    fn step_zones(&self, track: &mut Track, t_s: f64, events: &mut Vec<ZoneEvent>) {
+        for zi in 0..self.zones.len() {
+            let inside = self.inside(&self.zones[zi].zone, track.rect);
+            match (track.inside_since[zi], inside) {
+                (None, true) => {
+                    track.inside_since[zi] = Some(t_s);
+                    events.push(self.event(ZoneEventKind::ZoneEnter, zi, track, t_s));
+                }
+                (Some(since), false) => {
+                    track.dwell[zi] += t_s - since;
+                    track.inside_since[zi] = None;
+                    events.push(self.event(ZoneEventKind::ZoneExit, zi, track, t_s));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Close every open stay at `t_s` (the track's last sighting).
+    fn leave_all(&self, track: &mut Track, t_s: f64, events: &mut Vec<ZoneEvent>) {
+        for zi in 0..self.zones.len() {
+            if let Some(since) = track.inside_since[zi].take() {
+                track.dwell[zi] += t_s - since;
+                events.push(self.event(ZoneEventKind::ZoneExit, zi, track, t_s));
+            }
+        }
+    }
+
+    /// End all tracks, write `tracks.csv` to `csv_path` and return the per-track summary for the
+    /// run summary. A CSV that cannot be written is logged; the summary is returned either way.
+    pub fn finish(mut self, csv_path: &Path) -> serde_json::Value {
+        for mut track in std::mem::take(&mut self.active) {
+            let at = track.last_seen;
+            self.leave_all(&mut track, at, &mut Vec::new());
+            self.ended.push(track);
+        }
+        self.ended.sort_by_key(|t| t.id);
+
+        match self.write_csv(csv_path) {
+            Ok(()) => tracing::info!(
+                "Wrote {} track(s) to {}",
+                self.ended.len(),
+                csv_path.display()
+            ),
+            Err(e) => tracing::warn!("Track CSV not written: {e:#}"),
+        }
+
+        let tracks: Vec<serde_json::Value> = self
+            .ended
+            .iter()
+            .map(|t| {
+                let dwell: serde_json::Map<String, serde_json::Value> = self
+                    .zones
+                    .iter()
+                    .zip(&t.dwell)
+                    .map(|(z, d)| (z.name.clone(), (*d).into()))
+                    .collect();
+                serde_json::json!({
+                    "track_id": t.id,
+                    "label": t.label,
+                    "first_seen_s": t.first_seen,
+                    "last_seen_s": t.last_seen,
+                    "dwell_s": dwell,
+                })
+            })
+            .collect();
+        tracks.into()
+    }
+
+    fn write_csv(&self, csv_path: &Path) -> Result<()> {
+        if let Some(dir) = csv_path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let mut csv = std::io::BufWriter::new(
+            std::fs::File::create(csv_path)
+                .with_context(|| format!("failed to create {}", csv_path.display()))?,
+        );
+        let zone_cols: Vec<String> = self
+            .zones
+            .iter()
+            .map(|z| csv_field(&format!("dwell_{}_s", z.name)).into_owned())
+            .collect();
+        writeln!(
+            csv,
+            "track_id,label,first_seen_s,last_seen_s,{}",
+            zone_cols.join(",")
+        )?;
+        for t in &self.ended {
+            let dwell: Vec<String> = t.dwell.iter().map(|d| format!("{d:.3}")).collect();
+            writeln!(
+                csv,
+                "{},{},{:.3},{:.3},{}",
+                t.id,
+                csv_field(&t.label),
+                t.first_seen,
+                t.last_seen,
+                dwell.join(",")
+            )?;
+        }
+        csv.flush()
+            .with_context(|| format!("failed to write {}", csv_path.display()))
+    }
+}
+
+/// `s` as a CSV field: quoted, with doubled quotes, when it holds a comma, quote or line break.
+fn csv_field(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\"")).into()
+    } else {
+        s.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> DwellTracker {
+        DwellTracker::new(vec!["a=0,0,100,100".parse().unwrap()], None, false).unwrap()
+    }
+
+    fn person(x: f32) -> (String, Rect) {
+        ("person".to_string(), (x, 10.0, x + 40.0, 50.0))
+    }
+
+    #[test]
+    fn parses_named_zones() {
+        let z: NamedZone = "queue=10,20,30,40".parse().unwrap();
+        assert_eq!(z.name, "queue");
+        assert_eq!(z.zone, "10,20,30,40".parse().unwrap());
+        assert!("10,20,30,40".parse::<NamedZone>().is_err());
+        assert!("=10,20,30,40".parse::<NamedZone>().is_err());
+    }
+
+    #[test]
+    fn dwell_accumulates_between_enter_and_exit() {
+        let mut dwell = tracker();
+        let events = dwell.update_boxes(0.0, &[person(40.0)]);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, ZoneEventKind::ZoneEnter);
+        assert!(dwell.update_boxes(1.0, &[person(60.0)]).is_empty());
+        // Center x=100 is on the edge, still inside; x=120 is outside.
+        assert!(dwell.update_boxes(2.0, &[person(80.0)]).is_empty());
+        let events = dwell.update_boxes(3.0, &[person(100.0)]);
+        assert_eq!(events[0].event, ZoneEventKind::ZoneExit);
+        assert_eq!(events[0].track_id, 1);
+        assert_eq!(dwell.active[0].dwell, [3.0]);
+    }
+
+    #[test]
+    fn lost_tracks_close_at_last_sighting_and_bystanders_are_kept() {
+        let mut dwell = tracker();
+        dwell.update_boxes(0.0, &[person(40.0), person(300.0)]);
+        dwell.update_boxes(2.0, &[person(40.0), person(300.0)]);
+        let mut exits = Vec::new();
+        for i in 1..=MAX_MISSES + 1 {
+            exits.extend(dwell.update_boxes(2.0 + i as f64, &[person(300.0)]));
+        }
+        assert_eq!(exits.len(), 1);
+        assert_eq!(exits[0].t_s, 2.0);
+        assert_eq!(dwell.ended[0].dwell, [2.0]);
+        // The second person never entered but is still tracked.
+        assert_eq!(dwell.active[0].id, 2);
+        assert_eq!(dwell.active[0].dwell, [0.0]);
+    }
+
+    #[test]
+    fn kalman_tracks_enter_zones_between_inferences() {
+        let mut dwell =
+            DwellTracker::new(vec!["a=0,0,100,100".parse().unwrap()], None, true).unwrap();
+        // Walking left at 20 px/s, center x = 220 - 20t.
+        for t in 0..5 {
+            assert!(
+                dwell
+                    .update_boxes(t as f64, &[person(200.0 - 20.0 * t as f32)])
+                    .is_empty()
+            );
+        }
+        let mut events = Vec::new();
+        for t in [5.0, 6.0, 7.0, 8.0] {
+            events.extend(dwell.predict(t));
+        }
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, ZoneEventKind::ZoneEnter);
+        assert!((6.0..=7.0).contains(&events[0].t_s), "{}", events[0].t_s);
+        // The next detection continues the predicted track.
+        assert!(dwell.update_boxes(9.0, &[person(20.0)]).is_empty());
+        assert_eq!(dwell.active.len(), 1);
+        assert!(tracker().predict(1.0).is_empty());
+    }
+
+    #[test]
+    fn kalman_tracks_coast_through_missed_detections() {
+        let mut dwell =
+            DwellTracker::new(vec!["a=0,0,100,100".parse().unwrap()], None, true).unwrap();
+        for t in 0..5 {
+            dwell.update_boxes(t as f64, &[person(200.0 - 20.0 * t as f32)]);
+        }
+        // The detector loses the person; the track keeps walking into the zone.
+        let mut events = Vec::new();
+        for t in [5.0, 6.0, 7.0] {
+            events.extend(dwell.update_boxes(t, &[]));
+            events.extend(dwell.predict(t + 0.5));
+        }
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, ZoneEventKind::ZoneEnter);
+        assert!((6.0..=7.5).contains(&events[0].t_s), "{}", events[0].t_s);
+    }
+
+    #[test]
+    fn finish_writes_quoted_labels_into_a_new_dir() {
+        let dir = crate::test_util::TempDir::new("dwell");
+        let csv_path = dir.join("run/tracks.csv");
+        let mut dwell = tracker();
+        let (_, rect) = person(40.0);
+        dwell.update_boxes(0.0, &[("car, \"red\"".to_string(), rect)]);
+        dwell.update_boxes(1.0, &[("car, \"red\"".to_string(), rect)]);
+        let summary = dwell.finish(&csv_path);
+        assert_eq!(summary[0]["label"], "car, \"red\"");
+        let csv = std::fs::read_to_string(&csv_path).unwrap();
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("1,\"car, \"\"red\"\"\",0.000,1.000,1.000")
+        );
+
+        // An unwritable CSV still returns the summary.
+        let blocked = dir.join("run/tracks.csv/tracks.csv");
+        assert_eq!(tracker().finish(&blocked), serde_json::json!([]));
+    }
+}
@@ -0,0 +1,350 @@
+//! Presence events and `--save-event-clips`.
+//!
+//! A prompt label *enters* when an inference finds it after one that didn't, and *leaves* on the
+//! first inference without it. An enter opens a clip seeded with the pre-roll ring buffer; the
+//! clip stays open while anything is present and closes `post` seconds after the last leave.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use crate::color::ColorMatrix;
+use crate::ffmpeg_log::FfmpegLogLevel;
+use crate::output::FfmpegVideoWriter;
+use crate::viz::to_rgb;
+
+/// JPEG quality of pre-roll frames; keeps a 10 s 1080p ring in the tens of MB.
+const RING_JPEG_QUALITY: u8 = 85;
+
+/// Labels detected in `y` (boxes and masks), `object` for unnamed ones.
+pub fn present_labels(y: &usls::Y) -> BTreeSet<String> {
+    let name = |n: Option<&str>| n.unwrap_or("object").to_string();
+    y.hbbs()
+        .iter()
+        .map(|h| name(h.name()))
+        .chain(y.masks().iter().map(|m| name(m.name())))
+        .collect()
+}
+
+/// Enter/leave transitions between consecutive inferences.
+#[derive(Default)]
+pub struct PresenceTracker {
+    present: BTreeSet<String>,
+}
+
+impl PresenceTracker {
+    /// Replace the present set; returns `(entered, left)`.
+    pub fn update(&mut self, now: BTreeSet<String>) -> (Vec<String>, Vec<String>) {
+        let entered = now.difference(&self.present).cloned().collect();
+        let left = self.present.difference(&now).cloned().collect();
+        self.present = now;
+        (entered, left)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.present.is_empty()
+    }
+}
+
+struct OpenClip {
+    writer: FfmpegVideoWriter,
+    partial: PathBuf,
+    labels: BTreeSet<String>,
+    wall_stamp: String,
+    start_s: f64,
+    /// Set once nothing is present; cleared again if something re-enters.
+    close_at_s: Option<f64>,
+}
+
+/// Writes one clip per event with `pre` seconds of pre-roll and `post` seconds after the last leave.
+pub struct EventClips {
+    dir: PathBuf,
+    pre_s: f64,
+    post_s: f64,
+    ring: VecDeque<(f64, Vec<u8>)>,
+    frame_dt_s: Option<f64>,
+    last_s: Option<f64>,
+    presence: PresenceTracker,
+    open: Option<OpenClip>,
+    written: Vec<PathBuf>,
+    loglevel: FfmpegLogLevel,
+}
+
+impl EventClips {
+    pub fn new(
+        dir: &Path,
+        pre_secs: f32,
+        post_secs: f32,
+        loglevel: FfmpegLogLevel,
+    ) -> Result<Self> {
+        if !(pre_secs.is_finite() && pre_secs >= 0.0 && post_secs.is_finite() && post_secs >= 0.0) {
+            anyhow::bail!("--clip-pre-seconds/--clip-post-seconds must be >= 0");
+        }
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create clip directory: {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            pre_s: pre_secs as f64,
+            post_s: post_secs as f64,
+            ring: VecDeque::new(),
+            frame_dt_s: None,
+            last_s: None,
+            presence: PresenceTracker::default(),
+            open: None,
+            written: Vec::new(),
+            loglevel,
+        })
+    }
+
+    /// Feed the raw frame at `now_s`; `labels` is `Some` on inference frames.
+    pub fn push(
+        &mut self,
+        now_s: f64,
+        frame: &usls::Image,
+        labels: Option<BTreeSet<String>>,
+    ) -> Result<()> {
+        if let Some(last) = self.last_s.replace(now_s) {
+            let dt = (now_s - last).max(0.0);
+            // Smoothed frame interval: the clip frame rate for cameras without a nominal rate.
+            self.frame_dt_s = Some(self.frame_dt_s.map_or(dt, |avg| avg * 0.9 + dt * 0.1));
+        }
+
+        if let Some(labels) = labels {
+            let (entered, left) = self.presence.update(labels);
+            for label in &left {
+                tracing::info!("Event: `{label}` left at {now_s:.2}s");
+            }
+            for label in &entered {
+                tracing::info!("Event: `{label}` entered at {now_s:.2}s");
+            }
+            if !entered.is_empty() {
+                match self.open.as_mut() {
+                    // Overlapping event: keep writing into the same clip.
+                    Some(clip) => {
+                        clip.close_at_s = None;
+                        clip.labels.extend(entered);
+                    }
+                    None => self.open_clip(now_s, frame, entered.into_iter().collect())?,
+                }
+            }
+            if self.presence.is_empty()
+                && let Some(clip) = self.open.as_mut()
+                && clip.close_at_s.is_none()
+            {
+                clip.close_at_s = Some(now_s + self.post_s);
+            }
+        }
+
+        match self.open.as_mut() {
+            Some(clip) => {
+                if let Err(e) = clip.writer.write_frame(frame) {
+                    if let Some(clip) = self.open.take() {
+                        discard(clip.writer, &clip.partial);
+                    }
+                    return Err(e);
+                }
+                if clip.close_at_s.is_some_and(|t| now_s >= t) {
+                    self.close_clip(now_s)?;
+                }
+            }
+            None => self.buffer(now_s, frame)?,
+        }
+        Ok(())
+    }
+
+    /// Finalize a clip still open at the end of the run and log every clip written.
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(end) = self.last_s {
+            self.close_clip(end)?;
+        }
+        tracing::info!("Event clips written: {}", self.written.len());
+        for path in &self.written {
+            tracing::info!("  {}", path.display());
+        }
+        Ok(())
+    }
+
+    fn buffer(&mut self, now_s: f64, frame: &usls::Image) -> Result<()> {
+        while self
+            .ring
+            .front()
+            .is_some_and(|(t, _)| *t < now_s - self.pre_s)
+        {
+            self.ring.pop_front();
+        }
+        if self.pre_s <= 0.0 {
+            return Ok(());
+        }
+        let rgb = to_rgb(frame)?;
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, RING_JPEG_QUALITY)
+            .encode_image(&rgb)
+            .context("failed to encode pre-roll frame")?;
+        self.ring.push_back((now_s, jpeg));
+        Ok(())
+    }
+
+    fn open_clip(
+        &mut self,
+        now_s: f64,
+        frame: &usls::Image,
+        labels: BTreeSet<String>,
+    ) -> Result<()> {
+        let fps = self
+            .frame_dt_s
+            .filter(|dt| *dt > 0.0)
+            .map_or(30.0, |dt| (1.0 / dt).clamp(1.0, 120.0)) as f32;
+        let wall_stamp = usls::timestamp(None);
+        let partial = self.dir.join(format!("{wall_stamp}.partial.mp4"));
+        let mut writer = FfmpegVideoWriter::spawn(
+            &partial,
+            frame.width(),
+            frame.height(),
+            fps,
+            ColorMatrix::default(),
+            self.loglevel,
+        )?;
+
+        let start_s = self.ring.front().map_or(now_s, |(t, _)| *t);
+        let preroll = self.ring.drain(..).try_for_each(|(_, jpeg)| {
+            let rgb = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg)
+                .context("failed to decode pre-roll frame")?
+                .to_rgb8();
+            writer.write_frame(&usls::Image::from(rgb))
+        });
+        if let Err(e) = preroll {
+            discard(writer, &partial);
+            return Err(e);
+        }
+        tracing::info!("Event clip started at {start_s:.2}s: {}", partial.display());
+        self.open = Some(OpenClip {
+            writer,
+            partial,
+            labels,
+            wall_stamp,
+            start_s,
+            close_at_s: None,
+        });
+        Ok(())
+    }
+
+    fn close_clip(&mut self, end_s: f64) -> Result<()> {
+        let Some(clip) = self.open.take() else {
+            return Ok(());
+        };
+        if let Err(e) = clip.writer.finish() {
+            discard_file(&clip.partial);
+            return Err(e);
+        }
+        let labels: Vec<String> = clip
+            .labels
+            .iter()
+            .map(|l| {
+                l.chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect()
+            })
+            .collect();
+        let path = self.dir.join(format!(
+            "{}_{}_{:.1}s-{:.1}s.mp4",
+            labels.join("+"),
+            clip.wall_stamp,
+            clip.start_s,
+            end_s
+        ));
+        std::fs::rename(&clip.partial, &path)
+            .with_context(|| format!("failed to rename clip to {}", path.display()))?;
+        tracing::info!("Event clip written: {}", path.display());
+        self.written.push(path);
+        Ok(())
+    }
+}
+
+/// Stop a clip's encoder after an error and delete the unfinished file.
+fn discard(writer: FfmpegVideoWriter, partial: &Path) {
+    // Dropping the writer kills ffmpeg, which may still hold the file open.
+    drop(writer);
+    discard_file(partial);
+}
+
+fn discard_file(partial: &Path) {
+    match std::fs::remove_file(partial) {
+        Ok(()) => tracing::warn!("Event clip failed; removed {}", partial.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => tracing::warn!("Failed to remove {}: {e}", partial.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn labels(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn presence_reports_enters_and_leaves() {
+        let mut presence = PresenceTracker::default();
+        assert_eq!(
+            presence.update(labels(&["car"])),
+            (vec!["car".to_string()], vec![])
+        );
+        assert_eq!(
+            presence.update(labels(&["car", "dog"])),
+            (vec!["dog".to_string()], vec![])
+        );
+        assert_eq!(
+            presence.update(labels(&["dog"])),
+            (vec![], vec!["car".to_string()])
+        );
+        assert!(!presence.is_empty());
+        presence.update(BTreeSet::new());
+        assert!(presence.is_empty());
+    }
+
+    #[test]
+    fn labels_name_unnamed_detections_object() {
+        let y = usls::Y::default().with_hbbs(&[
+            usls::Hbb::from_xywh(0.0, 0.0, 1.0, 1.0).with_name("car"),
+            usls::Hbb::from_xywh(0.0, 0.0, 1.0, 1.0),
+        ]);
+        assert_eq!(present_labels(&y), labels(&["car", "object"]));
+    }
+
+    #[test]
+    fn pre_roll_keeps_only_the_last_seconds() {
+        let dir = TempDir::new("event_clips");
+        let mut clips =
+            EventClips::new(&dir.join("clips"), 1.0, 0.0, FfmpegLogLevel::default()).unwrap();
+        let frame = usls::Image::from(image::RgbImage::new(8, 8));
+        for i in 0..30 {
+            clips.push(i as f64 * 0.1, &frame, None).unwrap();
+        }
+        // Frames at 1.9 s .. 2.9 s.
+        assert!(
+            (10..=11).contains(&clips.ring.len()),
+            "{}",
+            clips.ring.len()
+        );
+        assert!(clips.ring.front().is_some_and(|(t, _)| *t >= 1.85));
+        assert!((clips.frame_dt_s.unwrap() - 0.1).abs() < 1e-6);
+
+        let mut no_preroll =
+            EventClips::new(&dir.join("clips"), 0.0, 0.0, FfmpegLogLevel::default()).unwrap();
+        no_preroll.push(0.0, &frame, None).unwrap();
+        assert!(no_preroll.ring.is_empty());
+        assert!(EventClips::new(&dir.join("clips"), -1.0, 0.0, FfmpegLogLevel::default()).is_err());
+    }
+
+    #[test]
+    fn a_failed_clip_leaves_no_partial_file() {
+        let dir = TempDir::new("event_partial");
+        let partial = dir.join("x.partial.mp4");
+        std::fs::write(&partial, b"").unwrap();
+        discard_file(&partial);
+        assert!(!partial.exists());
+        discard_file(&partial);
+    }
+}
@@ -0,0 +1,197 @@
+//! `--ffmpeg-loglevel` and live logging of the `ffmpeg` decoder's and encoders' stderr.
+//!
+//! ffmpeg runs with `-loglevel level+<level>`, which prefixes every line with its level
+//! (`[h264 @ 0x...] [error] ...`). [`StderrDrain`] reads the child's stderr on a thread and
+//! forwards each line to `tracing` at the matching level as it arrives, so dropped frames and
+//! corrupt packets show up while the run is going rather than only when ffmpeg exits.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader};
+use std::process::ChildStderr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::JoinHandle;
+
+use crate::redact::scrub_credentials;
+
+/// ffmpeg's `-loglevel` names, quietest first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FfmpegLogLevel {
+    Quiet,
+    Panic,
+    Fatal,
+    #[default]
+    Error,
+    Warning,
+    Info,
+    Verbose,
+    Debug,
+    Trace,
+}
+
+const LEVELS: [(FfmpegLogLevel, &str); 9] = [
+    (FfmpegLogLevel::Quiet, "quiet"),
+    (FfmpegLogLevel::Panic, "panic"),
+    (FfmpegLogLevel::Fatal, "fatal"),
+    (FfmpegLogLevel::Error, "error"),
+    (FfmpegLogLevel::Warning, "warning"),
+    (FfmpegLogLevel::Info, "info"),
+    (FfmpegLogLevel::Verbose, "verbose"),
+    (FfmpegLogLevel::Debug, "debug"),
+    (FfmpegLogLevel::Trace, "trace"),
+];
+
+impl FfmpegLogLevel {
+    pub fn as_str(self) -> &'static str {
+        LEVELS[self as usize].1
+    }
+}
+
+impl FromStr for FfmpegLogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        LEVELS
+            .iter()
+            .find(|(_, name)| *name == s)
+            .map(|(level, _)| *level)
+            .ok_or_else(|| {
+                let names: Vec<&str> = LEVELS.iter().map(|(_, name)| *name).collect();
+                format!(
+                    "invalid --ffmpeg-loglevel `{s}` (expected one of {})",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// `-loglevel level+<level>` for a streaming `ffmpeg` whose stderr goes to a [`StderrDrain`].
+pub fn loglevel_args(level: FfmpegLogLevel) -> [String; 2] {
+    ["-loglevel".to_string(), format!("level+{}", level.as_str())]
+}
+
+/// The `[level]` tag of one prefixed stderr line and the line without it. Lines without a tag
+/// (e.g. from an ffmpeg that ignores the `level` flag) count as errors.
+fn split_level(line: &str) -> (FfmpegLogLevel, String) {
+    let first_tag = LEVELS
+        .iter()
+        .filter_map(|(level, name)| {
+            let tag = format!("[{name}] ");
+            line.find(&tag).map(|at| (at, tag.len(), *level))
+        })
+        .min_by_key(|(at, _, _)| *at);
+    match first_tag {
+        Some((at, len, level)) => (level, format!("{}{}", &line[..at], &line[at + len..])),
+        None => (FfmpegLogLevel::Error, line.to_string()),
+    }
+}
+
+fn log_line(process: &str, level: FfmpegLogLevel, line: &str) {
+    let line = scrub_credentials(line);
+    match level {
+        FfmpegLogLevel::Quiet
+        | FfmpegLogLevel::Panic
+        | FfmpegLogLevel::Fatal
+        | FfmpegLogLevel::Error => tracing::error!("{process}: {line}"),
+        FfmpegLogLevel::Warning => tracing::warn!("{process}: {line}"),
+        FfmpegLogLevel::Info => tracing::info!("{process}: {line}"),
+        FfmpegLogLevel::Verbose | FfmpegLogLevel::Debug | FfmpegLogLevel::Trace => {
+            tracing::debug!("{process}: {line}")
+        }
+    }
+}
+
+/// Reads `ffmpeg`'s stderr while it runs: logs every line, counts error lines for the quality
+/// gate and keeps the last error lines for the message when ffmpeg fails. Also drains
+/// `gst-launch-1.0`, whose untagged lines all count as errors.
+pub struct StderrDrain {
+    errors: Arc<AtomicU64>,
+    thread: Option<JoinHandle<String>>,
+}
+
+impl StderrDrain {
+    /// Lines kept for the error message when `ffmpeg` fails.
+    const TAIL_LINES: usize = 20;
+
+    /// `process` names the child in the log, e.g. `ffmpeg (decoder)`.
+    pub fn spawn(stderr: ChildStderr, process: &'static str) -> Self {
+        let errors = Arc::new(AtomicU64::new(0));
+        let counter = errors.clone();
+        let thread = std::thread::spawn(move || {
+            let mut tail = VecDeque::with_capacity(Self::TAIL_LINES);
+            for line in BufReader::new(stderr).split(b'\n').map_while(Result::ok) {
+                let line = String::from_utf8_lossy(&line).trim_end().to_string();
+                if line.is_empty() {
+                    continue;
+                }
+                let (level, line) = split_level(&line);
+                log_line(process, level, &line);
+                if level > FfmpegLogLevel::Error {
+                    continue;
+                }
+                counter.fetch_add(1, Ordering::Relaxed);
+                if tail.len() == Self::TAIL_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line);
+            }
+            Vec::from(tail).join("\n")
+        });
+        Self {
+            errors,
+            thread: Some(thread),
+        }
+    }
+
+    /// Error lines seen so far.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// The last error lines; only complete once `ffmpeg` has exited.
+    pub fn join(&mut self) -> String {
+        self.thread
+            .take()
+            .and_then(|thread| thread.join().ok())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_loglevels() {
+        assert_eq!(
+            "warning".parse::<FfmpegLogLevel>(),
+            Ok(FfmpegLogLevel::Warning)
+        );
+        assert_eq!("INFO".parse::<FfmpegLogLevel>(), Ok(FfmpegLogLevel::Info));
+        assert!("loud".parse::<FfmpegLogLevel>().is_err());
+        for (level, name) in LEVELS {
+            assert_eq!(level.as_str(), name);
+        }
+    }
+
+    #[test]
+    fn splits_level_tags() {
+        assert_eq!(
+            split_level("[h264 @ 0x55d0] [error] Invalid NAL unit size"),
+            (
+                FfmpegLogLevel::Error,
+                "[h264 @ 0x55d0] Invalid NAL unit size".to_string()
+            )
+        );
+        assert_eq!(
+            split_level("[warning] 3 frames dropped"),
+            (FfmpegLogLevel::Warning, "3 frames dropped".to_string())
+        );
+        assert_eq!(
+            split_level("Conversion failed!"),
+            (FfmpegLogLevel::Error, "Conversion failed!".to_string())
+        );
+    }
+}
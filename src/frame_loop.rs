@@ -0,0 +1,1298 @@
+//! The parts of a frame's trip through `video-sam3`, `v4l-sam3` and `hikvision-sam3` that do not
+//! depend on where frames come from or go to: the result filters, drawing results with the
+//! annotation preset and prompt visibility in use, the window overlays, prompt updates and the
+//! viewer keys.
+//!
+//! [`FrameLoop::run`] is the capture loop of all three: frames arrive through
+//! [`crate::common::Capture`], generic over the [`crate::common::VideoSource`], and each binary
+//! adds only its source handling and its sinks through [`FrameHooks`]. [`DetectionFilters`]
+//! cleans up each inference and [`DisplayState`] holds everything that decides how a result is
+//! drawn and shown.
+
+use anyhow::{Context, Result};
+use image::GrayImage;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use usls::models::{SAM3, Sam3Prompt};
+use usls::{Annotator, Viewer};
+
+use crate::annotate_async::{AnnotationWorker, OrderedOutput};
+use crate::best::BestFrames;
+use crate::common::{
+    AnnotationPreset, AnnotationPresets, AnnotationStyle, Capture, ModelOptions, SceneCutDetector,
+    VideoSource, annotate_prompts, build_annotator, frame_hash, prompt_only_annotator,
+};
+use crate::control::{ControlCommand, ControlSocket};
+use crate::crops::CropWriter;
+use crate::debug::{dump_input_tensor, export_prompt_vis};
+use crate::events::{EventClips, present_labels};
+use crate::ffmpeg_log::FfmpegLogLevel;
+use crate::groups::{GroupStyle, PromptGroups, split_group_tags};
+use crate::history::PromptHistory;
+use crate::hold::DetectionHold;
+use crate::notify::{Notifier, NotifyKind, NotifyOptions};
+use crate::output::{
+    Detection, FrameRecord, IntervalSaver, IntervalSnapshots, JsonlWriter, MaskEncoding,
+    PerPromptSaver, StageDetection,
+};
+use crate::pipeline::Pipeline;
+use crate::postprocess::{
+    CooldownTracker, ExclusiveGroup, ExclusiveGroups, ExclusiveWin, MaskDedup, retain_detections,
+};
+use crate::preprocess::{ViewportState, apply_roi_mask, roi_mask};
+use crate::prompts::with_mirrored;
+use crate::repro::{finish_run, run_summary, saved_frame_name};
+use crate::schedule::InferSchedule;
+use crate::session::{Session, SessionFile};
+use crate::shutdown::StopSignal;
+use crate::stats::DetectionStats;
+use crate::svg::{SvgWriter, shapes_from_y};
+use crate::tiling::forward_tiled;
+use crate::visibility::PromptVisibility;
+use crate::viz::{FpsMeter, Legend, cross_fade, make_window_title};
+use crate::watchdog::{Watchdog, WatchdogFired};
+use crate::zones::{ExcludeZones, Zone};
+
+pub fn parse_prompts(raw: &[String]) -> Result<Vec<Sam3Prompt>> {
+    if raw.is_empty() {
+        anyhow::bail!("No prompt. Use -p \"text\" or -p \"visual;pos:x,y,w,h\"");
+    }
+    raw.iter()
+        .map(|s| s.parse())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+/// Read replacement prompts from stdin; returns the raw strings (parse with `parse_prompts`).
+fn prompt_update_loop(history: &mut PromptHistory) -> Result<Option<Vec<String>>> {
+    eprint!("New prompt(s) (split with `|`, `!!`/`!N` recalls history, empty keeps current): ");
+    std::io::stderr().flush().ok();
+    let mut line = String::new();
+    std::io::stdin()
+        .read_line(&mut line)
+        .context("failed to read prompt from stdin")?;
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let Some(expanded) = history.expand(line) else {
+        eprintln!("No prompt history entry matches `{line}` (H lists history)");
+        return Ok(None);
+    };
+    if expanded != line {
+        eprintln!("{expanded}");
+    }
+    let parts: Vec<String> = expanded
+        .split('|')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect();
+    if !parts.is_empty() {
+        history.push(&parts.join(" | "));
+    }
+    Ok(Some(parts))
+}
+
+/// The filters every inference result goes through, in order: the confidence threshold, duplicate
+/// masks (`--deduplicate-masks-iou`), `--exclusive-group`, exclude zones and
+/// `--prompt-cooldown-frames`.
+pub struct DetectionFilters {
+    mask_dedup: MaskDedup,
+    exclusive: ExclusiveGroups,
+    cooldown: CooldownTracker,
+}
+
+impl DetectionFilters {
+    pub fn new(
+        mask_dedup: MaskDedup,
+        exclusive: ExclusiveGroups,
+        cooldown: CooldownTracker,
+    ) -> Self {
+        Self {
+            mask_dedup,
+            exclusive,
+            cooldown,
+        }
+    }
+
+    /// `ys` of the frame `frame_idx` through every filter, with what they dropped and what is left
+    /// counted in `stats`; also the exclusive-group wins and the number of duplicates removed, for
+    /// the frame's record.
+    ///
+    /// `conf` is the current threshold. The model drops everything under the `--conf` it was
+    /// built with; this applies a threshold raised since (`conf` on the control socket) without
+    /// rebuilding it.
+    pub fn apply(
+        &mut self,
+        ys: Vec<usls::Y>,
+        frame_idx: u64,
+        prompts: &[Sam3Prompt],
+        conf: f32,
+        zones: &ExcludeZones,
+        stats: &mut DetectionStats,
+    ) -> (Vec<usls::Y>, Vec<ExclusiveWin>, usize) {
+        let ys = ys
+            .into_iter()
+            .map(|y| retain_detections(y, |c| c.confidence.is_none_or(|s| s >= conf)))
+            .collect();
+        let (ys, deduplicated) = self.mask_dedup.filter(ys);
+        stats.record_deduplicated(deduplicated);
+        let (ys, exclusive) = self.exclusive.filter(ys, prompts);
+        stats.record_exclusive(&exclusive);
+        let (ys, suppressed) = zones.filter(ys);
+        stats.record_suppressed(suppressed);
+        let ys = self.cooldown.filter(ys, frame_idx, prompts);
+        stats.record(&ys[0]);
+        (ys, exclusive, deduplicated)
+    }
+}
+
+/// How results are drawn over frames and shown in the window, and the prompts they are drawn for.
+pub struct DisplayState {
+    /// `None` when nothing is annotated (`--annotation-style none`, `--mask-to-json`).
+    pub annotator: Option<Annotator>,
+    pub legend: Legend,
+    pub groups: PromptGroups,
+    visibility: PromptVisibility,
+    presets: AnnotationPresets,
+    hold: Option<DetectionHold>,
+    history: PromptHistory,
+    /// The prompts as given, `#grp=` tags included, for `--session`.
+    session_prompts: Vec<String>,
+    /// One label per prompt, mirrored copies included.
+    labels: Vec<String>,
+    /// Frame width for `--mirror-prompt`.
+    mirror_width: Option<u32>,
+    /// The last annotated frame, shown again between inferences.
+    pub last_displayed: Option<usls::Image>,
+    /// Frame and result behind `last_displayed`, so `M` and `1`-`9` can redraw it; kept only for
+    /// the window.
+    last_result: Option<(usls::Image, usls::Y)>,
+    window: bool,
+    points_only: bool,
+    /// Weight of the last annotation in `--cross-fade-alpha` frames.
+    fade_alpha: f32,
+}
+
+impl DisplayState {
+    /// Draw results with `annotator` and `legend`, for `prompts` prompts styled by `groups`; `P`
+    /// recalls from `history`.
+    pub fn new(
+        annotator: Option<Annotator>,
+        legend: Legend,
+        groups: PromptGroups,
+        history: PromptHistory,
+        prompts: usize,
+    ) -> Self {
+        Self {
+            annotator,
+            legend,
+            groups,
+            visibility: PromptVisibility::new(prompts),
+            presets: AnnotationPresets::default(),
+            hold: None,
+            history,
+            session_prompts: Vec::new(),
+            labels: Vec::new(),
+            mirror_width: None,
+            last_displayed: None,
+            last_result: None,
+            window: false,
+            points_only: false,
+            fade_alpha: 1.0,
+        }
+    }
+
+    /// Results are shown in a window, so keep the last one for redraws.
+    pub fn with_window(mut self, window: bool) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// `--visualize-points-only`: only the prompts are drawn, so the presets are off.
+    pub fn with_points_only(mut self, points_only: bool) -> Self {
+        self.points_only = points_only;
+        self
+    }
+
+    /// `--hold-ms`; nothing is held without an annotator.
+    pub fn with_hold(mut self, hold_ms: u64) -> Self {
+        self.hold = DetectionHold::new(hold_ms).filter(|_| self.annotator.is_some());
+        self
+    }
+
+    /// The prompts as given (`#grp=` tags included), their labels, and the frame width when
+    /// updates get `--mirror-prompt` copies.
+    pub fn with_prompts(
+        mut self,
+        session_prompts: Vec<String>,
+        labels: Vec<String>,
+        mirror_width: Option<u32>,
+    ) -> Self {
+        self.session_prompts = session_prompts;
+        self.labels = labels;
+        self.mirror_width = mirror_width;
+        self
+    }
+
+    /// Start with the annotation preset a `--session` file restored; set after
+    /// [`DisplayState::with_points_only`].
+    pub fn with_preset(mut self, preset: Option<AnnotationPreset>) -> Self {
+        if let Some(preset) = preset
+            && self.annotator.is_some()
+            && !self.points_only
+        {
+            tracing::info!("Annotation style: {}", preset.name());
+            self.annotator = Some(self.presets.select(preset).clone());
+        }
+        self
+    }
+
+    /// Labels of the prompts in use, mirrored copies included.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Switch to the prompts given as `raw` (`P`, the control socket) and return them parsed.
+    /// Visibility, held boxes and the legend start over.
+    pub fn set_prompts(&mut self, raw: Vec<String>) -> Result<Vec<Sam3Prompt>> {
+        let (texts, tags) = split_group_tags(&raw);
+        let parsed = parse_prompts(&texts)?;
+        let (labels, tags, prompts) = with_mirrored(self.mirror_width, texts, tags, parsed);
+        self.session_prompts = raw;
+        self.groups.set_tags(&tags);
+        self.visibility.reset(prompts.len());
+        self.legend.set_labels(labels.clone());
+        self.labels = labels;
+        if let Some(hold) = self.hold.as_mut() {
+            hold.clear();
+        }
+        tracing::info!("Updated prompts: {:?}", prompts);
+        Ok(prompts)
+    }
+
+    /// Ask for new prompts on stdin (`P`); `None` keeps the current ones.
+    pub fn edit_prompts(&mut self) -> Result<Option<Vec<String>>> {
+        prompt_update_loop(&mut self.history)
+    }
+
+    /// The state `W` and `--session` save.
+    pub fn session(
+        &self,
+        conf: f32,
+        zones: &ExcludeZones,
+        view: Option<&ViewportState>,
+    ) -> Session {
+        Session::capture(
+            &self.session_prompts,
+            conf,
+            &self.presets,
+            &self.legend,
+            zones,
+            view,
+        )
+    }
+
+    /// Take the filtered result `y` of an inference on the frame at media time `now`.
+    pub fn record(&mut self, y: &usls::Y, prompts: &[Sam3Prompt], now: Duration) {
+        self.legend.record(y);
+        if let Some(hold) = self.hold.as_mut() {
+            hold.update(&self.visibility.filter(y, prompts), now);
+        }
+        self.fade_alpha = 1.0;
+    }
+
+    /// `img` annotated with `y`, hidden prompts left out; `None` without an annotator.
+    pub fn annotate(
+        &mut self,
+        img: &usls::Image,
+        y: &usls::Y,
+        prompts: &[Sam3Prompt],
+    ) -> Result<Option<usls::Image>> {
+        let Some(annotator) = &self.annotator else {
+            return Ok(None);
+        };
+        if self.window {
+            self.last_result = Some((img.clone(), y.clone()));
+        }
+        self.visibility
+            .annotate(annotator, &self.groups, img, y, prompts)
+            .map(Some)
+    }
+
+    /// `img` with only the prompts drawn (`--visualize-points-only`); `None` without an annotator.
+    pub fn annotate_prompts(
+        &self,
+        img: &usls::Image,
+        prompts: &[Sam3Prompt],
+    ) -> Result<Option<usls::Image>> {
+        self.annotator
+            .as_ref()
+            .map(|annotator| annotate_prompts(annotator, img, prompts))
+            .transpose()
+    }
+
+    /// `img` blended with the last annotation, which fades by `step` per frame
+    /// (`--cross-fade-alpha`); `None` when there is nothing to fade.
+    pub fn faded(&mut self, img: &usls::Image, step: f32) -> Result<Option<usls::Image>> {
+        match (&self.annotator, &self.last_displayed) {
+            (Some(_), Some(last)) if step > 0.0 => {
+                self.fade_alpha = (self.fade_alpha - step).max(0.0);
+                cross_fade(img, last, self.fade_alpha).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Show `display`, the frame at media time `now`, in `viewer` with the held boxes, the legend,
+    /// the last visibility toggle and, when given, the exclude `zones` drawn over it.
+    pub fn show(
+        &self,
+        viewer: &mut usls::Viewer,
+        display: &usls::Image,
+        now: Duration,
+        zones: Option<&ExcludeZones>,
+    ) -> Result<()> {
+        let with_hold = match &self.hold {
+            Some(hold) => hold.render(display, now)?,
+            None => None,
+        };
+        let held = with_hold.as_ref().unwrap_or(display);
+        let with_legend = self.legend.render(held)?;
+        let shown = with_legend.as_ref().unwrap_or(held);
+        let with_notice = self.visibility.render(shown, Instant::now())?;
+        let shown = with_notice.as_ref().unwrap_or(shown);
+        let with_zones = zones.map(|zones| zones.draw(shown)).transpose()?;
+        viewer.imshow(with_zones.as_ref().unwrap_or(shown))
+    }
+
+    /// Handle the keys every binary shares: `S` saves the last annotated frame to `save_path()`,
+    /// `L` toggles the legend, `1`-`9` the prompts, `H` prints the prompt history and `M` cycles
+    /// the annotation presets. `pressed` tells whether a key is down.
+    pub fn handle_keys(
+        &mut self,
+        pressed: impl Fn(usls::Key) -> bool,
+        prompts: &[Sam3Prompt],
+        save_path: impl FnOnce() -> PathBuf,
+    ) -> Result<()> {
+        if pressed(usls::Key::S)
+            && let Some(img) = &self.last_displayed
+        {
+            let path = save_path();
+            img.save(&path)?;
+            tracing::info!("Saved: {}", path.display());
+        }
+        if pressed(usls::Key::L) {
+            self.legend.toggle();
+        }
+        if self
+            .visibility
+            .toggle_pressed(&pressed, prompts, Instant::now())
+        {
+            // Held boxes of a hidden prompt would otherwise fade out on screen.
+            if let Some(hold) = self.hold.as_mut() {
+                hold.clear();
+            }
+            self.redraw(prompts)?;
+        }
+        if pressed(usls::Key::H) {
+            self.history.print();
+        }
+        if pressed(usls::Key::M) && self.annotator.is_some() && !self.points_only {
+            let (preset, next) = self.presets.cycle();
+            tracing::info!("Annotation style: {}", preset.name());
+            self.annotator = Some(next.clone());
+            self.redraw(prompts)?;
+        }
+        Ok(())
+    }
+
+    /// Annotate the last result again after a toggle or a preset switch.
+    fn redraw(&mut self, prompts: &[Sam3Prompt]) -> Result<()> {
+        if let Some(redrawn) = self.visibility.redraw(
+            self.annotator.as_ref(),
+            &self.groups,
+            self.last_result.as_ref(),
+            prompts,
+        )? {
+            self.last_displayed = Some(redrawn);
+        }
+        Ok(())
+    }
+}
+
+/// The flags and parts [`FrameLoop`] is built from; each binary fills it from its own `Args` once
+/// `--session`, `--pipeline` and `--mirror-prompt` have settled the prompts.
+pub struct LoopArgs<'a> {
+    pub model: SAM3,
+    pub model_opts: ModelOptions,
+    pub pipeline: Option<Pipeline>,
+    pub prompts: Vec<Sam3Prompt>,
+    /// The prompts as given, `#grp=` tags included, for `--session`.
+    pub session_prompts: Vec<String>,
+    /// One label per prompt, mirrored copies included.
+    pub labels: Vec<String>,
+    pub group_tags: Vec<Option<String>>,
+    /// Frame width for `--mirror-prompt`.
+    pub mirror_width: Option<u32>,
+    pub session: SessionFile,
+    pub viewer: Option<Viewer>,
+    /// Size of the frames [`Capture`] delivers, for `--roi-file`.
+    pub frame_size: (u32, u32),
+    pub save_base: PathBuf,
+    /// `--json-out`, or stdout under `--mask-to-json`.
+    pub json_out: Option<&'a Path>,
+    pub task: &'a str,
+    pub mask_to_json: bool,
+    pub annotation_style: AnnotationStyle,
+    pub show_mask: bool,
+    pub group_style: &'a [GroupStyle],
+    pub legend: bool,
+    pub legend_outside: bool,
+    pub hold_ms: u64,
+    pub prompt_history: Option<&'a str>,
+    pub visualize_points_only: bool,
+    pub cross_fade_alpha: f32,
+    pub show_zones: bool,
+    pub sequential_names: bool,
+    pub jsonl_dedup: Option<f32>,
+    pub mask_encoding: MaskEncoding,
+    pub dedup_frames: bool,
+    pub infer_every: Option<u32>,
+    pub infer_hz: Option<f32>,
+    pub roi_file: Option<&'a str>,
+    pub export_prompt_vis_dir: Option<&'a str>,
+    pub dump_tensors_dir: Option<&'a str>,
+    pub control_socket: Option<&'a str>,
+    pub exclude_zone: Vec<Zone>,
+    pub zone_overlap: Option<f32>,
+    pub zones_file: Option<&'a str>,
+    pub deduplicate_masks_iou: f32,
+    pub exclusive_group: Vec<ExclusiveGroup>,
+    pub exclusive_iou: f32,
+    pub prompt_cooldown_frames: u64,
+    pub stats_interval: Option<f32>,
+    pub no_color: bool,
+    pub save_best: Option<&'a str>,
+    pub best_k: usize,
+    pub save_crops: Option<&'a str>,
+    pub crop_pad: u32,
+    pub split_by_prompt: bool,
+    pub save_svg: Option<&'a str>,
+    pub save_dir_per_prompt: bool,
+    /// `--sync-annotate`: `--save-dir-per-prompt` writes on the loop.
+    pub sync_annotate: bool,
+    pub snapshot_interval: Option<f32>,
+    pub timelapse_video: Option<&'a str>,
+    pub save_on_interval_secs: Option<f32>,
+    pub save_on_interval_annotated: bool,
+    pub save_event_clips: Option<&'a str>,
+    pub clip_pre_seconds: f32,
+    pub clip_post_seconds: f32,
+    pub ffmpeg_loglevel: FfmpegLogLevel,
+    pub notify: Option<NotifyKind>,
+    pub notify_command: Option<&'a str>,
+    pub notify_cooldown_secs: f32,
+    pub notify_max_running: usize,
+    pub notify_timeout_secs: f32,
+    pub watchdog_secs: Option<f32>,
+    pub summary_json: Option<&'a str>,
+    pub compare: Option<&'a str>,
+    pub seed: Option<u64>,
+}
+
+/// What [`FrameHooks::next_frame`] got.
+pub enum Next {
+    Frame(u64, usls::Image),
+    /// No frame this time (a failed grab, the switch to the next input); the loop asks again.
+    Retry,
+    /// As `Retry`, and the model starts over without state first (`--reset-per-input`).
+    Restart,
+    /// The input ended.
+    End,
+}
+
+/// A binary's part of [`FrameLoop::run`]: how frames come out of its source and where finished
+/// frames go. Everything but [`FrameHooks::write`] and [`FrameHooks::finish`] defaults to
+/// nothing.
+pub trait FrameHooks<S: VideoSource> {
+    /// The next frame of `frames`.
+    fn next_frame(&mut self, frames: &mut Capture<S>) -> Result<Next> {
+        Ok(match frames.next_frame()? {
+            Some((frame_idx, img)) => Next::Frame(frame_idx, img),
+            None => Next::End,
+        })
+    }
+
+    /// The part of `img` that is inferred on and shown, with `prompts` mapped into it when that is
+    /// not the whole frame.
+    fn view(
+        &mut self,
+        img: usls::Image,
+        _prompts: &[Sam3Prompt],
+    ) -> Result<(usls::Image, Option<Vec<Sam3Prompt>>)> {
+        Ok((img, None))
+    }
+
+    /// Whether the frame, as captured, may go to the model; `false` only skips the inference.
+    fn inferable(&mut self, _frames: &mut Capture<S>, _frame_idx: u64, _img: &usls::Image) -> bool {
+        true
+    }
+
+    /// The filtered result of an inferred frame at `t` seconds; `None` between inferences.
+    fn result(&mut self, _frame_idx: u64, _t: f64, _y: Option<&usls::Y>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Add the binary's fields to the frame's `--json-out` record.
+    fn record(&mut self, _record: &mut FrameRecord) {}
+
+    /// What to show and write instead of `display`, made from the unannotated frame `img`; `None`
+    /// keeps `display`.
+    fn compose(
+        &mut self,
+        _frame_idx: u64,
+        _img: &usls::Image,
+        _display: &usls::Image,
+        _labels: &[String],
+        _conf: f32,
+    ) -> Result<Option<usls::Image>> {
+        Ok(None)
+    }
+
+    /// Take a finished frame, in frame order, into the binary's own sinks.
+    fn write(&mut self, frame_idx: u64, display: &usls::Image) -> Result<()>;
+
+    /// Keys beyond the shared ones; `pressed` tells whether a key is down.
+    fn handle_keys(&mut self, _frames: &mut Capture<S>, _pressed: &dyn Fn(usls::Key) -> bool) {}
+
+    /// The zoomed view saved with the session.
+    fn viewport(&self) -> Option<&ViewportState> {
+        None
+    }
+
+    /// Close the binary's sinks and `frames` after the last frame; `stopped` when the run ended
+    /// before the input did. Returns extra fields of the run summary.
+    fn finish(
+        self,
+        frames: Capture<S>,
+        stopped: bool,
+    ) -> Result<Vec<(&'static str, serde_json::Value)>>;
+}
+
+/// The capture loop every binary runs: model, filters, overlays, the outputs that do not depend
+/// on the source, the control socket and the window.
+pub struct FrameLoop {
+    model: SAM3,
+    model_opts: ModelOptions,
+    pipeline: Option<Pipeline>,
+    prompts: Vec<Sam3Prompt>,
+    session: SessionFile,
+    viewer: Option<Viewer>,
+    overlay: DisplayState,
+    filters: DetectionFilters,
+    stats: DetectionStats,
+    zones: ExcludeZones,
+    schedule: InferSchedule,
+    control: Option<ControlSocket>,
+    roi: Option<GrayImage>,
+    dump_dir: Option<PathBuf>,
+    prompt_vis_dir: Option<PathBuf>,
+    tiling: Option<(u32, u32)>,
+    scene_cut: Option<SceneCutDetector>,
+    ordered: Option<OrderedOutput>,
+    json_out: Option<JsonlWriter>,
+    svg_writer: Option<SvgWriter>,
+    crop_writer: Option<CropWriter>,
+    best_frames: Option<BestFrames>,
+    per_prompt: Option<PerPromptSaver>,
+    notifier: Option<Notifier>,
+    event_clips: Option<EventClips>,
+    snapshots: Option<IntervalSnapshots>,
+    interval_saver: Option<IntervalSaver>,
+    timelapse_video: Option<PathBuf>,
+    ffmpeg_loglevel: FfmpegLogLevel,
+    watchdog_secs: Option<f32>,
+    /// Frame rate of the media clock; `None` times frames by the wall clock.
+    media_fps: Option<f32>,
+    /// How long the window waits for a key after each frame; `None` polls.
+    key_wait_ms: Option<u64>,
+    save_base: PathBuf,
+    task: String,
+    mask_encoding: MaskEncoding,
+    dedup_frames: bool,
+    points_only: bool,
+    cross_fade_alpha: f32,
+    show_zones: bool,
+    sequential_names: bool,
+    summary_json: Option<String>,
+    compare: Option<String>,
+    seed: Option<u64>,
+}
+
+impl FrameLoop {
+    /// Open every output `args` asks for; fails on bad flags and unwritable paths.
+    pub fn new(args: LoopArgs<'_>) -> Result<Self> {
+        let snapshots = match args.snapshot_interval {
+            Some(secs) => Some(IntervalSnapshots::new(&args.save_base, secs)?),
+            None => None,
+        };
+        if args.timelapse_video.is_some() && snapshots.is_none() {
+            anyhow::bail!("--timelapse-video requires --snapshot-interval");
+        }
+        let annotation_style = if args.mask_to_json {
+            AnnotationStyle::None
+        } else {
+            args.annotation_style
+        };
+        let annotator = prompt_only_annotator(
+            build_annotator(annotation_style, args.show_mask),
+            args.visualize_points_only,
+        );
+        let legend = Legend::new(
+            args.legend,
+            args.legend_outside,
+            args.pipeline
+                .as_ref()
+                .map_or_else(|| args.labels.clone(), Pipeline::labels),
+        );
+        let overlay = DisplayState::new(
+            annotator,
+            legend,
+            PromptGroups::new(args.group_style, &args.group_tags),
+            PromptHistory::open(args.prompt_history.map(Path::new)),
+            args.prompts.len(),
+        )
+        .with_window(args.viewer.is_some())
+        .with_points_only(args.visualize_points_only)
+        .with_hold(args.hold_ms)
+        .with_prompts(args.session_prompts, args.labels, args.mirror_width)
+        .with_preset(args.session.restored().preset());
+        let per_prompt = match (&overlay.annotator, args.save_dir_per_prompt) {
+            (Some(_), true) => Some(PerPromptSaver::new(
+                args.save_base.clone(),
+                args.sync_annotate,
+            )?),
+            (None, true) => {
+                tracing::warn!("--save-dir-per-prompt: nothing is annotated, ignoring");
+                None
+            }
+            _ => None,
+        };
+        let notifier = match args.notify {
+            Some(kind) => Some(Notifier::new(NotifyOptions {
+                kind,
+                command: args.notify_command.map(str::to_string),
+                cooldown_secs: args.notify_cooldown_secs,
+                max_running: args.notify_max_running,
+                timeout_secs: args.notify_timeout_secs,
+                snapshot_dir: args.save_base.join("notify"),
+            })?),
+            None => None,
+        };
+        let event_clips = match args.save_event_clips {
+            Some(dir) => Some(EventClips::new(
+                Path::new(dir),
+                args.clip_pre_seconds,
+                args.clip_post_seconds,
+                args.ffmpeg_loglevel,
+            )?),
+            None => None,
+        };
+        let interval_saver = match args.save_on_interval_secs {
+            Some(secs) => Some(IntervalSaver::new(
+                &args.save_base,
+                secs,
+                args.save_on_interval_annotated,
+            )?),
+            None => None,
+        };
+        let json_out = match args.json_out {
+            Some(path) => Some(JsonlWriter::create(path)?.with_dedup(args.jsonl_dedup)?),
+            None => None,
+        };
+        let control = match args.control_socket {
+            Some(path) => Some(ControlSocket::bind(Path::new(path))?),
+            None => None,
+        };
+        let best_frames = match args.save_best {
+            Some(dir) => Some(BestFrames::new(Path::new(dir), args.best_k)?),
+            None => None,
+        };
+        let crop_writer = match args.save_crops {
+            Some(dir) => Some(CropWriter::new(
+                Path::new(dir),
+                args.crop_pad,
+                args.split_by_prompt,
+            )?),
+            None => None,
+        };
+        let svg_writer = match args.save_svg {
+            Some(dir) => Some(SvgWriter::new(Path::new(dir))?),
+            None => None,
+        };
+        Ok(Self {
+            model: args.model,
+            model_opts: args.model_opts,
+            pipeline: args.pipeline,
+            prompts: args.prompts,
+            session: args.session,
+            viewer: args.viewer,
+            overlay,
+            filters: DetectionFilters::new(
+                MaskDedup::new(args.deduplicate_masks_iou)?,
+                ExclusiveGroups::new(args.exclusive_group, args.exclusive_iou)?,
+                CooldownTracker::new(args.prompt_cooldown_frames),
+            ),
+            stats: DetectionStats::new(args.stats_interval, args.no_color),
+            zones: ExcludeZones::new(
+                args.exclude_zone,
+                args.zone_overlap,
+                args.zones_file.map(Path::new),
+            )?,
+            schedule: InferSchedule::from_args(args.infer_every, args.infer_hz)?,
+            control,
+            roi: roi_mask(args.roi_file, args.frame_size)?,
+            dump_dir: output_dir(args.dump_tensors_dir, "tensor dump")?,
+            prompt_vis_dir: output_dir(args.export_prompt_vis_dir, "prompt visualization")?,
+            tiling: None,
+            scene_cut: None,
+            ordered: None,
+            json_out,
+            svg_writer,
+            crop_writer,
+            best_frames,
+            per_prompt,
+            notifier,
+            event_clips,
+            snapshots,
+            interval_saver,
+            timelapse_video: args.timelapse_video.map(PathBuf::from),
+            ffmpeg_loglevel: args.ffmpeg_loglevel,
+            watchdog_secs: args.watchdog_secs,
+            media_fps: None,
+            key_wait_ms: None,
+            save_base: args.save_base,
+            task: args.task.to_string(),
+            mask_encoding: args.mask_encoding,
+            dedup_frames: args.dedup_frames,
+            points_only: args.visualize_points_only,
+            cross_fade_alpha: args.cross_fade_alpha,
+            show_zones: args.show_zones,
+            sequential_names: args.sequential_names,
+            summary_json: args.summary_json.map(str::to_string),
+            compare: args.compare.map(str::to_string),
+            seed: args.seed,
+        })
+    }
+
+    /// `--tile-size`/`--tile-overlap`: infer on overlapping tiles.
+    pub fn with_tiling(mut self, tiling: Option<(u32, u32)>) -> Self {
+        self.tiling = tiling;
+        self
+    }
+
+    /// `--detect-scene-cut`: start the model over at hard cuts.
+    pub fn with_scene_cut(mut self, scene_cut: Option<SceneCutDetector>) -> Self {
+        self.scene_cut = scene_cut;
+        self
+    }
+
+    /// Time frames by their position in a stream at `fps` instead of the wall clock.
+    pub fn with_media_clock(mut self, fps: f32) -> Self {
+        self.media_fps = Some(fps);
+        self
+    }
+
+    /// Wait up to `ms` for a key after each frame, which paces playback, instead of polling.
+    pub fn with_key_wait(mut self, ms: u64) -> Self {
+        self.key_wait_ms = Some(ms);
+        self
+    }
+
+    /// Annotate on worker threads, `queue` frames deep, and hand frames to
+    /// [`FrameHooks::write`] in order; only for runs that neither show nor draw over the
+    /// annotated frame. Nothing changes without an annotator.
+    pub fn with_ordered_output(mut self, queue: usize) -> Result<Self> {
+        if let Some(annotator) = &self.overlay.annotator {
+            self.ordered = Some(OrderedOutput::new(AnnotationWorker::spawn(
+                annotator,
+                &self.overlay.groups,
+                queue,
+            )?));
+        }
+        Ok(self)
+    }
+
+    /// Whether results are drawn at all.
+    pub fn annotates(&self) -> bool {
+        self.overlay.annotator.is_some()
+    }
+
+    /// Run the frames of `frames` through the model and every output until the input ends, the
+    /// window closes or a stop is requested (SIGINT/SIGTERM, `--watchdog-secs`), then flush the
+    /// outputs; `hooks` adds the binary's part.
+    pub fn run<S: VideoSource>(
+        self,
+        mut frames: Capture<S>,
+        mut hooks: impl FrameHooks<S>,
+    ) -> Result<()> {
+        let Self {
+            mut model,
+            mut model_opts,
+            pipeline,
+            mut prompts,
+            session,
+            mut viewer,
+            mut overlay,
+            mut filters,
+            mut stats,
+            mut zones,
+            mut schedule,
+            mut control,
+            roi,
+            dump_dir,
+            prompt_vis_dir,
+            tiling,
+            mut scene_cut,
+            mut ordered,
+            mut json_out,
+            svg_writer,
+            mut crop_writer,
+            mut best_frames,
+            mut per_prompt,
+            mut notifier,
+            mut event_clips,
+            mut snapshots,
+            mut interval_saver,
+            timelapse_video,
+            ffmpeg_loglevel,
+            watchdog_secs,
+            media_fps,
+            key_wait_ms,
+            save_base,
+            task,
+            mask_encoding,
+            dedup_frames,
+            points_only,
+            cross_fade_alpha,
+            show_zones,
+            sequential_names,
+            summary_json,
+            compare,
+            seed,
+        } = self;
+        let stop = StopSignal::install()?;
+        let watchdog = watchdog_secs
+            .map(|secs| Watchdog::spawn(secs, stop.flag()))
+            .transpose()?;
+        let started = Instant::now();
+        // The model's own threshold; `conf` commands only raise the one applied after it.
+        let model_conf = model_opts.conf;
+        let mut snapshot_requested = false;
+        let mut last_inferred: Option<(u64, Vec<usls::Y>)> = None;
+        // Stage tree of the last pipeline run, kept for frames that reuse its results.
+        let mut stage_tree: Vec<StageDetection> = Vec::new();
+        let mut title_fps = FpsMeter::default();
+        let mut stopped = false;
+        loop {
+            if stop.requested()
+                || viewer
+                    .as_ref()
+                    .is_some_and(|v| v.is_window_exist_and_closed())
+            {
+                stopped = true;
+                break;
+            }
+            if let Some(control) = control.as_mut() {
+                control.poll(|cmd| {
+                    match cmd {
+                        ControlCommand::Prompt(raw) => {
+                            prompts = overlay.set_prompts(raw)?;
+                            last_inferred = None;
+                        }
+                        ControlCommand::Conf(conf) => {
+                            anyhow::ensure!(
+                                conf >= model_conf,
+                                "conf {conf} is below the --conf {model_conf} the model was \
+                                 built with; restart with a lower --conf"
+                            );
+                            model_opts.conf = conf;
+                            tracing::info!("Updated conf: {conf}");
+                        }
+                        ControlCommand::InferEvery(n) => {
+                            schedule = InferSchedule::Frames(n);
+                            tracing::info!("Updated infer-every: {n}");
+                        }
+                        ControlCommand::Snapshot => snapshot_requested = true,
+                        ControlCommand::ExcludeZone(zone) => {
+                            zones.add(zone);
+                            tracing::info!("Added exclude zone: {zone}");
+                        }
+                        ControlCommand::ClearZones => {
+                            zones.clear();
+                            tracing::info!("Cleared exclude zones");
+                        }
+                        ControlCommand::Ping => return Ok("pong".to_string()),
+                    }
+                    Ok(String::new())
+                });
+            }
+
+            let (frame_idx, img) = match hooks.next_frame(&mut frames)? {
+                Next::Frame(frame_idx, img) => (frame_idx, img),
+                Next::Retry => continue,
+                Next::Restart => {
+                    reset_tracker(&mut model, &model_opts)?;
+                    last_inferred = None;
+                    continue;
+                }
+                Next::End => break,
+            };
+            let _frame_span = tracing::info_span!("frame", frame_idx).entered();
+            if let Some(watchdog) = &watchdog {
+                watchdog.kick();
+            }
+            // Seconds into the stream, or since the start for live sources.
+            let t = match media_fps {
+                Some(fps) => (frame_idx - 1) as f64 / fps.max(0.001) as f64,
+                None => started.elapsed().as_secs_f64(),
+            };
+            stats.record_frame();
+            stats.maybe_report();
+            let (img, view_prompts) = hooks.view(img, &prompts)?;
+            let frame_prompts = view_prompts.as_deref().unwrap_or(&prompts);
+            let inferable = hooks.inferable(&mut frames, frame_idx, &img);
+            let run_infer = !points_only && inferable && schedule.due(frame_idx);
+            // The shown frame and, when only one of them is adjusted, the model input.
+            let (img, infer_img) = frames.split_tone(img, run_infer);
+
+            if points_only && let Some(annotated) = overlay.annotate_prompts(&img, frame_prompts)? {
+                overlay.last_displayed = Some(annotated);
+            }
+            let mut record = FrameRecord {
+                frame_idx,
+                ..Default::default()
+            };
+            let mut event_labels = None;
+            if run_infer {
+                if let Some(detector) = scene_cut.as_mut()
+                    && detector.is_cut(&img)
+                {
+                    tracing::info!("Scene cut detected at frame {frame_idx}");
+                    reset_tracker(&mut model, &model_opts)?;
+                    last_inferred = None;
+                }
+                let hash = dedup_frames.then(|| frame_hash(&img));
+                let (ys, reused) = match (&last_inferred, hash) {
+                    (Some((prev, ys)), Some(hash)) if *prev == hash => {
+                        stats.record_reuse();
+                        (ys.clone(), true)
+                    }
+                    _ => {
+                        let raw_input = infer_img.unwrap_or(&img);
+                        let masked = roi
+                            .as_ref()
+                            .map(|m| apply_roi_mask(raw_input, m))
+                            .transpose()?;
+                        let input = masked.as_ref().unwrap_or(raw_input);
+                        if let Some(dir) = &dump_dir {
+                            dump_input_tensor(dir, frame_idx, input, &model_opts)?;
+                        }
+                        if let Some(dir) = &prompt_vis_dir {
+                            export_prompt_vis(dir, frame_idx, &img, frame_prompts)?;
+                        }
+                        match (&pipeline, tiling) {
+                            (Some(pipeline), _) => {
+                                let (ys, tree) = pipeline.forward(&mut model, input)?;
+                                stage_tree = tree;
+                                (ys, false)
+                            }
+                            (None, Some((size, overlap))) => (
+                                vec![forward_tiled(
+                                    &mut model,
+                                    input,
+                                    frame_prompts,
+                                    size,
+                                    overlap,
+                                )?],
+                                false,
+                            ),
+                            (None, None) => {
+                                let batch = vec![input.clone()];
+                                (model.forward(&batch, frame_prompts)?, false)
+                            }
+                        }
+                    }
+                };
+                if let Some(hash) = hash {
+                    last_inferred = Some((hash, ys.clone()));
+                }
+                let (ys, exclusive, deduplicated) = filters.apply(
+                    ys,
+                    frame_idx,
+                    frame_prompts,
+                    model_opts.conf,
+                    &zones,
+                    &mut stats,
+                );
+                overlay.record(&ys[0], frame_prompts, Duration::from_secs_f64(t));
+                hooks.result(frame_idx, t, Some(&ys[0]))?;
+                if let Some(svg) = &svg_writer {
+                    svg.write(frame_idx, &img, &shapes_from_y(&ys[0], frame_prompts))?;
+                }
+                if let Some(crops) = crop_writer.as_mut() {
+                    crops.write(frame_idx, &img, &ys[0])?;
+                }
+                if let Some(viewer) = viewer.as_mut() {
+                    viewer.set_title(&make_window_title(&task, frame_prompts, title_fps.tick()));
+                }
+                if event_clips.is_some() {
+                    event_labels = Some(present_labels(&ys[0]));
+                }
+                if let Some(notifier) = notifier.as_mut() {
+                    notifier.update(&ys[0], &img)?;
+                }
+                if json_out.is_some() {
+                    record = FrameRecord {
+                        frame_idx,
+                        inferred: true,
+                        reused,
+                        detections: Detection::from_y_with_masks(
+                            &ys[0],
+                            mask_encoding,
+                            img.width(),
+                            img.height(),
+                        ),
+                        stages: stage_tree.clone(),
+                        exclusive,
+                        deduplicated,
+                        ..Default::default()
+                    };
+                }
+
+                if let (Some(saver), Some(annotator)) = (per_prompt.as_mut(), &overlay.annotator) {
+                    let name = saved_frame_name(sequential_names, frame_idx);
+                    saver.save_split(annotator, &img, &ys[0], frame_prompts, &name)?;
+                }
+                if let Some(ordered) = ordered.as_mut() {
+                    ordered.push_inferred(frame_idx, img.clone(), ys[0].clone(), frame_prompts)?;
+                } else {
+                    let annotated = match overlay.annotate(&img, &ys[0], frame_prompts)? {
+                        Some(annotated) => annotated,
+                        None => img.clone(),
+                    };
+                    if let Some(best) = best_frames.as_mut() {
+                        best.offer(frame_idx, &ys[0], &annotated);
+                    }
+                    overlay.last_displayed = Some(annotated);
+                }
+            } else {
+                hooks.result(frame_idx, t, None)?;
+            }
+            if let Some(json_out) = json_out.as_mut() {
+                hooks.record(&mut record);
+                json_out.write_record(&record)?;
+            }
+            if let Some(clips) = event_clips.as_mut() {
+                clips.push(t, &img, event_labels)?;
+            }
+            if let Some(ordered) = ordered.as_mut() {
+                if !run_infer {
+                    ordered.push_repeat(frame_idx, &img);
+                }
+                ordered.drain(|idx, frame| hooks.write(idx, frame))?;
+                continue;
+            }
+
+            // Prompt-only frames are not faded: they have no inference to fade from.
+            let faded = if run_infer || points_only {
+                None
+            } else {
+                overlay.faded(&img, cross_fade_alpha)?
+            };
+            let display = match (&faded, &overlay.annotator, &overlay.last_displayed) {
+                (Some(faded), _, _) => faded,
+                (None, Some(_), Some(last)) => last,
+                _ => &img,
+            };
+            let composed =
+                hooks.compose(frame_idx, &img, display, overlay.labels(), model_opts.conf)?;
+            let display = composed.as_ref().unwrap_or(display);
+            if std::mem::take(&mut snapshot_requested) {
+                let path = save_base.join(saved_frame_name(sequential_names, frame_idx));
+                display.save(&path)?;
+                tracing::info!("Saved snapshot: {}", path.display());
+            }
+            if let Some(snapshots) = snapshots.as_mut() {
+                snapshots.maybe_save(t, display)?;
+            }
+            if let Some(saver) = interval_saver.as_mut() {
+                saver.maybe_save(if saver.annotated() { display } else { &img })?;
+            }
+            hooks.write(frame_idx, display)?;
+
+            let Some(viewer) = viewer.as_mut() else {
+                continue;
+            };
+            let shown_zones = (show_zones && !zones.is_empty()).then_some(&zones);
+            overlay.show(viewer, display, Duration::from_secs_f64(t), shown_zones)?;
+            let key = key_wait_ms.and_then(|ms| viewer.wait_key(ms));
+            let pressed = |k: usls::Key| match key_wait_ms {
+                Some(_) => key == Some(k),
+                None => viewer.is_key_pressed(k),
+            };
+
+            if pressed(usls::Key::Escape) || pressed(usls::Key::Q) {
+                stopped = true;
+                break;
+            }
+
+            overlay.handle_keys(pressed, frame_prompts, || {
+                save_base.join(saved_frame_name(sequential_names, frame_idx))
+            })?;
+            hooks.handle_keys(&mut frames, &pressed);
+
+            if pressed(usls::Key::W) {
+                session.save(&overlay.session(model_opts.conf, &zones, hooks.viewport()))?;
+            }
+
+            if pressed(usls::Key::P)
+                && let Some(raw) = overlay.edit_prompts()?
+            {
+                prompts = overlay.set_prompts(raw)?;
+                last_inferred = None;
+            }
+        }
+        let watchdog_fired = watchdog.is_some_and(Watchdog::finish);
+
+        if let Some(ordered) = ordered {
+            ordered.finish(|idx, frame| hooks.write(idx, frame))?;
+        }
+        if let Some(json_out) = json_out {
+            json_out.finish()?;
+        }
+        if let (Some(snapshots), Some(path)) = (&snapshots, &timelapse_video) {
+            snapshots.write_timelapse(path, ffmpeg_loglevel)?;
+        }
+        if let Some(notifier) = notifier {
+            notifier.finish();
+        }
+        if let Some(best) = best_frames {
+            best.finish()?;
+        }
+        if let Some(crops) = crop_writer {
+            crops.finish()?;
+        }
+        if let Some(saver) = per_prompt {
+            saver.finish()?;
+        }
+        if let Some(clips) = event_clips {
+            clips.finish()?;
+        }
+        zones.save()?;
+        session.save_on_exit(&overlay.session(model_opts.conf, &zones, hooks.viewport()))?;
+        let extra = hooks.finish(frames, stopped)?;
+        if summary_json.is_some() || compare.is_some() {
+            let mut summary = run_summary(
+                std::env::args(),
+                &model_opts,
+                model.spec(),
+                seed,
+                stats.totals(),
+            );
+            for (key, value) in extra {
+                summary[key] = value;
+            }
+            finish_run(&summary, summary_json.as_deref(), compare.as_deref())?;
+        }
+        usls::perf(false);
+        if watchdog_fired {
+            return Err(WatchdogFired.into());
+        }
+        Ok(())
+    }
+}
+
+/// `dir` created if missing; `what` names it in the error.
+fn output_dir(dir: Option<&str>, what: &str) -> Result<Option<PathBuf>> {
+    let Some(dir) = dir else {
+        return Ok(None);
+    };
+    let dir = PathBuf::from(dir);
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create {what} directory: {}", dir.display()))?;
+    Ok(Some(dir))
+}
+
+/// Start `model` over without tracker state, for `--reset-per-input` and scene cuts.
+///
+/// `sam3-tracker` carries objects from frame to frame and usls has no tracker reset at the pinned
+/// revision, so only a fresh model forgets them. `sam3-image` keeps nothing between frames and is
+/// left loaded.
+fn reset_tracker(model: &mut SAM3, opts: &ModelOptions) -> Result<()> {
+    if opts.is_tracker() {
+        *model = opts.load_model(opts.build_config()?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_must_be_given_and_valid() {
+        assert!(parse_prompts(&[]).is_err());
+        let parsed = parse_prompts(&["person".to_string(), "car".to_string()]).unwrap();
+        assert_eq!(parsed.len(), 2);
+    }
+
+    #[test]
+    fn raised_conf_filters_after_inference() {
+        let mut filters = DetectionFilters::new(
+            MaskDedup::new(0.0).unwrap(),
+            ExclusiveGroups::new(Vec::new(), 0.5).unwrap(),
+            CooldownTracker::new(0),
+        );
+        let zones = ExcludeZones::new(Vec::new(), None, None).unwrap();
+        let mut stats = DetectionStats::new(None, true);
+        let hbb = |x: f32, conf: f32| {
+            usls::Hbb::from_xyxy(x, 0.0, x + 10.0, 10.0)
+                .with_id(0)
+                .with_confidence(conf)
+        };
+        let y = usls::Y::default().with_hbbs(&[hbb(0.0, 0.4), hbb(20.0, 0.8)]);
+        let prompts = parse_prompts(&["card".to_string()]).unwrap();
+
+        let (ys, _, _) = filters.apply(vec![y.clone()], 1, &prompts, 0.3, &zones, &mut stats);
+        assert_eq!(ys[0].hbbs().len(), 2);
+        let (ys, _, _) = filters.apply(vec![y], 2, &prompts, 0.6, &zones, &mut stats);
+        assert_eq!(ys[0].hbbs().len(), 1);
+        assert_eq!(ys[0].hbbs()[0].confidence(), Some(0.8));
+    }
+
+    #[test]
+    fn removed_duplicates_are_returned() {
+        let mut filters = DetectionFilters::new(
+            MaskDedup::new(0.5).unwrap(),
+            ExclusiveGroups::new(Vec::new(), 0.5).unwrap(),
+            CooldownTracker::new(0),
+        );
+        let zones = ExcludeZones::new(Vec::new(), None, None).unwrap();
+        let mut stats = DetectionStats::new(None, true);
+        let hbb = |x: f32, conf: f32| {
+            usls::Hbb::from_xyxy(x, 0.0, x + 10.0, 10.0)
+                .with_id(0)
+                .with_confidence(conf)
+        };
+        let y = usls::Y::default().with_hbbs(&[hbb(0.0, 0.9), hbb(1.0, 0.7), hbb(30.0, 0.6)]);
+        let prompts = parse_prompts(&["card".to_string()]).unwrap();
+        let (ys, _, deduplicated) = filters.apply(vec![y], 1, &prompts, 0.0, &zones, &mut stats);
+        assert_eq!(deduplicated, 1);
+        assert_eq!(ys[0].hbbs().len(), 2);
+        assert_eq!(stats.totals().to_json()["suppressed_by_mask_dedup"], 1);
+    }
+}
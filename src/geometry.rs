@@ -0,0 +1,44 @@
+//! Axis-aligned boxes shared by the trackers, filters and writers.
+
+/// `(x0, y0, x1, y1)`.
+pub type Rect = (f32, f32, f32, f32);
+
+/// A `[x, y, w, h]` box as a [`Rect`].
+pub fn from_xywh([x, y, w, h]: [f32; 4]) -> Rect {
+    (x, y, x + w, y + h)
+}
+
+/// Area of `r`; zero for an inverted box.
+pub fn area(r: Rect) -> f32 {
+    (r.2 - r.0).max(0.0) * (r.3 - r.1).max(0.0)
+}
+
+/// Area of the overlap of `a` and `b`.
+pub fn intersection(a: Rect, b: Rect) -> f32 {
+    let w = (a.2.min(b.2) - a.0.max(b.0)).max(0.0);
+    let h = (a.3.min(b.3) - a.1.max(b.1)).max(0.0);
+    w * h
+}
+
+/// Intersection over union of `a` and `b`; 0.0 when both are empty.
+pub fn iou(a: Rect, b: Rect) -> f32 {
+    let inter = intersection(a, b);
+    let union = area(a) + area(b) - inter;
+    if union > 0.0 { inter / union } else { 0.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iou_of_overlapping_disjoint_and_empty_boxes() {
+        let a = (0.0, 0.0, 10.0, 10.0);
+        assert_eq!(iou(a, a), 1.0);
+        assert!((iou(a, (5.0, 0.0, 15.0, 10.0)) - 50.0 / 150.0).abs() < 1e-6);
+        assert_eq!(iou(a, (20.0, 20.0, 30.0, 30.0)), 0.0);
+        assert_eq!(iou((1.0, 1.0, 1.0, 1.0), (1.0, 1.0, 1.0, 1.0)), 0.0);
+        assert_eq!(from_xywh([5.0, 0.0, 10.0, 10.0]), (5.0, 0.0, 15.0, 10.0));
+        assert_eq!(intersection(a, (5.0, 5.0, 20.0, 20.0)), 25.0);
+    }
+}
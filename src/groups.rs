@@ -0,0 +1,222 @@
+//! Prompt groups with their own overlay style (`-p "person#grp=people"` plus
+//! `--group-style people=thickness:1,color:00ff00,masks:off`).
+//!
+//! Each prompt's detections are annotated with its group's [`Annotator`]; untagged prompts, groups
+//! without a `--group-style` and detections that match no current prompt use the run's regular
+//! annotator.
+
+use anyhow::Result;
+use usls::{Annotator, models::Sam3Prompt};
+
+use crate::common::{annotate_frame, annotate_prompts, hbb_style, mask_style, polygon_style};
+use crate::postprocess::{split_by_prompt, unmatched};
+
+const GROUP_TAG: &str = "#grp=";
+
+/// Split `#grp=<name>` off every raw prompt: the prompts to parse, and each one's group.
+pub fn split_group_tags(raw: &[String]) -> (Vec<String>, Vec<Option<String>>) {
+    raw.iter()
+        .map(|s| match s.rfind(GROUP_TAG) {
+            Some(at) => {
+                let group = s[at + GROUP_TAG.len()..].trim();
+                (
+                    s[..at].trim_end().to_string(),
+                    (!group.is_empty()).then(|| group.to_string()),
+                )
+            }
+            None => (s.clone(), None),
+        })
+        .unzip()
+}
+
+/// One `--group-style name=key:value,...`; keys are `thickness`, `color` (RRGGBB hex) and
+/// `masks` (on/off).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GroupStyle {
+    pub name: String,
+    pub thickness: usize,
+    /// `None` keeps the per-prompt palette.
+    pub color: Option<[u8; 3]>,
+    pub masks: bool,
+}
+
+impl std::str::FromStr for GroupStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, spec) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid group style `{s}` (expected name=key:value,...)"))?;
+        let mut style = Self {
+            name: name.trim().to_string(),
+            thickness: 2,
+            color: None,
+            masks: true,
+        };
+        if style.name.is_empty() {
+            return Err(format!("invalid group style `{s}`: empty group name"));
+        }
+        for item in spec.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let (key, value) = item.split_once(':').ok_or_else(|| {
+                format!("invalid group style entry `{item}` (expected key:value)")
+            })?;
+            let value = value.trim();
+            match key.trim() {
+                "thickness" => {
+                    style.thickness =
+                        value.parse().ok().filter(|t| *t > 0).ok_or_else(|| {
+                            format!("invalid thickness `{value}` (expected >= 1)")
+                        })?;
+                }
+                "color" => {
+                    let hex = value.trim_start_matches('#');
+                    let rgb = u32::from_str_radix(hex, 16)
+                        .ok()
+                        .filter(|_| hex.len() == 6)
+                        .ok_or_else(|| format!("invalid color `{value}` (expected RRGGBB)"))?;
+                    let [_, r, g, b] = rgb.to_be_bytes();
+                    style.color = Some([r, g, b]);
+                }
+                "masks" | "mask" => {
+                    style.masks = match value {
+                        "on" | "true" | "1" => true,
+                        "off" | "false" | "0" => false,
+                        _ => {
+                            return Err(format!("invalid masks value `{value}` (expected on/off)"));
+                        }
+                    };
+                }
+                other => {
+                    return Err(format!(
+                        "unknown group style key `{other}` (expected thickness, color or masks)"
+                    ));
+                }
+            }
+        }
+        Ok(style)
+    }
+}
+
+impl GroupStyle {
+    fn annotator(&self) -> Annotator {
+        let mut hbb = hbb_style().with_thickness(self.thickness);
+        let mut polygon = polygon_style().with_thickness(self.thickness);
+        let mut mask = mask_style()
+            .with_visible(self.masks)
+            .with_draw_polygon_largest(true);
+        if let Some([r, g, b]) = self.color {
+            let palette = [usls::Color::from([r, g, b, 255])];
+            hbb = hbb.with_palette(&palette);
+            polygon = polygon.with_palette(&palette);
+            mask = mask.with_palette(&palette);
+        }
+        Annotator::default()
+            .with_hbb_style(hbb)
+            .with_polygon_style(polygon)
+            .with_mask_style(mask)
+    }
+}
+
+/// The `--group-style` annotators and which one each current prompt uses.
+#[derive(Clone)]
+pub struct PromptGroups {
+    styles: Vec<(String, Annotator)>,
+    /// Per prompt, an index into `styles`.
+    assigned: Vec<Option<usize>>,
+}
+
+impl PromptGroups {
+    pub fn new(styles: &[GroupStyle], tags: &[Option<String>]) -> Self {
+        let mut groups = Self {
+            styles: styles
+                .iter()
+                .map(|s| (s.name.clone(), s.annotator()))
+                .collect(),
+            assigned: Vec::new(),
+        };
+        groups.set_tags(tags);
+        groups
+    }
+
+    /// Re-map after the prompts changed; `tags` as returned by [`split_group_tags`].
+    pub fn set_tags(&mut self, tags: &[Option<String>]) {
+        self.assigned = tags
+            .iter()
+            .map(|tag| {
+                let tag = tag.as_deref()?;
+                let found = self.styles.iter().position(|(name, _)| name == tag);
+                if found.is_none() {
+                    tracing::warn!("Prompt group `{tag}` has no --group-style; using the default");
+                }
+                found
+            })
+            .collect();
+    }
+
+    /// [`annotate_frame`], with each grouped prompt's detections drawn in its group's style.
+    pub fn annotate(
+        &self,
+        default: &Annotator,
+        img: &usls::Image,
+        y: &usls::Y,
+        prompts: &[Sam3Prompt],
+    ) -> Result<usls::Image> {
+        if self.assigned.iter().all(Option::is_none) {
+            return annotate_frame(default, img, y, prompts);
+        }
+        let mut annotated = default.annotate(img, &unmatched(y, prompts))?;
+        for (i, y) in split_by_prompt(y, prompts).iter().enumerate() {
+            let annotator = match self.assigned.get(i).copied().flatten() {
+                Some(g) => &self.styles[g].1,
+                None => default,
+            };
+            annotated = annotator.annotate(&annotated, y)?;
+        }
+        annotate_prompts(default, &annotated, prompts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_group_tags() {
+        let raw = vec![
+            "person#grp=people".to_string(),
+            "forklift".to_string(),
+            "visual;pos:10,10,50,50 #grp=alerts".to_string(),
+            "box#grp=".to_string(),
+        ];
+        let (prompts, tags) = split_group_tags(&raw);
+        assert_eq!(
+            prompts,
+            ["person", "forklift", "visual;pos:10,10,50,50", "box"]
+        );
+        assert_eq!(
+            tags,
+            [Some("people".into()), None, Some("alerts".into()), None]
+        );
+    }
+
+    #[test]
+    fn parses_group_styles() {
+        let style: GroupStyle = "alerts=thickness:4,color:#FF0000,masks:on".parse().unwrap();
+        assert_eq!(
+            style,
+            GroupStyle {
+                name: "alerts".into(),
+                thickness: 4,
+                color: Some([255, 0, 0]),
+                masks: true,
+            }
+        );
+        let style: GroupStyle = "people=color:00ff00,masks:off".parse().unwrap();
+        assert_eq!((style.thickness, style.color), (2, Some([0, 255, 0])));
+        assert!(!style.masks);
+        assert!("people".parse::<GroupStyle>().is_err());
+        assert!("people=color:0f0".parse::<GroupStyle>().is_err());
+        assert!("people=thickness:0".parse::<GroupStyle>().is_err());
+        assert!("people=width:3".parse::<GroupStyle>().is_err());
+    }
+}
@@ -11,29 +11,78 @@ pub fn run() -> Result<()> {
 pub fn run() -> Result<()> {
     use anyhow::{Context, Result};
     use argh::FromArgs;
-    use std::ffi::{CStr, CString};
-    use std::io::Write;
+    use std::ffi::CString;
+    use std::net::Ipv4Addr;
     use std::ptr;
-    use usls::{
-        Annotator, Config, Task, Viewer,
-        models::{SAM3, Sam3Prompt},
+    use usls::Viewer;
+    use usls::models::Sam3Prompt;
+
+    use crate::build_info::handle_version_flag;
+    use crate::camera_profile::{CameraProfile, CameraSelector, NodeValue, load_dir};
+    use crate::common::{
+        AnnotationStyle, Capture, ChannelValues, CheckReport, DEFAULT_CONF, MASK_TO_JSON_PATH,
+        ModelOptions, VideoSource, annotate_frame, build_annotator, check_dir_writable,
+        check_mask_to_json, default_save_base, dummy_forward, log_writer, warmup_with_image,
+        warn_prompts_outside,
     };
+    use crate::device::{ExecutionProvider, auto_select_device_and_ep, ep_flag};
+    use crate::ffmpeg_log::FfmpegLogLevel;
+    use crate::frame_loop::{FrameHooks, FrameLoop, LoopArgs, Next, parse_prompts};
+    use crate::groups::{GroupStyle, split_group_tags};
+    use crate::kafka_sink::now_unix_ms;
+    use crate::logging::init_logging;
+    use crate::multi_cam::{DEFAULT_TILE, MultiCameraRunner, TileSender};
+    use crate::notify::NotifyKind;
+    use crate::output::{MaskEncoding, json_schema};
+    use crate::pipeline::Pipeline;
+    use crate::postprocess::ExclusiveGroup;
+    use crate::preprocess::{
+        OutputSize, ToneAdjust, ToneParams, ToneTarget, ViewportState, apply_viewport,
+        temporal_average,
+    };
+    use crate::prompts::with_mirrored;
+    use crate::repro::next_run_dir;
+    use crate::schedule::InferSchedule;
+    use crate::session::{SessionArgs, SessionFile};
+    use crate::shm_sink::ShmSink;
+    use crate::shutdown::StopSignal;
+    use crate::undistort::{Calibration, Undistorter};
+    use crate::viz::to_rgb;
+    use crate::zones::Zone;
 
     use hikvision_mvs_sys as mvs;
 
     const PIXEL_TYPE_RGB8_PACKED: u64 = 0x02180014;
+    /// Anything smaller than a 16x16 RGB frame is a bogus size report, not a real frame.
+    const MIN_FRAME_BYTES: usize = 16 * 16 * 3;
+    /// `--strobe-duration-us` when not given.
+    const DEFAULT_STROBE_DURATION_US: u64 = 1000;
 
     #[derive(FromArgs)]
     /// SAM3 inference from Hikvision MVS camera (RGB8Packed).
     struct Args {
-        /// list connected camera user-defined names and exit
+        /// list connected cameras (index, name, serial, model, GigE address and reachability) and
+        /// exit
         #[argh(switch)]
         list: bool,
 
-        /// camera user-defined name (from `--list`)
+        /// camera user-defined name or serial number (from `--list`)
         #[argh(option)]
         camera_name: Option<String>,
 
+        /// camera enumeration index (from `--list`), instead of --camera-name
+        #[argh(option)]
+        camera_index: Option<usize>,
+
+        /// TOML profile naming the camera (name, serial or IP) and the GenICam node values to set
+        /// after opening it; --camera-name/--camera-index override its camera
+        #[argh(option)]
+        profile: Option<String>,
+
+        /// with --list, show which cameras the `*.toml` profiles in this directory select
+        #[argh(option)]
+        profile_dir: Option<String>,
+
         /// set Width (best-effort; depends on camera)
         #[argh(option)]
         width: Option<u32>,
@@ -46,13 +95,64 @@ pub fn run() -> Result<()> {
         #[argh(option, default = "1000")]
         timeout_ms: u32,
 
+        /// stream packet size in bytes for GigE cameras (GevSCPSPacketSize), e.g. 8164 with jumbo frames
+        #[argh(option)]
+        packet_size: Option<u32>,
+
+        /// set the GigE packet size to the largest the network path supports (instead of
+        /// --packet-size)
+        #[argh(switch)]
+        auto_packet_size: bool,
+
+        /// inter-packet delay in ns for GigE cameras (GevSCPD); raise it when frames drop
+        #[argh(option)]
+        packet_delay: Option<u32>,
+
+        /// drive a light from this I/O line (e.g. 1 for Line1), strobed with each exposure
+        #[argh(option)]
+        strobe_line: Option<u8>,
+
+        /// strobe pulse length in us for --strobe-line (default: 1000)
+        #[argh(option)]
+        strobe_duration_us: Option<u64>,
+
+        /// delay in us from exposure start to the strobe pulse for --strobe-line (StrobeDelay)
+        #[argh(option)]
+        strobe_delay_us: Option<u64>,
+
+        /// assign this IP to the selected GigE camera before opening it (e.g. a fresh camera outside
+        /// the host's subnet); lasts until the camera restarts
+        #[argh(option)]
+        force_ip: Option<Ipv4Addr>,
+
+        /// subnet mask for --force-ip (default: 255.255.255.0)
+        #[argh(option, default = "Ipv4Addr::new(255, 255, 255, 0)")]
+        force_netmask: Ipv4Addr,
+
+        /// default gateway for --force-ip (default: 0.0.0.0)
+        #[argh(option, default = "Ipv4Addr::UNSPECIFIED")]
+        force_gateway: Ipv4Addr,
+
+        /// exit with code 75 when no frame has been captured for this many seconds (for supervisors)
+        #[argh(option)]
+        watchdog_secs: Option<f32>,
+
         /// task (sam3-image, sam3-tracker)
         #[argh(option, default = "String::from(\"sam3-image\")")]
         task: String,
 
-        /// device (cpu:0, cuda:0, etc.)
-        #[argh(option, default = "String::from(\"cpu:0\")")]
-        device: String,
+        /// device (cpu:0, cuda:0, etc., or auto to pick one for this machine; default: cpu:0)
+        #[argh(option)]
+        device: Option<String>,
+
+        /// execution provider: cpu, cuda, tensorrt, directml, coreml or openvino (default: from
+        /// --device)
+        #[argh(option)]
+        execution_provider: Option<ExecutionProvider>,
+
+        /// short for --execution-provider; errors if that provider is not compiled in
+        #[argh(option)]
+        ep: Option<ExecutionProvider>,
 
         /// dtype (q4f16, fp16, fp32, etc.)
         #[argh(option, default = "String::from(\"q4f16\")")]
@@ -62,17 +162,162 @@ pub fn run() -> Result<()> {
         #[argh(option, short = 'p')]
         prompt: Vec<String>,
 
+        /// also prompt with the left-right mirror image of every box prompt (`pos:`/`neg:`),
+        /// for symmetric parts
+        #[argh(switch)]
+        mirror_prompt: bool,
+
+        /// run the multi-stage detection pipeline in this YAML file instead of -p prompts
+        #[argh(option)]
+        pipeline: Option<String>,
+
+        /// average each frame with the previous N-1 (odd N; default 1 = off) to smooth sensor noise;
+        /// moving objects ghost, stationary defects stay sharp
+        #[argh(option, default = "1")]
+        motion_blur_kernel: u32,
+
+        /// rescale every captured frame to WxH before inference/display (prompts use these coordinates)
+        #[argh(option)]
+        output_size: Option<OutputSize>,
+
+        /// with --output-size: keep aspect ratio and pad with black bars instead of stretching
+        #[argh(switch)]
+        letterbox: bool,
+
         /// confidence threshold (default: 0.5)
-        #[argh(option, default = "0.5")]
-        conf: f32,
+        #[argh(option)]
+        conf: Option<f32>,
 
         /// show mask
         #[argh(option, default = "false")]
         show_mask: bool,
 
-        /// run inference every N frames (set 0 to disable)
-        #[argh(option, default = "3")]
-        infer_every: u32,
+        /// skip inference and only draw the prompt boxes/points (for positioning prompts)
+        #[argh(switch)]
+        visualize_points_only: bool,
+
+        /// reuse the previous inference when a frame is byte-identical to the last inferred one
+        #[argh(switch)]
+        dedup_frames: bool,
+
+        /// annotation style: minimal, full, none (default: full)
+        #[argh(option, default = "AnnotationStyle::Full")]
+        annotation_style: AnnotationStyle,
+
+        /// style for prompts tagged `-p "text#grp=<name>"`, as
+        /// <name>=thickness:N,color:RRGGBB,masks:on|off (repeatable)
+        #[argh(option)]
+        group_style: Vec<GroupStyle>,
+
+        /// fade the last annotation out between inferences by this much per frame (0 = hold, 1 = instant)
+        #[argh(option, default = "0.0")]
+        cross_fade_alpha: f32,
+
+        /// keep detections missing from the latest inference drawn in the window for up to N ms,
+        /// fading out (0 = off); saved frames and records are not affected
+        #[argh(option, default = "0")]
+        hold_ms: u64,
+
+        /// run inference every N frames (default 3, set 0 to disable)
+        #[argh(option)]
+        infer_every: Option<u32>,
+
+        /// run inference at most this many times per second, timed on the wall clock instead of
+        /// counting frames (exclusive with --infer-every)
+        #[argh(option)]
+        infer_hz: Option<f32>,
+
+        /// show a per-prompt legend (swatch, count, rolling average) in the window; toggle with L
+        #[argh(switch)]
+        legend: bool,
+
+        /// start with the legend hidden even when the --session file has it shown
+        #[argh(switch)]
+        no_legend: bool,
+
+        /// draw the legend on a panel beside the frame instead of over it
+        #[argh(switch)]
+        legend_outside: bool,
+
+        /// suppress detections whose box center falls inside x,y,w,h (output pixels; repeatable)
+        #[argh(option)]
+        exclude_zone: Vec<Zone>,
+
+        /// black out everything outside the polygons in this JSON file before inference
+        /// (`{"polygons": [[[x, y], ...], ...]}`, output pixels); display and saves stay unmasked
+        #[argh(option)]
+        roi_file: Option<String>,
+
+        /// gamma applied to each frame before inference, as a lookup table; above 1 lifts dark
+        /// frames (default: 1)
+        #[argh(option, default = "1.0")]
+        gamma: f32,
+
+        /// brightness offset applied with --gamma, -1 to 1 of full scale (default: 0)
+        #[argh(option, default = "0.0")]
+        brightness: f32,
+
+        /// contrast factor around mid-gray applied with --gamma (default: 1)
+        #[argh(option, default = "1.0")]
+        contrast: f32,
+
+        /// stretch each frame's levels (0.5th to 99.5th percentile) to the full range before
+        /// --gamma/--contrast/--brightness
+        #[argh(switch)]
+        auto_levels: bool,
+
+        /// apply --gamma/--brightness/--contrast/--auto-levels only to what is shown and saved; the
+        /// model sees the raw frame
+        #[argh(switch)]
+        preprocess_display_only: bool,
+
+        /// apply --gamma/--brightness/--contrast/--auto-levels only to the model input; display and
+        /// saves stay raw
+        #[argh(switch)]
+        preprocess_infer_only: bool,
+
+        /// with --exclude-zone: suppress when at least this fraction of the box is covered instead
+        #[argh(option)]
+        zone_overlap: Option<f32>,
+
+        /// after a prompt is detected, drop its detections for the next N frames (default: 0 = off)
+        #[argh(option, default = "0")]
+        prompt_cooldown_frames: u64,
+
+        /// of two detections whose boxes overlap with IoU above this, drop the less confident one
+        /// (default: 0 = off)
+        #[argh(option, default = "0.0")]
+        deduplicate_masks_iou: f32,
+
+        /// mutually exclusive prompts, `<name>=<prompt>,<prompt>,...` (repeatable): of overlapping
+        /// detections from different prompts of a group only the most confident is kept
+        #[argh(option)]
+        exclusive_group: Vec<ExclusiveGroup>,
+
+        /// with --exclusive-group: box IoU above which detections compete (default: 0.5)
+        #[argh(option, default = "0.5")]
+        exclusive_iou: f32,
+
+        /// load exclude zones from this file (one x,y,w,h per line); runtime edits are saved back
+        #[argh(option)]
+        zones_file: Option<String>,
+
+        /// draw the exclude zones as hatched rectangles in the window
+        #[argh(switch)]
+        show_zones: bool,
+
+        /// log per-label detection stats every N seconds
+        #[argh(option)]
+        stats_interval: Option<f32>,
+
+        /// restore prompts, conf, annotation preset, legend, zones and view from this JSON file; W and the
+        /// end of the run save them back (flags given on the command line win)
+        #[argh(option)]
+        session: Option<String>,
+
+        /// disable ANSI colors in terminal output
+        #[argh(switch)]
+        no_color: bool,
 
         /// window scale (1.0 = native resolution)
         #[argh(option, default = "1.0")]
@@ -90,47 +335,316 @@ pub fn run() -> Result<()> {
         #[argh(option, default = "true")]
         trt_timing_cache: bool,
 
+        /// before opening the input, run the model on this image to warm up ONNX Runtime
+        #[argh(option)]
+        warmup_image: Option<String>,
+
+        /// with --warmup-image: number of warm-up forwards (default: 3)
+        #[argh(option, default = "3")]
+        warmup_runs: u32,
+
+        /// retry model init N times with backoff when the weight download fails (default: 0)
+        #[argh(option, default = "0")]
+        retry_init: u32,
+
+        /// model input size (square side; multiple of 14 in 336..=1008, default 1008). Lower is faster but less accurate
+        #[argh(option)]
+        imgsz: Option<u32>,
+
+        /// per-channel normalization mean as R,G,B (default: model preset)
+        #[argh(option)]
+        image_mean: Option<ChannelValues>,
+
+        /// per-channel normalization std as R,G,B (default: model preset)
+        #[argh(option)]
+        image_std: Option<ChannelValues>,
+
         /// save directory (default: ./runs/<model-spec>/)
         #[argh(option)]
         save_dir: Option<String>,
+
+        /// save into a new numbered <save-dir>/run-NNNN/ and name saved frames by frame index
+        #[argh(switch)]
+        sequential_names: bool,
+
+        /// on every inference also save one annotated frame per prompt with detections, showing only
+        /// that prompt's results, under <save-dir>/<prompt>/
+        #[argh(switch)]
+        save_dir_per_prompt: bool,
+
+        /// seed recorded in the run summary; nothing in the crate is random, so it only labels the run
+        #[argh(option)]
+        seed: Option<u64>,
+
+        /// write a run summary (configuration, versions, model hashes, metrics) as JSON to path
+        #[argh(option)]
+        summary_json: Option<String>,
+
+        /// at exit, diff this run's summary against a --summary-json file from another run
+        #[argh(option)]
+        compare: Option<String>,
+
+        /// save one output frame to the save directory every N seconds (wall time)
+        #[argh(option)]
+        snapshot_interval: Option<f32>,
+
+        /// save a frame to <save-dir>/interval/ every N seconds (wall time), detections or not
+        #[argh(option)]
+        save_on_interval_secs: Option<f32>,
+
+        /// with --save-on-interval-secs: save the annotated frame (true) or the raw frame (false)
+        #[argh(option, default = "true")]
+        save_on_interval_annotated: bool,
+
+        /// notify when a prompt label enters: bell, desktop or command
+        #[argh(option)]
+        notify: Option<NotifyKind>,
+
+        /// with --notify command: shell command to run; gets SAM3_PROMPT, SAM3_CONFIDENCE and
+        /// SAM3_SNAPSHOT in its environment
+        #[argh(option)]
+        notify_command: Option<String>,
+
+        /// with --notify: seconds before the same label can notify again (default: 30)
+        #[argh(option, default = "30.0")]
+        notify_cooldown_secs: f32,
+
+        /// with --notify command: commands allowed to run at once; further enters are skipped
+        /// (default: 2)
+        #[argh(option, default = "2")]
+        notify_max_running: usize,
+
+        /// with --notify command: kill a command after this many seconds (default: 10)
+        #[argh(option, default = "10.0")]
+        notify_timeout_secs: f32,
+
+        /// publish every output frame, uncompressed, to the shared-memory ring buffer
+        /// /dev/shm/<name> for a local reader (Linux)
+        #[argh(option)]
+        shm: Option<String>,
+
+        /// with --shm: remove an existing /dev/shm/<name> (e.g. left by a crashed run) instead of
+        /// failing
+        #[argh(switch)]
+        shm_replace: bool,
+
+        /// write a clip around every detection event (prompt enters .. leaves) into this directory
+        #[argh(option)]
+        save_event_clips: Option<String>,
+
+        /// ffmpeg log level (quiet, error, warning, info, verbose, ...; default error); ffmpeg's
+        /// messages are logged as they arrive
+        #[argh(option, default = "FfmpegLogLevel::Error")]
+        ffmpeg_loglevel: FfmpegLogLevel,
+
+        /// with --save-event-clips: seconds of pre-roll kept before an event (default: 5)
+        #[argh(option, default = "5.0")]
+        clip_pre_seconds: f32,
+
+        /// with --save-event-clips: seconds recorded after the last leave (default: 5)
+        #[argh(option, default = "5.0")]
+        clip_post_seconds: f32,
+
+        /// assemble the interval snapshots into a time-lapse video at path on exit
+        #[argh(option)]
+        timelapse_video: Option<String>,
+
+        /// write per-frame detections as JSON Lines to path
+        #[argh(option)]
+        json_out: Option<String>,
+
+        /// with --json-out: skip a frame's record when its detections match the last written record
+        /// (same labels, box IoU >= this, confidence within 0.05); the file then has gaps
+        #[argh(option)]
+        jsonl_dedup: Option<f32>,
+
+        /// geometry only: no window, JSON Lines to stdout (as --json-out), no annotation; logs go
+        /// to stderr
+        #[argh(switch)]
+        mask_to_json: bool,
+
+        /// log as JSON Lines (one object per event, with the current frame_idx)
+        #[argh(switch)]
+        log_json: bool,
+
+        /// masks in --json-out records: polygon, rle (uncompressed COCO RLE) or none (default)
+        #[argh(option, default = "MaskEncoding::None")]
+        mask_encoding: MaskEncoding,
+
+        /// dump a reconstruction of the vision encoder input of every inference as
+        /// `<frame>_vision-encoder-approx-input.npy` into this directory
+        #[argh(option)]
+        dump_tensors_dir: Option<String>,
+
+        /// before each inference, save every prompt's boxes (red) and points (green) drawn on the
+        /// frame as `<frame>_prompt_<i>.jpg` into this directory
+        #[argh(option)]
+        export_prompt_vis_dir: Option<String>,
+
+        /// file that prompts entered with P are appended to and recalled from (`!!`, `!N`, `!text`;
+        /// H lists it); default: ~/.cache/sam3/prompt_history
+        #[argh(option)]
+        prompt_history: Option<String>,
+
+        /// undistort every frame with this OpenCV calibration file (camera_matrix and
+        /// distortion_coefficients); toggle with `U`
+        #[argh(option)]
+        calibration: Option<String>,
+
+        /// write every inferred frame as an SVG overlay (boxes, polygons, labels) over a JPEG
+        /// background into this directory, for documentation figures
+        #[argh(option)]
+        save_svg: Option<String>,
+
+        /// at exit, write the top --best-k annotated frames per prompt (by confidence) here
+        #[argh(option)]
+        save_best: Option<String>,
+
+        /// with --save-best: frames to keep per prompt (default: 5)
+        #[argh(option, default = "5")]
+        best_k: usize,
+
+        /// save every detected instance as its own crop (plus crops.jsonl) into this directory
+        #[argh(option)]
+        save_crops: Option<String>,
+
+        /// with --save-crops: grow each crop by N pixels on every side (default: 0)
+        #[argh(option, default = "0")]
+        crop_pad: u32,
+
+        /// with --save-crops: put crops in one subdirectory per prompt label
+        #[argh(switch)]
+        split_by_prompt: bool,
+
+        /// unix socket for runtime commands (prompt, conf, infer-every, snapshot, ping)
+        #[argh(option)]
+        control_socket: Option<String>,
+
+        /// comma-separated camera names for a tiled multi-camera view (one model per camera)
+        #[argh(option)]
+        camera_names: Option<String>,
+
+        /// validate camera, model and save paths, then exit without running
+        #[argh(switch)]
+        check: bool,
+
+        /// print the JSON Schema of --json-out records (detections, COCO RLE masks) to stdout and
+        /// exit
+        #[argh(switch)]
+        print_json_schema: bool,
+    }
+
+    /// `MvErrorDefine.h` codes that [`HikCamera::open_info`] turns into specific errors.
+    const MV_E_ACCESS_DENIED: u32 = 0x8000_0203;
+    const MV_E_BUSY: u32 = 0x8000_0204;
+    const MV_E_NETER: u32 = 0x8000_0206;
+
+    /// Re-enumeration attempts, `FORCE_IP_POLL_MS` apart, waiting for a camera to come up at its
+    /// forced address.
+    const FORCE_IP_POLLS: u32 = 10;
+    const FORCE_IP_POLL_MS: u64 = 500;
+
+    /// One enumerated camera, as printed by `--list`.
+    struct DeviceEntry {
+        index: usize,
+        name: String,
+        serial: String,
+        model: String,
+        transport: &'static str,
+        /// `None` for USB3 cameras.
+        gige: Option<GigeAddress>,
     }
 
-    fn parse_prompts(raw: &[String]) -> Result<Vec<Sam3Prompt>> {
-        if raw.is_empty() {
-            anyhow::bail!("No prompt. Use -p \"text\" or -p \"visual;pos:x,y,w,h\"");
+    /// Current address of a GigE camera and of the host interface it was found on.
+    #[derive(Clone, Copy)]
+    struct GigeAddress {
+        ip: Ipv4Addr,
+        netmask: Ipv4Addr,
+        host_ip: Ipv4Addr,
+    }
+
+    impl GigeAddress {
+        /// Whether the host interface is inside the camera's subnet, i.e. whether the camera can
+        /// be opened without `--force-ip`; `None` when the SDK did not report the interface.
+        fn reachable(&self) -> Option<bool> {
+            if self.host_ip.is_unspecified() {
+                return None;
+            }
+            let mask = u32::from(self.netmask);
+            Some(u32::from(self.ip) & mask == u32::from(self.host_ip) & mask)
         }
-        raw.iter()
-            .map(|s| s.parse())
-            .collect::<std::result::Result<Vec<_>, _>>()
-            .map_err(|e| anyhow::anyhow!("{}", e))
     }
 
-    fn prompt_update_loop() -> Result<Option<Vec<Sam3Prompt>>> {
-        eprint!("New prompt(s) (split with `|`, empty keeps current): ");
-        std::io::stderr().flush().ok();
-        let mut line = String::new();
-        std::io::stdin()
-            .read_line(&mut line)
-            .context("failed to read prompt from stdin")?;
-        let line = line.trim();
-        if line.is_empty() {
-            return Ok(None);
+    /// NUL-terminated SDK string field.
+    fn c_field(bytes: &[u8]) -> String {
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+    }
+
+    impl DeviceEntry {
+        /// # Safety
+        /// `info` must come from a successful `MV_CC_EnumDevices`.
+        unsafe fn from_info(index: usize, info: &mvs::MV_CC_DEVICE_INFO) -> Self {
+            unsafe {
+                if info.nTLayerType == mvs::MV_GIGE_DEVICE {
+                    let gige = &info.SpecialInfo.stGigEInfo;
+                    Self {
+                        index,
+                        name: c_field(&gige.chUserDefinedName),
+                        serial: c_field(&gige.chSerialNumber),
+                        model: c_field(&gige.chModelName),
+                        transport: "GigE",
+                        gige: Some(GigeAddress {
+                            ip: Ipv4Addr::from(gige.nCurrentIp),
+                            netmask: Ipv4Addr::from(gige.nCurrentSubNetMask),
+                            host_ip: Ipv4Addr::from(gige.nNetExport),
+                        }),
+                    }
+                } else {
+                    let usb = &info.SpecialInfo.stUsb3VInfo;
+                    Self {
+                        index,
+                        name: c_field(&usb.chUserDefinedName),
+                        serial: c_field(&usb.chSerialNumber),
+                        model: c_field(&usb.chModelName),
+                        transport: "USB3",
+                        gige: None,
+                    }
+                }
+            }
+        }
+    }
+
+    /// `--packet-size`, `--auto-packet-size` and `--packet-delay`.
+    #[derive(Clone, Copy)]
+    struct GigeOptions {
+        packet_size: Option<u32>,
+        auto_packet_size: bool,
+        packet_delay: Option<u32>,
+    }
+
+    impl GigeOptions {
+        fn from_args(args: &Args) -> Result<Self> {
+            if args.auto_packet_size && args.packet_size.is_some() {
+                anyhow::bail!("--packet-size and --auto-packet-size are mutually exclusive");
+            }
+            Ok(Self {
+                packet_size: args.packet_size,
+                auto_packet_size: args.auto_packet_size,
+                packet_delay: args.packet_delay,
+            })
         }
-        let parts: Vec<String> = line
-            .split('|')
-            .map(str::trim)
-            .filter(|s| !s.is_empty())
-            .map(|s| s.to_string())
-            .collect();
-        Ok(Some(parse_prompts(&parts)?))
     }
 
     struct HikCamera {
         handle: *mut std::ffi::c_void,
+        /// Grab timeout for [`VideoSource::next_frame`].
+        timeout_ms: u32,
     }
 
     impl HikCamera {
-        fn enumerate_names() -> Result<Vec<String>> {
+        /// Enumerated GigE and USB3 devices; `pDeviceInfo` stays valid while the list is alive.
+        fn enum_devices() -> Result<mvs::MV_CC_DEVICE_INFO_LIST> {
             unsafe {
                 let mut device_list: mvs::MV_CC_DEVICE_INFO_LIST = std::mem::zeroed();
                 let status = mvs::MV_CC_EnumDevices(
@@ -140,88 +654,195 @@ pub fn run() -> Result<()> {
                 if status != mvs::MV_OK as i32 {
                     anyhow::bail!("MV_CC_EnumDevices failed: {}", status);
                 }
-                let mut names = Vec::new();
-                for i in 0..device_list.nDeviceNum as usize {
-                    let device_info = &*device_list.pDeviceInfo[i];
-                    let name_ptr = if device_info.nTLayerType == mvs::MV_GIGE_DEVICE {
-                        device_info
-                            .SpecialInfo
-                            .stGigEInfo
-                            .chUserDefinedName
-                            .as_ptr()
-                    } else {
-                        device_info
-                            .SpecialInfo
-                            .stUsb3VInfo
-                            .chUserDefinedName
-                            .as_ptr()
-                    };
-                    let name = CStr::from_ptr(name_ptr as *const i8)
-                        .to_string_lossy()
-                        .to_string();
-                    if !name.trim().is_empty() {
-                        names.push(name);
-                    }
-                }
-                Ok(names)
+                Ok(device_list)
+            }
+        }
+
+        /// Name, serial and model of every enumerated device, in enumeration order.
+        fn enumerate() -> Result<Vec<DeviceEntry>> {
+            Ok(Self::entries(&Self::enum_devices()?))
+        }
+
+        fn entries(device_list: &mvs::MV_CC_DEVICE_INFO_LIST) -> Vec<DeviceEntry> {
+            (0..device_list.nDeviceNum as usize)
+                .map(|i| unsafe { DeviceEntry::from_info(i, &*device_list.pDeviceInfo[i]) })
+                .collect()
+        }
+
+        /// Position of the device whose user-defined name is `name`, falling back to its serial
+        /// number (fresh cameras often have no name).
+        fn find_by_name(entries: &[DeviceEntry], name: &str) -> Result<usize> {
+            entries
+                .iter()
+                .position(|e| e.name == name)
+                .or_else(|| entries.iter().position(|e| e.serial == name))
+                .with_context(|| {
+                    format!(
+                        "Device not found: no camera named {name} or with that serial (see --list)"
+                    )
+                })
+        }
+
+        /// Position of the device a `--profile` selects.
+        fn find_by_selector(entries: &[DeviceEntry], selector: &CameraSelector) -> Result<usize> {
+            entries
+                .iter()
+                .position(|e| selector.matches(&e.name, &e.serial, e.gige.map(|g| g.ip)))
+                .with_context(|| {
+                    format!("Device not found: no camera with {selector} (see --list)")
+                })
+        }
+
+        /// `index` checked against the enumerated device count (`--camera-index`).
+        fn check_index(entries: &[DeviceEntry], index: usize) -> Result<usize> {
+            if index >= entries.len() {
+                anyhow::bail!(
+                    "Device not found: --camera-index {index} out of range, {} camera(s) found",
+                    entries.len()
+                );
             }
+            Ok(index)
+        }
+
+        /// Open the device named `name` (see [`HikCamera::find_by_name`]).
+        fn open_by_name(name: &str, timeout_ms: u32) -> Result<Self> {
+            let device_list = Self::enum_devices()?;
+            let index = Self::find_by_name(&Self::entries(&device_list), name)?;
+            Self::open_info(device_list.pDeviceInfo[index], timeout_ms)
+        }
+
+        /// Open the device a `--profile` selects.
+        fn open_by_selector(selector: &CameraSelector, timeout_ms: u32) -> Result<Self> {
+            let device_list = Self::enum_devices()?;
+            let index = Self::find_by_selector(&Self::entries(&device_list), selector)?;
+            Self::open_info(device_list.pDeviceInfo[index], timeout_ms)
         }
 
-        fn open_by_name(name: &str) -> Result<Self> {
+        /// Open the `index`-th enumerated device (`--camera-index`).
+        fn open_by_index(index: usize, timeout_ms: u32) -> Result<Self> {
+            let device_list = Self::enum_devices()?;
+            let index = Self::check_index(&Self::entries(&device_list), index)?;
+            Self::open_info(device_list.pDeviceInfo[index], timeout_ms)
+        }
+
+        /// `--force-ip`: give the GigE camera picked by `index` or `name` a new address, then wait
+        /// until it enumerates there. Returns its serial number, since enumeration order may change.
+        fn force_ip(
+            index: Option<usize>,
+            name: &str,
+            ip: Ipv4Addr,
+            netmask: Ipv4Addr,
+            gateway: Ipv4Addr,
+        ) -> Result<String> {
+            let device_list = Self::enum_devices()?;
+            let entries = Self::entries(&device_list);
+            let i = match index {
+                Some(index) => Self::check_index(&entries, index)?,
+                None => Self::find_by_name(&entries, name)?,
+            };
+            let entry = &entries[i];
+            let Some(current) = entry.gige else {
+                anyhow::bail!(
+                    "--force-ip only applies to GigE cameras, {name} is {}",
+                    entry.transport
+                );
+            };
+            if current.ip == ip {
+                tracing::info!("Camera {} is already at {ip}", entry.serial);
+                return Ok(entry.serial.clone());
+            }
             unsafe {
-                let mut device_list: mvs::MV_CC_DEVICE_INFO_LIST = std::mem::zeroed();
-                let status = mvs::MV_CC_EnumDevices(
-                    mvs::MV_GIGE_DEVICE | mvs::MV_USB_DEVICE,
-                    &mut device_list,
+                let mut handle: *mut std::ffi::c_void = ptr::null_mut();
+                let status = mvs::MV_CC_CreateHandle(&mut handle, device_list.pDeviceInfo[i]);
+                if status != mvs::MV_OK as i32 {
+                    anyhow::bail!("MV_CC_CreateHandle failed: {}", status);
+                }
+                let status = mvs::MV_GIGE_ForceIpEx(
+                    handle,
+                    u32::from(ip),
+                    u32::from(netmask),
+                    u32::from(gateway),
                 );
+                mvs::MV_CC_DestroyHandle(handle);
                 if status != mvs::MV_OK as i32 {
-                    anyhow::bail!("MV_CC_EnumDevices failed: {}", status);
+                    anyhow::bail!(
+                        "MV_GIGE_ForceIpEx({ip}/{netmask}) failed: {:#x}",
+                        status as u32
+                    );
                 }
+            }
+            tracing::info!(
+                "Forced camera {} from {}/{} to {ip}/{netmask} (gateway {gateway})",
+                entry.serial,
+                current.ip,
+                current.netmask
+            );
+            for _ in 0..FORCE_IP_POLLS {
+                std::thread::sleep(std::time::Duration::from_millis(FORCE_IP_POLL_MS));
+                let moved = Self::enumerate()?
+                    .iter()
+                    .any(|e| e.serial == entry.serial && e.gige.is_some_and(|g| g.ip == ip));
+                if moved {
+                    return Ok(entry.serial.clone());
+                }
+            }
+            anyhow::bail!(
+                "Device not found: camera {} did not re-enumerate at {ip} after --force-ip",
+                entry.serial
+            )
+        }
 
-                for i in 0..device_list.nDeviceNum as usize {
-                    let device_info = &*device_list.pDeviceInfo[i];
-                    let name_ptr = if device_info.nTLayerType == mvs::MV_GIGE_DEVICE {
-                        device_info
-                            .SpecialInfo
-                            .stGigEInfo
-                            .chUserDefinedName
-                            .as_ptr()
-                    } else {
-                        device_info
-                            .SpecialInfo
-                            .stUsb3VInfo
-                            .chUserDefinedName
-                            .as_ptr()
-                    };
-
-                    let found_name = CStr::from_ptr(name_ptr as *const i8).to_string_lossy();
-                    if found_name != name {
-                        continue;
-                    }
+        fn open_info(info: *mut mvs::MV_CC_DEVICE_INFO, timeout_ms: u32) -> Result<Self> {
+            unsafe {
+                let mut handle: *mut std::ffi::c_void = ptr::null_mut();
+                let status = mvs::MV_CC_CreateHandle(&mut handle, info);
+                if status != mvs::MV_OK as i32 {
+                    anyhow::bail!("MV_CC_CreateHandle failed: {}", status);
+                }
 
-                    let mut handle: *mut std::ffi::c_void = ptr::null_mut();
-                    let status = mvs::MV_CC_CreateHandle(&mut handle, device_list.pDeviceInfo[i]);
-                    if status != mvs::MV_OK as i32 {
-                        anyhow::bail!("MV_CC_CreateHandle failed: {}", status);
+                let status = mvs::MV_CC_OpenDevice(handle, mvs::MV_ACCESS_Exclusive, 0);
+                if status != mvs::MV_OK as i32 {
+                    mvs::MV_CC_DestroyHandle(handle);
+                    let entry = DeviceEntry::from_info(0, &*info);
+                    if let Some(addr) = entry.gige
+                        && addr.reachable() == Some(false)
+                    {
+                        anyhow::bail!(
+                            "Device unreachable (wrong subnet): camera {} is at {}/{} but was found on host interface {}; move it with --force-ip (MV_CC_OpenDevice: {:#x})",
+                            entry.serial,
+                            addr.ip,
+                            addr.netmask,
+                            addr.host_ip,
+                            status as u32
+                        );
                     }
-
-                    let status = mvs::MV_CC_OpenDevice(handle, mvs::MV_ACCESS_Exclusive, 0);
-                    if status != mvs::MV_OK as i32 {
-                        mvs::MV_CC_DestroyHandle(handle);
-                        anyhow::bail!("MV_CC_OpenDevice failed: {}", status);
+                    match status as u32 {
+                        MV_E_ACCESS_DENIED => anyhow::bail!(
+                            "Device already opened by another process: camera {} (MV_CC_OpenDevice: {:#x})",
+                            entry.serial,
+                            status as u32
+                        ),
+                        MV_E_BUSY | MV_E_NETER => anyhow::bail!(
+                            "Device unreachable: camera {} did not answer (MV_CC_OpenDevice: {:#x})",
+                            entry.serial,
+                            status as u32
+                        ),
+                        _ => anyhow::bail!("MV_CC_OpenDevice failed: {:#x}", status as u32),
                     }
-
-                    return Ok(HikCamera { handle });
                 }
 
-                anyhow::bail!("Camera not found by name: {}", name);
+                Ok(HikCamera { handle, timeout_ms })
             }
         }
 
         fn set_int(&self, key: &str, value: u32) -> Result<()> {
+            self.set_i64(key, i64::from(value))
+        }
+
+        fn set_i64(&self, key: &str, value: i64) -> Result<()> {
             unsafe {
                 let c_key = CString::new(key).context("key contains NUL")?;
-                let status = mvs::MV_CC_SetIntValueEx(self.handle, c_key.as_ptr(), value as i64);
+                let status = mvs::MV_CC_SetIntValueEx(self.handle, c_key.as_ptr(), value);
                 if status != mvs::MV_OK as i32 {
                     anyhow::bail!("MV_CC_SetIntValue({key}={value}) failed: {}", status);
                 }
@@ -229,6 +850,139 @@ pub fn run() -> Result<()> {
             }
         }
 
+        fn set_float(&self, key: &str, value: f32) -> Result<()> {
+            unsafe {
+                let c_key = CString::new(key).context("key contains NUL")?;
+                let status = mvs::MV_CC_SetFloatValue(self.handle, c_key.as_ptr(), value);
+                if status != mvs::MV_OK as i32 {
+                    anyhow::bail!("MV_CC_SetFloatValue({key}={value}) failed: {}", status);
+                }
+                Ok(())
+            }
+        }
+
+        /// Set an enumeration node by the symbolic name of its entry (e.g. `ExposureAuto=Off`).
+        fn set_enum_string(&self, key: &str, value: &str) -> Result<()> {
+            unsafe {
+                let c_key = CString::new(key).context("key contains NUL")?;
+                let c_value = CString::new(value).context("value contains NUL")?;
+                let status =
+                    mvs::MV_CC_SetEnumValueByString(self.handle, c_key.as_ptr(), c_value.as_ptr());
+                if status != mvs::MV_OK as i32 {
+                    anyhow::bail!(
+                        "MV_CC_SetEnumValueByString({key}={value}) failed: {}",
+                        status
+                    );
+                }
+                Ok(())
+            }
+        }
+
+        fn set_bool(&self, key: &str, value: bool) -> Result<()> {
+            unsafe {
+                let c_key = CString::new(key).context("key contains NUL")?;
+                let status = mvs::MV_CC_SetBoolValue(self.handle, c_key.as_ptr(), value);
+                if status != mvs::MV_OK as i32 {
+                    anyhow::bail!("MV_CC_SetBoolValue({key}={value}) failed: {}", status);
+                }
+                Ok(())
+            }
+        }
+
+        fn set_string(&self, key: &str, value: &str) -> Result<()> {
+            unsafe {
+                let c_key = CString::new(key).context("key contains NUL")?;
+                let c_value = CString::new(value).context("value contains NUL")?;
+                let status =
+                    mvs::MV_CC_SetStringValue(self.handle, c_key.as_ptr(), c_value.as_ptr());
+                if status != mvs::MV_OK as i32 {
+                    anyhow::bail!("MV_CC_SetStringValue({key}={value:?}) failed: {}", status);
+                }
+                Ok(())
+            }
+        }
+
+        /// Set the profile's nodes in order; `label` prefixes the log lines. A node that fails
+        /// aborts with `strict`, otherwise it is logged and skipped.
+        fn apply_profile(&self, label: &str, profile: &CameraProfile) -> Result<()> {
+            let mut failed = 0;
+            for node in &profile.nodes {
+                let key = node.name.as_str();
+                let set = match &node.value {
+                    NodeValue::Int(v) => self.set_i64(key, *v),
+                    NodeValue::Float(v) => self.set_float(key, *v as f32),
+                    NodeValue::Enum(v) => self.set_enum_string(key, v),
+                    NodeValue::Bool(v) => self.set_bool(key, *v),
+                    NodeValue::String(v) => self.set_string(key, v),
+                };
+                match set {
+                    Ok(()) => tracing::info!("{label}: {key} = {}", node.value),
+                    Err(e) if profile.strict => {
+                        return Err(e.context(format!("profile node {key} (strict profile)")));
+                    }
+                    Err(e) => {
+                        tracing::warn!("{label}: skipping profile node {key}: {e}");
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0 {
+                tracing::warn!(
+                    "{label}: {failed} of {} profile node(s) could not be set",
+                    profile.nodes.len()
+                );
+            }
+            Ok(())
+        }
+
+        /// Apply the GigE streaming settings after open; `label` prefixes the log lines.
+        fn configure_gige(&self, label: &str, opts: GigeOptions) -> Result<()> {
+            let packet_size = if opts.auto_packet_size {
+                let optimal = unsafe { mvs::MV_GIGE_GetOptimalPacketSize(self.handle) };
+                if optimal <= 0 {
+                    anyhow::bail!(
+                        "MV_GIGE_GetOptimalPacketSize failed: {optimal} (is this a GigE camera?)"
+                    );
+                }
+                Some(optimal as u32)
+            } else {
+                opts.packet_size
+            };
+            if let Some(size) = packet_size {
+                self.set_int("GevSCPSPacketSize", size)?;
+                tracing::info!(
+                    "{label}: GevSCPSPacketSize = {size} bytes{}",
+                    if opts.auto_packet_size {
+                        " (optimal)"
+                    } else {
+                        ""
+                    }
+                );
+            }
+            if let Some(delay) = opts.packet_delay {
+                self.set_int("GevSCPD", delay)?;
+                tracing::info!("{label}: GevSCPD = {delay} ns");
+            }
+            Ok(())
+        }
+
+        /// Turn I/O line `line` into a strobe output: a `duration_us` pulse with every exposure.
+        fn configure_strobe(&self, line: u8, duration_us: u64) -> Result<()> {
+            let duration =
+                i64::try_from(duration_us).context("--strobe-duration-us is out of range")?;
+            self.set_enum_string("LineSelector", &format!("Line{line}"))?;
+            self.set_enum_string("LineMode", "Output")?;
+            self.set_bool("StrobeEnable", true)?;
+            self.set_i64("StrobeDuration", duration)?;
+            Ok(())
+        }
+
+        /// Delay from exposure start to the strobe pulse of [`Self::configure_strobe`].
+        fn set_strobe_delay(&self, delay_us: u64) -> Result<()> {
+            let delay = i64::try_from(delay_us).context("--strobe-delay-us is out of range")?;
+            self.set_i64("StrobeDelay", delay)
+        }
+
         fn start_grabbing(&self) -> Result<()> {
             unsafe {
                 let status = mvs::MV_CC_StartGrabbing(self.handle);
@@ -257,10 +1011,41 @@ pub fn run() -> Result<()> {
             }
         }
 
+        /// Bytes needed for one frame.
+        ///
+        /// Some models only report a valid `PayloadSize` once grabbing has started (or report 0),
+        /// so it is cross-checked against `Width * Height * 3` for the expected RGB8Packed format.
+        fn frame_buffer_size(&self) -> Result<usize> {
+            let payload = self
+                .get_int_param("PayloadSize")
+                .ok()
+                .map(|v| v as usize)
+                .filter(|v| *v > 0);
+            let derived = match (self.get_int_param("Width"), self.get_int_param("Height")) {
+                (Ok(w), Ok(h)) => (w as usize)
+                    .checked_mul(h as usize)
+                    .and_then(|px| px.checked_mul(3)),
+                _ => None,
+            };
+            let size = match (payload, derived) {
+                (Some(p), Some(d)) => p.max(d),
+                (Some(p), None) => p,
+                (None, Some(d)) => d,
+                (None, None) => anyhow::bail!(
+                    "Cannot determine frame buffer size: PayloadSize, Width and Height are unavailable"
+                ),
+            };
+            if size < MIN_FRAME_BYTES {
+                anyhow::bail!(
+                    "Implausible frame buffer size: {size} bytes (PayloadSize={payload:?}, Width*Height*3={derived:?})"
+                );
+            }
+            Ok(size)
+        }
+
         fn get_frame_rgb8(&self, timeout_ms: u32) -> Result<(Vec<u8>, u32, u32)> {
+            let mut buffer = vec![0u8; self.frame_buffer_size()?];
             unsafe {
-                let payload_size = self.get_int_param("PayloadSize").unwrap_or(0);
-                let mut buffer = vec![0u8; payload_size.max(1) as usize];
                 let mut frame_info: mvs::MV_FRAME_OUT_INFO_EX = std::mem::zeroed();
                 let status = mvs::MV_CC_GetOneFrameTimeout(
                     self.handle,
@@ -301,6 +1086,28 @@ pub fn run() -> Result<()> {
         }
     }
 
+    impl VideoSource for HikCamera {
+        /// One RGB8Packed frame; a timeout or grab error is an `Err` the caller may retry.
+        fn next_frame(&mut self) -> Result<Option<usls::Image>> {
+            let (rgb, width, height) = self.get_frame_rgb8(self.timeout_ms)?;
+            let rgb8 = image::RgbImage::from_raw(width, height, rgb)
+                .context("failed to construct RgbImage")?;
+            Ok(Some(usls::Image::from(rgb8)))
+        }
+
+        fn size(&self) -> (u32, u32) {
+            (
+                self.get_int_param("Width").unwrap_or(0),
+                self.get_int_param("Height").unwrap_or(0),
+            )
+        }
+
+        /// Cameras run at whatever rate their persisted settings give; not queried.
+        fn fps(&self) -> Option<f32> {
+            None
+        }
+    }
+
     impl Drop for HikCamera {
         fn drop(&mut self) {
             unsafe {
@@ -310,6 +1117,257 @@ pub fn run() -> Result<()> {
         }
     }
 
+    /// The zoomed view and the shared-memory sink of a single-camera run.
+    struct CameraView {
+        viewport: ViewportState,
+        shm: Option<ShmSink>,
+    }
+
+    impl FrameHooks<HikCamera> for CameraView {
+        fn next_frame(&mut self, frames: &mut Capture<HikCamera>) -> Result<Next> {
+            Ok(match frames.next_frame() {
+                Ok(Some((frame_idx, img))) => Next::Frame(frame_idx, img),
+                Ok(None) => Next::End,
+                Err(e) => {
+                    tracing::warn!("Frame grab failed: {e}");
+                    Next::Retry
+                }
+            })
+        }
+
+        /// Zoomed in, inference and every output see only the visible region; prompt boxes are
+        /// in full-frame pixels.
+        fn view(
+            &mut self,
+            img: usls::Image,
+            prompts: &[Sam3Prompt],
+        ) -> Result<(usls::Image, Option<Vec<Sam3Prompt>>)> {
+            if self.viewport.is_full_frame() {
+                return Ok((img, None));
+            }
+            let (full_width, full_height) = (img.width(), img.height());
+            let rgb = to_rgb(&img)?;
+            Ok((
+                usls::Image::from(apply_viewport(&rgb, &self.viewport)),
+                Some(
+                    self.viewport
+                        .prompts_in_view(prompts, full_width, full_height),
+                ),
+            ))
+        }
+
+        fn write(&mut self, frame_idx: u64, display: &usls::Image) -> Result<()> {
+            if let Some(shm) = self.shm.as_mut() {
+                shm.write_frame(display, frame_idx, now_unix_ms())?;
+            }
+            Ok(())
+        }
+
+        fn handle_keys(
+            &mut self,
+            frames: &mut Capture<HikCamera>,
+            pressed: &dyn Fn(usls::Key) -> bool,
+        ) {
+            let before = self.viewport;
+            if pressed(usls::Key::Equal) || pressed(usls::Key::NumPadPlus) {
+                self.viewport.zoom_by(1.0);
+            }
+            if pressed(usls::Key::Minus) || pressed(usls::Key::NumPadMinus) {
+                self.viewport.zoom_by(-1.0);
+            }
+            for (key, dx, dy) in [
+                (usls::Key::Left, -1.0, 0.0),
+                (usls::Key::Right, 1.0, 0.0),
+                (usls::Key::Up, 0.0, -1.0),
+                (usls::Key::Down, 0.0, 1.0),
+            ] {
+                if pressed(key) {
+                    self.viewport.pan(dx, dy);
+                }
+            }
+            if pressed(usls::Key::Home) {
+                self.viewport = ViewportState::default();
+            }
+            if self.viewport != before {
+                tracing::info!(
+                    "Viewport: {:.2}x at ({:.2}, {:.2})",
+                    self.viewport.zoom,
+                    self.viewport.offset.0,
+                    self.viewport.offset.1
+                );
+            }
+
+            if pressed(usls::Key::U)
+                && let Some(undistort) = frames.undistort_mut()
+            {
+                undistort.enabled = !undistort.enabled;
+                tracing::info!(
+                    "Undistortion {}",
+                    if undistort.enabled { "on" } else { "off" }
+                );
+            }
+        }
+
+        fn viewport(&self) -> Option<&ViewportState> {
+            Some(&self.viewport)
+        }
+
+        fn finish(
+            self,
+            frames: Capture<HikCamera>,
+            _stopped: bool,
+        ) -> Result<Vec<(&'static str, serde_json::Value)>> {
+            frames.source().stop_grabbing();
+            Ok(Vec::new())
+        }
+    }
+
+    /// Per-camera settings handed to each `--camera-names` worker thread.
+    #[derive(Clone)]
+    struct WorkerOptions {
+        prompts: Vec<String>,
+        model: ModelOptions,
+        annotation_style: AnnotationStyle,
+        show_mask: bool,
+        schedule: InferSchedule,
+        timeout_ms: u32,
+        gige: GigeOptions,
+        width: Option<u32>,
+        height: Option<u32>,
+        output_size: Option<OutputSize>,
+        letterbox: bool,
+        motion_blur_kernel: u32,
+    }
+
+    /// Consecutive grab failures after which a camera is treated as disconnected.
+    const MAX_GRAB_FAILURES: u32 = 20;
+
+    /// Grab+inference loop for one camera of `--camera-names`; returns when the run stops.
+    fn camera_worker(name: &str, opts: &WorkerOptions, tile: &TileSender) -> Result<()> {
+        let prompts = parse_prompts(&opts.prompts)?;
+        tile.status("loading model");
+        let mut model = opts.model.load_model(opts.model.build_config()?)?;
+        let annotator = build_annotator(opts.annotation_style, opts.show_mask);
+        let mut schedule = opts.schedule.clone();
+
+        tile.status("opening camera");
+        let camera = HikCamera::open_by_name(name, opts.timeout_ms)?;
+        camera.configure_gige(name, opts.gige)?;
+        if let Some(width) = opts.width
+            && let Err(e) = camera.set_int("Width", width)
+        {
+            tracing::warn!("{name}: failed to set Width={width}: {e}");
+        }
+        if let Some(height) = opts.height
+            && let Err(e) = camera.set_int("Height", height)
+        {
+            tracing::warn!("{name}: failed to set Height={height}: {e}");
+        }
+        camera.start_grabbing()?;
+
+        let mut frames = Capture::new(camera)
+            .with_output_size(opts.output_size, opts.letterbox)
+            .with_temporal_average(temporal_average(opts.motion_blur_kernel)?);
+        let mut failures = 0;
+        let mut last_annotated: Option<usls::Image> = None;
+        let result = loop {
+            if tile.is_stopped() {
+                break Ok(());
+            }
+            let (frame_idx, img) = match frames.next_frame() {
+                Ok(Some(frame)) => {
+                    failures = 0;
+                    frame
+                }
+                Ok(None) => break Ok(()),
+                Err(e) => {
+                    failures += 1;
+                    if failures >= MAX_GRAB_FAILURES {
+                        break Err(e.context(format!("{failures} consecutive grab failures")));
+                    }
+                    tracing::warn!("{name}: frame grab failed: {e}");
+                    continue;
+                }
+            };
+            let _frame_span = tracing::info_span!("frame", camera = name, frame_idx).entered();
+            let run_infer = schedule.due(frame_idx);
+            let display = if run_infer {
+                let batch = vec![img.clone()];
+                let ys = match model.forward(&batch, &prompts) {
+                    Ok(ys) => ys,
+                    Err(e) => break Err(e),
+                };
+                let annotated = match &annotator {
+                    Some(annotator) => match annotate_frame(annotator, &img, &ys[0], &prompts) {
+                        Ok(a) => a,
+                        Err(e) => break Err(e),
+                    },
+                    None => img,
+                };
+                last_annotated = Some(annotated.clone());
+                annotated
+            } else if annotator.is_some() {
+                last_annotated.clone().unwrap_or(img)
+            } else {
+                img
+            };
+            tile.send_frame(display);
+        };
+        frames.source().stop_grabbing();
+        result
+    }
+
+    fn run_multi(args: &Args, names: Vec<String>) -> Result<()> {
+        let (device, _) = auto_select_device_and_ep(
+            args.device.as_deref(),
+            ep_flag(args.ep, args.execution_provider)?,
+        )?;
+        let opts = WorkerOptions {
+            prompts: args.prompt.clone(),
+            model: ModelOptions {
+                task: args.task.clone(),
+                device,
+                dtype: args.dtype.clone(),
+                conf: args.conf.unwrap_or(DEFAULT_CONF),
+                trt_fp16: args.trt_fp16,
+                trt_engine_cache: args.trt_engine_cache,
+                trt_timing_cache: args.trt_timing_cache,
+                imgsz: args.imgsz,
+                image_mean: args.image_mean,
+                image_std: args.image_std,
+                retry_init: args.retry_init,
+            },
+            annotation_style: args.annotation_style,
+            show_mask: args.show_mask,
+            schedule: InferSchedule::from_args(args.infer_every, args.infer_hz)?,
+            timeout_ms: args.timeout_ms,
+            gige: GigeOptions::from_args(args)?,
+            width: args.width,
+            height: args.height,
+            output_size: args.output_size,
+            letterbox: args.letterbox,
+            motion_blur_kernel: args.motion_blur_kernel,
+        };
+        // Fail fast on bad prompts instead of once per camera thread.
+        parse_prompts(&opts.prompts)?;
+
+        let save_base = match &args.save_dir {
+            Some(dir) => std::path::PathBuf::from(dir),
+            None => default_save_base("hikvision-multi", true)?,
+        };
+        tracing::info!("Cameras: {}", names.join(", "));
+        tracing::info!("Controls: ESC/Q quit, S save composite");
+
+        let stop = StopSignal::install()?;
+        let mut runner = MultiCameraRunner::new(names, args.output_size.unwrap_or(DEFAULT_TILE))
+            .with_stop_flag(stop.flag());
+        runner.spawn_workers(move |name, tile| camera_worker(name, &opts, tile))?;
+        let mut viewer = Viewer::new("sam3-hikvision").with_window_scale(args.window_scale);
+        runner.run(&mut viewer, &save_base)?;
+        usls::perf(false);
+        Ok(())
+    }
+
     fn initialize_sdk() -> Result<()> {
         let status = unsafe { mvs::MV_CC_Initialize() };
         if status != mvs::MV_OK as i32 {
@@ -318,137 +1376,472 @@ pub fn run() -> Result<()> {
         Ok(())
     }
 
+    if handle_version_flag("hikvision-sam3") {
+        return Ok(());
+    }
+    let mut args: Args = argh::from_env();
+    if args.print_json_schema {
+        println!("{}", serde_json::to_string_pretty(&json_schema())?);
+        return Ok(());
+    }
     initialize_sdk()?;
-
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_timer(tracing_subscriber::fmt::time::ChronoLocal::rfc_3339())
-        .init();
-
-    let args: Args = argh::from_env();
+    init_logging(args.log_json, log_writer(args.mask_to_json));
+    let session = SessionFile::open(args.session.as_deref())?;
+    session.restored().restore_args(SessionArgs {
+        prompt: &mut args.prompt,
+        conf: &mut args.conf,
+        legend: &mut args.legend,
+        no_legend: args.no_legend,
+        exclude_zone: &mut args.exclude_zone,
+        zones_file: args.zones_file.is_some(),
+        pipeline: args.pipeline.is_some(),
+    })?;
+    // As given, `#grp=` tags included, for the session file.
+    let session_prompts = if args.pipeline.is_some() {
+        // The prompts come from the pipeline file; keep the ones saved in the session.
+        session.restored().prompts.clone()
+    } else {
+        args.prompt.clone()
+    };
+    let (prompt, mut group_tags) = split_group_tags(&args.prompt);
+    args.prompt = prompt;
+
+    check_mask_to_json(
+        args.mask_to_json,
+        &[
+            ("--json-out", args.json_out.is_some()),
+            ("--visualize-points-only", args.visualize_points_only),
+            ("--camera-names", args.camera_names.is_some()),
+        ],
+    )?;
 
     if args.list {
-        for name in HikCamera::enumerate_names()? {
-            println!("{name}");
+        let devices = HikCamera::enumerate()?;
+        if devices.is_empty() {
+            eprintln!("No GigE/USB3 cameras found");
+        }
+        println!(
+            "{:<5} {:<6} {:<20} {:<20} {:<16} {:<16} {:<10} MODEL",
+            "INDEX", "TYPE", "NAME", "SERIAL", "IP", "NETMASK", "REACHABLE"
+        );
+        for d in &devices {
+            let name = if d.name.is_empty() { "-" } else { &d.name };
+            let (ip, netmask, reachable) = match d.gige {
+                Some(addr) => (
+                    addr.ip.to_string(),
+                    addr.netmask.to_string(),
+                    match addr.reachable() {
+                        Some(true) => "yes",
+                        Some(false) => "no",
+                        None => "?",
+                    },
+                ),
+                None => ("-".to_string(), "-".to_string(), "-"),
+            };
+            println!(
+                "{:<5} {:<6} {:<20} {:<20} {:<16} {:<16} {:<10} {}",
+                d.index, d.transport, name, d.serial, ip, netmask, reachable, d.model
+            );
+        }
+        if let Some(dir) = &args.profile_dir {
+            println!();
+            println!("{:<32} {:<32} CAMERA", "PROFILE", "SELECTS");
+            for (path, profile) in load_dir(std::path::Path::new(dir))? {
+                let file = path.file_name().unwrap_or_default().to_string_lossy();
+                let matched: Vec<String> = devices
+                    .iter()
+                    .filter(|d| {
+                        profile
+                            .camera
+                            .matches(&d.name, &d.serial, d.gige.map(|g| g.ip))
+                    })
+                    .map(|d| format!("#{}", d.index))
+                    .collect();
+                let matched = if matched.is_empty() {
+                    "- (not connected)".to_string()
+                } else {
+                    matched.join(", ")
+                };
+                println!("{file:<32} {:<32} {matched}", profile.camera.to_string());
+            }
         }
         return Ok(());
     }
+    let gige = GigeOptions::from_args(&args)?;
+    if args.strobe_line.is_none() {
+        if args.strobe_duration_us.is_some() {
+            anyhow::bail!("--strobe-duration-us needs --strobe-line");
+        }
+        if args.strobe_delay_us.is_some() {
+            anyhow::bail!("--strobe-delay-us needs --strobe-line");
+        }
+    }
 
-    let camera_name = args
-        .camera_name
-        .clone()
-        .context("Missing --camera-name (use --list to see available names)")?;
-
-    let mut prompts = parse_prompts(&args.prompt)?;
+    if let Some(names) = &args.camera_names {
+        if args.check {
+            anyhow::bail!("--check is not supported with --camera-names");
+        }
+        let names: Vec<String> = names
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        if names.is_empty() {
+            anyhow::bail!("--camera-names needs at least one camera name");
+        }
+        if args.json_out.is_some() {
+            anyhow::bail!("--json-out is not supported with --camera-names");
+        }
+        if args.pipeline.is_some() {
+            anyhow::bail!("--pipeline is not supported with --camera-names");
+        }
+        if args.session.is_some() {
+            anyhow::bail!("--session is not supported with --camera-names");
+        }
+        if args.roi_file.is_some() {
+            anyhow::bail!("--roi-file is not supported with --camera-names");
+        }
+        if args.gamma != 1.0 || args.brightness != 0.0 || args.contrast != 1.0 || args.auto_levels {
+            anyhow::bail!(
+                "--gamma/--brightness/--contrast/--auto-levels are not supported with --camera-names"
+            );
+        }
+        if args.mirror_prompt {
+            anyhow::bail!("--mirror-prompt is not supported with --camera-names");
+        }
+        if args.shm.is_some() {
+            anyhow::bail!("--shm is not supported with --camera-names");
+        }
+        if args.profile.is_some() {
+            anyhow::bail!("--profile is not supported with --camera-names");
+        }
+        if args.strobe_line.is_some() {
+            anyhow::bail!("--strobe-line is not supported with --camera-names");
+        }
+        if args.force_ip.is_some() {
+            anyhow::bail!("--force-ip is not supported with --camera-names");
+        }
+        return run_multi(&args, names);
+    }
 
-    let config = match args.task.parse()? {
-        Task::Sam3Image => Config::sam3_image(),
-        Task::Sam3Tracker => Config::sam3_tracker(),
-        _ => anyhow::bail!(
-            "Sam3 Task now only support: {}, {}",
-            Task::Sam3Image,
-            Task::Sam3Tracker
+    if args.camera_name.is_some() && args.camera_index.is_some() {
+        anyhow::bail!("--camera-name and --camera-index are mutually exclusive");
+    }
+    let profile = match &args.profile {
+        Some(path) => Some(CameraProfile::load(std::path::Path::new(path))?),
+        None => None,
+    };
+    // The profile's camera, unless --camera-name/--camera-index pick another one.
+    let selector = profile
+        .as_ref()
+        .map(|p| &p.camera)
+        .filter(|_| args.camera_name.is_none() && args.camera_index.is_none());
+    let camera_label = match (&args.camera_name, args.camera_index, selector) {
+        (Some(name), _, _) => name.clone(),
+        (None, Some(index), _) => format!("#{index}"),
+        (None, None, Some(CameraSelector::Name(name) | CameraSelector::Serial(name))) => {
+            name.clone()
+        }
+        (None, None, Some(selector)) => selector.to_string(),
+        (None, None, None) => anyhow::bail!(
+            "Missing --camera-name, --camera-index or --profile (use --list to see available cameras)"
         ),
+    };
+    if args.force_ip.is_some() && matches!(selector, Some(CameraSelector::Ip(_))) {
+        anyhow::bail!(
+            "--force-ip cannot find a camera by the IP it is about to change; select it with --camera-name or --camera-index"
+        );
     }
-    .with_tensorrt_fp16_all(args.trt_fp16)
-    .with_tensorrt_engine_cache_all(args.trt_engine_cache)
-    .with_tensorrt_timing_cache_all(args.trt_timing_cache)
-    .with_dtype_all(args.dtype.parse()?)
-    .with_class_confs(&[args.conf])
-    .with_device_all(args.device.parse()?)
-    .commit()?;
-
-    let mut model = SAM3::new(config)?;
-    let annotator = Annotator::default()
-        .with_mask_style(
-            usls::MaskStyle::default()
-                .with_visible(args.show_mask)
-                .with_cutout(true)
-                .with_draw_polygon_largest(true),
-        )
-        .with_polygon_style(usls::PolygonStyle::default().with_thickness(2));
-
-    let mut viewer = Viewer::new("sam3-hikvision").with_window_scale(args.window_scale);
-
-    let camera = HikCamera::open_by_name(&camera_name)?;
-
-    // Use the camera's persisted/default settings; ensure output is RGB8Packed.
 
-    if let Some(width) = args.width {
-        if let Err(e) = camera.set_int("Width", width) {
-            tracing::warn!("Failed to set Width={width}: {e}");
+    let mut report = CheckReport::new(args.check);
+    let pipeline = match &args.pipeline {
+        Some(path) => Some(report.record("pipeline", Pipeline::load(std::path::Path::new(path)))?),
+        None => None,
+    };
+    let mut prompts = match &pipeline {
+        Some(_) if !args.prompt.is_empty() => {
+            anyhow::bail!("--pipeline takes its prompts from the file; drop -p")
         }
-    }
-    if let Some(height) = args.height {
-        if let Err(e) = camera.set_int("Height", height) {
-            tracing::warn!("Failed to set Height={height}: {e}");
+        Some(_) if args.mask_encoding != MaskEncoding::None => {
+            anyhow::bail!(
+                "--mask-encoding does not apply to --pipeline (its stages keep polygons, not masks)"
+            )
         }
+        Some(pipeline) => pipeline.prompts(),
+        None => report.record("prompts", parse_prompts(&args.prompt))?,
+    };
+    report.detail(format!("{} prompt(s)", prompts.len()));
+
+    let (device, _) = auto_select_device_and_ep(
+        args.device.as_deref(),
+        ep_flag(args.ep, args.execution_provider)?,
+    )?;
+    let model_opts = ModelOptions {
+        task: args.task.clone(),
+        device,
+        dtype: args.dtype.clone(),
+        conf: args.conf.unwrap_or(DEFAULT_CONF),
+        trt_fp16: args.trt_fp16,
+        trt_engine_cache: args.trt_engine_cache,
+        trt_timing_cache: args.trt_timing_cache,
+        imgsz: args.imgsz,
+        image_mean: args.image_mean,
+        image_std: args.image_std,
+        retry_init: args.retry_init,
+    };
+    let config = report.record("config", model_opts.build_config())?;
+    report.detail(format!(
+        "{} on {} ({})",
+        args.task, model_opts.device, args.dtype
+    ));
+
+    let mut model = report.record("model", model_opts.load_model(config))?;
+    report.detail(model.spec().to_string());
+    if let Some(path) = &args.warmup_image
+        && !args.check
+    {
+        warmup_with_image(
+            &mut model,
+            std::path::Path::new(path),
+            &prompts,
+            args.warmup_runs,
+        )?;
     }
-
-    camera.start_grabbing()?;
-
-    let save_base = match args.save_dir {
-        Some(dir) => std::path::PathBuf::from(dir),
-        None => usls::Dir::Current.base_dir_with_subs(&["runs", model.spec()])?,
+    let viewer = (!args.mask_to_json)
+        .then(|| Viewer::new("sam3-hikvision").with_window_scale(args.window_scale));
+
+    let forced_serial = match args.force_ip {
+        Some(ip) => Some(report.record(
+            "force-ip",
+            HikCamera::force_ip(
+                args.camera_index,
+                &camera_label,
+                ip,
+                args.force_netmask,
+                args.force_gateway,
+            ),
+        )?),
+        None => None,
     };
+    let camera = report.record(
+        "camera",
+        match (&forced_serial, args.camera_index) {
+            (Some(serial), _) => HikCamera::open_by_name(serial, args.timeout_ms),
+            (None, Some(index)) => HikCamera::open_by_index(index, args.timeout_ms),
+            (None, None) => match selector {
+                Some(selector) => HikCamera::open_by_selector(selector, args.timeout_ms),
+                None => HikCamera::open_by_name(&camera_label, args.timeout_ms),
+            },
+        },
+    )?;
+    report.detail(camera_label.clone());
+    report.record("gige", camera.configure_gige(&camera_label, gige))?;
+    if let Some(profile) = &profile {
+        report.record("profile", camera.apply_profile(&camera_label, profile))?;
+    }
 
-    tracing::info!("Controls: ESC/Q quit, P update prompt, S save frame");
+    // Use the camera's persisted/default settings; ensure output is RGB8Packed.
+
+    if let Some(width) = args.width
+        && let Err(e) = camera.set_int("Width", width)
+    {
+        tracing::warn!("Failed to set Width={width}: {e}");
+    }
+    if let Some(height) = args.height
+        && let Err(e) = camera.set_int("Height", height)
+    {
+        tracing::warn!("Failed to set Height={height}: {e}");
+    }
 
-    let mut last_displayed: Option<usls::Image> = None;
-    let mut frame_idx: u64 = 0;
-    loop {
-        if viewer.is_window_exist_and_closed() {
-            break;
+    if let Some(line) = args.strobe_line {
+        let duration_us = args
+            .strobe_duration_us
+            .unwrap_or(DEFAULT_STROBE_DURATION_US);
+        report.record("strobe", camera.configure_strobe(line, duration_us))?;
+        if let Some(delay) = args.strobe_delay_us {
+            report.record("strobe-delay", camera.set_strobe_delay(delay))?;
         }
+        tracing::info!(
+            "{camera_label}: strobe on Line{line}, {duration_us} us{}",
+            args.strobe_delay_us
+                .map_or(String::new(), |d| format!(" after {d} us"))
+        );
+    }
 
-        let (rgb, width, height) = match camera.get_frame_rgb8(args.timeout_ms) {
-            Ok(x) => x,
-            Err(e) => {
-                tracing::warn!("Frame grab failed: {e}");
-                continue;
-            }
-        };
+    report.record("grab-start", camera.start_grabbing())?;
 
-        let rgb8 = image::RgbImage::from_raw(width, height, rgb)
-            .context("failed to construct RgbImage")?;
-        let img = usls::Image::from(rgb8);
-
-        frame_idx += 1;
-        let run_infer = args.infer_every > 0 && frame_idx.is_multiple_of(args.infer_every as u64);
-        let display = if run_infer {
-            let batch = vec![img.clone()];
-            let ys = model.forward(&batch, &prompts)?;
-
-            let mut annotated = annotator.annotate(&img, &ys[0])?;
-            for prompt in &prompts {
-                annotated = annotator.annotate(&annotated, &prompt.boxes)?;
-                annotated = annotator.annotate(&annotated, &prompt.points)?;
-            }
-            last_displayed = Some(annotated.clone());
-            annotated
-        } else {
-            last_displayed.clone().unwrap_or(img)
-        };
+    if report.enabled() {
+        let grabbed = camera.get_frame_rgb8(args.timeout_ms);
+        camera.stop_grabbing();
+        drop(camera);
+        let (_, width, height) = report.record("capture", grabbed)?;
+        report.detail(format!("{width}x{height} RGB8Packed"));
 
-        viewer.imshow(&display)?;
+        report.record("forward", dummy_forward(&mut model, &prompts, width, height))?;
 
-        if viewer.is_key_pressed(usls::Key::Escape) || viewer.is_key_pressed(usls::Key::Q) {
-            break;
-        }
+        let save_base = match &args.save_dir {
+            Some(dir) => std::path::PathBuf::from(dir),
+            None => default_save_base(model.spec(), false)?,
+        };
+        report.record("save-dir", check_dir_writable(&save_base))?;
+        report.detail(save_base.display().to_string());
 
-        if viewer.is_key_pressed(usls::Key::S) && let Some(img) = &last_displayed {
-            let path = save_base.join(format!("{}.jpg", usls::timestamp(None)));
-            img.save(&path)?;
-            tracing::info!("Saved: {}", path.display());
-        }
+        report.print();
+        return Ok(());
+    }
 
-        if viewer.is_key_pressed(usls::Key::P) && let Some(new_prompts) = prompt_update_loop()? {
-            prompts = new_prompts;
-            tracing::info!("Updated prompts: {:?}", prompts);
-        }
+    let save_base = match args.save_dir {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => default_save_base(model.spec(), true)?,
+    };
+    let save_base = if args.sequential_names {
+        next_run_dir(&save_base)?
+    } else {
+        save_base
+    };
+    let undistort = match &args.calibration {
+        Some(path) => Some(Undistorter::new(Calibration::load(std::path::Path::new(
+            path,
+        ))?)),
+        None => None,
+    };
+    let frames = Capture::new(camera)
+        .with_undistort(undistort)
+        .with_output_size(args.output_size, args.letterbox)
+        .with_temporal_average(temporal_average(args.motion_blur_kernel)?)
+        .with_tone(ToneAdjust::new(
+            ToneParams {
+                gamma: args.gamma,
+                brightness: args.brightness,
+                contrast: args.contrast,
+                auto_levels: args.auto_levels,
+            },
+            ToneTarget::from_flags(args.preprocess_display_only, args.preprocess_infer_only)?,
+        )?);
+    // Prompts are in captured-frame pixels (the zoomed view maps them per frame), so they are
+    // mirrored across the width of the frames `Capture` delivers.
+    let mirror_width = args.mirror_prompt.then(|| frames.size().0);
+    anyhow::ensure!(
+        mirror_width != Some(0),
+        "--mirror-prompt needs the frame width"
+    );
+    if mirror_width.is_some() {
+        anyhow::ensure!(
+            pipeline.is_none(),
+            "--mirror-prompt is not supported with --pipeline"
+        );
+        let (raw, tags) = (
+            std::mem::take(&mut args.prompt),
+            std::mem::take(&mut group_tags),
+        );
+        (args.prompt, group_tags, prompts) = with_mirrored(mirror_width, raw, tags, prompts);
+        tracing::info!(
+            "--mirror-prompt: {} prompt(s) with the mirrored boxes",
+            prompts.len()
+        );
+    }
+    if let Some(size) = args.output_size {
+        tracing::info!(
+            "Output size: {}x{} ({})",
+            size.width,
+            size.height,
+            if args.letterbox {
+                "letterbox"
+            } else {
+                "stretch"
+            }
+        );
+        warn_prompts_outside(&prompts, size.width, size.height);
+    }
+    let shm = args
+        .shm
+        .as_deref()
+        .map(|name| ShmSink::new(name, args.shm_replace))
+        .transpose()?;
+
+    if viewer.is_some() {
+        tracing::info!(
+            "Controls: ESC/Q quit, P update prompt, S save frame, L legend, M cycle annotation style, H prompt history, W save session, +/- zoom, arrows pan, Home full frame"
+        );
     }
 
-    camera.stop_grabbing();
-    usls::perf(false);
-    Ok(())
+    let json_out_path = if args.mask_to_json {
+        Some(MASK_TO_JSON_PATH)
+    } else {
+        args.json_out.as_deref()
+    };
+    let viewport = session.restored().viewport().unwrap_or_default();
+    let frame_loop = FrameLoop::new(LoopArgs {
+        model,
+        model_opts,
+        pipeline,
+        prompts,
+        session_prompts,
+        labels: args.prompt.clone(),
+        group_tags,
+        mirror_width,
+        session,
+        viewer,
+        frame_size: frames.size(),
+        save_base,
+        json_out: json_out_path.map(std::path::Path::new),
+        task: &args.task,
+        mask_to_json: args.mask_to_json,
+        annotation_style: args.annotation_style,
+        show_mask: args.show_mask,
+        group_style: &args.group_style,
+        legend: args.legend,
+        legend_outside: args.legend_outside,
+        hold_ms: args.hold_ms,
+        prompt_history: args.prompt_history.as_deref(),
+        visualize_points_only: args.visualize_points_only,
+        cross_fade_alpha: args.cross_fade_alpha,
+        show_zones: args.show_zones,
+        sequential_names: args.sequential_names,
+        jsonl_dedup: args.jsonl_dedup,
+        mask_encoding: args.mask_encoding,
+        dedup_frames: args.dedup_frames,
+        infer_every: args.infer_every,
+        infer_hz: args.infer_hz,
+        roi_file: args.roi_file.as_deref(),
+        export_prompt_vis_dir: args.export_prompt_vis_dir.as_deref(),
+        dump_tensors_dir: args.dump_tensors_dir.as_deref(),
+        control_socket: args.control_socket.as_deref(),
+        exclude_zone: args.exclude_zone.clone(),
+        zone_overlap: args.zone_overlap,
+        zones_file: args.zones_file.as_deref(),
+        deduplicate_masks_iou: args.deduplicate_masks_iou,
+        exclusive_group: args.exclusive_group.clone(),
+        exclusive_iou: args.exclusive_iou,
+        prompt_cooldown_frames: args.prompt_cooldown_frames,
+        stats_interval: args.stats_interval,
+        no_color: args.no_color,
+        save_best: args.save_best.as_deref(),
+        best_k: args.best_k,
+        save_crops: args.save_crops.as_deref(),
+        crop_pad: args.crop_pad,
+        split_by_prompt: args.split_by_prompt,
+        save_svg: args.save_svg.as_deref(),
+        save_dir_per_prompt: args.save_dir_per_prompt,
+        sync_annotate: false,
+        snapshot_interval: args.snapshot_interval,
+        timelapse_video: args.timelapse_video.as_deref(),
+        save_on_interval_secs: args.save_on_interval_secs,
+        save_on_interval_annotated: args.save_on_interval_annotated,
+        save_event_clips: args.save_event_clips.as_deref(),
+        clip_pre_seconds: args.clip_pre_seconds,
+        clip_post_seconds: args.clip_post_seconds,
+        ffmpeg_loglevel: args.ffmpeg_loglevel,
+        notify: args.notify,
+        notify_command: args.notify_command.as_deref(),
+        notify_cooldown_secs: args.notify_cooldown_secs,
+        notify_max_running: args.notify_max_running,
+        notify_timeout_secs: args.notify_timeout_secs,
+        watchdog_secs: args.watchdog_secs,
+        summary_json: args.summary_json.as_deref(),
+        compare: args.compare.as_deref(),
+        seed: args.seed,
+    })?;
+    frame_loop.run(frames, CameraView { viewport, shm })
 }
@@ -0,0 +1,191 @@
+//! Prompt history for the `P` key: every accepted prompt line is appended to a file, `H` lists
+//! the recent ones and the prompt accepts shell-style recalls (`!!`, `!3`, `!person`).
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Entries kept in memory and recallable; the file is cut back to these once it holds twice as
+/// many lines.
+const MAX_ENTRIES: usize = 500;
+const LISTED: usize = 20;
+
+pub struct PromptHistory {
+    /// `None` when no cache directory could be found; history then lasts for the session only.
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+    /// Lines in the history file, blank ones included.
+    file_lines: usize,
+}
+
+impl PromptHistory {
+    /// `--prompt-history`, or `$XDG_CACHE_HOME/sam3/prompt_history` (`~/.cache` without it).
+    /// A missing file starts an empty history.
+    pub fn open(path: Option<&Path>) -> Self {
+        let path = path.map(Path::to_path_buf).or_else(default_path);
+        let text = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => {
+                    tracing::warn!("Failed to read prompt history {}: {e}", path.display());
+                    String::new()
+                }
+            },
+            None => String::new(),
+        };
+        let entries = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_string)
+            .collect();
+        let mut history = Self {
+            path,
+            entries,
+            file_lines: text.lines().count(),
+        };
+        history.trim();
+        history
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Expand a recall: `!!` is the last entry, `!N` entry `N` as numbered by [`Self::print`],
+    /// `!text` the latest entry starting with `text`. Anything else is returned unchanged;
+    /// `None` when the recall matches nothing.
+    pub fn expand(&self, line: &str) -> Option<String> {
+        let Some(recall) = line.strip_prefix('!') else {
+            return Some(line.to_string());
+        };
+        let found = if recall == "!" {
+            self.entries.last()
+        } else if let Ok(n) = recall.parse::<usize>() {
+            n.checked_sub(1).and_then(|i| self.entries.get(i))
+        } else {
+            self.entries.iter().rev().find(|e| e.starts_with(recall))
+        };
+        found.cloned()
+    }
+
+    /// Record an accepted prompt line and append it to the history file, or rewrite the file with
+    /// the last [`MAX_ENTRIES`] entries once it has doubled. Repeating the last entry is not
+    /// recorded again. Write failures are logged, not fatal.
+    pub fn push(&mut self, line: &str) {
+        if line.is_empty() || self.entries.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        self.trim();
+        let Some(path) = &self.path else {
+            return;
+        };
+        let compact = self.file_lines >= 2 * MAX_ENTRIES;
+        let written = (|| {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            if compact {
+                // Replaced in one rename, so another binary appending meanwhile never sees a
+                // half-written file.
+                let tmp = path.with_extension("tmp");
+                let mut text = self.entries.join("\n");
+                text.push('\n');
+                std::fs::write(&tmp, text)?;
+                return std::fs::rename(&tmp, path);
+            }
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            writeln!(file, "{line}")
+        })();
+        match written {
+            Ok(()) if compact => self.file_lines = self.entries.len(),
+            Ok(()) => self.file_lines += 1,
+            Err(e) => tracing::warn!("Failed to write prompt history {}: {e}", path.display()),
+        }
+    }
+
+    /// Print the most recent entries, numbered for `!N`, to stderr.
+    pub fn print(&self) {
+        if self.entries.is_empty() {
+            eprintln!("Prompt history is empty");
+            return;
+        }
+        let start = self.entries.len().saturating_sub(LISTED);
+        for (i, entry) in self.entries.iter().enumerate().skip(start) {
+            eprintln!("{:>4}  {entry}", i + 1);
+        }
+    }
+
+    fn trim(&mut self) {
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|cache| cache.join("sam3").join("prompt_history"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn history(entries: &[&str]) -> PromptHistory {
+        PromptHistory {
+            path: None,
+            entries: entries.iter().map(|e| e.to_string()).collect(),
+            file_lines: 0,
+        }
+    }
+
+    #[test]
+    fn expands_recalls() {
+        let h = history(&["person", "car | truck", "person | dog"]);
+        assert_eq!(h.expand("cat").as_deref(), Some("cat"));
+        assert_eq!(h.expand("!!").as_deref(), Some("person | dog"));
+        assert_eq!(h.expand("!2").as_deref(), Some("car | truck"));
+        assert_eq!(h.expand("!pers").as_deref(), Some("person | dog"));
+        assert_eq!(h.expand("!0"), None);
+        assert_eq!(h.expand("!9"), None);
+        assert_eq!(h.expand("!bike"), None);
+        assert_eq!(history(&[]).expand("!!"), None);
+    }
+
+    #[test]
+    fn appends_to_file_and_skips_repeats() {
+        let dir = TempDir::new("history");
+        let path = dir.join("nested").join("prompt_history");
+        let mut h = PromptHistory::open(Some(&path));
+        assert!(h.entries().is_empty());
+        h.push("person");
+        h.push("person");
+        h.push("car | truck");
+        let reopened = PromptHistory::open(Some(&path));
+        assert_eq!(reopened.entries(), ["person", "car | truck"]);
+    }
+
+    #[test]
+    fn file_is_cut_back_once_it_doubles() {
+        let dir = TempDir::new("history-bound");
+        let path = dir.join("prompt_history");
+        let mut h = PromptHistory::open(Some(&path));
+        let lines = || std::fs::read_to_string(&path).unwrap().lines().count();
+        for i in 0..2 * MAX_ENTRIES {
+            h.push(&format!("prompt {i}"));
+        }
+        assert_eq!(lines(), 2 * MAX_ENTRIES);
+        h.push("one more");
+        assert_eq!(lines(), MAX_ENTRIES);
+        let reopened = PromptHistory::open(Some(&path));
+        assert_eq!(reopened.entries().last().unwrap(), "one more");
+        assert_eq!(reopened.entries().len(), MAX_ENTRIES);
+    }
+}
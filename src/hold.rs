@@ -0,0 +1,215 @@
+//! `--hold-ms <n>`: keep detections that dropped out of the latest inference on screen for a while,
+//! fading out, so an object missed for a single inference does not blink.
+//!
+//! Only the window image gets the held boxes, like the legend; saved frames, encoded video and JSON
+//! records keep the raw per-inference results. A detection stops being held once a box with the same
+//! label overlaps it again (the annotator draws that one) or when it has been gone for `n` ms.
+//!
+//! Times are media time: the frame's position in the stream for video files, time since start for
+//! cameras, so a video held for `n` ms plays back the same at any decode or inference speed.
+
+use anyhow::Result;
+use image::RgbImage;
+use std::time::Duration;
+
+use crate::geometry::{Rect, iou};
+use crate::viz::{draw_text, prompt_color, text_scale, text_size, to_rgb};
+
+/// Minimum IoU for a current box to take over a held one.
+const MIN_IOU: f32 = 0.3;
+
+#[derive(Clone, Debug, PartialEq)]
+struct Seen {
+    label: String,
+    class_id: Option<usize>,
+    rect: Rect,
+    at: Duration,
+}
+
+impl Seen {
+    fn matches(&self, other: &Seen) -> bool {
+        self.label == other.label && iou(self.rect, other.rect) >= MIN_IOU
+    }
+}
+
+pub struct DetectionHold {
+    hold: Duration,
+    /// Detections of the latest inference.
+    current: Vec<Seen>,
+    /// Detections missing from the latest inference, with when they were last seen.
+    held: Vec<Seen>,
+}
+
+impl DetectionHold {
+    /// `None` for `--hold-ms 0`.
+    pub fn new(hold_ms: u64) -> Option<Self> {
+        (hold_ms > 0).then(|| Self {
+            hold: Duration::from_millis(hold_ms),
+            current: Vec::new(),
+            held: Vec::new(),
+        })
+    }
+
+    /// Take the boxes of the inference on the frame at media time `now`.
+    pub fn update(&mut self, y: &usls::Y, now: Duration) {
+        let current: Vec<Seen> = y
+            .hbbs()
+            .iter()
+            .map(|hbb| Seen {
+                label: hbb.name().unwrap_or("object").to_string(),
+                class_id: hbb.id(),
+                rect: hbb.xyxy(),
+                at: now,
+            })
+            .collect();
+        self.update_seen(current, now);
+    }
+
+    fn update_seen(&mut self, current: Vec<Seen>, now: Duration) {
+        let dropped = std::mem::take(&mut self.current)
+            .into_iter()
+            .filter(|seen| !current.iter().any(|c| c.matches(seen)));
+        self.held.extend(dropped);
+        let hold = self.hold;
+        self.held.retain(|seen| {
+            now.saturating_sub(seen.at) < hold && !current.iter().any(|c| c.matches(seen))
+        });
+        self.current = current;
+    }
+
+    /// Forget everything, e.g. when the prompts change.
+    pub fn clear(&mut self) {
+        self.current.clear();
+        self.held.clear();
+    }
+
+    /// Held boxes still on screen at `now`, with their opacity (1 when just dropped, towards 0 at
+    /// the end of the hold).
+    fn fading(&self, now: Duration) -> impl Iterator<Item = (&Seen, f32)> {
+        self.held.iter().filter_map(move |seen| {
+            let age = now.saturating_sub(seen.at).as_secs_f32();
+            let alpha = 1.0 - age / self.hold.as_secs_f32();
+            (alpha > 0.0).then_some((seen, alpha))
+        })
+    }
+
+    /// `img` (the frame at media time `now`) with the held boxes drawn over it; `None` when
+    /// nothing is held.
+    pub fn render(&self, img: &usls::Image, now: Duration) -> Result<Option<usls::Image>> {
+        if self.fading(now).next().is_none() {
+            return Ok(None);
+        }
+        let mut frame = to_rgb(img)?;
+        let scale = text_scale(frame.height());
+        for (seen, alpha) in self.fading(now) {
+            draw_faded(&mut frame, seen, scale, prompt_color(seen.class_id), alpha);
+        }
+        Ok(Some(usls::Image::from(frame)))
+    }
+}
+
+/// Outline and label of `seen`, blended over `frame` with `alpha`.
+fn draw_faded(frame: &mut RgbImage, seen: &Seen, scale: u32, color: [u8; 3], alpha: f32) {
+    let (fw, fh) = (frame.width() as f32, frame.height() as f32);
+    let (x0, y0, x1, y1) = seen.rect;
+    let (text_w, text_h) = text_size(&seen.label, scale);
+    // The region the label (above the box) and outline cover, clipped to the frame.
+    let rx0 = x0.clamp(0.0, fw) as u32;
+    let ry0 = (y0 - (text_h + 2 * scale) as f32).clamp(0.0, fh) as u32;
+    let rx1 = x1.max(x0 + text_w as f32).clamp(0.0, fw) as u32;
+    let ry1 = y1.clamp(0.0, fh) as u32;
+    if rx1 <= rx0 || ry1 <= ry0 {
+        return;
+    }
+    let mut layer = image::imageops::crop_imm(frame, rx0, ry0, rx1 - rx0, ry1 - ry0).to_image();
+    let (ox, oy) = (rx0 as f32, ry0 as f32);
+    let t = scale as f32;
+    for (x, y, px) in layer.enumerate_pixels_mut() {
+        let (fx, fy) = (x as f32 + ox, y as f32 + oy);
+        let inside = fx >= x0 && fx < x1 && fy >= y0 && fy < y1;
+        if inside && (fx < x0 + t || fx >= x1 - t || fy < y0 + t || fy >= y1 - t) {
+            px.0 = color;
+        }
+    }
+    let label_y = (y0 - ry0 as f32 - (text_h + scale) as f32).max(0.0) as u32;
+    draw_text(
+        &mut layer,
+        (x0 - ox).max(0.0) as u32,
+        label_y,
+        &seen.label,
+        scale,
+        color,
+    );
+    for (x, y, px) in layer.enumerate_pixels() {
+        let under = frame.get_pixel_mut(x + rx0, y + ry0);
+        for c in 0..3 {
+            under[c] = (px[c] as f32 * alpha + under[c] as f32 * (1.0 - alpha)).round() as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seen(boxes: &[(&str, Rect)], at: Duration) -> Vec<Seen> {
+        boxes
+            .iter()
+            .map(|(label, rect)| Seen {
+                label: label.to_string(),
+                class_id: None,
+                rect: *rect,
+                at,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn holds_dropped_detections_until_they_expire() {
+        let mut hold = DetectionHold::new(500).unwrap();
+        let t0 = Duration::ZERO;
+        hold.update_seen(seen(&[("cat", (10.0, 10.0, 50.0, 50.0))], t0), t0);
+        assert!(hold.held.is_empty());
+
+        // Missed once: held, fading from the time it was last seen.
+        let t1 = t0 + Duration::from_millis(100);
+        hold.update_seen(Vec::new(), t1);
+        let alphas: Vec<f32> = hold.fading(t1).map(|(_, a)| a).collect();
+        assert_eq!(alphas.len(), 1);
+        assert!((alphas[0] - 0.8).abs() < 1e-3, "{alphas:?}");
+
+        // Gone for longer than the hold.
+        hold.update_seen(Vec::new(), t0 + Duration::from_millis(600));
+        assert!(hold.held.is_empty());
+        assert!(DetectionHold::new(0).is_none());
+    }
+
+    #[test]
+    fn redetection_releases_the_held_box() {
+        let mut hold = DetectionHold::new(1000).unwrap();
+        let t0 = Duration::ZERO;
+        hold.update_seen(seen(&[("cat", (10.0, 10.0, 50.0, 50.0))], t0), t0);
+        hold.update_seen(Vec::new(), t0 + Duration::from_millis(100));
+        assert_eq!(hold.held.len(), 1);
+        // A different label in the same place does not take over.
+        let t2 = t0 + Duration::from_millis(200);
+        hold.update_seen(seen(&[("dog", (12.0, 10.0, 52.0, 50.0))], t2), t2);
+        assert_eq!(hold.held.len(), 1);
+        let t3 = t0 + Duration::from_millis(300);
+        hold.update_seen(seen(&[("cat", (12.0, 10.0, 52.0, 50.0))], t3), t3);
+        // The cat is back; the dog dropped out and is held instead.
+        assert_eq!(hold.held.len(), 1);
+        assert_eq!(hold.held[0].label, "dog");
+    }
+
+    #[test]
+    fn draws_a_faded_outline() {
+        let mut frame = RgbImage::new(64, 48);
+        let cat = &seen(&[("cat", (10.0, 20.0, 40.0, 40.0))], Duration::ZERO)[0];
+        draw_faded(&mut frame, cat, 1, [200, 100, 0], 0.5);
+        assert_eq!(frame.get_pixel(10, 30).0, [100, 50, 0]);
+        assert_eq!(frame.get_pixel(25, 30).0, [0, 0, 0]);
+        // Label above the box.
+        assert!((10..28).any(|x| frame.get_pixel(x, 13).0 != [0, 0, 0]));
+    }
+}
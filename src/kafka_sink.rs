@@ -0,0 +1,311 @@
+//! `--kafka-brokers`/`--kafka-topic` (`kafka` feature): publish every output frame to Kafka as a
+//! JPEG message with `frame_idx` and `timestamp_ms` headers.
+//!
+//! Raw frames are handed to a producer thread through a bounded channel; that thread encodes them
+//! and queues them in librdkafka, whose own thread delivers them. Delivery failures are reported
+//! from librdkafka's callback, so the producer never waits on a single message. When the brokers
+//! fall behind and the channel is full, frames are dropped (and counted) instead of stalling
+//! capture, before any encoding work is spent on them.
+
+use anyhow::{Context, Result};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+
+/// Frames waiting for the producer thread.
+const QUEUE_DEPTH: usize = 8;
+
+/// A raw RGB24 output frame on its way to the producer thread.
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+struct KafkaFrame {
+    frame_idx: u64,
+    ts_ms: u64,
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+impl KafkaFrame {
+    #[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+    fn encode_jpeg(&self, quality: u8) -> Result<Vec<u8>> {
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality)
+            .encode(
+                &self.rgb,
+                self.width,
+                self.height,
+                image::ExtendedColorType::Rgb8,
+            )
+            .context("failed to encode frame for Kafka")?;
+        Ok(jpeg)
+    }
+}
+
+pub struct KafkaSink {
+    frames: Option<SyncSender<KafkaFrame>>,
+    thread: Option<JoinHandle<()>>,
+    dropped: u64,
+}
+
+impl KafkaSink {
+    /// Connect a producer to `brokers` (`host:port,...`) for `topic`; `quality` is the JPEG
+    /// quality (1-100).
+    pub fn new(brokers: &str, topic: &str, quality: u8) -> Result<Self> {
+        let sink = Self::spawn_with(quality, |frames| {
+            producer::spawn(brokers, topic, quality, frames)
+        })?;
+        tracing::info!("Publishing frames to Kafka topic `{topic}` on {brokers}");
+        Ok(sink)
+    }
+
+    /// A sink whose frames go to the thread `spawn` starts.
+    fn spawn_with(
+        quality: u8,
+        spawn: impl FnOnce(Receiver<KafkaFrame>) -> Result<JoinHandle<()>>,
+    ) -> Result<Self> {
+        anyhow::ensure!(
+            (1..=100).contains(&quality),
+            "--kafka-jpeg-quality must be between 1 and 100, got {quality}"
+        );
+        let (tx, rx) = std::sync::mpsc::sync_channel(QUEUE_DEPTH);
+        Ok(Self {
+            frames: Some(tx),
+            thread: Some(spawn(rx)?),
+            dropped: 0,
+        })
+    }
+
+    /// `--kafka-brokers` with `--kafka-topic`; `None` when neither is given.
+    pub fn from_args(
+        brokers: Option<&str>,
+        topic: Option<&str>,
+        quality: u8,
+    ) -> Result<Option<Self>> {
+        match (brokers, topic) {
+            (Some(brokers), Some(topic)) => Self::new(brokers, topic, quality).map(Some),
+            (None, None) => Ok(None),
+            _ => anyhow::bail!("--kafka-brokers and --kafka-topic must be given together"),
+        }
+    }
+
+    /// Queue a copy of `img` for publishing; `ts_ms` is unix-epoch milliseconds.
+    pub fn send_frame(&mut self, img: &usls::Image, frame_idx: u64, ts_ms: u64) -> Result<()> {
+        let Some(frames) = &self.frames else {
+            return Ok(());
+        };
+        match frames.try_send(KafkaFrame {
+            frame_idx,
+            ts_ms,
+            width: img.width(),
+            height: img.height(),
+            rgb: img.as_raw().to_vec(),
+        }) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                if self.dropped == 0 {
+                    tracing::warn!("Kafka producer is falling behind; dropping frames");
+                }
+                self.dropped += 1;
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => anyhow::bail!("Kafka producer thread stopped"),
+        }
+    }
+
+    /// Deliver what is queued and stop the producer thread.
+    pub fn finish(mut self) {
+        self.frames = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        if self.dropped > 0 {
+            tracing::warn!("Kafka: dropped {} frames", self.dropped);
+        }
+    }
+}
+
+/// Wall-clock time for frames without a capture timestamp.
+pub fn now_unix_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(feature = "kafka")]
+mod producer {
+    use super::KafkaFrame;
+    use anyhow::{Context, Result};
+    use rdkafka::message::{Header, OwnedHeaders};
+    use rdkafka::producer::{
+        BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer,
+    };
+    use rdkafka::{ClientConfig, ClientContext};
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::mpsc::Receiver;
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    /// How long `finish` waits for queued messages to be delivered.
+    const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Counts (and logs the first of) the failed deliveries librdkafka reports.
+    #[derive(Default)]
+    struct DeliveryReport {
+        failed: AtomicU64,
+    }
+
+    impl ClientContext for DeliveryReport {}
+
+    impl ProducerContext for DeliveryReport {
+        type DeliveryOpaque = ();
+
+        fn delivery(&self, result: &DeliveryResult<'_>, _: ()) {
+            if let Err((e, _)) = result
+                && self.failed.fetch_add(1, Ordering::Relaxed) == 0
+            {
+                tracing::warn!("Kafka: a frame was not delivered: {e}");
+            }
+        }
+    }
+
+    pub(super) fn spawn(
+        brokers: &str,
+        topic: &str,
+        quality: u8,
+        frames: Receiver<KafkaFrame>,
+    ) -> Result<JoinHandle<()>> {
+        let producer: ThreadedProducer<DeliveryReport> = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", "5000")
+            .create_with_context(DeliveryReport::default())
+            .context("failed to create Kafka producer")?;
+        let topic = topic.to_string();
+        std::thread::Builder::new()
+            .name("kafka-producer".to_string())
+            .spawn(move || {
+                for frame in frames {
+                    let jpeg = match frame.encode_jpeg(quality) {
+                        Ok(jpeg) => jpeg,
+                        Err(e) => {
+                            tracing::warn!("Kafka: frame {}: {e:#}", frame.frame_idx);
+                            continue;
+                        }
+                    };
+                    let frame_idx = frame.frame_idx.to_string();
+                    let ts_ms = frame.ts_ms.to_string();
+                    let headers = OwnedHeaders::new()
+                        .insert(Header {
+                            key: "frame_idx",
+                            value: Some(frame_idx.as_str()),
+                        })
+                        .insert(Header {
+                            key: "timestamp_ms",
+                            value: Some(ts_ms.as_str()),
+                        });
+                    let record = BaseRecord::<(), [u8]>::to(&topic)
+                        .payload(&jpeg)
+                        .headers(headers)
+                        .timestamp(frame.ts_ms as i64);
+                    // Only queues the message; the delivery report arrives in `DeliveryReport`.
+                    if let Err((e, _)) = producer.send(record) {
+                        tracing::warn!("Kafka: failed to queue frame {frame_idx}: {e}");
+                    }
+                }
+                if let Err(e) = producer.flush(FLUSH_TIMEOUT) {
+                    tracing::warn!("Kafka: flush failed: {e}");
+                }
+                let failed = producer.context().failed.load(Ordering::Relaxed);
+                if failed > 0 {
+                    tracing::warn!("Kafka: {failed} frames were not delivered");
+                }
+            })
+            .context("failed to spawn the Kafka producer thread")
+    }
+}
+
+#[cfg(not(feature = "kafka"))]
+mod producer {
+    use super::KafkaFrame;
+    use anyhow::Result;
+    use std::sync::mpsc::Receiver;
+    use std::thread::JoinHandle;
+
+    pub(super) fn spawn(
+        _brokers: &str,
+        _topic: &str,
+        _quality: u8,
+        _frames: Receiver<KafkaFrame>,
+    ) -> Result<JoinHandle<()>> {
+        anyhow::bail!("--kafka-brokers needs a build with `--features kafka`")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn image(width: u32, height: u32) -> usls::Image {
+        usls::Image::from(image::RgbImage::from_pixel(
+            width,
+            height,
+            image::Rgb([200, 10, 10]),
+        ))
+    }
+
+    /// `(frame_idx, jpeg)` of each frame the test producer took.
+    type Published = Arc<Mutex<Vec<(u64, Vec<u8>)>>>;
+
+    /// A producer thread that waits for `go` before taking frames, and records what it got.
+    fn stalled_producer(
+        go: std::sync::mpsc::Receiver<()>,
+        got: Published,
+    ) -> impl FnOnce(Receiver<KafkaFrame>) -> Result<JoinHandle<()>> {
+        move |frames| {
+            Ok(std::thread::spawn(move || {
+                let _ = go.recv();
+                for frame in frames {
+                    let jpeg = frame.encode_jpeg(70).unwrap();
+                    got.lock().unwrap().push((frame.frame_idx, jpeg));
+                }
+            }))
+        }
+    }
+
+    #[test]
+    fn a_full_queue_drops_frames_instead_of_blocking() {
+        let (go_tx, go) = std::sync::mpsc::channel();
+        let got = Arc::new(Mutex::new(Vec::new()));
+        let mut sink = KafkaSink::spawn_with(70, stalled_producer(go, got.clone())).unwrap();
+        for i in 0..QUEUE_DEPTH as u64 + 5 {
+            sink.send_frame(&image(16, 8), i, 1000 + i).unwrap();
+        }
+        assert_eq!(sink.dropped, 5);
+        go_tx.send(()).unwrap();
+        sink.finish();
+
+        let got = got.lock().unwrap();
+        let idx: Vec<u64> = got.iter().map(|(i, _)| *i).collect();
+        assert_eq!(idx, (0..QUEUE_DEPTH as u64).collect::<Vec<_>>());
+        let decoded = image::load_from_memory(&got[0].1).unwrap().to_rgb8();
+        assert_eq!(decoded.dimensions(), (16, 8));
+        assert!(decoded.get_pixel(3, 3).0[0] > 150);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let (_go_tx, go) = std::sync::mpsc::channel();
+        let spawn = stalled_producer(go, Arc::default());
+        assert!(KafkaSink::spawn_with(0, spawn).is_err());
+        assert!(KafkaSink::from_args(Some("localhost:9092"), None, 70).is_err());
+        assert!(KafkaSink::from_args(None, None, 70).unwrap().is_none());
+    }
+
+    #[test]
+    fn a_stopped_producer_is_an_error() {
+        let mut sink =
+            KafkaSink::spawn_with(70, |frames| Ok(std::thread::spawn(move || drop(frames))))
+                .unwrap();
+        sink.thread.take().unwrap().join().unwrap();
+        assert!(sink.send_frame(&image(2, 2), 0, 0).is_err());
+    }
+}
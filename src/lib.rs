@@ -1,3 +1,61 @@
+pub mod annotate_async;
+pub mod annotate_only;
+pub mod benchmark;
+pub mod best;
+pub mod build_info;
+pub mod camera_profile;
+pub mod capture;
+pub mod color;
+pub mod common;
+pub mod control;
+pub mod crops;
+pub mod debug;
+pub mod device;
+pub mod dwell;
+pub mod events;
+pub mod ffmpeg_log;
+pub mod frame_loop;
+pub mod geometry;
+pub mod groups;
 pub mod hikvision_sam3;
+pub mod history;
+pub mod hold;
+pub mod kafka_sink;
+#[cfg(feature = "ffmpeg-static")]
+pub mod libav;
+pub mod logging;
+pub mod matte;
+pub mod mjpeg;
+pub mod multi_cam;
+pub mod notify;
+#[cfg(feature = "opencv")]
+pub mod opencv_camera;
+pub mod output;
+pub mod pipeline;
+pub mod postprocess;
+pub mod preprocess;
+pub mod prompts;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod quality;
+pub mod redact;
+pub mod repro;
+pub mod rle;
+pub mod schedule;
+pub mod session;
+pub mod shm_sink;
+pub mod shutdown;
+pub mod stats;
+pub mod svg;
+#[cfg(test)]
+mod test_util;
+pub mod tiling;
+pub mod tracking;
+pub mod undistort;
 pub mod v4l_sam3;
+pub mod v4l_util;
 pub mod video_sam3;
+pub mod visibility;
+pub mod viz;
+pub mod watchdog;
+pub mod zones;
@@ -0,0 +1,174 @@
+//! In-process decoding through the FFmpeg libraries (`ffmpeg-static` feature).
+//!
+//! Stands in for the `ffmpeg`/`ffprobe` child processes of `video-sam3` so the binary runs on hosts
+//! without FFmpeg installed. Frames go through swscale with the same bicubic filter the CLI inserts
+//! for `-vf scale=W:H -pix_fmt rgb24`, so [`LibavRgb24::read_frame`] yields the same `RgbImage`s as
+//! the raw-video pipe.
+
+use anyhow::{Context, Result};
+use ffmpeg::format::Pixel;
+use ffmpeg::media::Type;
+use ffmpeg::software::scaling::{Context as Scaler, Flags};
+use ffmpeg::util::frame::video::Video;
+use ffmpeg_next as ffmpeg;
+
+/// What `ffprobe` would report for the first video stream.
+pub struct LibavInfo {
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+    pub frames: Option<u64>,
+    pub duration_seconds: Option<f64>,
+}
+
+pub fn probe(input: &str) -> Result<LibavInfo> {
+    ffmpeg::init().context("failed to initialize libav")?;
+    let ictx = ffmpeg::format::input(&input).with_context(|| format!("failed to open {input}"))?;
+    let stream = ictx
+        .streams()
+        .best(Type::Video)
+        .context("input has no video stream")?;
+    let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()
+        .context("failed to open video decoder")?;
+    let rate = stream.rate();
+    let fps = if rate.numerator() > 0 && rate.denominator() > 0 {
+        f64::from(rate) as f32
+    } else {
+        30.0
+    };
+    Ok(LibavInfo {
+        width: decoder.width(),
+        height: decoder.height(),
+        fps,
+        frames: u64::try_from(stream.frames()).ok().filter(|n| *n > 0),
+        duration_seconds: (ictx.duration() > 0)
+            .then(|| ictx.duration() as f64 / f64::from(ffmpeg::ffi::AV_TIME_BASE)),
+    })
+}
+
+pub struct LibavRgb24 {
+    input: ffmpeg::format::context::Input,
+    stream_index: usize,
+    decoder: ffmpeg::decoder::Video,
+    scaler: Scaler,
+    width: u32,
+    height: u32,
+    time_base: f64,
+    /// `--decode-fps`: seconds between kept frames and the timestamp of the next one to keep.
+    keep_every: Option<f64>,
+    next_keep: f64,
+    eof: bool,
+    decode_errors: u64,
+}
+
+impl LibavRgb24 {
+    /// Decode the first video stream of `input` to `width` x `height` RGB24.
+    pub fn open(input: &str, width: u32, height: u32, decode_fps: Option<f32>) -> Result<Self> {
+        ffmpeg::init().context("failed to initialize libav")?;
+        let ictx =
+            ffmpeg::format::input(&input).with_context(|| format!("failed to open {input}"))?;
+        let stream = ictx
+            .streams()
+            .best(Type::Video)
+            .context("input has no video stream")?;
+        let stream_index = stream.index();
+        let time_base = f64::from(stream.time_base());
+        let decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .video()
+            .context("failed to open video decoder")?;
+        let scaler = Scaler::get(
+            decoder.format(),
+            decoder.width(),
+            decoder.height(),
+            Pixel::RGB24,
+            width,
+            height,
+            Flags::BICUBIC,
+        )
+        .context("failed to create swscale context")?;
+        Ok(Self {
+            input: ictx,
+            stream_index,
+            decoder,
+            scaler,
+            width,
+            height,
+            time_base,
+            keep_every: decode_fps.map(|rate| 1.0 / f64::from(rate)),
+            next_keep: 0.0,
+            eof: false,
+            decode_errors: 0,
+        })
+    }
+
+    /// Approximates the CLI's `fps=` filter: keep the first frame at or after each tick.
+    fn keep(&mut self, pts: Option<i64>) -> bool {
+        let (Some(step), Some(pts)) = (self.keep_every, pts) else {
+            return true;
+        };
+        let t = pts as f64 * self.time_base;
+        if t + 1e-6 < self.next_keep {
+            return false;
+        }
+        while self.next_keep <= t + 1e-6 {
+            self.next_keep += step;
+        }
+        true
+    }
+
+    pub fn read_frame(&mut self) -> Result<Option<image::RgbImage>> {
+        loop {
+            let mut decoded = Video::empty();
+            if self.decoder.receive_frame(&mut decoded).is_ok() {
+                if !self.keep(decoded.pts()) {
+                    continue;
+                }
+                let mut rgb = Video::empty();
+                self.scaler
+                    .run(&decoded, &mut rgb)
+                    .context("swscale failed")?;
+                return self.to_image(&rgb).map(Some);
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            let mut packet = ffmpeg::Packet::empty();
+            match packet.read(&mut self.input) {
+                Ok(()) if packet.stream() == self.stream_index => {
+                    // Like the CLI, carry on past corrupt packets; the quality gate counts them.
+                    if let Err(e) = self.decoder.send_packet(&packet) {
+                        self.decode_errors += 1;
+                        tracing::debug!("libav: failed to decode packet: {e}");
+                    }
+                }
+                Ok(()) => {}
+                Err(ffmpeg::Error::Eof) => {
+                    self.decoder.send_eof().context("failed to flush decoder")?;
+                    self.eof = true;
+                }
+                Err(e) => return Err(e).context("failed to read packet"),
+            }
+        }
+    }
+
+    /// Packets the decoder rejected so far.
+    pub fn decode_errors(&self) -> u64 {
+        self.decode_errors
+    }
+
+    /// Copy the (possibly padded) RGB24 plane into a tightly packed image.
+    fn to_image(&self, rgb: &Video) -> Result<image::RgbImage> {
+        let row = self.width as usize * 3;
+        let stride = rgb.stride(0);
+        let data = rgb.data(0);
+        let mut buf = Vec::with_capacity(row * self.height as usize);
+        for y in 0..self.height as usize {
+            buf.extend_from_slice(&data[y * stride..y * stride + row]);
+        }
+        image::RgbImage::from_raw(self.width, self.height, buf)
+            .context("failed to construct RgbImage")
+    }
+}
@@ -0,0 +1,202 @@
+//! Log setup shared by the binaries, including `--log-json`.
+//!
+//! With `--log-json` every line is one JSON object from `tracing-subscriber`'s JSON formatter:
+//! `timestamp`, `level`, `target`, the event's fields (`message` included) and `spans`, the
+//! enclosing spans from the outermost in with their fields. The capture loops open a `frame` span
+//! carrying `frame_idx`, so anything logged while a frame is processed (inference warnings, saves,
+//! drops) can be grouped by frame afterwards.
+//!
+//! [`HeartbeatLogger`] (`--heartbeat-secs`) logs a periodic liveness line for long unattended runs.
+
+use anyhow::Result;
+use std::time::{Duration, Instant};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::time::ChronoLocal;
+
+/// Install the global subscriber: human-readable lines, or JSON Lines with `json`.
+pub fn init_logging<W>(json: bool, writer: W)
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_timer(ChronoLocal::rfc_3339())
+        .with_writer(writer);
+    if json {
+        builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .init();
+    } else {
+        builder.init();
+    }
+}
+
+/// `HH:MM:SS.mmm`; negative and NaN inputs show as zero.
+pub fn fmt_hms(seconds: f64) -> String {
+    let seconds = seconds.max(0.0);
+    let total_ms = (seconds * 1000.0).round() as u64;
+    let ms = total_ms % 1000;
+    let total_s = total_ms / 1000;
+    let s = total_s % 60;
+    let total_m = total_s / 60;
+    let m = total_m % 60;
+    let h = total_m / 60;
+    format!("{h:02}:{m:02}:{s:02}.{ms:03}")
+}
+
+/// `VmRSS` from the text of `/proc/self/status`, in KB.
+fn parse_vm_rss_kb(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Resident set size of this process in KB.
+#[cfg(target_os = "linux")]
+fn rss_kb() -> Option<u64> {
+    parse_vm_rss_kb(&std::fs::read_to_string("/proc/self/status").ok()?)
+}
+
+/// Peak resident set size in KB; the closest `getrusage` gets to the current one.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn rss_kb() -> Option<u64> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let max_rss = u64::try_from(usage.ru_maxrss).ok()?;
+    // macOS reports bytes, the BSDs kilobytes.
+    Some(if cfg!(target_os = "macos") {
+        max_rss / 1024
+    } else {
+        max_rss
+    })
+}
+
+#[cfg(not(unix))]
+fn rss_kb() -> Option<u64> {
+    None
+}
+
+/// Logs a "still alive" line every `--heartbeat-secs`, whether or not progress is shown.
+pub struct HeartbeatLogger {
+    interval: Option<Duration>,
+    started: Instant,
+    last_heartbeat: Instant,
+}
+
+impl HeartbeatLogger {
+    /// `secs == 0` disables the heartbeat.
+    pub fn new(secs: f64) -> Result<Self> {
+        anyhow::ensure!(
+            secs.is_finite() && secs >= 0.0,
+            "--heartbeat-secs must be >= 0 (got {secs})"
+        );
+        let now = Instant::now();
+        Ok(Self {
+            interval: (secs > 0.0).then(|| Duration::from_secs_f64(secs)),
+            started: now,
+            last_heartbeat: now,
+        })
+    }
+
+    /// Call once per frame; `fps` converts `frame_idx` to a stream position.
+    pub fn poll(&mut self, frame_idx: u64, total_frames: Option<u64>, fps: f32) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let now = Instant::now();
+        if now.duration_since(self.last_heartbeat) < interval {
+            return;
+        }
+        self.last_heartbeat = now;
+        let elapsed_s = now.duration_since(self.started).as_secs_f64();
+        let speed_fps = if elapsed_s > 0.0 {
+            frame_idx as f64 / elapsed_s
+        } else {
+            0.0
+        };
+        let pos_s = frame_idx as f64 / f64::from(fps.max(0.001));
+        let total = total_frames.map_or("?".to_string(), |t| t.to_string());
+        let rss_kb = rss_kb().map_or("?".to_string(), |kb| kb.to_string());
+        tracing::info!(
+            "Heartbeat: frame {frame_idx}/{total} pos {} speed {speed_fps:.1} fps memory RSS {} KB",
+            fmt_hms(pos_s),
+            rss_kb
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn fmt_hms_carries_rounded_milliseconds() {
+        assert_eq!(fmt_hms(0.0), "00:00:00.000");
+        assert_eq!(fmt_hms(1.0004), "00:00:01.000");
+        assert_eq!(fmt_hms(1.0006), "00:00:01.001");
+        assert_eq!(fmt_hms(59.9996), "00:01:00.000");
+        assert_eq!(fmt_hms(60.0), "00:01:00.000");
+        assert_eq!(fmt_hms(3599.9999), "01:00:00.000");
+        assert_eq!(fmt_hms(3600.0), "01:00:00.000");
+        assert_eq!(fmt_hms(3661.5), "01:01:01.500");
+        assert_eq!(fmt_hms(-5.0), "00:00:00.000");
+        assert_eq!(fmt_hms(f64::NAN), "00:00:00.000");
+    }
+
+    #[test]
+    fn parses_proc_status_rss() {
+        let status = "Name:\tvideo-sam3\nVmPeak:\t 2048000 kB\nVmRSS:\t  812344 kB\nThreads:\t12\n";
+        assert_eq!(parse_vm_rss_kb(status), Some(812344));
+        assert_eq!(parse_vm_rss_kb("Name:\tkthreadd\n"), None);
+        assert!(HeartbeatLogger::new(-1.0).is_err());
+    }
+
+    #[test]
+    fn json_lines_carry_the_frame_span() {
+        let buf = Arc::new(Mutex::new(Vec::<u8>::new()));
+        let sink = buf.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .with_writer(move || SinkWriter(sink.clone()))
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let _frame = tracing::info_span!("frame", frame_idx = 42u64).entered();
+            tracing::warn!(dropped = 3, "queue full");
+        });
+        let text = String::from_utf8(buf.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(text.trim()).unwrap();
+        assert_eq!(line["spans"][0]["name"], "frame");
+        assert_eq!(line["spans"][0]["frame_idx"], 42);
+        assert_eq!(line["dropped"], 3);
+        assert_eq!(line["message"], "queue full");
+        assert_eq!(line["level"], "WARN");
+    }
+
+    struct SinkWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SinkWriter {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+}
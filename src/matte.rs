@@ -0,0 +1,105 @@
+use image::{GrayImage, Luma, RgbImage, RgbaImage};
+
+const BLUR_SIGMA: f32 = 12.0;
+
+/// Background used by `--matte`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatteMode {
+    /// Alpha channel from the mask (RGBA output).
+    Transparent,
+    /// Solid background color.
+    Color([u8; 3]),
+    /// Blurred copy of the frame as background.
+    Blur,
+}
+
+impl MatteMode {
+    pub fn is_transparent(&self) -> bool {
+        matches!(self, Self::Transparent)
+    }
+}
+
+impl std::str::FromStr for MatteMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_ascii_lowercase().as_str() {
+            "transparent" => return Ok(Self::Transparent),
+            "blur" => return Ok(Self::Blur),
+            _ => {}
+        }
+        let Some(hex) = s.strip_prefix("color:") else {
+            return Err(format!(
+                "invalid matte `{s}` (expected transparent, blur or color:#rrggbb)"
+            ));
+        };
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 {
+            return Err(format!("invalid matte color `{hex}` (expected #rrggbb)"));
+        }
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| format!("invalid matte color `{hex}` (expected #rrggbb)"))
+        };
+        Ok(Self::Color([channel(0)?, channel(2)?, channel(4)?]))
+    }
+}
+
+/// Union of all detection masks in `y`, at `width`x`height`.
+pub fn union_mask(y: &usls::Y, width: u32, height: u32) -> GrayImage {
+    let mut union = GrayImage::new(width, height);
+    for mask in y.masks() {
+        let m = mask.mask();
+        let resized;
+        let m = if m.dimensions() == (width, height) {
+            m
+        } else {
+            resized = image::imageops::resize(m, width, height, image::imageops::FilterType::Nearest);
+            &resized
+        };
+        for (dst, src) in union.pixels_mut().zip(m.pixels()) {
+            if src[0] > 0 {
+                *dst = Luma([255]);
+            }
+        }
+    }
+    union
+}
+
+/// Soften mask edges by `px` pixels (0 keeps the hard edge).
+pub fn feather(mask: GrayImage, px: u32) -> GrayImage {
+    if px == 0 {
+        return mask;
+    }
+    image::imageops::blur(&mask, px as f32 / 2.0)
+}
+
+/// Frame with the mask as alpha channel.
+pub fn composite_rgba(frame: &RgbImage, mask: &GrayImage) -> RgbaImage {
+    RgbaImage::from_fn(frame.width(), frame.height(), |x, y| {
+        let [r, g, b] = frame.get_pixel(x, y).0;
+        image::Rgba([r, g, b, mask.get_pixel(x, y)[0]])
+    })
+}
+
+/// Frame blended over an opaque background according to the mask.
+pub fn composite_rgb(frame: &RgbImage, mask: &GrayImage, mode: MatteMode) -> RgbImage {
+    let blurred = matches!(mode, MatteMode::Blur).then(|| image::imageops::blur(frame, BLUR_SIGMA));
+    let mut out = frame.clone();
+    for (x, y, px) in out.enumerate_pixels_mut() {
+        let alpha = mask.get_pixel(x, y)[0] as u32;
+        if alpha == 255 {
+            continue;
+        }
+        let bg = match (mode, &blurred) {
+            (MatteMode::Color(c), _) => c,
+            (_, Some(b)) => b.get_pixel(x, y).0,
+            _ => [0, 0, 0],
+        };
+        for c in 0..3 {
+            px[c] = ((px[c] as u32 * alpha + bg[c] as u32 * (255 - alpha) + 127) / 255) as u8;
+        }
+    }
+    out
+}
@@ -0,0 +1,215 @@
+//! MJPEG frame decoding for V4L2 cameras.
+//!
+//! Many USB cameras send "AVI1"-style motion JPEG: each frame omits its DHT (Huffman table)
+//! segments and relies on the standard tables from JPEG Annex K.3, which a still-image decoder
+//! then rejects. Such frames get those tables inserted before the first scan.
+
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+
+const SOI: u8 = 0xD8;
+const SOS: u8 = 0xDA;
+const DHT: u8 = 0xC4;
+
+/// Annex K.3 tables as `(class << 4 | id, code counts per length 1..=16, symbols)`.
+const STANDARD_TABLES: [(u8, [u8; 16], &[u8]); 4] = [
+    (
+        0x00,
+        [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+    ),
+    (
+        0x01,
+        [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0],
+        &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+    ),
+    (
+        0x10,
+        [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d],
+        &[
+            0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51,
+            0x61, 0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1,
+            0x15, 0x52, 0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18,
+            0x19, 0x1a, 0x25, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39,
+            0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57,
+            0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75,
+            0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92,
+            0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+            0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+            0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8,
+            0xd9, 0xda, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2,
+            0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+        ],
+    ),
+    (
+        0x11,
+        [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77],
+        &[
+            0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07,
+            0x61, 0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09,
+            0x23, 0x33, 0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25,
+            0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38,
+            0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56,
+            0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74,
+            0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+            0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+            0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba,
+            0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6,
+            0xd7, 0xd8, 0xd9, 0xda, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2,
+            0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+        ],
+    ),
+];
+
+/// Offset of the first SOS marker if no DHT segment comes before it, i.e. where the standard
+/// tables have to go. `None` for frames that carry their own tables or are not a JPEG at all
+/// (left for the decoder to report).
+pub fn missing_dht_at(jpeg: &[u8]) -> Option<usize> {
+    if jpeg.get(..2) != Some(&[0xFF, SOI]) {
+        return None;
+    }
+    let mut i = 2;
+    loop {
+        if *jpeg.get(i)? != 0xFF {
+            return None;
+        }
+        // Any number of 0xFF fill bytes may precede a marker.
+        let mut m = i + 1;
+        while *jpeg.get(m)? == 0xFF {
+            m += 1;
+        }
+        match jpeg[m] {
+            DHT => return None,
+            SOS => return Some(m - 1),
+            // Standalone markers without a length.
+            0x01 | 0xD0..=0xD8 => i = m + 1,
+            _ => {
+                let len = u16::from_be_bytes([*jpeg.get(m + 1)?, *jpeg.get(m + 2)?]) as usize;
+                i = m + 1 + len;
+            }
+        }
+    }
+}
+
+/// A DHT segment holding the four Annex K.3 tables.
+fn standard_dht() -> Vec<u8> {
+    let len = 2 + STANDARD_TABLES
+        .iter()
+        .map(|(_, _, symbols)| 17 + symbols.len())
+        .sum::<usize>();
+    let mut segment = vec![0xFF, DHT];
+    segment.extend_from_slice(&(len as u16).to_be_bytes());
+    for (class_id, counts, symbols) in STANDARD_TABLES {
+        segment.push(class_id);
+        segment.extend_from_slice(&counts);
+        segment.extend_from_slice(symbols);
+    }
+    segment
+}
+
+/// `jpeg` with the standard Huffman tables inserted if it has none; borrowed otherwise.
+pub fn with_huffman_tables(jpeg: &[u8]) -> Cow<'_, [u8]> {
+    let Some(at) = missing_dht_at(jpeg) else {
+        return Cow::Borrowed(jpeg);
+    };
+    let dht = standard_dht();
+    let mut fixed = Vec::with_capacity(jpeg.len() + dht.len());
+    fixed.extend_from_slice(&jpeg[..at]);
+    fixed.extend_from_slice(&dht);
+    fixed.extend_from_slice(&jpeg[at..]);
+    Cow::Owned(fixed)
+}
+
+/// Decode one MJPEG frame, repairing missing Huffman tables first.
+pub fn decode_mjpeg(jpeg: &[u8]) -> Result<image::RgbImage> {
+    let jpeg = with_huffman_tables(jpeg);
+    let img = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg)
+        .context("failed to decode MJPEG frame")?;
+    Ok(img.to_rgb8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Synthetic 64x48 frame, not a camera capture: an encoded test image with its DHT segment
+    /// removed, which is the layout of a UVC camera's MJPEG output.
+    const SYNTHETIC_NO_DHT_FRAME: &[u8] = include_bytes!("../tests/data/synthetic_no_dht.jpg");
+
+    fn encode(img: &image::RgbImage) -> Vec<u8> {
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 90)
+            .encode_image(img)
+            .unwrap();
+        jpeg
+    }
+
+    /// Remove every DHT segment, as AVI1-style encoders do.
+    fn strip_dht(jpeg: &[u8]) -> Vec<u8> {
+        let mut out = jpeg[..2].to_vec();
+        let mut i = 2;
+        while jpeg[i + 1] != SOS {
+            let len = u16::from_be_bytes([jpeg[i + 2], jpeg[i + 3]]) as usize;
+            if jpeg[i + 1] != DHT {
+                out.extend_from_slice(&jpeg[i..i + 2 + len]);
+            }
+            i += 2 + len;
+        }
+        out.extend_from_slice(&jpeg[i..]);
+        out
+    }
+
+    fn gradient() -> image::RgbImage {
+        image::RgbImage::from_fn(64, 48, |x, y| {
+            image::Rgb([(x * 4) as u8, (y * 5) as u8, 128])
+        })
+    }
+
+    #[test]
+    fn standard_table_counts_match_symbols() {
+        for (_, counts, symbols) in STANDARD_TABLES {
+            assert_eq!(
+                counts.iter().map(|&c| c as usize).sum::<usize>(),
+                symbols.len()
+            );
+        }
+    }
+
+    #[test]
+    fn repairs_frame_without_tables() {
+        assert!(missing_dht_at(SYNTHETIC_NO_DHT_FRAME).is_some());
+        assert!(image::load_from_memory(SYNTHETIC_NO_DHT_FRAME).is_err());
+        let img = decode_mjpeg(SYNTHETIC_NO_DHT_FRAME).unwrap();
+        assert_eq!(img.dimensions(), (64, 48));
+        // Same pixels as the frame's source, within JPEG loss.
+        let source = gradient();
+        let max_diff = img
+            .as_raw()
+            .iter()
+            .zip(source.as_raw())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max();
+        assert!(max_diff.unwrap() < 16, "max diff {max_diff:?}");
+    }
+
+    #[test]
+    fn repair_matches_original_tables() {
+        let jpeg = encode(&gradient());
+        assert_eq!(missing_dht_at(&jpeg), None);
+        assert!(matches!(with_huffman_tables(&jpeg), Cow::Borrowed(_)));
+        let stripped = strip_dht(&jpeg);
+        assert!(missing_dht_at(&stripped).is_some());
+        assert_eq!(
+            decode_mjpeg(&stripped).unwrap(),
+            decode_mjpeg(&jpeg).unwrap()
+        );
+    }
+
+    #[test]
+    fn ignores_non_jpeg_and_truncated_input() {
+        assert_eq!(missing_dht_at(b""), None);
+        assert_eq!(missing_dht_at(b"not a jpeg"), None);
+        assert_eq!(missing_dht_at(&[0xFF, SOI, 0xFF, 0xE0, 0x00]), None);
+        assert!(decode_mjpeg(&[0xFF, SOI, 0xFF]).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Tiled multi-camera view: one grab+inference worker thread per camera, one window.
+
+use anyhow::{Context, Result};
+use image::RgbImage;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::JoinHandle;
+use usls::Viewer;
+
+use crate::preprocess::{OutputSize, fit_to_size};
+use crate::viz::{draw_text_box, text_scale, to_rgb};
+
+/// Tile size when `--output-size` is not given.
+pub const DEFAULT_TILE: OutputSize = OutputSize {
+    width: 640,
+    height: 480,
+};
+
+enum TileMsg {
+    Frame(usls::Image),
+    Status(String),
+    Failed(String),
+}
+
+/// Handle given to each camera worker to publish frames to its tile.
+pub struct TileSender {
+    index: usize,
+    tx: Sender<(usize, TileMsg)>,
+    stop: Arc<AtomicBool>,
+}
+
+impl TileSender {
+    /// Set when the user quits; workers should release their camera and return.
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub fn send_frame(&self, img: usls::Image) {
+        let _ = self.tx.send((self.index, TileMsg::Frame(img)));
+    }
+
+    /// Show `msg` on a black tile (e.g. while the model loads).
+    pub fn status(&self, msg: impl Into<String>) {
+        let _ = self.tx.send((self.index, TileMsg::Status(msg.into())));
+    }
+}
+
+/// Runs one worker thread per camera and composites their latest frames into a grid.
+///
+/// A worker that fails (camera disconnect, model error, panic) turns its tile black with the
+/// error message; the other cameras keep running.
+pub struct MultiCameraRunner {
+    names: Vec<String>,
+    tile: OutputSize,
+    tx: Sender<(usize, TileMsg)>,
+    rx: Receiver<(usize, TileMsg)>,
+    stop: Arc<AtomicBool>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl MultiCameraRunner {
+    pub fn new(names: Vec<String>, tile: OutputSize) -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            names,
+            tile,
+            tx,
+            rx,
+            stop: Arc::new(AtomicBool::new(false)),
+            handles: Vec::new(),
+        }
+    }
+
+    /// Share `stop` (e.g. [`crate::shutdown::StopSignal::flag`]) so setting it ends the run.
+    pub fn with_stop_flag(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Spawn `worker(name, tile)` on its own thread for every camera.
+    pub fn spawn_workers<F>(&mut self, worker: F) -> Result<()>
+    where
+        F: Fn(&str, &TileSender) -> Result<()> + Send + Sync + 'static,
+    {
+        let worker = Arc::new(worker);
+        for (index, name) in self.names.iter().enumerate() {
+            let sender = TileSender {
+                index,
+                tx: self.tx.clone(),
+                stop: self.stop.clone(),
+            };
+            let worker = worker.clone();
+            let name = name.clone();
+            let handle = std::thread::Builder::new()
+                .name(format!("cam-{name}"))
+                .spawn(move || {
+                    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        worker(&name, &sender)
+                    }));
+                    let err = match result {
+                        Ok(Ok(())) => return,
+                        Ok(Err(e)) => format!("{e:#}"),
+                        Err(_) => "worker panicked".to_string(),
+                    };
+                    tracing::error!("Camera {name} stopped: {err}");
+                    let _ = sender.tx.send((index, TileMsg::Failed(err)));
+                })
+                .with_context(|| {
+                    format!("failed to spawn worker thread for {}", self.names[index])
+                })?;
+            self.handles.push(handle);
+        }
+        Ok(())
+    }
+
+    /// Composite and display tiles until the window is closed or ESC/Q is pressed.
+    ///
+    /// The compositor runs on the calling thread since the window has to be driven from it.
+    /// `S` saves the current composite to `save_base`.
+    pub fn run(mut self, viewer: &mut Viewer, save_base: &Path) -> Result<()> {
+        let n = self.names.len();
+        let cols = (n as f64).sqrt().ceil().max(1.0) as u32;
+        let rows = (n as u32).div_ceil(cols);
+        let mut tiles: Vec<RgbImage> = self
+            .names
+            .iter()
+            .map(|name| self.message_tile(name, "waiting for frames"))
+            .collect();
+
+        let result = loop {
+            // Only the newest message per camera matters; older frames are dropped unconverted.
+            let mut latest: Vec<Option<TileMsg>> = (0..n).map(|_| None).collect();
+            while let Ok((index, msg)) = self.rx.try_recv() {
+                latest[index] = Some(msg);
+            }
+            for (index, msg) in latest.into_iter().enumerate() {
+                let Some(msg) = msg else {
+                    continue;
+                };
+                let name = &self.names[index];
+                tiles[index] = match msg {
+                    TileMsg::Frame(img) => match self.frame_tile(name, &img) {
+                        Ok(tile) => tile,
+                        Err(e) => self.message_tile(name, &format!("error: {e:#}")),
+                    },
+                    TileMsg::Status(s) => self.message_tile(name, &s),
+                    TileMsg::Failed(e) => self.message_tile(name, &format!("error: {e}")),
+                };
+            }
+
+            let mut canvas = RgbImage::new(cols * self.tile.width, rows * self.tile.height);
+            for (i, tile) in tiles.iter().enumerate() {
+                let (c, r) = (i as u32 % cols, i as u32 / cols);
+                image::imageops::replace(
+                    &mut canvas,
+                    tile,
+                    (c * self.tile.width) as i64,
+                    (r * self.tile.height) as i64,
+                );
+            }
+            let composite = usls::Image::from(canvas);
+
+            if self.stop.load(Ordering::Relaxed) || viewer.is_window_exist_and_closed() {
+                break Ok(());
+            }
+            if let Err(e) = viewer.imshow(&composite) {
+                break Err(e);
+            }
+            match viewer.wait_key(30) {
+                Some(usls::Key::Escape | usls::Key::Q) => break Ok(()),
+                Some(usls::Key::S) => {
+                    let path = save_base.join(format!("{}.jpg", usls::timestamp(None)));
+                    if let Err(e) = composite.save(&path) {
+                        break Err(e);
+                    }
+                    tracing::info!("Saved: {}", path.display());
+                }
+                _ => {}
+            }
+        };
+
+        self.stop.store(true, Ordering::Relaxed);
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+        result
+    }
+
+    fn frame_tile(&self, name: &str, img: &usls::Image) -> Result<RgbImage> {
+        let rgb = to_rgb(img)?;
+        let mut tile = fit_to_size(rgb, self.tile, true);
+        draw_text_box(&mut tile, &[name.to_string()], text_scale(self.tile.height));
+        Ok(tile)
+    }
+
+    fn message_tile(&self, name: &str, msg: &str) -> RgbImage {
+        let mut tile = RgbImage::new(self.tile.width, self.tile.height);
+        let scale = text_scale(self.tile.height);
+        // 6 px per glyph (5 + spacing); wrap so long errors stay inside the tile.
+        let per_line = ((self.tile.width / (6 * scale)).saturating_sub(2)).max(8) as usize;
+        let mut lines = vec![name.to_string()];
+        let chars: Vec<char> = msg.chars().collect();
+        lines.extend(chars.chunks(per_line).map(|c| c.iter().collect::<String>()));
+        draw_text_box(&mut tile, &lines, scale);
+        tile
+    }
+}
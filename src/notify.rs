@@ -0,0 +1,259 @@
+//! `--notify`: alert when a prompt label enters.
+//!
+//! Enters come from the same [`PresenceTracker`] as the event log, so a label fires once when it
+//! appears, not on every inference it stays in view. `--notify-cooldown-secs` additionally keeps
+//! a label that flickers in and out from firing again until the cooldown has passed.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::events::{PresenceTracker, present_labels};
+
+/// How a notification is delivered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotifyKind {
+    /// Terminal bell on stderr.
+    Bell,
+    /// freedesktop notification over D-Bus (`--features desktop-notify`).
+    Desktop,
+    /// `--notify-command`, run through `sh -c`.
+    Command,
+}
+
+impl std::str::FromStr for NotifyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "bell" => Ok(Self::Bell),
+            "desktop" => Ok(Self::Desktop),
+            "command" => Ok(Self::Command),
+            _ => Err(format!(
+                "invalid notify kind `{s}` (expected bell, desktop or command)"
+            )),
+        }
+    }
+}
+
+/// `--notify*` settings.
+pub struct NotifyOptions {
+    pub kind: NotifyKind,
+    pub command: Option<String>,
+    pub cooldown_secs: f32,
+    pub max_running: usize,
+    pub timeout_secs: f32,
+    /// Where `command` notifications save the triggering frame.
+    pub snapshot_dir: PathBuf,
+}
+
+pub struct Notifier {
+    opts: NotifyOptions,
+    presence: PresenceTracker,
+    last_fired: HashMap<String, Instant>,
+    /// Running `--notify-command`s and when they were started.
+    running: Vec<(Child, Instant)>,
+}
+
+impl Notifier {
+    pub fn new(opts: NotifyOptions) -> Result<Self> {
+        if !(opts.cooldown_secs.is_finite() && opts.cooldown_secs >= 0.0) {
+            anyhow::bail!("--notify-cooldown-secs must be >= 0");
+        }
+        if !(opts.timeout_secs.is_finite() && opts.timeout_secs > 0.0) {
+            anyhow::bail!("--notify-timeout-secs must be > 0");
+        }
+        match opts.kind {
+            NotifyKind::Command => {
+                if opts.command.is_none() {
+                    anyhow::bail!("--notify command requires --notify-command <shell command>");
+                }
+                if opts.max_running == 0 {
+                    anyhow::bail!("--notify-max-running must be >= 1");
+                }
+                std::fs::create_dir_all(&opts.snapshot_dir).with_context(|| {
+                    format!(
+                        "failed to create notify snapshot directory: {}",
+                        opts.snapshot_dir.display()
+                    )
+                })?;
+            }
+            NotifyKind::Desktop if !cfg!(feature = "desktop-notify") => {
+                anyhow::bail!("--notify desktop needs a build with `--features desktop-notify`")
+            }
+            _ => {}
+        }
+        Ok(Self {
+            opts,
+            presence: PresenceTracker::default(),
+            last_fired: HashMap::new(),
+            running: Vec::new(),
+        })
+    }
+
+    /// Labels to notify for: enters that are not within the cooldown of their last notification.
+    fn due(&mut self, labels: BTreeSet<String>, now: Instant) -> Vec<String> {
+        let cooldown = Duration::from_secs_f32(self.opts.cooldown_secs);
+        let (entered, _) = self.presence.update(labels);
+        entered
+            .into_iter()
+            .filter(|label| {
+                let ready = self
+                    .last_fired
+                    .get(label)
+                    .is_none_or(|t| now.duration_since(*t) >= cooldown);
+                if ready {
+                    self.last_fired.insert(label.clone(), now);
+                }
+                ready
+            })
+            .collect()
+    }
+
+    /// Feed one inference result; `frame` is saved for `command` notifications.
+    pub fn update(&mut self, y: &usls::Y, frame: &usls::Image) -> Result<()> {
+        self.reap();
+        for label in self.due(present_labels(y), Instant::now()) {
+            let confidence = y
+                .hbbs()
+                .iter()
+                .filter(|h| h.name().unwrap_or("object") == label)
+                .filter_map(|h| h.confidence())
+                .fold(None, |best: Option<f32>, c| {
+                    Some(best.map_or(c, |b| b.max(c)))
+                });
+            tracing::info!("Notify: `{label}` entered");
+            match self.opts.kind {
+                NotifyKind::Bell => {
+                    let mut stderr = std::io::stderr();
+                    stderr.write_all(b"\x07").ok();
+                    stderr.flush().ok();
+                }
+                NotifyKind::Desktop => desktop(&label, confidence),
+                NotifyKind::Command => self.run_command(&label, confidence, frame)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn run_command(
+        &mut self,
+        label: &str,
+        confidence: Option<f32>,
+        frame: &usls::Image,
+    ) -> Result<()> {
+        if self.running.len() >= self.opts.max_running {
+            tracing::warn!(
+                "Notify: {} command(s) still running; skipping `{label}`",
+                self.running.len()
+            );
+            return Ok(());
+        }
+        let safe: String = label
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let snapshot = self
+            .opts
+            .snapshot_dir
+            .join(format!("{}_{safe}.jpg", usls::timestamp(None)));
+        frame.save(&snapshot)?;
+        let command = self.opts.command.as_deref().unwrap_or_default();
+        let child = Command::new("sh")
+            .args(["-c", command])
+            .env("SAM3_PROMPT", label)
+            .env(
+                "SAM3_CONFIDENCE",
+                confidence.map_or(String::new(), |c| format!("{c:.3}")),
+            )
+            .env("SAM3_SNAPSHOT", &snapshot)
+            .stdin(Stdio::null())
+            .spawn()
+            .context("failed to run --notify-command")?;
+        self.running.push((child, Instant::now()));
+        Ok(())
+    }
+
+    /// Drop finished commands and kill those past `--notify-timeout-secs`.
+    fn reap(&mut self) {
+        let timeout = Duration::from_secs_f32(self.opts.timeout_secs.max(0.0));
+        self.running.retain_mut(|(child, started)| {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    if !status.success() {
+                        tracing::warn!("Notify command exited with {status}");
+                    }
+                    return false;
+                }
+                Ok(None) if started.elapsed() < timeout => return true,
+                Ok(None) => tracing::warn!("Notify command timed out; killing it"),
+                Err(e) => tracing::warn!("Notify command: {e}"),
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+            false
+        });
+    }
+
+    /// Give running commands until their timeout to finish.
+    pub fn finish(mut self) {
+        while !self.running.is_empty() {
+            self.reap();
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+#[cfg(feature = "desktop-notify")]
+fn desktop(label: &str, confidence: Option<f32>) {
+    let body = match confidence {
+        Some(c) => format!("`{label}` entered (confidence {c:.2})"),
+        None => format!("`{label}` entered"),
+    };
+    if let Err(e) = notify_rust::Notification::new()
+        .summary("sam3")
+        .body(&body)
+        .show()
+    {
+        tracing::warn!("Desktop notification failed: {e}");
+    }
+}
+
+#[cfg(not(feature = "desktop-notify"))]
+fn desktop(_label: &str, _confidence: Option<f32>) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn fires_on_enter_and_respects_cooldown() {
+        let mut notifier = Notifier::new(NotifyOptions {
+            kind: NotifyKind::Bell,
+            command: None,
+            cooldown_secs: 10.0,
+            max_running: 1,
+            timeout_secs: 1.0,
+            snapshot_dir: PathBuf::new(),
+        })
+        .unwrap();
+        let t0 = Instant::now();
+        assert_eq!(notifier.due(labels(&["person"]), t0), ["person"]);
+        // Still present: no new enter.
+        assert!(notifier.due(labels(&["person"]), t0).is_empty());
+        // Flickers out and back within the cooldown.
+        notifier.due(labels(&[]), t0);
+        let soon = t0 + Duration::from_secs(3);
+        assert!(notifier.due(labels(&["person", "dog"]), soon) == ["dog"]);
+        notifier.due(labels(&[]), soon);
+        let later = t0 + Duration::from_secs(11);
+        assert_eq!(notifier.due(labels(&["person"]), later), ["person"]);
+    }
+}
@@ -0,0 +1,76 @@
+//! `v4l-sam3 --backend opencv`: cameras through OpenCV `VideoCapture` (macOS, Windows, or Linux
+//! without V4L2 access). Frames arrive as BGR `Mat`s and are converted to RGB8.
+
+use anyhow::{Context, Result};
+use opencv::core::Mat;
+use opencv::prelude::*;
+use opencv::{imgproc, videoio};
+
+use crate::common::VideoSource;
+
+pub struct OpenCvCamera {
+    capture: videoio::VideoCapture,
+    width: u32,
+    height: u32,
+    fps: Option<f32>,
+    bgr: Mat,
+    rgb: Mat,
+}
+
+impl OpenCvCamera {
+    /// Open camera `index` with the platform's default API, asking for `width` x `height`.
+    pub fn open(index: usize, width: u32, height: u32) -> Result<Self> {
+        let index = i32::try_from(index).context("camera index out of range")?;
+        let mut capture = videoio::VideoCapture::new(index, videoio::CAP_ANY)
+            .with_context(|| format!("failed to open OpenCV camera {index}"))?;
+        if !capture.is_opened()? {
+            anyhow::bail!("OpenCV could not open camera {index}");
+        }
+        // Best-effort, like the V4L2 format request; the backend may pick another mode.
+        capture.set(videoio::CAP_PROP_FRAME_WIDTH, f64::from(width))?;
+        capture.set(videoio::CAP_PROP_FRAME_HEIGHT, f64::from(height))?;
+        let width = capture.get(videoio::CAP_PROP_FRAME_WIDTH)? as u32;
+        let height = capture.get(videoio::CAP_PROP_FRAME_HEIGHT)? as u32;
+        let fps = capture.get(videoio::CAP_PROP_FPS)? as f32;
+        tracing::info!("OpenCV camera {index}: {width}x{height}");
+        Ok(Self {
+            capture,
+            width,
+            height,
+            fps: (fps.is_finite() && fps > 0.0).then_some(fps),
+            bgr: Mat::default(),
+            rgb: Mat::default(),
+        })
+    }
+}
+
+impl VideoSource for OpenCvCamera {
+    fn next_frame(&mut self) -> Result<Option<usls::Image>> {
+        if !self
+            .capture
+            .read(&mut self.bgr)
+            .context("failed to capture frame")?
+            || self.bgr.empty()
+        {
+            anyhow::bail!("OpenCV camera returned no frame");
+        }
+        imgproc::cvt_color(&self.bgr, &mut self.rgb, imgproc::COLOR_BGR2RGB, 0)
+            .context("failed to convert BGR frame to RGB")?;
+        let (width, height) = (self.rgb.cols() as u32, self.rgb.rows() as u32);
+        let bytes = self
+            .rgb
+            .data_bytes()
+            .context("RGB frame is not contiguous")?;
+        let img = image::RgbImage::from_raw(width, height, bytes.to_vec())
+            .context("failed to construct RgbImage")?;
+        Ok(Some(usls::Image::from(img)))
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn fps(&self) -> Option<f32> {
+        self.fps
+    }
+}
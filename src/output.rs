@@ -0,0 +1,1307 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{Sender, bounded};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread::JoinHandle;
+use usls::models::Sam3Prompt;
+
+use crate::color::ColorMatrix;
+use crate::common::annotate_frame;
+use crate::dwell::ZoneEvent;
+use crate::ffmpeg_log::{FfmpegLogLevel, StderrDrain, loglevel_args};
+use crate::geometry::{self, from_xywh};
+use crate::postprocess::{ExclusiveWin, paired_boxes, split_by_prompt};
+use crate::quality::{FrameQuality, QualityIssue};
+use crate::redact::{InputUrl, scrub_credentials};
+use crate::rle::{self, Rle};
+
+/// `--output-fps`: picks which input frames go to the encoder so the output runs at a lower rate.
+///
+/// Frame `n` is kept when it is the first to reach the next output slot, which spreads drops
+/// evenly for non-integer ratios (e.g. 25 -> 10 fps keeps 2 of every 5 frames).
+pub struct FrameDecimator {
+    ratio: f64,
+    kept: u64,
+}
+
+impl FrameDecimator {
+    pub fn new(input_fps: f32, output_fps: f32) -> Result<Self> {
+        if !(output_fps.is_finite() && output_fps > 0.0) {
+            anyhow::bail!("--output-fps must be > 0 (got {output_fps})");
+        }
+        if output_fps > input_fps {
+            anyhow::bail!(
+                "--output-fps {output_fps} exceeds the input rate {input_fps:.3}; frames are only dropped, never duplicated"
+            );
+        }
+        Ok(Self {
+            ratio: output_fps as f64 / input_fps as f64,
+            kept: 0,
+        })
+    }
+
+    /// Whether 1-based `frame_idx` should be encoded.
+    pub fn keep(&mut self, frame_idx: u64) -> bool {
+        // Output slots reached by the start of this frame, with slack for float error.
+        let due = ((frame_idx - 1) as f64 * self.ratio + 1e-9).floor() as u64 + 1;
+        if due > self.kept {
+            self.kept = due;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Encodes RGB24 frames to a video file by piping them into `ffmpeg`.
+pub struct FfmpegVideoWriter {
+    child: Child,
+    stderr: StderrDrain,
+    output: PathBuf,
+    /// `--preserve-audio`: source whose audio is muxed in by [`FfmpegVideoWriter::finish`].
+    audio_source: Option<InputUrl>,
+}
+
+impl FfmpegVideoWriter {
+    /// `color` is the matrix the RGB frames are converted to yuv420p with, and tagged as;
+    /// `loglevel` is the `--ffmpeg-loglevel`.
+    pub fn spawn(
+        output: &Path,
+        width: u32,
+        height: u32,
+        fps: f32,
+        color: ColorMatrix,
+        loglevel: FfmpegLogLevel,
+    ) -> Result<Self> {
+        if let Some(parent) = output.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create output directory: {}", parent.display()))?;
+        }
+
+        let mut cmd = Command::new("ffmpeg");
+        cmd.arg("-hide_banner")
+            .args(loglevel_args(loglevel))
+            .arg("-y");
+        cmd.args(["-f", "rawvideo", "-pix_fmt", "rgb24"]);
+        cmd.args(["-video_size", &format!("{width}x{height}")]);
+        cmd.args(["-framerate", &format!("{fps:.3}")]);
+        cmd.args(["-i", "-"]);
+        cmd.args(["-an", "-sn", "-dn"]);
+        cmd.args(["-vf", &color.encode_filter()]);
+        cmd.args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23"]);
+        cmd.args(["-pix_fmt", "yuv420p"]);
+        cmd.args(color.encode_tags());
+        cmd.arg(output);
+        // Keep a terminal Ctrl-C away from the encoder: the run stops on its own and closes stdin,
+        // so ffmpeg still gets every frame and writes the trailer.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| "failed to run `ffmpeg` for encoding (is FFmpeg installed?)")?;
+        let stderr = StderrDrain::spawn(
+            child.stderr.take().context("ffmpeg stderr missing")?,
+            "ffmpeg (encoder)",
+        );
+
+        Ok(Self {
+            child,
+            stderr,
+            output: output.to_path_buf(),
+            audio_source: None,
+        })
+    }
+
+    /// Mux the first audio track of `source` into the output once encoding finishes.
+    pub fn with_audio_from(mut self, source: &InputUrl) -> Self {
+        self.audio_source = Some(source.clone());
+        self
+    }
+
+    pub fn write_frame(&mut self, img: &usls::Image) -> Result<()> {
+        let Some(stdin) = self.child.stdin.as_mut() else {
+            anyhow::bail!("ffmpeg stdin missing");
+        };
+        stdin
+            .write_all(img.as_raw())
+            .context("failed to write frame bytes to ffmpeg")?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        drop(self.child.stdin.take());
+        let status = self
+            .child
+            .wait()
+            .context("failed to wait for ffmpeg (encoder)")?;
+        let err = self.stderr.join();
+        if !status.success() {
+            anyhow::bail!(
+                "ffmpeg (encoder) exited with {status}: {}",
+                err.trim()
+            );
+        }
+        match self.audio_source.take() {
+            Some(source) => mux_audio(&self.output, &source),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `--gst-sink`: annotated frames piped into a `gst-launch-1.0` pipeline.
+///
+/// The pipeline is `fdsrc ! rawvideoparse ! <user pipeline>`; `rawvideoparse` cuts the byte stream
+/// into RGB frames with the given caps (a bare `video/x-raw` capsfilter after `fdsrc` would not).
+pub struct GstSinkWriter {
+    child: Child,
+    stderr: StderrDrain,
+}
+
+impl GstSinkWriter {
+    pub fn spawn(pipeline: &str, width: u32, height: u32, fps: f32) -> Result<Self> {
+        let framerate = (fps * 1000.0).round().max(1.0) as u32;
+        let launch = format!(
+            "fdsrc fd=0 ! rawvideoparse format=rgb width={width} height={height} \
+             framerate={framerate}/1000 ! {pipeline}"
+        );
+        tracing::info!("GStreamer sink: {launch}");
+        let mut cmd = Command::new("gst-launch-1.0");
+        // `-e` turns the EOS from a closed stdin into a clean shutdown of muxers and encoders.
+        cmd.args(["-q", "-e", &launch]);
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| "failed to run `gst-launch-1.0` (is GStreamer installed?)")?;
+        let stderr = StderrDrain::spawn(
+            child
+                .stderr
+                .take()
+                .context("gst-launch-1.0 stderr missing")?,
+            "gst-launch-1.0",
+        );
+        Ok(Self { child, stderr })
+    }
+
+    pub fn write_frame(&mut self, img: &usls::Image) -> Result<()> {
+        let Some(stdin) = self.child.stdin.as_mut() else {
+            anyhow::bail!("gst-launch-1.0 stdin missing");
+        };
+        stdin
+            .write_all(img.as_raw())
+            .context("failed to write frame bytes to gst-launch-1.0 (pipeline stopped?)")?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        drop(self.child.stdin.take());
+        let status = self
+            .child
+            .wait()
+            .context("failed to wait for gst-launch-1.0")?;
+        let err = self.stderr.join();
+        if !status.success() {
+            anyhow::bail!("gst-launch-1.0 exited with {status}: {}", err.trim());
+        }
+        Ok(())
+    }
+}
+
+/// Whether `input` has at least one audio stream.
+fn has_audio_stream(input: &InputUrl) -> Result<bool> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "a", "-show_streams"])
+        .args(["-show_entries", "stream=index", "-of", "csv=p=0"])
+        .args(input.ffmpeg_args()?)
+        .output()
+        .context("failed to run `ffprobe` (is FFmpeg installed?)")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "ffprobe failed: {}",
+            scrub_credentials(String::from_utf8_lossy(&output.stderr).trim())
+        );
+    }
+    Ok(!String::from_utf8_lossy(&output.stdout).trim().is_empty())
+}
+
+/// Replace the silent `video` with a copy that also carries `source`'s first audio track.
+fn mux_audio(video: &Path, source: &InputUrl) -> Result<()> {
+    if !has_audio_stream(source)? {
+        tracing::info!("--preserve-audio: {source} has no audio track; output stays silent");
+        return Ok(());
+    }
+    let ext = video.extension().and_then(|e| e.to_str()).unwrap_or("mp4");
+    let muxed = video.with_extension(format!("audio.{ext}"));
+    let result = Command::new("ffmpeg")
+        .args(["-hide_banner", "-loglevel", "error", "-y"])
+        .arg("-i")
+        .arg(video)
+        .args(source.ffmpeg_args()?)
+        .args([
+            "-c:v", "copy", "-c:a", "copy", "-map", "0:v:0", "-map", "1:a:0",
+        ])
+        // The annotated stream is shorter when the run stopped early.
+        .arg("-shortest")
+        .arg(&muxed)
+        .stdin(Stdio::null())
+        .output()
+        .context("failed to run `ffmpeg` for muxing (is FFmpeg installed?)")?;
+    if !result.status.success() {
+        std::fs::remove_file(&muxed).ok();
+        anyhow::bail!(
+            "ffmpeg (audio mux) exited with {}: {} (the silent video is kept at {})",
+            result.status,
+            scrub_credentials(String::from_utf8_lossy(&result.stderr).trim()),
+            video.display()
+        );
+    }
+    std::fs::rename(&muxed, video)
+        .with_context(|| format!("failed to replace {} with the muxed file", video.display()))?;
+    tracing::info!("Muxed source audio into: {}", video.display());
+    Ok(())
+}
+
+impl Drop for FfmpegVideoWriter {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+    }
+}
+
+/// Frame rate of the `--timelapse-video` clip assembled from interval snapshots.
+pub const TIMELAPSE_FPS: f32 = 10.0;
+
+/// `--snapshot-interval`: saves one output frame every `interval` seconds.
+///
+/// The clock is supplied by the caller (media time for video files, wall time for cameras).
+pub struct IntervalSnapshots {
+    dir: PathBuf,
+    interval_s: f64,
+    next_at_s: f64,
+    saved: Vec<PathBuf>,
+}
+
+impl IntervalSnapshots {
+    pub fn new(dir: &Path, interval_secs: f32) -> Result<Self> {
+        Self::for_flag(dir, interval_secs, "--snapshot-interval")
+    }
+
+    fn for_flag(dir: &Path, interval_secs: f32, flag: &str) -> Result<Self> {
+        if !(interval_secs.is_finite() && interval_secs > 0.0) {
+            anyhow::bail!("{flag} must be > 0 (got {interval_secs})");
+        }
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create snapshot directory: {}", dir.display()))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            interval_s: interval_secs as f64,
+            next_at_s: 0.0,
+            saved: Vec::new(),
+        })
+    }
+
+    pub fn is_due(&self, now_s: f64) -> bool {
+        now_s >= self.next_at_s
+    }
+
+    /// Save `img` if the next slot is due at `now_s`.
+    pub fn maybe_save(&mut self, now_s: f64, img: &usls::Image) -> Result<()> {
+        if !self.is_due(now_s) {
+            return Ok(());
+        }
+        // After a stall, restart the cadence from now instead of saving a burst of catch-up frames.
+        self.next_at_s = if now_s >= self.next_at_s + self.interval_s {
+            now_s + self.interval_s
+        } else {
+            self.next_at_s + self.interval_s
+        };
+        let path = self.dir.join(format!(
+            "snapshot_{:05}_{}.jpg",
+            self.saved.len(),
+            usls::timestamp(None)
+        ));
+        img.save(&path)?;
+        tracing::debug!("Saved snapshot: {}", path.display());
+        self.saved.push(path);
+        Ok(())
+    }
+
+    /// Encode the saved snapshots, in order, into a clip at [`TIMELAPSE_FPS`].
+    pub fn write_timelapse(&self, output: &Path, loglevel: FfmpegLogLevel) -> Result<()> {
+        let Some(first) = self.saved.first() else {
+            tracing::warn!("No snapshots were saved; skipping time-lapse video");
+            return Ok(());
+        };
+        let (width, height) = image::image_dimensions(first)
+            .with_context(|| format!("failed to read snapshot: {}", first.display()))?;
+        let mut writer = FfmpegVideoWriter::spawn(
+            output,
+            width,
+            height,
+            TIMELAPSE_FPS,
+            ColorMatrix::default(),
+            loglevel,
+        )?;
+        for path in &self.saved {
+            let frame = image::open(path)
+                .with_context(|| format!("failed to read snapshot: {}", path.display()))?
+                .to_rgb8();
+            writer.write_frame(&usls::Image::from(frame))?;
+        }
+        writer.finish()?;
+        tracing::info!(
+            "Wrote time-lapse ({} frames) to: {}",
+            self.saved.len(),
+            output.display()
+        );
+        Ok(())
+    }
+}
+
+/// `--save-on-interval-secs`: [`IntervalSnapshots`] into `<save_base>/interval/` on the wall
+/// clock, whether or not anything was detected.
+pub struct IntervalSaver {
+    snapshots: IntervalSnapshots,
+    annotated: bool,
+    started: std::time::Instant,
+}
+
+impl IntervalSaver {
+    pub fn new(save_base: &Path, interval_secs: f32, annotated: bool) -> Result<Self> {
+        Ok(Self {
+            snapshots: IntervalSnapshots::for_flag(
+                &save_base.join("interval"),
+                interval_secs,
+                "--save-on-interval-secs",
+            )?,
+            annotated,
+            started: std::time::Instant::now(),
+        })
+    }
+
+    /// Whether the annotated frame (rather than the raw capture) should be saved.
+    pub fn annotated(&self) -> bool {
+        self.annotated
+    }
+
+    pub fn is_due(&self) -> bool {
+        self.snapshots.is_due(self.started.elapsed().as_secs_f64())
+    }
+
+    /// Save `img` if an interval has elapsed since the last save.
+    pub fn maybe_save(&mut self, img: &usls::Image) -> Result<()> {
+        self.snapshots
+            .maybe_save(self.started.elapsed().as_secs_f64(), img)
+    }
+}
+
+/// `label` for a path: every non-alphanumeric character becomes `_`, and the result is cut to 64
+/// characters.
+fn sanitize_path_component(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .take(64)
+        .collect()
+}
+
+/// Directory name for prompt `index` with text `label`: `<index>_<sanitized label>`, or just the
+/// index for a prompt without text (visual prompts). The index keeps apart prompts that sanitize
+/// alike (`red car`, `red-car`) and mirrored copies of a prompt.
+pub fn prompt_dir_name(index: usize, label: &str) -> String {
+    let name = sanitize_path_component(label);
+    if name.is_empty() {
+        index.to_string()
+    } else {
+        format!("{index}_{name}")
+    }
+}
+
+/// Frames [`PerPromptSaver`] may queue for its thread before `save_split` waits.
+const PER_PROMPT_QUEUE: usize = 4;
+
+/// One inference result for the [`PerPromptSaver`] thread.
+struct PerPromptJob {
+    annotator: usls::Annotator,
+    img: usls::Image,
+    y: usls::Y,
+    prompts: Vec<Sam3Prompt>,
+    name: String,
+}
+
+/// `--save-dir-per-prompt`: annotated frames under `<save-dir>/<prompt_dir_name>/`.
+///
+/// Annotating and writing run on a thread of their own, like `video-sam3`'s annotation workers,
+/// unless the saver is created `sync` (`--sync-annotate`).
+pub struct PerPromptSaver {
+    /// `sync`: the directories, written on the calling thread.
+    inline: Option<PromptDirs>,
+    jobs: Option<Sender<PerPromptJob>>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl PerPromptSaver {
+    pub fn new(base: PathBuf, sync: bool) -> Result<Self> {
+        let dirs = PromptDirs {
+            base,
+            created: HashSet::new(),
+        };
+        if sync {
+            return Ok(Self {
+                inline: Some(dirs),
+                jobs: None,
+                thread: None,
+            });
+        }
+        let (jobs, queue) = bounded::<PerPromptJob>(PER_PROMPT_QUEUE);
+        let thread = std::thread::Builder::new()
+            .name("save-per-prompt".to_string())
+            .spawn(move || {
+                let mut dirs = dirs;
+                for job in queue {
+                    dirs.save_split(&job.annotator, &job.img, &job.y, &job.prompts, &job.name)?;
+                }
+                Ok(())
+            })
+            .context("failed to spawn the per-prompt save thread")?;
+        Ok(Self {
+            inline: None,
+            jobs: Some(jobs),
+            thread: Some(thread),
+        })
+    }
+
+    /// Save `img` annotated with each prompt's part of `y` as `name` in that prompt's directory;
+    /// prompts that detected nothing are skipped. Without `sync` this only queues the frame; a
+    /// failed save is reported by a later call or by [`PerPromptSaver::finish`].
+    pub fn save_split(
+        &mut self,
+        annotator: &usls::Annotator,
+        img: &usls::Image,
+        y: &usls::Y,
+        prompts: &[Sam3Prompt],
+        name: &str,
+    ) -> Result<()> {
+        if let Some(dirs) = self.inline.as_mut() {
+            return dirs.save_split(annotator, img, y, prompts, name);
+        }
+        let job = PerPromptJob {
+            annotator: annotator.clone(),
+            img: img.clone(),
+            y: y.clone(),
+            prompts: prompts.to_vec(),
+            name: name.to_string(),
+        };
+        let sent = self.jobs.as_ref().is_some_and(|jobs| jobs.send(job).is_ok());
+        if !sent {
+            // The thread only stops early on an error; surface it.
+            return self.join();
+        }
+        Ok(())
+    }
+
+    /// Wait for the queued frames to be written.
+    pub fn finish(mut self) -> Result<()> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<()> {
+        self.jobs = None;
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| anyhow::anyhow!("per-prompt save thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for PerPromptSaver {
+    fn drop(&mut self) {
+        let _ = self.join();
+    }
+}
+
+/// The per-prompt directories under the save directory, created on first use.
+struct PromptDirs {
+    base: PathBuf,
+    created: HashSet<PathBuf>,
+}
+
+impl PromptDirs {
+    fn save_split(
+        &mut self,
+        annotator: &usls::Annotator,
+        img: &usls::Image,
+        y: &usls::Y,
+        prompts: &[Sam3Prompt],
+        name: &str,
+    ) -> Result<()> {
+        for (index, (prompt, y)) in prompts.iter().zip(split_by_prompt(y, prompts)).enumerate() {
+            if y.hbbs().is_empty() && y.masks().is_empty() {
+                continue;
+            }
+            let annotated = annotate_frame(annotator, img, &y, std::slice::from_ref(prompt))?;
+            self.save(&prompt_dir_name(index, &prompt.text), name, &annotated)?;
+        }
+        Ok(())
+    }
+
+    /// Save `img` as `name` in the directory `dir_name`, creating it on first use.
+    fn save(&mut self, dir_name: &str, name: &str, img: &usls::Image) -> Result<()> {
+        let dir = self.base.join(dir_name);
+        if !self.created.contains(&dir) {
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("failed to create prompt directory: {}", dir.display()))?;
+            tracing::debug!("Created per-prompt save directory: {}", dir.display());
+            self.created.insert(dir.clone());
+        }
+        let path = dir.join(name);
+        img.save(&path)
+            .with_context(|| format!("failed to save frame: {}", path.display()))
+    }
+}
+
+/// One detection in a [`FrameRecord`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Detection {
+    pub label: Option<String>,
+    pub class_id: Option<usize>,
+    pub confidence: Option<f32>,
+    /// `[x, y, w, h]` in frame pixels.
+    pub bbox: [f32; 4],
+    /// `--mask-encoding polygon|rle`: the detection's mask.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segmentation: Option<Segmentation>,
+}
+
+impl Detection {
+    pub fn from_y(y: &usls::Y) -> Vec<Self> {
+        y.hbbs()
+            .iter()
+            .map(|hbb| Self {
+                label: hbb.name().map(str::to_string),
+                class_id: hbb.id(),
+                confidence: hbb.confidence(),
+                bbox: [hbb.xmin(), hbb.ymin(), hbb.width(), hbb.height()],
+                segmentation: None,
+            })
+            .collect()
+    }
+
+    /// [`Detection::from_y`] plus each mask in `encoding`, for a `width` x `height` frame.
+    ///
+    /// Masks are matched to their box by class id (see [`paired_boxes`]); a box without one gets
+    /// no `segmentation`.
+    pub fn from_y_with_masks(
+        y: &usls::Y,
+        encoding: MaskEncoding,
+        width: u32,
+        height: u32,
+    ) -> Vec<Self> {
+        let mut detections = Self::from_y(y);
+        if encoding == MaskEncoding::None {
+            return detections;
+        }
+        let boxes = paired_boxes(y.hbbs(), y.masks().iter().map(|m| m.id()));
+        for (mask, i) in y.masks().iter().zip(boxes) {
+            let Some(detection) = i.and_then(|i| detections.get_mut(i)) else {
+                continue;
+            };
+            detection.segmentation = match encoding {
+                MaskEncoding::Polygon => mask.polygon().map(|p| Segmentation::Polygon(p.points())),
+                MaskEncoding::Rle => {
+                    let m = mask.mask();
+                    Some(Segmentation::Rle(if m.dimensions() == (width, height) {
+                        rle::encode(m)
+                    } else {
+                        rle::encode(&image::imageops::resize(
+                            m,
+                            width,
+                            height,
+                            image::imageops::FilterType::Nearest,
+                        ))
+                    }))
+                }
+                MaskEncoding::None => None,
+            };
+        }
+        detections
+    }
+}
+
+/// `--mask-encoding`: how masks are written to the JSON export.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MaskEncoding {
+    /// Largest polygon, `[[x, y], ...]` in frame pixels.
+    Polygon,
+    /// Uncompressed COCO RLE at the frame resolution.
+    Rle,
+    /// Boxes only.
+    #[default]
+    None,
+}
+
+impl std::str::FromStr for MaskEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "polygon" => Ok(Self::Polygon),
+            "rle" => Ok(Self::Rle),
+            "none" => Ok(Self::None),
+            other => Err(format!(
+                "invalid mask encoding `{other}` (expected polygon, rle or none)"
+            )),
+        }
+    }
+}
+
+/// A mask in a [`Detection`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Segmentation {
+    Polygon(Vec<[f32; 2]>),
+    Rle(Rle),
+}
+
+/// A [`Detection`] of a `--pipeline` stage; `parent` is the `id` of the detection whose box this
+/// stage searched.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct StageDetection {
+    pub id: usize,
+    pub stage: String,
+    pub parent: Option<usize>,
+    #[serde(flatten)]
+    pub detection: Detection,
+}
+
+/// Largest polygon of every mask in `y`, as `[[x, y], ...]` in frame pixels.
+pub fn mask_polygons(y: &usls::Y) -> Vec<Vec<[f32; 2]>> {
+    y.masks()
+        .iter()
+        .filter_map(|m| m.polygon())
+        .map(|p| p.points())
+        .collect()
+}
+
+/// Driver capture time of a frame.
+#[derive(Clone, Copy, Debug, Serialize, JsonSchema)]
+pub struct CaptureTime {
+    /// Kernel (CLOCK_MONOTONIC) timestamp in seconds.
+    pub monotonic_s: f64,
+    /// The same instant on the wall clock, in unix-epoch milliseconds.
+    pub wall_unix_ms: f64,
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// Per-frame line of the `--json-out` JSONL stream.
+#[derive(Clone, Debug, Default, Serialize, JsonSchema)]
+pub struct FrameRecord {
+    pub frame_idx: u64,
+    /// `false` for frames between inferences (`detections` is then empty).
+    pub inferred: bool,
+    /// Detections copied from the previous identical frame (`--dedup-frames`).
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub reused: bool,
+    pub detections: Vec<Detection>,
+    /// `--pipeline` detections with their stage and parent.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stages: Vec<StageDetection>,
+    /// `--zone` enters and exits found by this inference.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub zone_events: Vec<ZoneEvent>,
+    /// `--exclusive-group` overlaps resolved by this inference.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclusive: Vec<ExclusiveWin>,
+    /// Duplicate detections `--deduplicate-masks-iou` removed from this inference.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub deduplicated: usize,
+    /// Set on frames the quality gate skipped, with the reason in `quality_issue`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<FrameQuality>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality_issue: Option<QualityIssue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture: Option<CaptureTime>,
+    /// Capture timestamp to inference completion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+}
+
+/// `--print-json-schema`: JSON Schema (2020-12) of one `--json-out` line.
+///
+/// Derived from [`FrameRecord`] and the types it nests, so it cannot drift from what is written;
+/// the COCO RLE mask ([`Rle`]) is one of its `$defs`.
+pub fn json_schema() -> serde_json::Value {
+    schemars::schema_for!(FrameRecord).to_value()
+}
+
+/// Confidence change `--jsonl-dedup` still treats as the same detection.
+const DEDUP_CONF_TOLERANCE: f32 = 0.05;
+
+/// Writes [`FrameRecord`]s as JSON Lines.
+pub struct JsonlWriter {
+    out: BufWriter<File>,
+    /// `--jsonl-dedup` IoU threshold.
+    dedup_iou: Option<f32>,
+    /// Detections of the last written inferred record.
+    last_written: Option<Vec<Detection>>,
+    /// Latest record dropped by `--jsonl-dedup`; written by `finish` so the file still ends at
+    /// the last frame.
+    last_suppressed: Option<FrameRecord>,
+    suppressed: u64,
+}
+
+impl JsonlWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create output directory: {}", parent.display()))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("failed to create JSON output: {}", path.display()))?;
+        Ok(Self {
+            out: BufWriter::new(file),
+            dedup_iou: None,
+            last_written: None,
+            last_suppressed: None,
+            suppressed: 0,
+        })
+    }
+
+    /// `--jsonl-dedup <iou>`: skip records whose detections match the last written ones.
+    pub fn with_dedup(mut self, iou: Option<f32>) -> Result<Self> {
+        if let Some(iou) = iou {
+            anyhow::ensure!(
+                (0.0..=1.0).contains(&iou),
+                "--jsonl-dedup must be an IoU between 0 and 1, got {iou}"
+            );
+        }
+        self.dedup_iou = iou;
+        Ok(self)
+    }
+
+    pub fn write_record(&mut self, record: &FrameRecord) -> Result<()> {
+        if let Some(iou) = self.dedup_iou {
+            if self.unchanged(record, iou) {
+                self.last_suppressed = Some(record.clone());
+                self.suppressed += 1;
+                return Ok(());
+            }
+            self.last_suppressed = None;
+            if record.inferred {
+                self.last_written = Some(record.detections.clone());
+            }
+        }
+        self.write_line(record)
+    }
+
+    /// Whether `record` adds nothing to the last written one: no events, exclusive-group wins,
+    /// stages or quality flags, and either no inference or the same detections within `iou` and
+    /// [`DEDUP_CONF_TOLERANCE`].
+    fn unchanged(&self, record: &FrameRecord, iou: f32) -> bool {
+        let Some(last) = &self.last_written else {
+            return false;
+        };
+        if !record.stages.is_empty()
+            || !record.zone_events.is_empty()
+            || !record.exclusive.is_empty()
+            || record.quality_issue.is_some()
+        {
+            return false;
+        }
+        !record.inferred || same_detections(last, &record.detections, iou)
+    }
+
+    fn write_line(&mut self, record: &FrameRecord) -> Result<()> {
+        serde_json::to_writer(&mut self.out, record).context("failed to serialize frame record")?;
+        self.out
+            .write_all(b"\n")
+            .context("failed to write JSON output")?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(record) = self.last_suppressed.take() {
+            self.write_line(&record)?;
+            self.suppressed -= 1;
+        }
+        if self.suppressed > 0 {
+            tracing::info!(
+                "--jsonl-dedup skipped {} unchanged records",
+                self.suppressed
+            );
+        }
+        self.out.flush().context("failed to flush JSON output")
+    }
+}
+
+/// Pairs every detection of `b` with a distinct one of `a` that has the same label, a box IoU
+/// of at least `iou` and a confidence within [`DEDUP_CONF_TOLERANCE`].
+fn same_detections(a: &[Detection], b: &[Detection], iou: f32) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut matched = vec![false; a.len()];
+    b.iter().all(|d| {
+        let found = a.iter().enumerate().position(|(i, c)| {
+            !matched[i]
+                && c.label == d.label
+                && geometry::iou(from_xywh(c.bbox), from_xywh(d.bbox)) >= iou
+                && match (c.confidence, d.confidence) {
+                    (Some(x), Some(y)) => (x - y).abs() <= DEDUP_CONF_TOLERANCE,
+                    (x, y) => x == y,
+                }
+        });
+        if let Some(i) = found {
+            matched[i] = true;
+        }
+        found.is_some()
+    })
+}
+
+/// Subtitle flavor for [`SubtitleWriter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubtitleFormat {
+    /// SubRip: one text cue per inference.
+    Srt,
+    /// Advanced SubStation Alpha: text cue plus one drawn box per detection.
+    Ass,
+}
+
+struct PendingCue {
+    start_frame: u64,
+    detections: Vec<Detection>,
+}
+
+/// Writes detection summaries as a subtitle track to load next to the source video.
+///
+/// Each inferred frame opens a cue that lasts until the next inference (or the end of the run).
+pub struct SubtitleWriter {
+    out: BufWriter<File>,
+    format: SubtitleFormat,
+    fps: f64,
+    index: usize,
+    pending: Option<PendingCue>,
+}
+
+impl SubtitleWriter {
+    /// `width`/`height` become the ASS play resolution so boxes land on the right pixels.
+    pub fn create(
+        path: &Path,
+        format: SubtitleFormat,
+        fps: f32,
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create output directory: {}", parent.display()))?;
+        }
+        let file = File::create(path)
+            .with_context(|| format!("failed to create subtitle output: {}", path.display()))?;
+        let mut out = BufWriter::new(file);
+        if format == SubtitleFormat::Ass {
+            let font_size = (height / 24).max(12);
+            write!(
+                out,
+                "[Script Info]\n\
+                 ScriptType: v4.00+\n\
+                 PlayResX: {width}\n\
+                 PlayResY: {height}\n\
+                 ScaledBorderAndShadow: yes\n\
+                 \n\
+                 [V4+ Styles]\n\
+                 Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+                 BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+                 BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\n\
+                 Style: Default,Arial,{font_size},&H00FFFFFF,&H000000FF,&H00000000,&H80000000,\
+                 0,0,0,0,100,100,0,0,1,2,0,7,10,10,10,1\n\
+                 \n\
+                 [Events]\n\
+                 Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n"
+            )
+            .context("failed to write subtitle header")?;
+        }
+        Ok(Self {
+            out,
+            format,
+            fps: fps.max(0.001) as f64,
+            index: 0,
+            pending: None,
+        })
+    }
+
+    /// Start a cue at `frame_idx` (1-based), closing the previous one.
+    pub fn record(&mut self, frame_idx: u64, detections: Vec<Detection>) -> Result<()> {
+        self.flush_pending(frame_idx)?;
+        self.pending = Some(PendingCue {
+            start_frame: frame_idx,
+            detections,
+        });
+        Ok(())
+    }
+
+    /// Close the last cue after `last_frame_idx` and flush the file.
+    pub fn finish(mut self, last_frame_idx: u64) -> Result<()> {
+        self.flush_pending(last_frame_idx + 1)?;
+        self.out.flush().context("failed to flush subtitle output")
+    }
+
+    fn flush_pending(&mut self, end_frame: u64) -> Result<()> {
+        let Some(cue) = self.pending.take() else {
+            return Ok(());
+        };
+        if cue.detections.is_empty() {
+            return Ok(());
+        }
+        // Frame N (1-based) is shown from (N - 1) / fps.
+        let start = cue.start_frame.saturating_sub(1) as f64 / self.fps;
+        let end = end_frame.max(cue.start_frame + 1).saturating_sub(1) as f64 / self.fps;
+        let lines = summarize(&cue.detections);
+        self.index += 1;
+        match self.format {
+            SubtitleFormat::Srt => write!(
+                self.out,
+                "{}\n{} --> {}\n{}\n\n",
+                self.index,
+                srt_time(start),
+                srt_time(end),
+                lines.join("\n")
+            ),
+            SubtitleFormat::Ass => {
+                let (start, end) = (ass_time(start), ass_time(end));
+                writeln!(
+                    self.out,
+                    "Dialogue: 0,{start},{end},Default,,0,0,0,,{}",
+                    lines.join("\\N")
+                )?;
+                for d in &cue.detections {
+                    let [x, y, w, h] = d.bbox.map(|v| v.round() as i64);
+                    writeln!(
+                        self.out,
+                        "Dialogue: 1,{start},{end},Default,,0,0,0,,\
+                         {{\\an7\\pos(0,0)\\bord2\\shad0\\1a&HFF&\\3c&H00FF00&\\p1}}\
+                         m {x} {y} l {} {y} {} {} {x} {}{{\\p0}}",
+                        x + w,
+                        x + w,
+                        y + h,
+                        y + h
+                    )?;
+                }
+                Ok(())
+            }
+        }
+        .context("failed to write subtitle cue")
+    }
+}
+
+impl Drop for SubtitleWriter {
+    fn drop(&mut self) {
+        // Error paths skip `finish`; keep the last cue (one frame long) rather than losing it.
+        if let Some(cue) = self.pending.as_ref() {
+            let end = cue.start_frame + 1;
+            let _ = self.flush_pending(end);
+        }
+        let _ = self.out.flush();
+    }
+}
+
+/// One line per label: `label xN (top confidence)`, in first-seen order.
+fn summarize(detections: &[Detection]) -> Vec<String> {
+    let mut groups: Vec<(String, usize, Option<f32>)> = Vec::new();
+    for d in detections {
+        let label = match (&d.label, d.class_id) {
+            (Some(name), _) => name.clone(),
+            (None, Some(id)) => format!("class {id}"),
+            (None, None) => "object".to_string(),
+        };
+        let idx = match groups.iter().position(|g| g.0 == label) {
+            Some(idx) => idx,
+            None => {
+                groups.push((label, 0, None));
+                groups.len() - 1
+            }
+        };
+        let group = &mut groups[idx];
+        group.1 += 1;
+        group.2 = match (group.2, d.confidence) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+    }
+    groups
+        .into_iter()
+        .map(|(label, count, conf)| match conf {
+            Some(conf) => format!("{label} x{count} ({conf:.2})"),
+            None => format!("{label} x{count}"),
+        })
+        .collect()
+}
+
+fn srt_time(secs: f64) -> String {
+    let ms = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+fn ass_time(secs: f64) -> String {
+    let cs = (secs.max(0.0) * 100.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        cs / 360_000,
+        cs / 6000 % 60,
+        cs / 100 % 60,
+        cs % 100
+    )
+}
+
+/// `--codec-copy`: annotations as a WebVTT side file, muxed with the untouched source stream.
+///
+/// Each inferred frame becomes one cue (until the next inference) whose text is a JSON object with
+/// the detections and mask polygons. [`finish`](Self::finish) stream-copies the input video (and
+/// audio, if any) and the cues into a Matroska file, so the video is never re-encoded.
+pub struct FfmpegSubtitleWriter {
+    out: BufWriter<File>,
+    vtt_path: PathBuf,
+    fps: f64,
+    pending: Option<(u64, String)>,
+}
+
+impl FfmpegSubtitleWriter {
+    pub fn create(vtt_path: &Path, fps: f32) -> Result<Self> {
+        if let Some(parent) = vtt_path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create output directory: {}", parent.display()))?;
+        }
+        let file = File::create(vtt_path)
+            .with_context(|| format!("failed to create WebVTT output: {}", vtt_path.display()))?;
+        let mut out = BufWriter::new(file);
+        out.write_all(b"WEBVTT\n\n")
+            .context("failed to write WebVTT header")?;
+        Ok(Self {
+            out,
+            vtt_path: vtt_path.to_path_buf(),
+            fps: fps.max(0.001) as f64,
+            pending: None,
+        })
+    }
+
+    /// Start a cue with `text` (a single line) at `frame_idx` (1-based), closing the previous one.
+    pub fn record(&mut self, frame_idx: u64, text: String) -> Result<()> {
+        self.flush_pending(frame_idx)?;
+        self.pending = Some((frame_idx, text));
+        Ok(())
+    }
+
+    /// Close the last cue after `last_frame_idx`, then mux `input` and the cues into `output`.
+    pub fn finish(mut self, input: &InputUrl, output: &Path, last_frame_idx: u64) -> Result<()> {
+        self.flush_pending(last_frame_idx + 1)?;
+        self.out.flush().context("failed to flush WebVTT output")?;
+
+        let result = Command::new("ffmpeg")
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(input.ffmpeg_args()?)
+            .arg("-i")
+            .arg(&self.vtt_path)
+            .args(["-map", "0:v:0", "-map", "0:a?", "-map", "1:0"])
+            .args(["-c:v", "copy", "-c:a", "copy", "-c:s", "webvtt"])
+            .args(["-metadata:s:s:0", "title=sam3 detections"])
+            .arg(output)
+            .stdin(Stdio::null())
+            .output()
+            .context("failed to run `ffmpeg` for muxing (is FFmpeg installed?)")?;
+        if !result.status.success() {
+            anyhow::bail!(
+                "ffmpeg (mux) exited with {}: {}",
+                result.status,
+                scrub_credentials(String::from_utf8_lossy(&result.stderr).trim())
+            );
+        }
+        tracing::info!(
+            "Muxed {input} with detection track into: {}",
+            output.display()
+        );
+        Ok(())
+    }
+
+    fn flush_pending(&mut self, end_frame: u64) -> Result<()> {
+        let Some((start_frame, text)) = self.pending.take() else {
+            return Ok(());
+        };
+        let start = start_frame.saturating_sub(1) as f64 / self.fps;
+        let end = end_frame.max(start_frame + 1).saturating_sub(1) as f64 / self.fps;
+        write!(
+            self.out,
+            "{} --> {}\n{}\n\n",
+            vtt_time(start),
+            vtt_time(end),
+            text.replace('\n', " ")
+        )
+        .context("failed to write WebVTT cue")
+    }
+}
+
+fn vtt_time(secs: f64) -> String {
+    srt_time(secs).replace(',', ".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn cat(x: f32, confidence: f32) -> Detection {
+        Detection {
+            label: Some("cat".into()),
+            class_id: Some(0),
+            confidence: Some(confidence),
+            bbox: [x, 0.0, 10.0, 10.0],
+            segmentation: None,
+        }
+    }
+
+    #[test]
+    fn prompt_dirs_stay_apart() {
+        assert_eq!(prompt_dir_name(0, "red car"), "0_red_car");
+        assert_eq!(prompt_dir_name(1, "red-car"), "1_red_car");
+        assert_eq!(prompt_dir_name(2, ""), "2");
+        assert_eq!(prompt_dir_name(3, "../etc"), "3____etc");
+        assert_eq!(prompt_dir_name(4, &"x".repeat(100)).len(), 2 + 64);
+    }
+
+    #[test]
+    fn per_prompt_saves_land_once_finished() {
+        let dir = TempDir::new("per-prompt");
+        let prompts: Vec<Sam3Prompt> = ["cat", "dog"].iter().map(|p| p.parse().unwrap()).collect();
+        let y = usls::Y::default().with_hbbs(&[usls::Hbb::from_xywh(1.0, 1.0, 2.0, 2.0).with_id(1)]);
+        let img = usls::Image::from(image::RgbImage::new(4, 4));
+        for sync in [true, false] {
+            let base = dir.join(if sync { "sync" } else { "threaded" });
+            let mut saver = PerPromptSaver::new(base.clone(), sync).unwrap();
+            for i in 0..10 {
+                let name = format!("{i}.jpg");
+                saver.save_split(&usls::Annotator::default(), &img, &y, &prompts, &name).unwrap();
+            }
+            saver.finish().unwrap();
+            assert!(base.join("1_dog").is_dir());
+            assert!(!base.join("0_cat").exists());
+        }
+    }
+
+    fn inferred(detections: Vec<Detection>) -> FrameRecord {
+        FrameRecord {
+            inferred: true,
+            detections,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn same_detections_matches_by_label_box_and_confidence() {
+        let a = [cat(0.0, 0.9), cat(50.0, 0.5)];
+        // Order does not matter.
+        assert!(same_detections(&a, &[cat(51.0, 0.52), cat(1.0, 0.9)], 0.8));
+        assert!(!same_detections(&a, &[cat(0.0, 0.9)], 0.8));
+        assert!(!same_detections(&a, &[cat(0.0, 0.9), cat(55.0, 0.5)], 0.8));
+        assert!(!same_detections(&a, &[cat(0.0, 0.9), cat(50.0, 0.7)], 0.8));
+        // Each detection is matched once.
+        assert!(!same_detections(&a, &[cat(0.0, 0.9), cat(0.0, 0.9)], 0.8));
+        let mut dog = cat(0.0, 0.9);
+        dog.label = Some("dog".into());
+        assert!(!same_detections(&a[..1], &[dog], 0.8));
+    }
+
+    #[test]
+    fn masks_pair_with_their_box_by_id() {
+        let mask = |id: usize, px: [u8; 4]| usls::Mask::new(&px, 2, 2).unwrap().with_id(id);
+        let y = usls::Y::default()
+            .with_hbbs(&[
+                usls::Hbb::from_xywh(0.0, 0.0, 1.0, 1.0).with_id(0),
+                usls::Hbb::from_xywh(1.0, 1.0, 1.0, 1.0).with_id(1),
+                usls::Hbb::from_xywh(0.0, 1.0, 1.0, 1.0).with_id(2),
+            ])
+            .with_masks(&[mask(1, [0, 0, 0, 255]), mask(0, [255, 0, 0, 0])]);
+        let detections = Detection::from_y_with_masks(&y, MaskEncoding::Rle, 2, 2);
+        let rle = |m: &usls::Mask| Some(Segmentation::Rle(rle::encode(m.mask())));
+        assert_eq!(detections[0].segmentation, rle(&y.masks()[1]));
+        assert_eq!(detections[1].segmentation, rle(&y.masks()[0]));
+        assert_eq!(detections[2].segmentation, None);
+    }
+
+    #[test]
+    fn dedup_skips_unchanged_records_and_ends_on_the_last_frame() {
+        let dir = TempDir::new("jsonl-dedup");
+        let path = dir.join("out.jsonl");
+        let mut writer = JsonlWriter::create(&path)
+            .unwrap()
+            .with_dedup(Some(0.8))
+            .unwrap();
+        assert!(!writer.unchanged(&inferred(vec![cat(0.0, 0.9)]), 0.8));
+        for (frame_idx, x) in [(1, 0.0), (2, 0.5), (3, 1.0)] {
+            let record = FrameRecord {
+                frame_idx,
+                ..inferred(vec![cat(x, 0.9)])
+            };
+            writer.write_record(&record).unwrap();
+        }
+        let win = ExclusiveWin {
+            group: "door".into(),
+            winner: "open door".into(),
+            confidence: 0.8,
+            bbox: [0.0, 0.0, 10.0, 10.0],
+            losers: vec!["closed door".into()],
+        };
+        let with_win = FrameRecord {
+            exclusive: vec![win],
+            ..inferred(vec![cat(1.0, 0.9)])
+        };
+        assert!(!writer.unchanged(&with_win, 0.8));
+        let between = FrameRecord {
+            frame_idx: 4,
+            ..Default::default()
+        };
+        assert!(writer.unchanged(&between, 0.8));
+        writer.write_record(&between).unwrap();
+        writer.finish().unwrap();
+
+        let frames: Vec<u64> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| {
+                serde_json::from_str::<serde_json::Value>(l).unwrap()["frame_idx"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(frames, [1, 4]);
+    }
+}
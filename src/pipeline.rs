@@ -0,0 +1,370 @@
+//! `--pipeline <file.yaml>`: multi-stage detection, where a stage only looks inside the
+//! detections of its parent stage.
+//!
+//! ```yaml
+//! stages:
+//!   - name: shelf
+//!     prompts: [shelf]
+//!     conf: 0.5
+//!   - name: slot
+//!     prompts: ["empty slot"]
+//!     parent: shelf
+//!     pad: 16
+//!     top_k: 8
+//! ```
+//!
+//! Stages run in file order on every inferred frame. A root stage sees the whole frame; a child
+//! stage runs once per parent detection on its box (grown by `pad`, cut like `--save-crops`), and
+//! its results are moved back to frame coordinates. `conf` drops detections below it (on top of the
+//! model's `--conf`) and `top_k` keeps the most confident per parent detection (per frame for root
+//! stages). Masks become polygons so crops and the full frame share one coordinate space.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use usls::models::{SAM3, Sam3Prompt};
+
+use crate::crops::crop_rect;
+use crate::geometry::Rect;
+use crate::output::{Detection, StageDetection};
+use crate::viz::to_rgb;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineFile {
+    stages: Vec<StageSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StageSpec {
+    name: String,
+    prompts: Vec<String>,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(default)]
+    conf: Option<f32>,
+    #[serde(default)]
+    top_k: Option<usize>,
+    /// Pixels added around each parent box before cropping.
+    #[serde(default)]
+    pad: u32,
+}
+
+struct Stage {
+    name: String,
+    labels: Vec<String>,
+    prompts: Vec<Sam3Prompt>,
+    parent: Option<usize>,
+    conf: Option<f32>,
+    top_k: Option<usize>,
+    pad: u32,
+    /// Index of this stage's first prompt in [`Pipeline::prompts`].
+    prompt_offset: usize,
+}
+
+/// A region a stage runs on: the parent detection's id and its crop, or the whole frame.
+type Roi = (Option<usize>, Option<(u32, u32, u32, u32)>);
+
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+/// Parent index of every stage; rejects unknown parents, cycles and parents listed after their
+/// children.
+fn resolve_parents(specs: &[StageSpec]) -> Result<Vec<Option<usize>>> {
+    let mut index = HashMap::new();
+    for (i, spec) in specs.iter().enumerate() {
+        if index.insert(spec.name.as_str(), i).is_some() {
+            anyhow::bail!("stages[{i}]: duplicate stage name `{}`", spec.name);
+        }
+    }
+    let parents = specs
+        .iter()
+        .enumerate()
+        .map(|(i, spec)| {
+            spec.parent
+                .as_deref()
+                .map(|p| {
+                    index.get(p).copied().with_context(|| {
+                        format!("stages[{i}] (`{}`): unknown parent stage `{p}`", spec.name)
+                    })
+                })
+                .transpose()
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for start in 0..specs.len() {
+        let mut chain = vec![start];
+        let mut at = start;
+        while let Some(parent) = parents[at] {
+            if let Some(pos) = chain.iter().position(|&c| c == parent) {
+                let names: Vec<&str> = chain[pos..]
+                    .iter()
+                    .chain([&parent])
+                    .map(|&c| specs[c].name.as_str())
+                    .collect();
+                anyhow::bail!("cycle in parent references: {}", names.join(" -> "));
+            }
+            chain.push(parent);
+            at = parent;
+        }
+    }
+    for (i, parent) in parents.iter().enumerate() {
+        if let Some(p) = *parent
+            && p > i
+        {
+            anyhow::bail!(
+                "stages[{i}] (`{}`) must come after its parent `{}` (stages run in file order)",
+                specs[i].name,
+                specs[p].name
+            );
+        }
+    }
+    Ok(parents)
+}
+
+impl Pipeline {
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: PipelineFile = serde_yaml::from_str(text)?;
+        if file.stages.is_empty() {
+            anyhow::bail!("`stages` is empty");
+        }
+        let parents = resolve_parents(&file.stages)?;
+        let mut stages = Vec::with_capacity(file.stages.len());
+        let mut prompt_offset = 0;
+        for ((i, spec), parent) in file.stages.into_iter().enumerate().zip(parents) {
+            if spec.prompts.is_empty() {
+                anyhow::bail!("stages[{i}] (`{}`): `prompts` is empty", spec.name);
+            }
+            if let Some(conf) = spec.conf
+                && !(0.0..=1.0).contains(&conf)
+            {
+                anyhow::bail!("stages[{i}] (`{}`): `conf` must be in [0, 1]", spec.name);
+            }
+            if spec.top_k == Some(0) {
+                anyhow::bail!("stages[{i}] (`{}`): `top_k` must be >= 1", spec.name);
+            }
+            let prompts = spec
+                .prompts
+                .iter()
+                .map(|s| s.parse())
+                .collect::<std::result::Result<Vec<Sam3Prompt>, _>>()
+                .map_err(|e| anyhow::anyhow!("stages[{i}] (`{}`): {e}", spec.name))?;
+            stages.push(Stage {
+                name: spec.name,
+                labels: spec.prompts,
+                prompts,
+                parent,
+                conf: spec.conf,
+                top_k: spec.top_k,
+                pad: spec.pad,
+                prompt_offset,
+            });
+            prompt_offset += stages[i].prompts.len();
+        }
+        Ok(Self { stages })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read pipeline file: {}", path.display()))?;
+        let pipeline = Self::parse(&text)
+            .with_context(|| format!("invalid pipeline file: {}", path.display()))?;
+        for stage in &pipeline.stages {
+            match stage.parent {
+                Some(p) => tracing::info!(
+                    "Pipeline stage `{}` in `{}`: {:?}",
+                    stage.name,
+                    pipeline.stages[p].name,
+                    stage.labels
+                ),
+                None => tracing::info!("Pipeline stage `{}`: {:?}", stage.name, stage.labels),
+            }
+        }
+        Ok(pipeline)
+    }
+
+    /// The prompts of all stages, in stage order; detections' class ids index into this.
+    pub fn prompts(&self) -> Vec<Sam3Prompt> {
+        self.stages
+            .iter()
+            .flat_map(|s| s.prompts.iter().cloned())
+            .collect()
+    }
+
+    /// Prompt labels matching [`Pipeline::prompts`], for the legend.
+    pub fn labels(&self) -> Vec<String> {
+        self.stages
+            .iter()
+            .flat_map(|s| s.labels.iter().cloned())
+            .collect()
+    }
+
+    /// Run every stage on `img`. Returns all detections as one result in frame coordinates, plus
+    /// the same detections tagged with their stage and parent for the JSON output.
+    pub fn forward(
+        &self,
+        model: &mut SAM3,
+        img: &usls::Image,
+    ) -> Result<(Vec<usls::Y>, Vec<StageDetection>)> {
+        let frame = to_rgb(img)?;
+        let (width, height) = frame.dimensions();
+        let mut hbbs = Vec::new();
+        let mut polygons = Vec::new();
+        let mut tree: Vec<StageDetection> = Vec::new();
+        // Per stage: (id in `tree`, box) of its detections, the ROIs of its children.
+        let mut found: Vec<Vec<(usize, Rect)>> = Vec::new();
+        for stage in &self.stages {
+            let rois: Vec<Roi> = match stage.parent {
+                None => vec![(None, None)],
+                Some(p) => found[p]
+                    .iter()
+                    .filter_map(|(id, rect)| {
+                        crop_rect(*rect, stage.pad, width, height).map(|r| (Some(*id), Some(r)))
+                    })
+                    .collect(),
+            };
+            let mut stage_found = Vec::new();
+            for (parent, roi) in rois {
+                let (input, dx, dy) = match roi {
+                    None => (img.clone(), 0.0, 0.0),
+                    Some((x, y, w, h)) => (
+                        usls::Image::from(image::imageops::crop_imm(&frame, x, y, w, h).to_image()),
+                        x as f32,
+                        y as f32,
+                    ),
+                };
+                let Some(y) = model
+                    .forward(std::slice::from_ref(&input), &stage.prompts)?
+                    .into_iter()
+                    .next()
+                else {
+                    continue;
+                };
+                let passes = |conf: Option<f32>| stage.conf.is_none_or(|min| conf >= Some(min));
+                let mut boxes: Vec<&usls::Hbb> =
+                    y.hbbs().iter().filter(|h| passes(h.confidence())).collect();
+                let mut masks: Vec<&usls::Mask> = y
+                    .masks()
+                    .iter()
+                    .filter(|m| passes(m.confidence()))
+                    .collect();
+                if let Some(k) = stage.top_k {
+                    let by_conf = |c: Option<f32>| std::cmp::Reverse(c.unwrap_or(0.0).to_bits());
+                    boxes.sort_by_key(|h| by_conf(h.confidence()));
+                    boxes.truncate(k);
+                    masks.sort_by_key(|m| by_conf(m.confidence()));
+                    masks.truncate(k);
+                }
+                for hbb in boxes {
+                    let (x0, y0, x1, y1) = hbb.xyxy();
+                    let rect = (x0 + dx, y0 + dy, x1 + dx, y1 + dy);
+                    let class_id = self.class_id(stage, hbb.id(), hbb.name());
+                    let mut moved = usls::Hbb::from_xyxy(rect.0, rect.1, rect.2, rect.3);
+                    if let Some(name) = hbb.name() {
+                        moved = moved.with_name(name);
+                    }
+                    if let Some(conf) = hbb.confidence() {
+                        moved = moved.with_confidence(conf);
+                    }
+                    if let Some(id) = class_id {
+                        moved = moved.with_id(id);
+                    }
+                    let id = tree.len();
+                    tree.push(StageDetection {
+                        id,
+                        stage: stage.name.clone(),
+                        parent,
+                        detection: Detection {
+                            label: hbb.name().map(str::to_string),
+                            class_id,
+                            confidence: hbb.confidence(),
+                            bbox: [rect.0, rect.1, rect.2 - rect.0, rect.3 - rect.1],
+                            segmentation: None,
+                        },
+                    });
+                    stage_found.push((id, rect));
+                    hbbs.push(moved);
+                }
+                for mask in masks {
+                    let Some(polygon) = mask.polygon() else {
+                        continue;
+                    };
+                    let points: Vec<[f32; 2]> = polygon
+                        .points()
+                        .iter()
+                        .map(|[x, y]| [x + dx, y + dy])
+                        .collect();
+                    let mut moved = usls::Polygon::from_xys(&points);
+                    if let Some(name) = mask.name() {
+                        moved = moved.with_name(name);
+                    }
+                    if let Some(conf) = mask.confidence() {
+                        moved = moved.with_confidence(conf);
+                    }
+                    if let Some(id) = self.class_id(stage, mask.id(), mask.name()) {
+                        moved = moved.with_id(id);
+                    }
+                    polygons.push(moved);
+                }
+            }
+            found.push(stage_found);
+        }
+        let y = usls::Y::default().with_hbbs(&hbbs).with_polygons(&polygons);
+        Ok((vec![y], tree))
+    }
+
+    /// Pipeline-wide class id of a detection from `stage`.
+    fn class_id(&self, stage: &Stage, id: Option<usize>, name: Option<&str>) -> Option<usize> {
+        id.filter(|i| *i < stage.prompts.len())
+            .or_else(|| name.and_then(|n| stage.labels.iter().position(|l| l == n)))
+            .map(|i| stage.prompt_offset + i)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(yaml: &str) -> String {
+        format!(
+            "{:#}",
+            Pipeline::parse(yaml).err().expect("invalid pipeline")
+        )
+    }
+
+    #[test]
+    fn parses_stages_in_order() {
+        let pipeline = Pipeline::parse(
+            "stages:\n  - name: shelf\n    prompts: [shelf]\n    conf: 0.5\n  - name: slot\n    prompts: [empty slot, gap]\n    parent: shelf\n    top_k: 4\n",
+        )
+        .unwrap();
+        assert_eq!(pipeline.labels(), ["shelf", "empty slot", "gap"]);
+        assert_eq!(pipeline.stages[1].parent, Some(0));
+        assert_eq!(pipeline.stages[1].prompt_offset, 1);
+        let slot = &pipeline.stages[1];
+        assert_eq!(pipeline.class_id(slot, None, Some("gap")), Some(2));
+    }
+
+    #[test]
+    fn rejects_unknown_keys_parents_and_cycles() {
+        assert!(
+            error("stages:\n  - name: a\n    prompts: [x]\n    parnt: b\n")
+                .contains("unknown field `parnt`")
+        );
+        assert!(
+            error("stages:\n  - name: a\n    prompts: [x]\n    parent: b\n")
+                .contains("unknown parent stage `b`")
+        );
+        assert!(
+            error("stages:\n  - name: a\n    prompts: [x]\n    parent: b\n  - name: b\n    prompts: [y]\n    parent: a\n")
+                .contains("cycle in parent references: a -> b -> a")
+        );
+        assert!(
+            error("stages:\n  - name: a\n    prompts: [x]\n    parent: b\n  - name: b\n    prompts: [y]\n")
+                .contains("must come after its parent `b`")
+        );
+        assert!(error("stages: []\n").contains("`stages` is empty"));
+    }
+}
@@ -0,0 +1,667 @@
+//! Filters applied to the model's detections before annotation and the sinks.
+//!
+//! [`CooldownTracker`] (`--prompt-cooldown-frames`): once a prompt has produced a detection, its
+//! detections are dropped for the next N frames, so an object that sits in view does not add a
+//! JSON/CSV record on every inference.
+//!
+//! [`MaskDedup`] (`--deduplicate-masks-iou`): non-maximum suppression over the detections' boxes,
+//! for prompts that overlap (e.g. "car" and "vehicle") and report the same object twice.
+//!
+//! [`ExclusiveGroups`] (`--exclusive-group door="open door,closed door"`): prompts that cannot both
+//! be true of one object; of overlapping detections from different prompts of a group only the most
+//! confident is kept. Prompts are matched by text, so a group keeps working across prompt updates.
+
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use usls::models::Sam3Prompt;
+
+use crate::geometry;
+
+/// Index into `prompts` of a detection, by class id or else by label.
+pub(crate) fn prompt_index(
+    id: Option<usize>,
+    name: Option<&str>,
+    prompts: &[Sam3Prompt],
+) -> Option<usize> {
+    id.filter(|i| *i < prompts.len())
+        .or_else(|| name.and_then(|n| prompts.iter().position(|p| p.text == n)))
+}
+
+/// For each of a result's masks or polygons (given by their class ids), the position of its box
+/// in `hbbs`: the k-th one with an id goes with the k-th box with that id. usls gives every
+/// detection of a prompt the prompt's id and keeps a prompt's boxes, masks and polygons in the
+/// same order, so this holds even when one part is missing for some detection.
+pub(crate) fn paired_boxes(
+    hbbs: &[usls::Hbb],
+    ids: impl IntoIterator<Item = Option<usize>>,
+) -> Vec<Option<usize>> {
+    let mut boxes: HashMap<Option<usize>, VecDeque<usize>> = HashMap::new();
+    for (i, hbb) in hbbs.iter().enumerate() {
+        boxes.entry(hbb.id()).or_default().push_back(i);
+    }
+    ids.into_iter()
+        .map(|id| boxes.get_mut(&id).and_then(VecDeque::pop_front))
+        .collect()
+}
+
+/// One detection of a result, as [`retain_detections`] asks about it: a box, or a mask or polygon
+/// that has no box.
+pub(crate) struct Candidate<'a> {
+    /// The box and its position in the result.
+    pub hbb: Option<(usize, &'a usls::Hbb)>,
+    pub id: Option<usize>,
+    pub name: Option<&'a str>,
+    pub confidence: Option<f32>,
+}
+
+/// `y` with only the detections `keep` accepts. `keep` sees every box once, in order; masks and
+/// polygons follow the box [`paired_boxes`] gives them, and those without one are asked about
+/// on their own.
+pub(crate) fn retain_detections(y: usls::Y, mut keep: impl FnMut(&Candidate) -> bool) -> usls::Y {
+    let kept_boxes: Vec<bool> = y
+        .hbbs()
+        .iter()
+        .enumerate()
+        .map(|(i, hbb)| {
+            keep(&Candidate {
+                hbb: Some((i, hbb)),
+                id: hbb.id(),
+                name: hbb.name(),
+                confidence: hbb.confidence(),
+            })
+        })
+        .collect();
+    let mut keep_part = |paired: Option<usize>, id, name, confidence| match paired {
+        Some(i) => kept_boxes[i],
+        None => keep(&Candidate {
+            hbb: None,
+            id,
+            name,
+            confidence,
+        }),
+    };
+    let mask_boxes = paired_boxes(y.hbbs(), y.masks().iter().map(|m| m.id()));
+    let masks: Vec<usls::Mask> = y
+        .masks()
+        .iter()
+        .zip(mask_boxes)
+        .filter(|(m, b)| keep_part(*b, m.id(), m.name(), m.confidence()))
+        .map(|(m, _)| m.clone())
+        .collect();
+    let polygon_boxes = paired_boxes(y.hbbs(), y.polygons().iter().map(|p| p.id()));
+    let polygons: Vec<usls::Polygon> = y
+        .polygons()
+        .iter()
+        .zip(polygon_boxes)
+        .filter(|(p, b)| keep_part(*b, p.id(), p.name(), p.confidence()))
+        .map(|(p, _)| p.clone())
+        .collect();
+    if kept_boxes.iter().all(|k| *k)
+        && masks.len() == y.masks().len()
+        && polygons.len() == y.polygons().len()
+    {
+        return y;
+    }
+    let hbbs: Vec<usls::Hbb> = y
+        .hbbs()
+        .iter()
+        .zip(&kept_boxes)
+        .filter(|(_, k)| **k)
+        .map(|(h, _)| h.clone())
+        .collect();
+    y.with_hbbs(&hbbs)
+        .with_masks(&masks)
+        .with_polygons(&polygons)
+}
+
+/// `y` split into one result per prompt (same order as `prompts`); detections that match no
+/// prompt are dropped.
+pub fn split_by_prompt(y: &usls::Y, prompts: &[Sam3Prompt]) -> Vec<usls::Y> {
+    (0..prompts.len())
+        .map(|i| {
+            retain_detections(y.clone(), |c| {
+                prompt_index(c.id, c.name, prompts) == Some(i)
+            })
+        })
+        .collect()
+}
+
+/// The detections of `y` that match none of `prompts`, the part [`split_by_prompt`] drops.
+pub fn unmatched(y: &usls::Y, prompts: &[Sam3Prompt]) -> usls::Y {
+    retain_detections(y.clone(), |c| prompt_index(c.id, c.name, prompts).is_none())
+}
+
+pub struct CooldownTracker {
+    frames: u64,
+    /// Prompt index -> frame of its last detection that was let through.
+    last: HashMap<usize, u64>,
+}
+
+impl CooldownTracker {
+    /// `frames == 0` disables the cooldown.
+    pub fn new(frames: u64) -> Self {
+        if frames > 0 {
+            tracing::info!("Prompt cooldown: {frames} frame(s)");
+        }
+        Self {
+            frames,
+            last: HashMap::new(),
+        }
+    }
+
+    /// Prompts whose detections are suppressed at `frame_idx`.
+    fn cooling(&self, frame_idx: u64) -> HashSet<usize> {
+        self.last
+            .iter()
+            .filter(|(_, last)| (**last + 1..=**last + self.frames).contains(&frame_idx))
+            .map(|(i, _)| *i)
+            .collect()
+    }
+
+    fn record(&mut self, frame_idx: u64, fired: impl IntoIterator<Item = usize>) {
+        for i in fired {
+            self.last.insert(i, frame_idx);
+        }
+    }
+
+    /// Drop the boxes, masks and polygons of prompts still cooling down at `frame_idx`, and start
+    /// the cooldown of every prompt that has a detection left.
+    pub fn filter(
+        &mut self,
+        ys: Vec<usls::Y>,
+        frame_idx: u64,
+        prompts: &[Sam3Prompt],
+    ) -> Vec<usls::Y> {
+        if self.frames == 0 {
+            return ys;
+        }
+        let cooling = self.cooling(frame_idx);
+        let mut fired = HashSet::new();
+        let mut keep = |c: &Candidate| {
+            let Some(i) = prompt_index(c.id, c.name, prompts) else {
+                return true;
+            };
+            if cooling.contains(&i) {
+                tracing::debug!(
+                    "Cooldown: dropped `{}` ({}) at frame {frame_idx}",
+                    prompts[i].text,
+                    c.confidence.map_or("-".to_string(), |c| format!("{c:.2}"))
+                );
+                return false;
+            }
+            fired.insert(i);
+            true
+        };
+        let ys = ys
+            .into_iter()
+            .map(|y| retain_detections(y, &mut keep))
+            .collect();
+        self.record(frame_idx, fired);
+        ys
+    }
+}
+
+/// One detection's bounding box, for [`nms_masks`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaskBbox {
+    /// Position of the detection in its `usls::Y`.
+    pub index: usize,
+    /// `[x0, y0, x1, y1]` in pixels.
+    pub xyxy: [f32; 4],
+    pub confidence: f32,
+}
+
+/// IoU of every pair of boxes; symmetric, with 1.0 on the diagonal.
+pub fn pairwise_bbox_iou(masks: &[MaskBbox]) -> Vec<Vec<f32>> {
+    masks
+        .iter()
+        .enumerate()
+        .map(|(i, a)| {
+            masks
+                .iter()
+                .enumerate()
+                .map(|(j, b)| {
+                    if i == j {
+                        1.0
+                    } else {
+                        geometry::iou(a.xyxy.into(), b.xyxy.into())
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Greedy non-maximum suppression: from the most confident box down, a box whose IoU with an
+/// already kept one is above `threshold` is removed. Of two equally confident boxes the later one
+/// goes. The kept boxes stay in their input order.
+pub fn nms_masks(masks: Vec<MaskBbox>, threshold: f32) -> Vec<MaskBbox> {
+    let iou = pairwise_bbox_iou(&masks);
+    let mut order: Vec<usize> = (0..masks.len()).collect();
+    order.sort_by(|&i, &j| masks[j].confidence.total_cmp(&masks[i].confidence));
+    let mut kept: Vec<usize> = Vec::new();
+    for i in order {
+        if kept.iter().all(|&k| iou[i][k] <= threshold) {
+            kept.push(i);
+        }
+    }
+    kept.sort_unstable();
+    let mut keep = vec![false; masks.len()];
+    for i in kept {
+        keep[i] = true;
+    }
+    masks
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(m, keep)| keep.then_some(m))
+        .collect()
+}
+
+pub struct MaskDedup {
+    iou: f32,
+}
+
+impl MaskDedup {
+    /// `iou == 0.0` disables the deduplication.
+    pub fn new(iou: f32) -> Result<Self> {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&iou),
+            "--deduplicate-masks-iou must be between 0 and 1, got {iou}"
+        );
+        if iou > 0.0 {
+            tracing::info!("Deduplicating detections with box IoU above {iou}");
+        }
+        Ok(Self { iou })
+    }
+
+    /// Run [`nms_masks`] on each result's boxes and drop the removed detections' boxes, masks and
+    /// polygons (see [`retain_detections`]); returns the filtered results and how many detections were
+    /// removed.
+    pub fn filter(&self, ys: Vec<usls::Y>) -> (Vec<usls::Y>, usize) {
+        if self.iou <= 0.0 {
+            return (ys, 0);
+        }
+        let mut removed = 0;
+        let ys = ys
+            .into_iter()
+            .map(|y| {
+                let boxes: Vec<MaskBbox> = y
+                    .hbbs()
+                    .iter()
+                    .enumerate()
+                    .map(|(index, h)| {
+                        let (x0, y0, x1, y1) = h.xyxy();
+                        MaskBbox {
+                            index,
+                            xyxy: [x0, y0, x1, y1],
+                            confidence: h.confidence().unwrap_or(0.0),
+                        }
+                    })
+                    .collect();
+                let n = boxes.len();
+                let mut keep = vec![false; n];
+                for m in nms_masks(boxes, self.iou) {
+                    keep[m.index] = true;
+                }
+                removed += keep.iter().filter(|k| !**k).count();
+                retain_detections(y, |c| c.hbb.is_none_or(|(i, _)| keep[i]))
+            })
+            .collect();
+        if removed > 0 {
+            tracing::debug!("Deduplicated {removed} overlapping detection(s)");
+        }
+        (ys, removed)
+    }
+}
+
+/// `--exclusive-group <name>=<prompt>,<prompt>,...`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExclusiveGroup {
+    pub name: String,
+    pub prompts: Vec<String>,
+}
+
+impl std::str::FromStr for ExclusiveGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, prompts) = s.split_once('=').ok_or_else(|| {
+            format!("invalid exclusive group `{s}` (expected name=prompt,prompt)")
+        })?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("exclusive group `{s}` has no name"));
+        }
+        let mut unique: Vec<String> = Vec::new();
+        for prompt in prompts.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            if !unique.iter().any(|p| p == prompt) {
+                unique.push(prompt.to_string());
+            }
+        }
+        if unique.len() < 2 {
+            return Err(format!(
+                "exclusive group `{name}` needs at least two different prompts"
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            prompts: unique,
+        })
+    }
+}
+
+/// One overlap resolved by an exclusive group, as recorded in `--json-out`.
+#[derive(Clone, Debug, PartialEq, Serialize, JsonSchema)]
+pub struct ExclusiveWin {
+    pub group: String,
+    /// Prompt of the detection that was kept.
+    pub winner: String,
+    pub confidence: f32,
+    /// `[x, y, w, h]` of the kept detection in frame pixels.
+    pub bbox: [f32; 4],
+    /// Prompts of the overlapping detections that were dropped for it.
+    pub losers: Vec<String>,
+}
+
+/// A detection as seen by [`resolve_exclusive`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExclusiveCandidate {
+    pub label: String,
+    /// `[x0, y0, x1, y1]` in pixels.
+    pub xyxy: [f32; 4],
+    pub confidence: f32,
+}
+
+/// From the most confident detection down, drop every other detection of a different prompt in
+/// the same group whose box overlaps it with IoU above `iou`. Returns which detections are kept and
+/// one [`ExclusiveWin`] per detection that beat another.
+pub fn resolve_exclusive(
+    detections: &[ExclusiveCandidate],
+    groups: &[ExclusiveGroup],
+    iou: f32,
+) -> (Vec<bool>, Vec<ExclusiveWin>) {
+    let group_of = |label: &str| {
+        groups
+            .iter()
+            .position(|g| g.prompts.iter().any(|p| p == label))
+    };
+    let mut order: Vec<usize> = (0..detections.len()).collect();
+    order.sort_by(|&i, &j| {
+        detections[j]
+            .confidence
+            .total_cmp(&detections[i].confidence)
+    });
+    let mut keep = vec![true; detections.len()];
+    let mut wins = Vec::new();
+    for (rank, &i) in order.iter().enumerate() {
+        let winner = &detections[i];
+        let Some(g) = group_of(&winner.label).filter(|_| keep[i]) else {
+            continue;
+        };
+        let mut losers = Vec::new();
+        for &j in &order[rank + 1..] {
+            let other = &detections[j];
+            if keep[j]
+                && other.label != winner.label
+                && group_of(&other.label) == Some(g)
+                && geometry::iou(winner.xyxy.into(), other.xyxy.into()) > iou
+            {
+                keep[j] = false;
+                losers.push(other.label.clone());
+            }
+        }
+        if !losers.is_empty() {
+            let [x0, y0, x1, y1] = winner.xyxy;
+            wins.push(ExclusiveWin {
+                group: groups[g].name.clone(),
+                winner: winner.label.clone(),
+                confidence: winner.confidence,
+                bbox: [x0, y0, x1 - x0, y1 - y0],
+                losers,
+            });
+        }
+    }
+    (keep, wins)
+}
+
+pub struct ExclusiveGroups {
+    groups: Vec<ExclusiveGroup>,
+    iou: f32,
+}
+
+impl ExclusiveGroups {
+    /// No groups disables the resolution.
+    pub fn new(groups: Vec<ExclusiveGroup>, iou: f32) -> Result<Self> {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&iou),
+            "--exclusive-iou must be between 0 and 1, got {iou}"
+        );
+        for (i, group) in groups.iter().enumerate() {
+            for other in &groups[i + 1..] {
+                if let Some(p) = group.prompts.iter().find(|p| other.prompts.contains(p)) {
+                    anyhow::bail!(
+                        "prompt `{p}` is in both exclusive groups `{}` and `{}`",
+                        group.name,
+                        other.name
+                    );
+                }
+            }
+            tracing::info!(
+                "Exclusive group `{}`: {} (box IoU above {iou})",
+                group.name,
+                group.prompts.join(" | ")
+            );
+        }
+        Ok(Self { groups, iou })
+    }
+
+    /// Run [`resolve_exclusive`] on each result and drop the losers' boxes, masks and polygons
+    /// (see [`retain_detections`]). Detections are matched to prompts by label, falling back to the text
+    /// of the prompt their class id points at.
+    pub fn filter(
+        &self,
+        ys: Vec<usls::Y>,
+        prompts: &[Sam3Prompt],
+    ) -> (Vec<usls::Y>, Vec<ExclusiveWin>) {
+        if self.groups.is_empty() {
+            return (ys, Vec::new());
+        }
+        let mut wins = Vec::new();
+        let ys = ys
+            .into_iter()
+            .map(|y| {
+                let detections: Vec<ExclusiveCandidate> = y
+                    .hbbs()
+                    .iter()
+                    .map(|h| {
+                        let (x0, y0, x1, y1) = h.xyxy();
+                        let label = h
+                            .name()
+                            .map(str::to_string)
+                            .or_else(|| h.id().and_then(|i| prompts.get(i)).map(|p| p.text.clone()))
+                            .unwrap_or_default();
+                        ExclusiveCandidate {
+                            label,
+                            xyxy: [x0, y0, x1, y1],
+                            confidence: h.confidence().unwrap_or(0.0),
+                        }
+                    })
+                    .collect();
+                let (keep, mut resolved) = resolve_exclusive(&detections, &self.groups, self.iou);
+                if resolved.is_empty() {
+                    return y;
+                }
+                wins.append(&mut resolved);
+                retain_detections(y, |c| c.hbb.is_none_or(|(i, _)| keep[i]))
+            })
+            .collect();
+        for win in &wins {
+            tracing::debug!(
+                "Exclusive group `{}`: `{}` ({:.2}) over {}",
+                win.group,
+                win.winner,
+                win.confidence,
+                win.losers.join(", ")
+            );
+        }
+        (ys, wins)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bbox(index: usize, xyxy: [f32; 4], confidence: f32) -> MaskBbox {
+        MaskBbox {
+            index,
+            xyxy,
+            confidence,
+        }
+    }
+
+    #[test]
+    fn pairwise_iou_matrix() {
+        let masks = [
+            bbox(0, [0.0, 0.0, 10.0, 10.0], 0.9),
+            bbox(1, [5.0, 0.0, 15.0, 10.0], 0.8),
+            bbox(2, [20.0, 20.0, 30.0, 30.0], 0.7),
+        ];
+        let iou = pairwise_bbox_iou(&masks);
+        assert_eq!(iou[0][0], 1.0);
+        assert!((iou[0][1] - 50.0 / 150.0).abs() < 1e-6);
+        assert_eq!(iou[0][1], iou[1][0]);
+        assert_eq!(iou[0][2], 0.0);
+    }
+
+    #[test]
+    fn nms_drops_less_confident_overlaps() {
+        let masks = vec![
+            bbox(0, [0.0, 0.0, 10.0, 10.0], 0.6),
+            bbox(1, [1.0, 0.0, 11.0, 10.0], 0.9),
+            bbox(2, [20.0, 20.0, 30.0, 30.0], 0.5),
+            bbox(3, [5.0, 0.0, 15.0, 10.0], 0.7),
+        ];
+        let kept: Vec<usize> = nms_masks(masks.clone(), 0.5)
+            .iter()
+            .map(|m| m.index)
+            .collect();
+        // 0 overlaps 1 (IoU 0.82); 3 overlaps 1 only by 0.43.
+        assert_eq!(kept, [1, 2, 3]);
+        let kept: Vec<usize> = nms_masks(masks, 0.3).iter().map(|m| m.index).collect();
+        assert_eq!(kept, [1, 2]);
+        assert!(MaskDedup::new(1.5).is_err());
+    }
+
+    fn candidate(label: &str, xyxy: [f32; 4], confidence: f32) -> ExclusiveCandidate {
+        ExclusiveCandidate {
+            label: label.to_string(),
+            xyxy,
+            confidence,
+        }
+    }
+
+    #[test]
+    fn parses_exclusive_groups() {
+        let group: ExclusiveGroup = "door= open door, closed door ,open door".parse().unwrap();
+        assert_eq!(group.name, "door");
+        assert_eq!(group.prompts, ["open door", "closed door"]);
+        assert!("door=open door".parse::<ExclusiveGroup>().is_err());
+        assert!("open door,closed door".parse::<ExclusiveGroup>().is_err());
+        assert!("=a,b".parse::<ExclusiveGroup>().is_err());
+        let overlapping = vec![
+            "a=x,y".parse().unwrap(),
+            "b=y,z".parse::<ExclusiveGroup>().unwrap(),
+        ];
+        assert!(ExclusiveGroups::new(overlapping, 0.5).is_err());
+    }
+
+    #[test]
+    fn exclusive_group_keeps_the_best_prompt_per_region() {
+        let groups = vec!["door=open door,closed door".parse().unwrap()];
+        let detections = [
+            candidate("open door", [0.0, 0.0, 10.0, 20.0], 0.6),
+            candidate("closed door", [1.0, 0.0, 11.0, 20.0], 0.8),
+            // Another door elsewhere: no competitor.
+            candidate("open door", [50.0, 0.0, 60.0, 20.0], 0.7),
+            // Same prompt overlapping is left to --deduplicate-masks-iou.
+            candidate("closed door", [0.0, 1.0, 10.0, 21.0], 0.5),
+            // Not in the group.
+            candidate("person", [0.0, 0.0, 10.0, 20.0], 0.9),
+        ];
+        let (keep, wins) = resolve_exclusive(&detections, &groups, 0.5);
+        assert_eq!(keep, [false, true, true, true, true]);
+        assert_eq!(
+            wins,
+            [ExclusiveWin {
+                group: "door".into(),
+                winner: "closed door".into(),
+                confidence: 0.8,
+                bbox: [1.0, 0.0, 10.0, 20.0],
+                losers: vec!["open door".into()],
+            }]
+        );
+        // Above the overlap threshold nothing competes.
+        let (keep, wins) = resolve_exclusive(&detections, &groups, 0.95);
+        assert!(keep.iter().all(|k| *k) && wins.is_empty());
+    }
+
+    /// Boxes of prompts 0, 1, 0 at x = 0, 100, 200; masks in prompt order and one polygon, for
+    /// the first box of prompt 0.
+    fn two_prompt_result() -> usls::Y {
+        let hbb = |id: usize, x: f32| usls::Hbb::from_xywh(x, 0.0, 10.0, 10.0).with_id(id);
+        let mask = |id: usize, value: u8| usls::Mask::new(&[value], 1, 1).unwrap().with_id(id);
+        usls::Y::default()
+            .with_hbbs(&[hbb(0, 0.0), hbb(1, 100.0), hbb(0, 200.0)])
+            .with_masks(&[mask(0, 1), mask(0, 3), mask(1, 2)])
+            .with_polygons(&[usls::Polygon::from_xys(&[[0.0, 0.0]]).with_id(0)])
+    }
+
+    #[test]
+    fn masks_and_polygons_follow_their_box_by_id() {
+        let y = two_prompt_result();
+        let ids = y.masks().iter().map(|m| m.id());
+        assert_eq!(paired_boxes(y.hbbs(), ids), [Some(0), Some(2), Some(1)]);
+
+        // Drop the box at x = 0: its mask (1) and polygon go with it.
+        let y = retain_detections(y, |c| c.hbb.is_none_or(|(_, h)| h.xmin() != 0.0));
+        let xs: Vec<f32> = y.hbbs().iter().map(|h| h.xmin()).collect();
+        assert_eq!(xs, [100.0, 200.0]);
+        let values: Vec<u8> = y.masks().iter().map(|m| m.to_vec()[0]).collect();
+        assert_eq!(values, [3, 2]);
+        assert!(y.polygons().is_empty());
+
+        let split = split_by_prompt(
+            &two_prompt_result(),
+            &["a".parse().unwrap(), "b".parse().unwrap()],
+        );
+        assert_eq!(split[0].hbbs().len(), 2);
+        assert_eq!(split[0].masks().len(), 2);
+        assert_eq!(split[0].polygons().len(), 1);
+        assert_eq!(split[1].masks()[0].to_vec(), [2]);
+        assert!(split[1].polygons().is_empty());
+
+        // With only the first prompt left, the second one's box and mask match nothing.
+        let rest = unmatched(&two_prompt_result(), &["a".parse().unwrap()]);
+        let xs: Vec<f32> = rest.hbbs().iter().map(|h| h.xmin()).collect();
+        assert_eq!(xs, [100.0]);
+        assert_eq!(rest.masks()[0].to_vec(), [2]);
+        assert!(rest.polygons().is_empty());
+    }
+
+    #[test]
+    fn prompts_cool_down_for_n_frames() {
+        let mut cooldown = CooldownTracker::new(3);
+        cooldown.record(10, [0]);
+        assert!(cooldown.cooling(10).is_empty());
+        assert_eq!(cooldown.cooling(11), HashSet::from([0]));
+        assert_eq!(cooldown.cooling(13), HashSet::from([0]));
+        assert!(cooldown.cooling(14).is_empty());
+        // Seeking back before the detection does not suppress.
+        assert!(cooldown.cooling(5).is_empty());
+        cooldown.record(14, [0, 1]);
+        assert_eq!(cooldown.cooling(15), HashSet::from([0, 1]));
+    }
+}
@@ -0,0 +1,747 @@
+use anyhow::Context;
+use image::imageops::FilterType;
+use image::{GrayImage, RgbImage};
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::path::Path;
+
+/// Fixed pipeline resolution (`--output-size WxH`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl std::str::FromStr for OutputSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid size `{s}` (expected WxH, e.g. 1280x720)");
+        let (w, h) = s.trim().split_once(['x', 'X']).ok_or_else(err)?;
+        let width: u32 = w.trim().parse().map_err(|_| err())?;
+        let height: u32 = h.trim().parse().map_err(|_| err())?;
+        if width == 0 || height == 0 {
+            return Err(err());
+        }
+        Ok(Self { width, height })
+    }
+}
+
+/// Rescale `img` to exactly `size`: stretched, or aspect-preserving on a black canvas with `letterbox`.
+///
+/// Everything downstream (inference, display, saving) sees the resized frame, so prompts are
+/// interpreted in output coordinates.
+pub fn fit_to_size(img: RgbImage, size: OutputSize, letterbox: bool) -> RgbImage {
+    let (w, h) = img.dimensions();
+    if (w, h) == (size.width, size.height) {
+        return img;
+    }
+    if !letterbox {
+        return image::imageops::resize(&img, size.width, size.height, FilterType::Triangle);
+    }
+
+    let scale = (size.width as f64 / w as f64).min(size.height as f64 / h as f64);
+    let sw = ((w as f64 * scale).round() as u32).clamp(1, size.width);
+    let sh = ((h as f64 * scale).round() as u32).clamp(1, size.height);
+    let scaled = image::imageops::resize(&img, sw, sh, FilterType::Triangle);
+    let mut canvas = RgbImage::new(size.width, size.height);
+    image::imageops::replace(
+        &mut canvas,
+        &scaled,
+        ((size.width - sw) / 2) as i64,
+        ((size.height - sh) / 2) as i64,
+    );
+    canvas
+}
+
+/// `--motion-blur-kernel N`: pixel-wise mean of the last `N` frames.
+///
+/// Static scenes lose their sensor noise while anything moving leaves a ghost, which helps with
+/// stationary defects. The window sum is an `f32` accumulator updated per frame (add the new frame,
+/// subtract the one leaving), so the cost does not grow with `N`.
+pub struct TemporalAverager {
+    kernel: usize,
+    window: VecDeque<RgbImage>,
+    sum: Vec<f32>,
+}
+
+impl TemporalAverager {
+    pub fn new(kernel: u32) -> anyhow::Result<Self> {
+        if kernel == 0 || kernel.is_multiple_of(2) {
+            anyhow::bail!("--motion-blur-kernel must be an odd number >= 1, got {kernel}");
+        }
+        Ok(Self {
+            kernel: kernel as usize,
+            window: VecDeque::with_capacity(kernel as usize),
+            sum: Vec::new(),
+        })
+    }
+
+    /// Add `img` and return the mean of the frames in the window (fewer than `N` at the start).
+    /// A change of frame size restarts the window.
+    pub fn push(&mut self, img: RgbImage) -> RgbImage {
+        if self
+            .window
+            .front()
+            .is_some_and(|f| f.dimensions() != img.dimensions())
+        {
+            self.window.clear();
+        }
+        if self.window.is_empty() {
+            self.sum = vec![0.0; img.as_raw().len()];
+        }
+        for (s, &v) in self.sum.iter_mut().zip(img.as_raw()) {
+            *s += v as f32;
+        }
+        let (w, h) = img.dimensions();
+        self.window.push_back(img);
+        if self.window.len() > self.kernel
+            && let Some(old) = self.window.pop_front()
+        {
+            for (s, &v) in self.sum.iter_mut().zip(old.as_raw()) {
+                *s -= v as f32;
+            }
+        }
+        let n = self.window.len() as f32;
+        let mean = self.sum.iter().map(|s| (s / n).round() as u8).collect();
+        RgbImage::from_raw(w, h, mean).expect("sum has one value per subpixel")
+    }
+}
+
+/// The averager for `--motion-blur-kernel`; `None` for the default of 1 (off).
+pub fn temporal_average(kernel: u32) -> anyhow::Result<Option<TemporalAverager>> {
+    let average = TemporalAverager::new(kernel)?;
+    if kernel > 1 {
+        tracing::info!("Temporal averaging over {kernel} frames");
+    }
+    Ok((kernel > 1).then_some(average))
+}
+
+/// Pan and zoom of the view (`hikvision-sam3`: `+`/`-`, arrow keys, `Home`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ViewportState {
+    /// Top-left corner of the view, as a fraction of the frame width and height.
+    pub offset: (f32, f32),
+    /// `1.0` shows the full frame.
+    pub zoom: f32,
+}
+
+impl Default for ViewportState {
+    fn default() -> Self {
+        Self {
+            offset: (0.0, 0.0),
+            zoom: 1.0,
+        }
+    }
+}
+
+impl ViewportState {
+    pub const ZOOM_STEP: f32 = 0.25;
+    pub const MAX_ZOOM: f32 = 8.0;
+    /// Pan step, as a fraction of the visible width/height.
+    pub const PAN_STEP: f32 = 0.1;
+
+    pub fn is_full_frame(&self) -> bool {
+        self.zoom <= 1.0
+    }
+
+    /// Zoom by `steps` [`Self::ZOOM_STEP`]s around the center of the view.
+    pub fn zoom_by(&mut self, steps: f32) {
+        let half = 0.5 / self.zoom;
+        let center = (self.offset.0 + half, self.offset.1 + half);
+        self.zoom = (self.zoom + steps * Self::ZOOM_STEP).clamp(1.0, Self::MAX_ZOOM);
+        let half = 0.5 / self.zoom;
+        self.offset = (center.0 - half, center.1 - half);
+        self.clamp();
+    }
+
+    /// Move the view by `dx`/`dy` [`Self::PAN_STEP`]s; it stays inside the frame.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        let step = Self::PAN_STEP / self.zoom;
+        self.offset = (self.offset.0 + dx * step, self.offset.1 + dy * step);
+        self.clamp();
+    }
+
+    fn clamp(&mut self) {
+        let max = 1.0 - 1.0 / self.zoom;
+        self.offset = (self.offset.0.clamp(0.0, max), self.offset.1.clamp(0.0, max));
+    }
+
+    /// The visible region of a `w`x`h` frame, `(x, y, width, height)` in pixels.
+    pub fn crop_rect(&self, w: u32, h: u32) -> (u32, u32, u32, u32) {
+        if self.is_full_frame() || w == 0 || h == 0 {
+            return (0, 0, w, h);
+        }
+        let cw = ((w as f32 / self.zoom).round() as u32).clamp(1, w);
+        let ch = ((h as f32 / self.zoom).round() as u32).clamp(1, h);
+        let x = ((self.offset.0 * w as f32).round() as u32).min(w - cw);
+        let y = ((self.offset.1 * h as f32).round() as u32).min(h - ch);
+        (x, y, cw, ch)
+    }
+
+    /// `prompts` with their boxes and points moved from `w`x`h` frame pixels into the pixels of
+    /// the zoomed view [`apply_viewport`] makes of that frame. Prompts written for the full frame
+    /// keep pointing at the same objects; boxes outside the view end up outside the image.
+    pub fn prompts_in_view(
+        &self,
+        prompts: &[usls::models::Sam3Prompt],
+        w: u32,
+        h: u32,
+    ) -> Vec<usls::models::Sam3Prompt> {
+        let (x0, y0, cw, ch) = self.crop_rect(w, h);
+        let (sx, sy) = (w as f32 / cw as f32, h as f32 / ch as f32);
+        let (x0, y0) = (x0 as f32, y0 as f32);
+        prompts
+            .iter()
+            .map(|p| {
+                let mut p = p.clone();
+                for hbb in &mut p.boxes {
+                    let (x, y, bw, bh) = hbb.xywh();
+                    let mut moved =
+                        usls::Hbb::from_xywh((x - x0) * sx, (y - y0) * sy, bw * sx, bh * sy);
+                    if let Some(name) = hbb.name() {
+                        moved = moved.with_name(name);
+                    }
+                    if let Some(id) = hbb.id() {
+                        moved = moved.with_id(id);
+                    }
+                    *hbb = moved;
+                }
+                for kpt in &mut p.points {
+                    let (x, y) = kpt.xy();
+                    *kpt = kpt.clone().with_xy((x - x0) * sx, (y - y0) * sy);
+                }
+                p
+            })
+            .collect()
+    }
+}
+
+/// The region of `img` selected by `vp`, scaled back up to the full frame size.
+pub fn apply_viewport(img: &RgbImage, vp: &ViewportState) -> RgbImage {
+    let (w, h) = img.dimensions();
+    if vp.is_full_frame() || w == 0 || h == 0 {
+        return img.clone();
+    }
+    let (x, y, cw, ch) = vp.crop_rect(w, h);
+    let crop = image::imageops::crop_imm(img, x, y, cw, ch).to_image();
+    image::imageops::resize(&crop, w, h, FilterType::Triangle)
+}
+
+/// `--roi-file` contents: polygons in output-frame pixels.
+#[derive(Deserialize)]
+struct RoiFile {
+    polygons: Vec<Vec<[f32; 2]>>,
+}
+
+/// Set the pixels of `mask` whose centers lie inside `polygon` (even-odd rule) to 1, one scanline
+/// at a time.
+fn fill_polygon(mask: &mut GrayImage, polygon: &[[f32; 2]]) {
+    let (w, h) = mask.dimensions();
+    let mut crossings = Vec::new();
+    for y in 0..h {
+        let yc = y as f32 + 0.5;
+        crossings.clear();
+        for (i, a) in polygon.iter().enumerate() {
+            let b = polygon[(i + 1) % polygon.len()];
+            if (a[1] <= yc) != (b[1] <= yc) {
+                crossings.push(a[0] + (yc - a[1]) * (b[0] - a[0]) / (b[1] - a[1]));
+            }
+        }
+        crossings.sort_by(f32::total_cmp);
+        for span in crossings.chunks_exact(2) {
+            // Pixels whose center x + 0.5 lies in [span[0], span[1]).
+            let x0 = (span[0] - 0.5).ceil().clamp(0.0, w as f32) as u32;
+            let x1 = (span[1] - 0.5).ceil().clamp(0.0, w as f32) as u32;
+            for x in x0..x1 {
+                mask.put_pixel(x, y, image::Luma([1]));
+            }
+        }
+    }
+}
+
+/// `--roi-file <path>`: a `width` x `height` mask that is 1 inside the union of the file's polygons
+/// and 0 outside, to multiply frames with.
+pub fn load_roi_mask(path: &Path, width: u32, height: u32) -> anyhow::Result<GrayImage> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read ROI file {}", path.display()))?;
+    let roi: RoiFile = serde_json::from_str(&text)
+        .with_context(|| format!("invalid ROI file {}", path.display()))?;
+    anyhow::ensure!(
+        !roi.polygons.is_empty(),
+        "ROI file {} has no polygons",
+        path.display()
+    );
+    let mut mask = GrayImage::new(width, height);
+    for (i, polygon) in roi.polygons.iter().enumerate() {
+        anyhow::ensure!(
+            polygon.len() >= 3,
+            "ROI polygon {i} in {} has fewer than 3 points",
+            path.display()
+        );
+        fill_polygon(&mut mask, polygon);
+    }
+    Ok(mask)
+}
+
+/// The mask for `--roi-file` at the frame size, with its coverage logged; `None` without one.
+pub fn roi_mask(
+    path: Option<&str>,
+    (width, height): (u32, u32),
+) -> anyhow::Result<Option<GrayImage>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let mask = load_roi_mask(Path::new(path), width, height)?;
+    let active = mask.as_raw().iter().filter(|v| **v != 0).count();
+    anyhow::ensure!(
+        active > 0,
+        "--roi-file {path} covers no pixels of the {width}x{height} frame"
+    );
+    tracing::info!(
+        "ROI: {active} of {} pixels active ({:.1}%)",
+        mask.as_raw().len(),
+        100.0 * active as f64 / mask.as_raw().len() as f64
+    );
+    Ok(Some(mask))
+}
+
+/// `img` with every channel multiplied by `mask`, i.e. black outside the ROI.
+pub fn apply_roi_mask(img: &usls::Image, mask: &GrayImage) -> anyhow::Result<usls::Image> {
+    anyhow::ensure!(
+        (img.width(), img.height()) == mask.dimensions(),
+        "frame is {}x{} but the ROI mask is {}x{}",
+        img.width(),
+        img.height(),
+        mask.width(),
+        mask.height()
+    );
+    let masked = img
+        .as_raw()
+        .chunks_exact(3)
+        .zip(mask.as_raw())
+        .flat_map(|(px, m)| px.iter().map(move |c| c * m))
+        .collect();
+    let rgb = RgbImage::from_raw(img.width(), img.height(), masked)
+        .context("failed to construct RgbImage")?;
+    Ok(usls::Image::from(rgb))
+}
+
+/// `--gamma`, `--brightness`, `--contrast` and `--auto-levels`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneParams {
+    /// Output is `input^(1/gamma)`, so values above 1 lift the shadows.
+    pub gamma: f32,
+    /// Added after the other steps, as a fraction of full scale (-1 to 1).
+    pub brightness: f32,
+    /// Scale around mid-gray.
+    pub contrast: f32,
+    /// Stretch each frame's [`AUTO_LEVELS_CLIP`] to `1 - AUTO_LEVELS_CLIP` percentile levels to
+    /// the full range first.
+    pub auto_levels: bool,
+}
+
+/// Fraction of the darkest and of the brightest samples `--auto-levels` clips.
+pub const AUTO_LEVELS_CLIP: f64 = 0.005;
+
+impl ToneParams {
+    fn is_identity(&self) -> bool {
+        self.gamma == 1.0 && self.brightness == 0.0 && self.contrast == 1.0 && !self.auto_levels
+    }
+}
+
+/// `--preprocess-display-only` / `--preprocess-infer-only`: which frames the tone curve changes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneTarget {
+    /// The model input and everything shown or saved.
+    #[default]
+    Both,
+    DisplayOnly,
+    InferOnly,
+}
+
+impl ToneTarget {
+    pub fn from_flags(display_only: bool, infer_only: bool) -> anyhow::Result<Self> {
+        match (display_only, infer_only) {
+            (false, false) => Ok(Self::Both),
+            (true, false) => Ok(Self::DisplayOnly),
+            (false, true) => Ok(Self::InferOnly),
+            (true, true) => anyhow::bail!(
+                "--preprocess-display-only and --preprocess-infer-only are mutually exclusive"
+            ),
+        }
+    }
+}
+
+/// The 8-bit curve of `params` for frames whose levels run from `black` to `white`.
+pub fn build_lut(params: &ToneParams, (black, white): (u8, u8)) -> [u8; 256] {
+    let (black, span) = (f32::from(black), f32::from(white.max(black + 1) - black));
+    std::array::from_fn(|i| {
+        let v = ((i as f32 - black) / span).clamp(0.0, 1.0);
+        let v = v.powf(1.0 / params.gamma);
+        let v = (v - 0.5) * params.contrast + 0.5 + params.brightness;
+        (v.clamp(0.0, 1.0) * 255.0).round() as u8
+    })
+}
+
+/// Bytes per task of [`apply_lut`] and [`apply_lut_into`].
+const LUT_CHUNK: usize = 64 * 1024;
+
+/// Map every byte of `buf` through `lut` in place, in parallel over 64 KiB chunks.
+pub fn apply_lut(lut: &[u8; 256], buf: &mut [u8]) {
+    buf.par_chunks_mut(LUT_CHUNK).for_each(|chunk| {
+        for v in chunk {
+            *v = lut[usize::from(*v)];
+        }
+    });
+}
+
+/// Map `src` through `lut` into `dst` (same length), in parallel over 64 KiB chunks.
+pub fn apply_lut_into(lut: &[u8; 256], src: &[u8], dst: &mut [u8]) {
+    dst.par_chunks_mut(LUT_CHUNK)
+        .zip(src.par_chunks(LUT_CHUNK))
+        .for_each(|(dst, src)| {
+            for (d, s) in dst.iter_mut().zip(src) {
+                *d = lut[usize::from(*s)];
+            }
+        });
+}
+
+/// Black and white points of an RGB buffer for `--auto-levels`, from every 16th pixel (130k
+/// samples at 1080p).
+fn auto_levels(rgb: &[u8]) -> (u8, u8) {
+    let mut hist = [0u64; 256];
+    for px in rgb.chunks_exact(3).step_by(16) {
+        for c in px {
+            hist[usize::from(*c)] += 1;
+        }
+    }
+    let total: u64 = hist.iter().sum();
+    let clip = (total as f64 * AUTO_LEVELS_CLIP) as u64;
+    let percentile = |mut bins: Box<dyn Iterator<Item = usize>>| {
+        let mut seen = 0;
+        bins.find(|&v| {
+            seen += hist[v];
+            seen > clip
+        })
+        .unwrap_or(0) as u8
+    };
+    let black = percentile(Box::new(0..256));
+    let white = percentile(Box::new((0..256).rev()));
+    if white > black {
+        (black, white)
+    } else {
+        (0, 255)
+    }
+}
+
+/// The tone curve of a run, as a LUT rebuilt only when its input levels change (i.e. never
+/// without `--auto-levels`).
+pub struct ToneAdjust {
+    params: ToneParams,
+    target: ToneTarget,
+    lut: [u8; 256],
+    levels: (u8, u8),
+    /// The second frame of [`ToneAdjust::split`], reused from frame to frame.
+    spare: usls::Image,
+}
+
+impl ToneAdjust {
+    /// `None` when `params` change nothing.
+    pub fn new(params: ToneParams, target: ToneTarget) -> anyhow::Result<Option<Self>> {
+        anyhow::ensure!(
+            params.gamma.is_finite() && params.gamma > 0.0,
+            "--gamma must be > 0, got {}",
+            params.gamma
+        );
+        anyhow::ensure!(
+            params.contrast.is_finite() && params.contrast >= 0.0,
+            "--contrast must be >= 0, got {}",
+            params.contrast
+        );
+        anyhow::ensure!(
+            (-1.0..=1.0).contains(&params.brightness),
+            "--brightness must be between -1 and 1, got {}",
+            params.brightness
+        );
+        if params.is_identity() {
+            if target != ToneTarget::Both {
+                tracing::warn!(
+                    "--preprocess-display-only/--preprocess-infer-only without --gamma, \
+                     --brightness, --contrast or --auto-levels; ignoring"
+                );
+            }
+            return Ok(None);
+        }
+        let levels = (0, 255);
+        Ok(Some(Self {
+            params,
+            target,
+            lut: build_lut(&params, levels),
+            levels,
+            spare: usls::Image::default(),
+        }))
+    }
+
+    /// Rebuild the LUT for the levels of `img` with `--auto-levels`.
+    fn update_levels(&mut self, img: &usls::Image) {
+        if self.params.auto_levels {
+            let levels = auto_levels(img.as_raw());
+            if levels != self.levels {
+                self.levels = levels;
+                self.lut = build_lut(&self.params, levels);
+            }
+        }
+    }
+
+    /// Put `img` through the curve, in place.
+    pub fn apply(&mut self, img: &mut usls::Image) {
+        self.update_levels(img);
+        apply_lut(&self.lut, img);
+    }
+
+    /// `img` through the curve into the spare frame, which is (re)allocated only when the
+    /// frame size changes.
+    fn apply_to_spare(&mut self, img: &usls::Image) {
+        self.update_levels(img);
+        if (self.spare.width(), self.spare.height()) != (img.width(), img.height()) {
+            self.spare = usls::Image::from(RgbImage::new(img.width(), img.height()));
+        }
+        apply_lut_into(&self.lut, img.as_raw(), &mut self.spare);
+    }
+
+    /// The frame to show and save, and the model input when it differs from that.
+    ///
+    /// Frames that are not inferred (`infer` false) only get what is shown adjusted, so with
+    /// `--preprocess-infer-only` they pass through untouched.
+    pub fn split(
+        &mut self,
+        mut img: usls::Image,
+        infer: bool,
+    ) -> (usls::Image, Option<&usls::Image>) {
+        match self.target {
+            ToneTarget::Both => {
+                self.apply(&mut img);
+                (img, None)
+            }
+            ToneTarget::DisplayOnly if infer => {
+                self.apply_to_spare(&img);
+                std::mem::swap(&mut img, &mut self.spare);
+                (img, Some(&self.spare))
+            }
+            ToneTarget::DisplayOnly => {
+                self.apply(&mut img);
+                (img, None)
+            }
+            ToneTarget::InferOnly if infer => {
+                self.apply_to_spare(&img);
+                (img, Some(&self.spare))
+            }
+            ToneTarget::InferOnly => (img, None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn tone(gamma: f32, brightness: f32, contrast: f32) -> ToneParams {
+        ToneParams {
+            gamma,
+            brightness,
+            contrast,
+            auto_levels: false,
+        }
+    }
+
+    #[test]
+    fn builds_tone_curves() {
+        let full = (0, 255);
+        let identity = build_lut(&tone(1.0, 0.0, 1.0), full);
+        assert!(
+            identity
+                .iter()
+                .enumerate()
+                .all(|(i, v)| usize::from(*v) == i)
+        );
+        // 255 * (64/255)^(1/2) = 127.75
+        let lifted = build_lut(&tone(2.0, 0.0, 1.0), full);
+        assert_eq!((lifted[0], lifted[64], lifted[255]), (0, 128, 255));
+        // ((100/255 - 0.5) * 1.5 + 0.5) * 255 = 86.25
+        let contrast = build_lut(&tone(1.0, 0.0, 1.5), full);
+        assert_eq!((contrast[100], contrast[10], contrast[250]), (86, 0, 255));
+        // 100 + 0.2 * 255 = 151
+        let brighter = build_lut(&tone(1.0, 0.2, 1.0), full);
+        assert_eq!((brighter[100], brighter[250]), (151, 255));
+        // 50..150 stretched to 0..255: 75 -> 63.75
+        let stretched = build_lut(&tone(1.0, 0.0, 1.0), (50, 150));
+        assert_eq!(
+            (stretched[40], stretched[75], stretched[150], stretched[200]),
+            (0, 64, 255, 255)
+        );
+    }
+
+    #[test]
+    fn adjusts_frames_through_the_lut() {
+        let img = usls::Image::from(RgbImage::from_fn(8, 8, |x, _| {
+            image::Rgb([50 + x as u8 * 10, 64, 100])
+        }));
+        let mut lifted = ToneAdjust::new(tone(2.0, 0.0, 1.0), ToneTarget::InferOnly)
+            .unwrap()
+            .unwrap();
+        let (shown, input) = lifted.split(img.clone(), true);
+        assert_eq!(shown.as_raw(), img.as_raw());
+        assert_eq!(&input.unwrap().as_raw()[..3], &[113, 128, 160]);
+        let (shown, input) = lifted.split(img.clone(), false);
+        assert_eq!(shown.as_raw(), img.as_raw());
+        assert!(input.is_none());
+
+        let mut shown_only = ToneAdjust::new(tone(2.0, 0.0, 1.0), ToneTarget::DisplayOnly)
+            .unwrap()
+            .unwrap();
+        for infer in [true, false, true] {
+            let (shown, input) = shown_only.split(img.clone(), infer);
+            assert_eq!(&shown.as_raw()[..3], &[113, 128, 160]);
+            assert_eq!(input.map(|i| i.as_raw() == img.as_raw()), infer.then_some(true));
+        }
+
+        let mut levels = ToneAdjust::new(
+            ToneParams {
+                auto_levels: true,
+                ..tone(1.0, 0.0, 1.0)
+            },
+            ToneTarget::Both,
+        )
+        .unwrap()
+        .unwrap();
+        let (stretched, input) = levels.split(img, true);
+        assert!(input.is_none());
+        let raw = stretched.as_raw();
+        assert_eq!((raw.iter().min(), raw.iter().max()), (Some(&0), Some(&255)));
+        assert_ne!(levels.levels, (0, 255));
+
+        assert!(
+            ToneAdjust::new(tone(1.0, 0.0, 1.0), ToneTarget::Both)
+                .unwrap()
+                .is_none()
+        );
+        assert!(ToneAdjust::new(tone(0.0, 0.0, 1.0), ToneTarget::Both).is_err());
+        assert!(ToneTarget::from_flags(true, true).is_err());
+    }
+
+    fn flat(v: u8) -> RgbImage {
+        RgbImage::from_pixel(2, 2, image::Rgb([v, v, v]))
+    }
+
+    #[test]
+    fn rasterizes_roi_polygons() {
+        let mut mask = GrayImage::new(10, 10);
+        // L shape: left column band plus bottom row band.
+        fill_polygon(
+            &mut mask,
+            &[
+                [0.0, 0.0],
+                [3.0, 0.0],
+                [3.0, 7.0],
+                [10.0, 7.0],
+                [10.0, 10.0],
+                [0.0, 10.0],
+            ],
+        );
+        let active = mask.as_raw().iter().filter(|v| **v == 1).count();
+        assert_eq!(active, 3 * 7 + 10 * 3);
+        assert_eq!(mask.get_pixel(2, 2).0, [1]);
+        assert_eq!(mask.get_pixel(5, 2).0, [0]);
+        assert_eq!(mask.get_pixel(9, 9).0, [1]);
+
+        // Out-of-frame points are clipped.
+        let mut mask = GrayImage::new(4, 4);
+        fill_polygon(&mut mask, &[[-5.0, -5.0], [20.0, -5.0], [20.0, 20.0]]);
+        assert_eq!(mask.get_pixel(3, 0).0, [1]);
+        assert_eq!(mask.get_pixel(0, 3).0, [0]);
+    }
+
+    #[test]
+    fn loads_roi_files() {
+        let dir = TempDir::new("roi");
+        let path = dir.join("roi.json");
+        std::fs::write(
+            &path,
+            r#"{"polygons": [[[0, 0], [2, 0], [2, 2], [0, 2]], [[6, 6], [8, 6], [8, 8], [6, 8]]]}"#,
+        )
+        .unwrap();
+        let mask = load_roi_mask(&path, 8, 8).unwrap();
+        assert_eq!(mask.as_raw().iter().filter(|v| **v == 1).count(), 8);
+        std::fs::write(&path, r#"{"polygons": [[[0, 0], [2, 0]]]}"#).unwrap();
+        assert!(load_roi_mask(&path, 8, 8).is_err());
+    }
+
+    #[test]
+    fn averages_a_sliding_window() {
+        assert!(TemporalAverager::new(2).is_err());
+        assert!(TemporalAverager::new(0).is_err());
+        let mut avg = TemporalAverager::new(3).unwrap();
+        assert_eq!(avg.push(flat(30)), flat(30));
+        assert_eq!(avg.push(flat(60)), flat(45));
+        assert_eq!(avg.push(flat(90)), flat(60));
+        // 30 leaves the window.
+        assert_eq!(avg.push(flat(120)), flat(90));
+        // A new size starts over.
+        let big = RgbImage::from_pixel(4, 2, image::Rgb([7, 7, 7]));
+        assert_eq!(avg.push(big.clone()), big);
+    }
+
+    #[test]
+    fn viewport_zooms_around_the_center_and_stays_inside() {
+        let mut vp = ViewportState::default();
+        vp.zoom_by(4.0);
+        assert_eq!(vp.zoom, 2.0);
+        assert_eq!(vp.offset, (0.25, 0.25));
+        vp.pan(-100.0, 100.0);
+        assert_eq!(vp.offset, (0.0, 0.5));
+        vp.zoom_by(-100.0);
+        assert!(vp.is_full_frame());
+        assert_eq!(vp.offset, (0.0, 0.0));
+    }
+
+    #[test]
+    fn apply_viewport_crops_and_rescales() {
+        // Left half black, right half white.
+        let img = RgbImage::from_fn(8, 4, |x, _| {
+            let v = if x < 4 { 0 } else { 255 };
+            image::Rgb([v, v, v])
+        });
+        assert_eq!(apply_viewport(&img, &ViewportState::default()), img);
+        let vp = ViewportState {
+            offset: (0.5, 0.0),
+            zoom: 2.0,
+        };
+        let zoomed = apply_viewport(&img, &vp);
+        assert_eq!(zoomed.dimensions(), (8, 4));
+        assert!(zoomed.pixels().all(|p| p.0 == [255, 255, 255]));
+    }
+
+    #[test]
+    fn prompt_boxes_follow_the_zoom() {
+        let prompts: Vec<usls::models::Sam3Prompt> =
+            vec!["car;pos:400,300,100,50".parse().unwrap()];
+        let full = ViewportState::default();
+        assert_eq!(
+            full.prompts_in_view(&prompts, 800, 600)[0].boxes[0].xywh(),
+            (400.0, 300.0, 100.0, 50.0)
+        );
+        // The bottom-right quarter of an 800x600 frame, shown at 800x600.
+        let vp = ViewportState {
+            offset: (0.5, 0.5),
+            zoom: 2.0,
+        };
+        assert_eq!(vp.crop_rect(800, 600), (400, 300, 400, 300));
+        let moved = vp.prompts_in_view(&prompts, 800, 600);
+        assert_eq!(moved[0].text, prompts[0].text);
+        assert_eq!(moved[0].boxes[0].xywh(), (0.0, 0.0, 200.0, 100.0));
+        assert_eq!(moved[0].boxes[0].name(), prompts[0].boxes[0].name());
+    }
+}
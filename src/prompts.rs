@@ -0,0 +1,128 @@
+//! `--mirror-prompt`: add the left-right mirror image of every box prompt, for parts inspected on
+//! both sides of a symmetric frame without typing each `pos:` box twice.
+//!
+//! The mirrored prompts follow the originals, so per-prompt lists (labels, `#grp=` tags) are
+//! extended with [`mirror_aligned`] to stay index-aligned with the prompts.
+
+use usls::{Hbb, models::Sam3Prompt};
+
+/// Prompts with a mirrored copy: those with box prompts and no point hints.
+fn mirrors(prompt: &Sam3Prompt) -> bool {
+    !prompt.boxes.is_empty() && prompt.points.is_empty()
+}
+
+/// `hbb` reflected across the vertical center line of an `image_width`-wide frame.
+/// Appended to the text and label of each mirrored prompt so its detections can be told apart.
+pub const MIRRORED_SUFFIX: &str = " (mirrored)";
+
+fn mirror_box(hbb: &Hbb, image_width: u32) -> Hbb {
+    let (x, y, w, h) = hbb.xywh();
+    let mut mirrored = Hbb::from_xywh(image_width as f32 - x - w, y, w, h);
+    if let Some(name) = hbb.name() {
+        mirrored = mirrored.with_name(name);
+    }
+    if let Some(id) = hbb.id() {
+        mirrored = mirrored.with_id(id);
+    }
+    mirrored
+}
+
+/// `prompts` followed by a mirrored copy of each prompt with boxes (`pos:`/`neg:`), every box at
+/// `x = image_width - x - w`. The copy's text gets [`MIRRORED_SUFFIX`]; text-only prompts and
+/// prompts with point hints are not copied.
+pub fn mirror_positional_prompts(prompts: &[Sam3Prompt], image_width: u32) -> Vec<Sam3Prompt> {
+    let mirrored = prompts.iter().filter(|p| mirrors(p)).map(|p| {
+        let mut p = p.clone();
+        p.boxes = p.boxes.iter().map(|b| mirror_box(b, image_width)).collect();
+        p.text.push_str(MIRRORED_SUFFIX);
+        p
+    });
+    prompts.iter().cloned().chain(mirrored).collect()
+}
+
+/// `items`, one per prompt, extended like [`mirror_positional_prompts`] extends `prompts`.
+/// Returned unchanged when the lengths differ (e.g. `--pipeline` prompts have no raw strings).
+pub fn mirror_aligned<T: Clone>(items: &[T], prompts: &[Sam3Prompt]) -> Vec<T> {
+    if items.len() != prompts.len() {
+        return items.to_vec();
+    }
+    let copies = items
+        .iter()
+        .zip(prompts)
+        .filter(|(_, p)| mirrors(p))
+        .map(|(item, _)| item.clone());
+    items.iter().cloned().chain(copies).collect()
+}
+
+/// Raw prompt strings, their `#grp=` tags and the parsed prompts, with the mirrored copies added
+/// when `mirror_width` (the frame width for `--mirror-prompt`) is set. The copied raw strings,
+/// used as labels, get [`MIRRORED_SUFFIX`].
+pub fn with_mirrored(
+    mirror_width: Option<u32>,
+    raw: Vec<String>,
+    tags: Vec<Option<String>>,
+    prompts: Vec<Sam3Prompt>,
+) -> (Vec<String>, Vec<Option<String>>, Vec<Sam3Prompt>) {
+    match mirror_width {
+        Some(width) => (
+            mirror_aligned(&raw, &prompts)
+                .into_iter()
+                .enumerate()
+                .map(|(i, mut label)| {
+                    if i >= raw.len() {
+                        label.push_str(MIRRORED_SUFFIX);
+                    }
+                    label
+                })
+                .collect(),
+            mirror_aligned(&tags, &prompts),
+            mirror_positional_prompts(&prompts, width),
+        ),
+        None => (raw, tags, prompts),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &[&str]) -> Vec<Sam3Prompt> {
+        raw.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn mirrors_box_prompts() {
+        let prompts = parse(&["screw", "visual;pos:10,20,30,40", "hole;pos:0,0,100,10"]);
+        let mirrored = mirror_positional_prompts(&prompts, 640);
+        assert_eq!(mirrored.len(), 5);
+        assert_eq!(mirrored[0].text, "screw");
+        assert_eq!(mirrored[1].boxes[0].xywh(), (10.0, 20.0, 30.0, 40.0));
+        assert_eq!(mirrored[1].text, prompts[1].text);
+        assert_eq!(
+            mirrored[3].text,
+            format!("{}{MIRRORED_SUFFIX}", prompts[1].text)
+        );
+        assert_eq!(mirrored[3].boxes[0].xywh(), (600.0, 20.0, 30.0, 40.0));
+        assert_eq!(mirrored[3].boxes[0].name(), prompts[1].boxes[0].name());
+        assert_eq!(mirrored[4].text, "hole (mirrored)");
+        assert_eq!(mirrored[4].boxes[0].xywh(), (540.0, 0.0, 100.0, 10.0));
+    }
+
+    #[test]
+    fn extends_aligned_lists() {
+        let prompts = parse(&["screw", "visual;pos:10,20,30,40"]);
+        let tags = [Some("a"), None];
+        assert_eq!(mirror_aligned(&tags, &prompts), [Some("a"), None, None]);
+        assert_eq!(mirror_aligned(&["x"], &prompts), ["x"]);
+    }
+
+    #[test]
+    fn mirrored_labels_are_tagged() {
+        let raw = vec!["screw".to_string(), "visual;pos:10,20,30,40".to_string()];
+        let prompts = parse(&["screw", "visual;pos:10,20,30,40"]);
+        let (labels, tags, prompts) = with_mirrored(Some(640), raw, vec![None, None], prompts);
+        assert_eq!(labels[2], "visual;pos:10,20,30,40 (mirrored)");
+        assert_eq!(labels[..2], ["screw", "visual;pos:10,20,30,40"]);
+        assert_eq!((tags.len(), prompts.len()), (3, 3));
+    }
+}
@@ -0,0 +1,117 @@
+//! Python bindings (`python` feature), built as an extension module with `maturin develop -r`:
+//!
+//! ```python
+//! import numpy as np
+//! from sam3_card_detector import Sam3Detector
+//!
+//! detector = Sam3Detector(device="cuda:0", conf=0.4)
+//! for d in detector.detect(rgb, ["playing card"]):  # rgb: HxWx3 uint8
+//!     print(d["label"], d["score"], d["box"])
+//! ```
+
+use numpy::{PyReadonlyArray3, PyUntypedArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use usls::models::{SAM3, Sam3Prompt};
+
+use crate::common::ModelOptions;
+use crate::output::{Detection, MaskEncoding, Segmentation};
+
+/// A loaded SAM3 model; `detect` runs it on one frame.
+#[pyclass(unsendable)]
+pub struct Sam3Detector {
+    model: SAM3,
+}
+
+#[pymethods]
+impl Sam3Detector {
+    /// Same meaning and defaults as the binaries' `--task`, `--device`, `--dtype` and `--conf`.
+    #[new]
+    #[pyo3(signature = (task = "sam3-image", device = "cpu:0", dtype = "q4f16", conf = 0.5))]
+    fn new(task: &str, device: &str, dtype: &str, conf: f32) -> PyResult<Self> {
+        let opts = ModelOptions::new(task, device, dtype, conf);
+        let model = opts.load_model(opts.build_config()?)?;
+        Ok(Self { model })
+    }
+
+    /// Run on an `HxWx3` `uint8` RGB array with `-p`-style prompts. Returns one dict per
+    /// detection: `label`, `class_id`, `score`, `box` (`[x, y, w, h]` in pixels) and `polygon`
+    /// (`[[x, y], ...]`, or `None` without a mask). The GIL is released during inference, so
+    /// other Python threads keep running.
+    fn detect<'py>(
+        &mut self,
+        py: Python<'py>,
+        image: PyReadonlyArray3<'py, u8>,
+        prompts: Vec<String>,
+    ) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        let (width, height) = rgb_size(image.shape()).map_err(PyValueError::new_err)?;
+        // Iterates in logical order, so non-contiguous views (slices, BGR->RGB flips) work too.
+        let data: Vec<u8> = image.as_array().iter().copied().collect();
+        let rgb = image::RgbImage::from_raw(width, height, data)
+            .ok_or_else(|| PyValueError::new_err("failed to construct RgbImage"))?;
+        let prompts = parse_prompts(&prompts).map_err(PyValueError::new_err)?;
+
+        let model = &mut self.model;
+        let ys = py.detach(|| model.forward(&[usls::Image::from(rgb)], &prompts))?;
+        let Some(y) = ys.first() else {
+            return Ok(Vec::new());
+        };
+        Detection::from_y_with_masks(y, MaskEncoding::Polygon, width, height)
+            .into_iter()
+            .map(|d| {
+                let dict = PyDict::new(py);
+                dict.set_item("label", d.label)?;
+                dict.set_item("class_id", d.class_id)?;
+                dict.set_item("score", d.confidence)?;
+                dict.set_item("box", d.bbox)?;
+                let polygon = match d.segmentation {
+                    Some(Segmentation::Polygon(points)) => Some(points),
+                    _ => None,
+                };
+                dict.set_item("polygon", polygon)?;
+                Ok(dict)
+            })
+            .collect()
+    }
+}
+
+/// `(width, height)` of an `HxWx3` array shape.
+fn rgb_size(shape: &[usize]) -> Result<(u32, u32), String> {
+    match *shape {
+        [height, width, 3] => Ok((width as u32, height as u32)),
+        _ => Err(format!("expected an HxWx3 RGB array, got shape {shape:?}")),
+    }
+}
+
+fn parse_prompts(raw: &[String]) -> Result<Vec<Sam3Prompt>, String> {
+    if raw.is_empty() {
+        return Err("No prompt. Pass e.g. [\"text\"] or [\"visual;pos:x,y,w,h\"]".to_string());
+    }
+    raw.iter().map(|s| s.parse()).collect()
+}
+
+#[pymodule]
+fn sam3_card_detector(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Sam3Detector>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_arrays_and_prompts_before_inference() {
+        assert_eq!(rgb_size(&[480, 640, 3]), Ok((640, 480)));
+        assert!(
+            rgb_size(&[480, 640, 4])
+                .unwrap_err()
+                .contains("[480, 640, 4]")
+        );
+        assert!(parse_prompts(&[]).unwrap_err().starts_with("No prompt"));
+        let prompts = parse_prompts(&["card".to_string(), "visual;pos:1,2,3,4".to_string()]);
+        assert_eq!(prompts.unwrap().len(), 2);
+        assert!(ModelOptions::new("sam3-image", "cpu:0", "q4f16", 0.5).trt_fp16);
+    }
+}
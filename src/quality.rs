@@ -0,0 +1,200 @@
+//! Frame-quality gate for `video-sam3`: skip inference on frames that are most likely decode
+//! garbage (corrupted GOPs come out black, flat gray or smeared) so they don't produce detections.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Luma samples per frame; larger frames are strided so the check stays cheap at 4K.
+const MAX_SAMPLES: usize = 1 << 16;
+
+/// `quality` of a [`crate::output::FrameRecord`] the gate flagged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameQuality {
+    Suspect,
+}
+
+/// Why a frame was flagged.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum QualityIssue {
+    /// Every sample at or below the black level.
+    Black,
+    /// All channels within a tiny range (e.g. the flat gray of a missing reference frame).
+    Uniform,
+    /// Luma standard deviation below the threshold.
+    LowVariance,
+    /// ffmpeg reported a decode error while this frame was being decoded.
+    DecodeError,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct QualityThresholds {
+    /// Highest channel value still counted as black.
+    pub black_level: u8,
+    /// Largest max - min channel spread counted as uniform.
+    pub uniform_range: u8,
+    /// Lowest acceptable luma standard deviation.
+    pub min_stddev: f32,
+}
+
+impl Default for QualityThresholds {
+    fn default() -> Self {
+        Self {
+            black_level: 16,
+            uniform_range: 4,
+            min_stddev: 2.0,
+        }
+    }
+}
+
+/// Pixel-only check of one frame; `None` when it looks like real content.
+pub fn inspect(img: &usls::Image, thresholds: &QualityThresholds) -> Option<QualityIssue> {
+    let pixels = img.as_raw().chunks_exact(3);
+    let step = (pixels.len() / MAX_SAMPLES).max(1);
+    let (mut lo, mut hi) = (u8::MAX, u8::MIN);
+    let (mut sum, mut sum_sq, mut n) = (0f64, 0f64, 0usize);
+    for px in pixels.step_by(step) {
+        lo = lo.min(px[0]).min(px[1]).min(px[2]);
+        hi = hi.max(px[0]).max(px[1]).max(px[2]);
+        let luma = 0.299 * f64::from(px[0]) + 0.587 * f64::from(px[1]) + 0.114 * f64::from(px[2]);
+        sum += luma;
+        sum_sq += luma * luma;
+        n += 1;
+    }
+    if n == 0 {
+        return Some(QualityIssue::Black);
+    }
+    if hi <= thresholds.black_level {
+        return Some(QualityIssue::Black);
+    }
+    if hi - lo <= thresholds.uniform_range {
+        return Some(QualityIssue::Uniform);
+    }
+    let mean = sum / n as f64;
+    let variance = (sum_sq / n as f64 - mean * mean).max(0.0);
+    (variance.sqrt() < f64::from(thresholds.min_stddev)).then_some(QualityIssue::LowVariance)
+}
+
+/// Per-frame gate: pixel checks plus decoder error reports, and a count for the run summary.
+pub struct QualityGate {
+    thresholds: QualityThresholds,
+    /// Frames flagged after each new decoder error.
+    decode_window: u64,
+    seen_errors: u64,
+    suspect_until: u64,
+    suspect: u64,
+}
+
+impl QualityGate {
+    pub fn new(thresholds: QualityThresholds, decode_window: u64) -> Self {
+        Self {
+            thresholds,
+            decode_window,
+            seen_errors: 0,
+            suspect_until: 0,
+            suspect: 0,
+        }
+    }
+
+    /// Check frame `frame_idx`; `decode_errors` is the decoder's running error count.
+    ///
+    /// ffmpeg's error lines carry no timestamp, so they are matched by arrival: errors that
+    /// showed up since the previous frame flag this frame and the next `decode_window - 1`.
+    pub fn check(
+        &mut self,
+        frame_idx: u64,
+        img: &usls::Image,
+        decode_errors: u64,
+    ) -> Option<QualityIssue> {
+        if decode_errors > self.seen_errors {
+            self.seen_errors = decode_errors;
+            self.suspect_until = frame_idx + self.decode_window;
+        }
+        let issue = if frame_idx < self.suspect_until {
+            Some(QualityIssue::DecodeError)
+        } else {
+            inspect(img, &self.thresholds)
+        };
+        if let Some(issue) = issue {
+            self.suspect += 1;
+            tracing::debug!("Frame {frame_idx} is suspect ({issue:?}), skipping inference");
+        }
+        issue
+    }
+
+    /// The decoder was replaced (next input file): its error count starts over from zero.
+    pub fn source_changed(&mut self) {
+        self.seen_errors = 0;
+    }
+
+    pub fn suspect_frames(&self) -> u64 {
+        self.suspect
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(w: u32, h: u32) -> usls::Image {
+        usls::Image::from(image::RgbImage::from_fn(w, h, |x, y| {
+            image::Rgb([(x * 255 / w) as u8, (y * 255 / h) as u8, 90])
+        }))
+    }
+
+    fn solid(w: u32, h: u32, v: [u8; 3]) -> usls::Image {
+        usls::Image::from(image::RgbImage::from_pixel(w, h, image::Rgb(v)))
+    }
+
+    #[test]
+    fn flags_black_uniform_and_flat_frames() {
+        let t = QualityThresholds::default();
+        let black = solid(64, 48, [3, 5, 2]);
+        assert_eq!(inspect(&black, &t), Some(QualityIssue::Black));
+        let gray = solid(64, 48, [128, 128, 128]);
+        assert_eq!(inspect(&gray, &t), Some(QualityIssue::Uniform));
+        // One bright pixel: spread above the uniform range, but almost no variance.
+        let flat = usls::Image::from(image::RgbImage::from_fn(64, 48, |x, y| {
+            let v = if x == 0 && y == 0 { 140 } else { 128 };
+            image::Rgb([v, v, v])
+        }));
+        assert_eq!(inspect(&flat, &t), Some(QualityIssue::LowVariance));
+        assert_eq!(inspect(&gradient(64, 48), &t), None);
+    }
+
+    #[test]
+    fn large_frames_are_sampled() {
+        let t = QualityThresholds::default();
+        assert_eq!(inspect(&gradient(1920, 1080), &t), None);
+        let gray = solid(1920, 1080, [100, 100, 100]);
+        assert_eq!(inspect(&gray, &t), Some(QualityIssue::Uniform));
+    }
+
+    #[test]
+    fn decode_errors_flag_a_window_of_frames() {
+        let mut gate = QualityGate::new(QualityThresholds::default(), 2);
+        let good = gradient(32, 32);
+        assert_eq!(gate.check(1, &good, 0), None);
+        assert_eq!(gate.check(2, &good, 1), Some(QualityIssue::DecodeError));
+        assert_eq!(gate.check(3, &good, 1), Some(QualityIssue::DecodeError));
+        assert_eq!(gate.check(4, &good, 1), None);
+        let gray = solid(32, 32, [128, 128, 128]);
+        assert_eq!(gate.check(5, &gray, 1), Some(QualityIssue::Uniform));
+        assert_eq!(gate.suspect_frames(), 3);
+    }
+
+    #[test]
+    fn decode_errors_are_counted_per_input() {
+        let mut gate = QualityGate::new(QualityThresholds::default(), 1);
+        let good = gradient(32, 32);
+        // The first input ends after 3 errors; the next decoder counts from zero again.
+        assert_eq!(gate.check(1, &good, 3), Some(QualityIssue::DecodeError));
+        assert_eq!(gate.check(2, &good, 3), None);
+        gate.source_changed();
+        assert_eq!(gate.check(3, &good, 0), None);
+        assert_eq!(gate.check(4, &good, 1), Some(QualityIssue::DecodeError));
+        assert_eq!(gate.check(5, &good, 1), None);
+        assert_eq!(gate.suspect_frames(), 2);
+    }
+}
@@ -0,0 +1,132 @@
+//! Uncompressed COCO run-length encoding of binary masks (`--mask-encoding rle`).
+//!
+//! Pixels are read in column-major order (down each column, then the next column) and `counts`
+//! alternates background and foreground runs, starting with background, so a mask whose first
+//! pixel is set begins with a `0`. This is the `{"size": [h, w], "counts": [...]}` form
+//! `pycocotools.mask.frPyObjects` accepts.
+
+use anyhow::Result;
+use image::{GrayImage, Luma};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Rle {
+    /// `[height, width]`, in COCO's order.
+    pub size: [u32; 2],
+    pub counts: Vec<u32>,
+}
+
+/// Encode `mask`; any non-zero pixel is foreground.
+pub fn encode(mask: &GrayImage) -> Rle {
+    let (width, height) = mask.dimensions();
+    let mut counts = Vec::new();
+    let mut run = 0u32;
+    let mut foreground = false;
+    for x in 0..width {
+        for y in 0..height {
+            let set = mask.get_pixel(x, y)[0] > 0;
+            if set != foreground {
+                counts.push(run);
+                run = 0;
+                foreground = set;
+            }
+            run += 1;
+        }
+    }
+    counts.push(run);
+    Rle {
+        size: [height, width],
+        counts,
+    }
+}
+
+/// Decode back to a 0/255 mask.
+pub fn decode(rle: &Rle) -> Result<GrayImage> {
+    let [height, width] = rle.size;
+    let total: u64 = rle.counts.iter().map(|&c| u64::from(c)).sum();
+    anyhow::ensure!(
+        total == u64::from(width) * u64::from(height),
+        "RLE counts cover {total} pixels, expected {width}x{height}"
+    );
+    let mut mask = GrayImage::new(width, height);
+    let mut i = 0u64;
+    for (run_idx, &run) in rle.counts.iter().enumerate() {
+        if run_idx % 2 == 1 {
+            for p in i..i + u64::from(run) {
+                let (x, y) = (
+                    (p / u64::from(height)) as u32,
+                    (p % u64::from(height)) as u32,
+                );
+                mask.put_pixel(x, y, Luma([255]));
+            }
+        }
+        i += u64::from(run);
+    }
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binarize(mask: &GrayImage) -> GrayImage {
+        GrayImage::from_fn(mask.width(), mask.height(), |x, y| {
+            Luma([if mask.get_pixel(x, y)[0] > 0 { 255 } else { 0 }])
+        })
+    }
+
+    fn round_trip(mask: &GrayImage) -> Rle {
+        let rle = encode(mask);
+        assert_eq!(decode(&rle).unwrap(), binarize(mask));
+        rle
+    }
+
+    #[test]
+    fn counts_are_column_major() {
+        // 3 rows x 2 columns; column 0 is (0, 1, 1), column 1 is (1, 0, 0).
+        let mut mask = GrayImage::new(2, 3);
+        mask.put_pixel(0, 1, Luma([1]));
+        mask.put_pixel(0, 2, Luma([1]));
+        mask.put_pixel(1, 0, Luma([1]));
+        let rle = round_trip(&mask);
+        assert_eq!(rle.size, [3, 2]);
+        assert_eq!(rle.counts, vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn round_trips_synthetic_shapes() {
+        let rect = GrayImage::from_fn(40, 30, |x, y| {
+            Luma([if (5..20).contains(&x) && (10..25).contains(&y) {
+                255
+            } else {
+                0
+            }])
+        });
+        round_trip(&rect);
+        let disc = GrayImage::from_fn(64, 48, |x, y| {
+            let (dx, dy) = (x as i32 - 30, y as i32 - 20);
+            Luma([if dx * dx + dy * dy < 15 * 15 { 128 } else { 0 }])
+        });
+        round_trip(&disc);
+        let full = GrayImage::from_pixel(7, 5, Luma([255]));
+        assert_eq!(round_trip(&full).counts, vec![0, 35]);
+    }
+
+    #[test]
+    fn empty_masks() {
+        let empty = GrayImage::new(16, 9);
+        assert_eq!(round_trip(&empty).counts, vec![144]);
+        let none = GrayImage::new(0, 0);
+        assert_eq!(round_trip(&none).counts, vec![0]);
+    }
+
+    #[test]
+    fn decode_rejects_wrong_totals() {
+        let rle = Rle {
+            size: [2, 2],
+            counts: vec![1, 2],
+        };
+        assert!(decode(&rle).is_err());
+    }
+}
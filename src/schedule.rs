@@ -0,0 +1,123 @@
+//! When to run inference: every N frames (`--infer-every`) or at a wall-clock rate
+//! (`--infer-hz`).
+
+use anyhow::{Result, bail, ensure};
+use std::time::{Duration, Instant};
+
+const DEFAULT_INFER_EVERY: u32 = 3;
+
+#[derive(Clone, Debug)]
+pub enum InferSchedule {
+    /// Every N frames; 0 disables inference.
+    Frames(u32),
+    /// On a fixed grid of `interval` steps on the monotonic clock: the first frame at or past
+    /// each step runs inference, and the next step is one interval after the previous one rather
+    /// than after that frame, so frame timing does not drift the rate. When inference falls more
+    /// than an interval behind, the grid restarts at the current frame: slow inference lowers the
+    /// rate, it never queues a backlog.
+    Interval {
+        interval: Duration,
+        /// The grid step the last inference ran for.
+        last: Option<Instant>,
+    },
+}
+
+impl InferSchedule {
+    /// `--infer-every` (default 3) or `--infer-hz`; giving both is an error.
+    pub fn from_args(infer_every: Option<u32>, infer_hz: Option<f32>) -> Result<Self> {
+        match (infer_every, infer_hz) {
+            (Some(_), Some(_)) => bail!("--infer-every and --infer-hz are mutually exclusive"),
+            (None, Some(hz)) => {
+                ensure!(
+                    hz.is_finite() && hz > 0.0,
+                    "--infer-hz must be positive (got {hz})"
+                );
+                Ok(Self::Interval {
+                    interval: Duration::from_secs_f64(1.0 / f64::from(hz)),
+                    last: None,
+                })
+            }
+            (n, None) => Ok(Self::Frames(n.unwrap_or(DEFAULT_INFER_EVERY))),
+        }
+    }
+
+    /// Whether to run inference on `frame_idx`. For `Interval`, a `true` starts the next
+    /// interval, so only call it once the other reasons to skip the frame are ruled out.
+    pub fn due(&mut self, frame_idx: u64) -> bool {
+        self.due_at(frame_idx, Instant::now())
+    }
+
+    fn due_at(&mut self, frame_idx: u64, now: Instant) -> bool {
+        match self {
+            Self::Frames(n) => *n > 0 && frame_idx.is_multiple_of(u64::from(*n)),
+            Self::Interval { interval, last } => {
+                let step = match *last {
+                    Some(t) if now.duration_since(t) < *interval => return false,
+                    // Within one interval of the next step: stay on the grid.
+                    Some(t) if now.duration_since(t) < 2 * *interval => t + *interval,
+                    _ => now,
+                };
+                *last = Some(step);
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_args() {
+        assert!(matches!(
+            InferSchedule::from_args(None, None).unwrap(),
+            InferSchedule::Frames(3)
+        ));
+        assert!(matches!(
+            InferSchedule::from_args(Some(0), None).unwrap(),
+            InferSchedule::Frames(0)
+        ));
+        assert!(InferSchedule::from_args(Some(2), Some(5.0)).is_err());
+        assert!(InferSchedule::from_args(None, Some(0.0)).is_err());
+        assert!(InferSchedule::from_args(None, Some(f32::NAN)).is_err());
+    }
+
+    #[test]
+    fn frame_schedule() {
+        let mut s = InferSchedule::Frames(3);
+        let due: Vec<u64> = (0..8).filter(|&i| s.due(i)).collect();
+        assert_eq!(due, [0, 3, 6]);
+        let mut off = InferSchedule::Frames(0);
+        assert!((0..8).all(|i| !off.due(i)));
+    }
+
+    #[test]
+    fn interval_schedule_does_not_queue() {
+        let mut s = InferSchedule::from_args(None, Some(10.0)).unwrap();
+        let t0 = Instant::now();
+        let ms = |n| t0 + Duration::from_millis(n);
+        assert!(s.due_at(0, ms(0)));
+        assert!(!s.due_at(1, ms(40)));
+        assert!(s.due_at(2, ms(110)));
+        // Inference took 350 ms: run on the next frame, then wait a full interval again.
+        assert!(s.due_at(3, ms(460)));
+        assert!(!s.due_at(4, ms(510)));
+        assert!(s.due_at(5, ms(560)));
+    }
+
+    #[test]
+    fn interval_schedule_keeps_the_rate_at_any_frame_timing() {
+        // 10 Hz on a 30 fps camera with a few ms of frame jitter: every 3rd or 4th frame, but
+        // 10 inferences per second on average, not one per 4 frames (7.5 Hz).
+        let mut s = InferSchedule::from_args(None, Some(10.0)).unwrap();
+        let t0 = Instant::now();
+        let due = (0..300u64)
+            .filter(|&i| {
+                let jitter = [0, 3, 1, 4][i as usize % 4];
+                s.due_at(i, t0 + Duration::from_micros(i * 33_333 + jitter * 1000))
+            })
+            .count();
+        assert!((99..=101).contains(&due), "{due}");
+    }
+}
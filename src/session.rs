@@ -0,0 +1,362 @@
+//! `--session <path.json>`: keep the interactively tuned state between runs.
+//!
+//! `W` (and the end of the run) writes the prompts, confidence threshold, `M` annotation preset,
+//! legend visibility, exclude zones and (`hikvision-sam3`) the zoomed view to the file; the next
+//! run with the same `--session` starts from it. Flags given on the command line win over the
+//! saved value of the same setting.
+//!
+//! Files carry a `version`. Fields added in later versions default when missing, so older files
+//! keep loading; a file from a newer build is rejected instead of being half-understood.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::common::{AnnotationPreset, AnnotationPresets};
+use crate::preprocess::ViewportState;
+use crate::viz::Legend;
+use crate::zones::{ExcludeZones, Zone};
+
+pub const SESSION_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub version: u32,
+    /// As given to `-p`, `#grp=` tags included.
+    pub prompts: Vec<String>,
+    pub conf: Option<f32>,
+    /// [`AnnotationPreset::name`] of the `M` preset; `None` keeps `--annotation-style`.
+    pub annotation_preset: Option<String>,
+    pub legend: bool,
+    /// `x,y,w,h`, as for `--exclude-zone`.
+    pub zones: Vec<String>,
+    pub view: Option<SessionView>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            version: SESSION_VERSION,
+            prompts: Vec::new(),
+            conf: None,
+            annotation_preset: None,
+            legend: false,
+            zones: Vec::new(),
+            view: None,
+        }
+    }
+}
+
+/// [`ViewportState`] of `hikvision-sam3`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SessionView {
+    pub zoom: f32,
+    pub offset: [f32; 2],
+}
+
+/// The command-line settings a restored session fills in when they were not given.
+pub struct SessionArgs<'a> {
+    pub prompt: &'a mut Vec<String>,
+    pub conf: &'a mut Option<f32>,
+    pub legend: &'a mut bool,
+    /// `--no-legend` was given: the legend starts hidden.
+    pub no_legend: bool,
+    pub exclude_zone: &'a mut Vec<Zone>,
+    /// `--zones-file` was given: zones come from there.
+    pub zones_file: bool,
+    /// `--pipeline` was given: prompts come from there, and the saved ones are kept untouched.
+    pub pipeline: bool,
+}
+
+impl Session {
+    /// Parse a session file's text; `path` is only used in error messages.
+    pub fn from_json(text: &str, path: &Path) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(text)
+            .with_context(|| format!("failed to parse session file {}", path.display()))?;
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .with_context(|| {
+                format!(
+                    "{} is not a session file (no `version` field)",
+                    path.display()
+                )
+            })?;
+        anyhow::ensure!(
+            version >= 1,
+            "{}: invalid session version {version}",
+            path.display()
+        );
+        anyhow::ensure!(
+            version <= u64::from(SESSION_VERSION),
+            "{} is a version {version} session, newer than this build reads (up to {SESSION_VERSION}); \
+             upgrade, or start without --session to overwrite it",
+            path.display()
+        );
+        let mut session: Self = serde_json::from_value(value)
+            .with_context(|| format!("invalid session file {}", path.display()))?;
+        session.version = SESSION_VERSION;
+        Ok(session)
+    }
+
+    /// Snapshot of the running state.
+    pub fn capture(
+        prompts: &[String],
+        conf: f32,
+        presets: &AnnotationPresets,
+        legend: &Legend,
+        zones: &ExcludeZones,
+        view: Option<&ViewportState>,
+    ) -> Self {
+        Self {
+            version: SESSION_VERSION,
+            prompts: prompts.to_vec(),
+            conf: Some(conf),
+            annotation_preset: presets.current().map(|p| p.name().to_string()),
+            legend: legend.is_visible(),
+            zones: zones.zones().iter().map(Zone::to_string).collect(),
+            view: view.map(|v| SessionView {
+                zoom: v.zoom,
+                offset: [v.offset.0, v.offset.1],
+            }),
+        }
+    }
+
+    /// Fill in every setting in `args` that was not given on the command line.
+    pub fn restore_args(&self, args: SessionArgs<'_>) -> Result<()> {
+        if args.prompt.is_empty() && !args.pipeline {
+            args.prompt.clone_from(&self.prompts);
+        }
+        if args.conf.is_none() {
+            *args.conf = self.conf;
+        }
+        anyhow::ensure!(
+            !(*args.legend && args.no_legend),
+            "--legend and --no-legend are mutually exclusive"
+        );
+        if !*args.legend && !args.no_legend {
+            *args.legend = self.legend;
+        }
+        if args.exclude_zone.is_empty() && !args.zones_file {
+            *args.exclude_zone = self
+                .zones
+                .iter()
+                .map(|z| z.parse().map_err(anyhow::Error::msg))
+                .collect::<Result<_>>()
+                .context("invalid zone in session file")?;
+        }
+        Ok(())
+    }
+
+    pub fn preset(&self) -> Option<AnnotationPreset> {
+        let name = self.annotation_preset.as_deref()?;
+        let preset = AnnotationPreset::from_name(name);
+        if preset.is_none() {
+            tracing::warn!("Session: unknown annotation preset `{name}`; ignored");
+        }
+        preset
+    }
+
+    pub fn viewport(&self) -> Option<ViewportState> {
+        self.view.map(|v| {
+            let mut viewport = ViewportState {
+                offset: (v.offset[0], v.offset[1]),
+                zoom: v.zoom.clamp(1.0, ViewportState::MAX_ZOOM),
+            };
+            // Keeps the view inside the frame.
+            viewport.pan(0.0, 0.0);
+            viewport
+        })
+    }
+}
+
+/// The `--session` file: what was restored from it, and where `W` saves.
+pub struct SessionFile {
+    path: Option<PathBuf>,
+    restored: Session,
+}
+
+impl SessionFile {
+    /// Load `path` if given; a file that does not exist yet starts a new session.
+    pub fn open(path: Option<&str>) -> Result<Self> {
+        let Some(path) = path.map(PathBuf::from) else {
+            return Ok(Self {
+                path: None,
+                restored: Session::default(),
+            });
+        };
+        let restored = match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                let session = Session::from_json(&text, &path)?;
+                tracing::info!("Restored session from {}", path.display());
+                session
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                tracing::info!("New session; W saves it to {}", path.display());
+                Session::default()
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read session file {}", path.display()));
+            }
+        };
+        Ok(Self {
+            path: Some(path),
+            restored,
+        })
+    }
+
+    pub fn restored(&self) -> &Session {
+        &self.restored
+    }
+
+    /// Write `session`; without `--session` there is nowhere to write and `W` only warns.
+    pub fn save(&self, session: &Session) -> Result<()> {
+        let Some(path) = &self.path else {
+            tracing::warn!("No --session file given; session not saved");
+            return Ok(());
+        };
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent).with_context(|| {
+                format!("failed to create session directory: {}", parent.display())
+            })?;
+        }
+        // Write next to the file and rename, so an interrupted save keeps the old session.
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, serde_json::to_string_pretty(session)?)
+            .with_context(|| format!("failed to write session file {}", partial.display()))?;
+        std::fs::rename(&partial, path)
+            .with_context(|| format!("failed to write session file {}", path.display()))?;
+        tracing::info!("Saved session to {}", path.display());
+        Ok(())
+    }
+
+    /// Save at the end of the run, only when `--session` was given.
+    pub fn save_on_exit(&self, session: &Session) -> Result<()> {
+        if self.path.is_some() {
+            self.save(session)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn sample() -> Session {
+        Session {
+            version: SESSION_VERSION,
+            prompts: vec!["person#grp=people".into(), "forklift".into()],
+            conf: Some(0.35),
+            annotation_preset: Some(AnnotationPreset::Polygons.name().into()),
+            legend: true,
+            zones: vec!["10,20,100,50".into()],
+            view: Some(SessionView {
+                zoom: 2.0,
+                offset: [0.25, 0.5],
+            }),
+        }
+    }
+
+    #[test]
+    fn round_trips_through_the_file() {
+        let dir = TempDir::new("session");
+        let path = dir.join("session.json");
+        let file = SessionFile {
+            path: Some(path.clone()),
+            restored: Session::default(),
+        };
+        file.save(&sample()).unwrap();
+        let reopened = SessionFile::open(path.to_str()).unwrap();
+        assert_eq!(reopened.restored(), &sample());
+        assert_eq!(
+            reopened.restored().preset(),
+            Some(AnnotationPreset::Polygons)
+        );
+        let view = reopened.restored().viewport().unwrap();
+        assert_eq!((view.zoom, view.offset), (2.0, (0.25, 0.5)));
+    }
+
+    #[test]
+    fn missing_file_starts_a_new_session() {
+        let dir = TempDir::new("session-new");
+        let path = dir.join("session.json");
+        let file = SessionFile::open(path.to_str()).unwrap();
+        assert_eq!(file.restored(), &Session::default());
+    }
+
+    #[test]
+    fn older_files_load_with_defaults() {
+        let path = Path::new("old.json");
+        let session = Session::from_json(r#"{"version": 1, "prompts": ["box"]}"#, path).unwrap();
+        assert_eq!(session.prompts, ["box"]);
+        assert_eq!(session.conf, None);
+        assert!(session.zones.is_empty() && session.view.is_none());
+    }
+
+    #[test]
+    fn rejects_newer_and_unversioned_files() {
+        let path = Path::new("s.json");
+        let err = Session::from_json(r#"{"version": 99}"#, path).unwrap_err();
+        assert!(err.to_string().contains("version 99"), "{err}");
+        let err = Session::from_json(r#"{"prompts": []}"#, path).unwrap_err();
+        assert!(err.to_string().contains("not a session file"), "{err}");
+        assert!(Session::from_json("[1, 2]", path).is_err());
+    }
+
+    #[test]
+    fn command_line_wins_over_the_session() {
+        let session = sample();
+        let (mut prompt, mut conf, mut legend, mut zones) =
+            (vec!["cat".to_string()], None, false, Vec::new());
+        session
+            .restore_args(SessionArgs {
+                prompt: &mut prompt,
+                conf: &mut conf,
+                legend: &mut legend,
+                no_legend: false,
+                exclude_zone: &mut zones,
+                zones_file: false,
+                pipeline: false,
+            })
+            .unwrap();
+        assert_eq!(prompt, ["cat"]);
+        assert_eq!(conf, Some(0.35));
+        assert!(legend);
+        assert_eq!(zones, ["10,20,100,50".parse::<Zone>().unwrap()]);
+
+        let (mut prompt, mut conf, mut zones) = (Vec::new(), Some(0.8), Vec::new());
+        session
+            .restore_args(SessionArgs {
+                prompt: &mut prompt,
+                conf: &mut conf,
+                legend: &mut legend,
+                no_legend: false,
+                exclude_zone: &mut zones,
+                zones_file: true,
+                pipeline: false,
+            })
+            .unwrap();
+        assert_eq!(prompt, session.prompts);
+        assert_eq!(conf, Some(0.8));
+        assert!(zones.is_empty());
+
+        let (mut prompt, mut legend) = (Vec::new(), false);
+        session
+            .restore_args(SessionArgs {
+                prompt: &mut prompt,
+                conf: &mut conf,
+                legend: &mut legend,
+                no_legend: true,
+                exclude_zone: &mut zones,
+                zones_file: true,
+                pipeline: true,
+            })
+            .unwrap();
+        assert!(prompt.is_empty());
+        assert!(!legend);
+    }
+}
@@ -0,0 +1,477 @@
+//! `--shm <name>`: publish every output frame into a POSIX shared-memory ring buffer, so another
+//! process on the same host reads raw frames without sockets or re-encoding.
+//!
+//! `/dev/shm/<name>` holds a 64-byte header followed by [`SLOTS`] frame slots (all integers
+//! little-endian):
+//!
+//! | offset | header field                                                       |
+//! |--------|--------------------------------------------------------------------|
+//! | 0      | magic `SAM3SHM\0`                                                  |
+//! | 8      | layout version, u32 ([`VERSION`])                                  |
+//! | 12     | number of slots, u32                                               |
+//! | 16, 20 | width, height, u32                                                 |
+//! | 24     | pixel format, u32 (1 = RGB24, rows packed)                         |
+//! | 32     | slot size in bytes, u64                                            |
+//! | 40     | sequence number of the newest complete frame, u64 (0 before any)   |
+//!
+//! A slot starts with its frame's sequence number, `frame_idx` and unix-epoch timestamp in ms
+//! (u64 each, then 8 reserved bytes); the pixels follow at offset 32. Frame `seq` (1, 2, ...)
+//! goes to slot `(seq - 1) % slots`. The writer zeroes a slot's sequence number before
+//! overwriting it and sets it once the frame is complete, so a reader copies the slot the header
+//! points at and keeps the copy only if the slot's number is unchanged afterwards
+//! ([`ShmReader::latest`]). The writer never waits for readers; a slow reader skips frames.
+//!
+//! An existing `/dev/shm/<name>` is never taken over silently: it may belong to a sink that is
+//! still running. `--shm-replace` removes it first (e.g. left behind by a crashed run).
+
+use anyhow::Result;
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+
+pub const VERSION: u32 = 1;
+pub const SLOTS: u32 = 4;
+const MAGIC: &[u8; 8] = b"SAM3SHM\0";
+const FORMAT_RGB24: u32 = 1;
+const HEADER_SIZE: usize = 64;
+const SLOT_HEADER_SIZE: usize = 32;
+const LATEST_OFFSET: usize = 40;
+
+/// Bytes per slot for a `width` x `height` frame, kept 64-byte aligned.
+fn slot_size(width: u32, height: u32) -> usize {
+    (SLOT_HEADER_SIZE + width as usize * height as usize * 3).next_multiple_of(64)
+}
+
+/// `/name` for `shm_open`; `name` may be given with or without the leading slash.
+fn shm_path(name: &str) -> Result<String> {
+    let bare = name.strip_prefix('/').unwrap_or(name);
+    anyhow::ensure!(
+        !bare.is_empty() && !bare.contains('/') && !bare.contains('\0'),
+        "invalid --shm name `{name}` (expected a name without slashes, e.g. sam3-frames)"
+    );
+    Ok(format!("/{bare}"))
+}
+
+/// A mapped shared-memory object.
+struct Region {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Region {
+    fn u32_at(&self, offset: usize) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.read(offset, &mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn u64_at(&self, offset: usize) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.read(offset, &mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    /// Copy `out.len()` bytes from `offset`. The memory may be changed by the other process at
+    /// any time, so it is only ever copied through the raw pointer, never borrowed as a slice.
+    fn read(&self, offset: usize, out: &mut [u8]) {
+        assert!(offset + out.len() <= self.len);
+        // SAFETY: in bounds of the mapping; `out` is private memory.
+        unsafe { std::ptr::copy_nonoverlapping(self.ptr.add(offset), out.as_mut_ptr(), out.len()) }
+    }
+
+    /// The u64 at `offset` (8-aligned), shared with the other process.
+    fn atomic(&self, offset: usize) -> &AtomicU64 {
+        assert!(offset.is_multiple_of(8) && offset + 8 <= self.len);
+        // SAFETY: in bounds and aligned (the mapping is page-aligned); other processes only
+        // access these words atomically.
+        unsafe { &*(self.ptr.add(offset) as *const AtomicU64) }
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        assert!(offset + data.len() <= self.len);
+        // SAFETY: in bounds of the writable mapping.
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), self.ptr.add(offset), data.len()) }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        sys::unmap(self.ptr, self.len);
+    }
+}
+
+/// Writer side of the ring buffer. The object is created at the first frame, sized for it, and
+/// removed from `/dev/shm` when the sink is dropped (readers that still have it mapped keep it),
+/// unless another sink has replaced it since.
+pub struct ShmSink {
+    path: String,
+    replace: bool,
+    region: Option<Region>,
+    object: sys::ObjectId,
+    size: (u32, u32),
+    seq: u64,
+}
+
+impl ShmSink {
+    /// `replace` (`--shm-replace`) removes an existing object of that name instead of failing.
+    pub fn new(name: &str, replace: bool) -> Result<Self> {
+        let path = shm_path(name)?;
+        tracing::info!("Publishing frames to shared memory /dev/shm{path}");
+        Ok(Self {
+            path,
+            replace,
+            region: None,
+            object: Default::default(),
+            size: (0, 0),
+            seq: 0,
+        })
+    }
+
+    fn create(&mut self, width: u32, height: u32) -> Result<()> {
+        let slot = slot_size(width, height);
+        let len = HEADER_SIZE + slot * SLOTS as usize;
+        let (mut region, object) = sys::create(&self.path, len, self.replace)?;
+        self.object = object;
+        let mut header = [0u8; LATEST_OFFSET];
+        header[..8].copy_from_slice(MAGIC);
+        for (offset, value) in [
+            (8, VERSION),
+            (12, SLOTS),
+            (16, width),
+            (20, height),
+            (24, FORMAT_RGB24),
+        ] {
+            header[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        header[32..40].copy_from_slice(&(slot as u64).to_le_bytes());
+        region.write(0, &header);
+        region.atomic(LATEST_OFFSET).store(0, Ordering::Release);
+        self.size = (width, height);
+        self.region = Some(region);
+        Ok(())
+    }
+
+    /// Publish `img` as the newest frame; `ts_ms` is unix-epoch milliseconds.
+    pub fn write_frame(&mut self, img: &usls::Image, frame_idx: u64, ts_ms: u64) -> Result<()> {
+        let (width, height) = (img.width(), img.height());
+        if self.region.is_none() {
+            self.create(width, height)?;
+        }
+        anyhow::ensure!(
+            self.size == (width, height),
+            "--shm: frame size changed from {}x{} to {width}x{height}",
+            self.size.0,
+            self.size.1
+        );
+        let region = self.region.as_mut().expect("created above");
+        self.seq += 1;
+        let seq = self.seq;
+        let slot = HEADER_SIZE + ((seq - 1) % u64::from(SLOTS)) as usize * slot_size(width, height);
+        region.atomic(slot).store(0, Ordering::Relaxed);
+        fence(Ordering::Release);
+        let mut meta = [0u8; 16];
+        meta[..8].copy_from_slice(&frame_idx.to_le_bytes());
+        meta[8..].copy_from_slice(&ts_ms.to_le_bytes());
+        region.write(slot + 8, &meta);
+        region.write(slot + SLOT_HEADER_SIZE, img.as_raw());
+        region.atomic(slot).store(seq, Ordering::Release);
+        region.atomic(LATEST_OFFSET).store(seq, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Drop for ShmSink {
+    fn drop(&mut self) {
+        if self.region.is_some() {
+            sys::unlink(&self.path, self.object);
+        }
+    }
+}
+
+/// A frame copied out of the ring buffer.
+pub struct ShmFrame {
+    pub seq: u64,
+    pub frame_idx: u64,
+    pub timestamp_ms: u64,
+    pub image: image::RgbImage,
+}
+
+/// Reader side, for consumers written in Rust; others follow the layout in the module docs.
+pub struct ShmReader {
+    region: Region,
+    width: u32,
+    height: u32,
+    slots: u32,
+    slot_size: usize,
+}
+
+impl ShmReader {
+    pub fn open(name: &str) -> Result<Self> {
+        let path = shm_path(name)?;
+        let region = sys::open(&path)?;
+        let mut magic = [0u8; 8];
+        if region.len >= HEADER_SIZE {
+            region.read(0, &mut magic);
+        }
+        anyhow::ensure!(&magic == MAGIC, "/dev/shm{path} is not a frame ring buffer");
+        let version = region.u32_at(8);
+        anyhow::ensure!(
+            version == VERSION,
+            "/dev/shm{path}: layout version {version}, expected {VERSION}"
+        );
+        let format = region.u32_at(24);
+        anyhow::ensure!(
+            format == FORMAT_RGB24,
+            "/dev/shm{path}: unknown pixel format {format}"
+        );
+        let (slots, width, height) = (region.u32_at(12), region.u32_at(16), region.u32_at(20));
+        let slot_size = region.u64_at(32) as usize;
+        anyhow::ensure!(
+            slots > 0
+                && slot_size >= SLOT_HEADER_SIZE + width as usize * height as usize * 3
+                && region.len >= HEADER_SIZE + slot_size * slots as usize,
+            "/dev/shm{path}: inconsistent header"
+        );
+        Ok(Self {
+            region,
+            width,
+            height,
+            slots,
+            slot_size,
+        })
+    }
+
+    /// Sequence number of the newest complete frame; 0 before the first.
+    pub fn latest_seq(&self) -> u64 {
+        self.region.atomic(LATEST_OFFSET).load(Ordering::Acquire)
+    }
+
+    /// A copy of the newest frame; `None` before the first frame, or when the writer overwrote
+    /// the slot while it was being copied (try again).
+    pub fn latest(&self) -> Option<ShmFrame> {
+        let seq = self.latest_seq();
+        if seq == 0 {
+            return None;
+        }
+        let slot = HEADER_SIZE + ((seq - 1) % u64::from(self.slots)) as usize * self.slot_size;
+        if self.region.atomic(slot).load(Ordering::Acquire) != seq {
+            return None;
+        }
+        let mut data = vec![0u8; self.width as usize * self.height as usize * 3];
+        self.region.read(slot + SLOT_HEADER_SIZE, &mut data);
+        let (frame_idx, timestamp_ms) =
+            (self.region.u64_at(slot + 8), self.region.u64_at(slot + 16));
+        fence(Ordering::Acquire);
+        // Only a copy the writer did not touch meanwhile is a frame.
+        if self.region.atomic(slot).load(Ordering::Relaxed) != seq {
+            return None;
+        }
+        let image = image::RgbImage::from_raw(self.width, self.height, data)?;
+        Some(ShmFrame {
+            seq,
+            frame_idx,
+            timestamp_ms,
+            image,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::Region;
+    use anyhow::{Context, Result};
+    use std::ffi::CString;
+    use std::os::unix::fs::MetadataExt;
+
+    /// Device and inode of a shared memory object, to recognize it under its name later.
+    pub(super) type ObjectId = (u64, u64);
+
+    fn c_path(path: &str) -> Result<CString> {
+        CString::new(path).context("shared memory name contains a NUL byte")
+    }
+
+    fn map(path: &str, fd: libc::c_int, len: usize, prot: libc::c_int) -> Result<Region> {
+        // SAFETY: `fd` is an open shared memory object of at least `len` bytes.
+        let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, prot, libc::MAP_SHARED, fd, 0) };
+        let mapped = std::io::Error::last_os_error();
+        // SAFETY: the mapping (if any) stays valid after the descriptor is closed.
+        unsafe { libc::close(fd) };
+        if ptr == libc::MAP_FAILED {
+            return Err(mapped).with_context(|| format!("failed to map /dev/shm{path}"));
+        }
+        Ok(Region {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    /// Create `path` with `len` zeroed bytes. An existing object is an error unless `replace`,
+    /// which unlinks it first (its readers keep their mapping).
+    pub(super) fn create(path: &str, len: usize, replace: bool) -> Result<(Region, ObjectId)> {
+        let c = c_path(path)?;
+        if replace {
+            // SAFETY: `c` is a valid C string.
+            unsafe { libc::shm_unlink(c.as_ptr()) };
+        }
+        // SAFETY: `c` is a valid C string.
+        let fd = unsafe {
+            libc::shm_open(
+                c.as_ptr(),
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+                0o644,
+            )
+        };
+        if fd < 0 {
+            let e = std::io::Error::last_os_error();
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                anyhow::bail!(
+                    "/dev/shm{path} already exists; another run may still be publishing there (pass --shm-replace to remove it)"
+                );
+            }
+            return Err(e).with_context(|| format!("failed to create /dev/shm{path}"));
+        }
+        // SAFETY: `fd` was just opened for writing.
+        if unsafe { libc::ftruncate(fd, len as libc::off_t) } != 0 {
+            let e = std::io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(e).with_context(|| format!("failed to size /dev/shm{path}"));
+        }
+        // SAFETY: `stat` is plain data filled in by `fstat`.
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        // SAFETY: `fd` is open.
+        let object = if unsafe { libc::fstat(fd, &mut stat) } == 0 {
+            (stat.st_dev as u64, stat.st_ino as u64)
+        } else {
+            ObjectId::default()
+        };
+        Ok((
+            map(path, fd, len, libc::PROT_READ | libc::PROT_WRITE)?,
+            object,
+        ))
+    }
+
+    /// Map an existing `path` read-only.
+    pub(super) fn open(path: &str) -> Result<Region> {
+        let c = c_path(path)?;
+        // SAFETY: `c` is a valid C string.
+        let fd = unsafe { libc::shm_open(c.as_ptr(), libc::O_RDONLY, 0) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("failed to open /dev/shm{path}"));
+        }
+        // SAFETY: `stat` is plain data filled in by `fstat`.
+        let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+            let e = std::io::Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(e).with_context(|| format!("failed to stat /dev/shm{path}"));
+        }
+        map(path, fd, stat.st_size as usize, libc::PROT_READ)
+    }
+
+    pub(super) fn unmap(ptr: *mut u8, len: usize) {
+        // SAFETY: `ptr`/`len` came from a successful `mmap`.
+        unsafe { libc::munmap(ptr.cast(), len) };
+    }
+
+    /// Remove `path` if it still names `object`.
+    pub(super) fn unlink(path: &str, object: ObjectId) {
+        let current = std::fs::metadata(format!("/dev/shm{path}")).map(|m| (m.dev(), m.ino()));
+        if current.is_ok_and(|id| id != object) {
+            tracing::debug!("/dev/shm{path} was replaced by another sink; leaving it");
+            return;
+        }
+        if let Ok(c) = c_path(path) {
+            // SAFETY: `c` is a valid C string.
+            unsafe { libc::shm_unlink(c.as_ptr()) };
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use super::Region;
+    use anyhow::Result;
+
+    pub(super) type ObjectId = ();
+
+    pub(super) fn create(_path: &str, _len: usize, _replace: bool) -> Result<(Region, ObjectId)> {
+        anyhow::bail!("--shm is only supported on Linux")
+    }
+
+    pub(super) fn open(_path: &str) -> Result<Region> {
+        anyhow::bail!("--shm is only supported on Linux")
+    }
+
+    pub(super) fn unmap(_ptr: *mut u8, _len: usize) {}
+
+    pub(super) fn unlink(_path: &str, _object: ObjectId) {}
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::test_util::unique_name;
+
+    fn frame(value: u8) -> usls::Image {
+        usls::Image::from(image::RgbImage::from_fn(6, 4, |x, y| {
+            image::Rgb([value, x as u8, y as u8])
+        }))
+    }
+
+    #[test]
+    fn readers_see_the_newest_frame() {
+        let name = unique_name("shm-test");
+        let mut sink = ShmSink::new(&name, false).unwrap();
+        sink.write_frame(&frame(1), 10, 1000).unwrap();
+        let reader = ShmReader::open(&name).unwrap();
+        assert_eq!((reader.width, reader.height, reader.slots), (6, 4, SLOTS));
+        for (i, value) in (2..=6u8).enumerate() {
+            sink.write_frame(&frame(value), 11 + i as u64, 2000)
+                .unwrap();
+        }
+        let latest = reader.latest().unwrap();
+        assert_eq!(
+            (latest.seq, latest.frame_idx, latest.timestamp_ms),
+            (6, 15, 2000)
+        );
+        assert_eq!(latest.image.as_raw(), frame(6).as_raw());
+        assert!(
+            sink.write_frame(&usls::Image::from(image::RgbImage::new(2, 2)), 16, 0)
+                .is_err()
+        );
+        drop(sink);
+        // Unlinked, but still mapped by the reader.
+        assert!(ShmReader::open(&name).is_err());
+        assert_eq!(reader.latest_seq(), 6);
+    }
+
+    #[test]
+    fn a_live_object_is_only_taken_over_on_request() {
+        let name = unique_name("shm-live");
+        let mut first = ShmSink::new(&name, false).unwrap();
+        first.write_frame(&frame(1), 1, 0).unwrap();
+        let mut second = ShmSink::new(&name, false).unwrap();
+        let err = second.write_frame(&frame(2), 1, 0).unwrap_err();
+        assert!(err.to_string().contains("--shm-replace"), "{err}");
+        // The first sink's readers are unaffected.
+        assert_eq!(ShmReader::open(&name).unwrap().latest().unwrap().seq, 1);
+        let mut replacing = ShmSink::new(&name, true).unwrap();
+        replacing.write_frame(&frame(3), 7, 0).unwrap();
+        assert_eq!(
+            ShmReader::open(&name).unwrap().latest().unwrap().frame_idx,
+            7
+        );
+        // The replaced sink leaves the new object in place.
+        drop(first);
+        assert!(ShmReader::open(&name).is_ok());
+        drop(replacing);
+        assert!(ShmReader::open(&name).is_err());
+    }
+
+    #[test]
+    fn validates_names() {
+        assert_eq!(shm_path("frames").unwrap(), "/frames");
+        assert_eq!(shm_path("/frames").unwrap(), "/frames");
+        assert!(shm_path("a/b").is_err());
+        assert!(shm_path("/").is_err());
+    }
+}
@@ -0,0 +1,42 @@
+//! SIGINT/SIGTERM handling shared by the binaries.
+//!
+//! The first signal only sets a flag; the capture loop sees it, leaves like ESC/Q would and lets
+//! the video encoder, JSON writers and clips flush. A second signal exits immediately, for when
+//! that flush hangs.
+
+use anyhow::{Context, Result};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone)]
+pub struct StopSignal(Arc<AtomicBool>);
+
+impl StopSignal {
+    pub fn install() -> Result<Self> {
+        let flag = Arc::new(AtomicBool::new(false));
+        for signal in [SIGINT, SIGTERM] {
+            // Registered first so it checks the flag before this signal sets it.
+            signal_hook::flag::register_conditional_shutdown(signal, 128 + signal, flag.clone())
+                .with_context(|| format!("failed to install handler for signal {signal}"))?;
+            signal_hook::flag::register(signal, flag.clone())
+                .with_context(|| format!("failed to install handler for signal {signal}"))?;
+        }
+        Ok(Self(flag))
+    }
+
+    /// Whether a stop was requested; logs the first time it returns `true`.
+    pub fn requested(&self) -> bool {
+        static LOGGED: AtomicBool = AtomicBool::new(false);
+        let stop = self.0.load(Ordering::Relaxed);
+        if stop && !LOGGED.swap(true, Ordering::Relaxed) {
+            tracing::info!("Stop signal received, finishing outputs (signal again to force exit)");
+        }
+        stop
+    }
+
+    /// The underlying flag, for components with their own stop flag.
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.0.clone()
+    }
+}
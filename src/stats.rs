@@ -0,0 +1,271 @@
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+use crate::postprocess::ExclusiveWin;
+use crate::viz::prompt_color;
+
+const BAR_MAX: usize = 20;
+
+/// Truecolor SGR parameters for a label of `class_id`: the color the annotators draw it in.
+fn label_color(class_id: Option<usize>) -> String {
+    let [r, g, b] = prompt_color(class_id);
+    format!("38;2;{r};{g};{b}")
+}
+
+/// Whole-run counters for the `--summary-json` metrics block.
+#[derive(Default)]
+pub struct RunTotals {
+    frames: u64,
+    inferences: u64,
+    reused: u64,
+    suppressed: u64,
+    exclusive_losers: u64,
+    deduplicated: u64,
+    detections: BTreeMap<String, u64>,
+    elapsed_s: f64,
+}
+
+impl RunTotals {
+    pub fn to_json(&self) -> serde_json::Value {
+        let fps = self.frames as f64 / self.elapsed_s.max(1e-6);
+        let inferences = self.inferences.max(1) as f64;
+        let per_inference: BTreeMap<&String, f64> = self
+            .detections
+            .iter()
+            .map(|(label, count)| (label, *count as f64 / inferences))
+            .collect();
+        serde_json::json!({
+            "frames": self.frames,
+            "inferences": self.inferences,
+            "reused": self.reused,
+            "suppressed_by_zones": self.suppressed,
+            "suppressed_by_exclusive_groups": self.exclusive_losers,
+            "suppressed_by_mask_dedup": self.deduplicated,
+            "elapsed_s": self.elapsed_s,
+            "fps": fps,
+            "inference_hz": self.inferences as f64 / self.elapsed_s.max(1e-6),
+            "detections": self.detections,
+            "detections_per_inference": per_inference,
+        })
+    }
+}
+
+/// Periodic detection-count summary (`--stats-interval`).
+pub struct DetectionStats {
+    interval: Option<Duration>,
+    color: bool,
+    started: Instant,
+    last_report: Instant,
+    frames: u64,
+    inferences: u64,
+    reused: u64,
+    suppressed: u64,
+    exclusive_losers: u64,
+    deduplicated: u64,
+    /// label -> (class id, detections since last report)
+    counts: BTreeMap<String, (Option<usize>, u64)>,
+    totals: RunTotals,
+}
+
+impl DetectionStats {
+    pub fn new(interval_secs: Option<f32>, no_color: bool) -> Self {
+        let interval = interval_secs
+            .filter(|s| s.is_finite() && *s > 0.0)
+            .map(Duration::from_secs_f32);
+        Self {
+            interval,
+            color: !no_color && std::io::stderr().is_terminal(),
+            started: Instant::now(),
+            last_report: Instant::now(),
+            frames: 0,
+            inferences: 0,
+            reused: 0,
+            suppressed: 0,
+            exclusive_losers: 0,
+            deduplicated: 0,
+            counts: BTreeMap::new(),
+            totals: RunTotals::default(),
+        }
+    }
+
+    pub fn record_frame(&mut self) {
+        self.frames += 1;
+        self.totals.frames += 1;
+    }
+
+    /// Count an inference skipped by `--dedup-frames` (its result is still `record`ed).
+    pub fn record_reuse(&mut self) {
+        self.reused += 1;
+        self.totals.reused += 1;
+    }
+
+    /// Count detections dropped by `--exclude-zone`.
+    pub fn record_suppressed(&mut self, n: usize) {
+        self.suppressed += n as u64;
+        self.totals.suppressed += n as u64;
+    }
+
+    /// Count detections dropped by `--deduplicate-masks-iou`.
+    pub fn record_deduplicated(&mut self, n: usize) {
+        self.deduplicated += n as u64;
+        self.totals.deduplicated += n as u64;
+    }
+
+    /// Count detections dropped by `--exclusive-group`.
+    pub fn record_exclusive(&mut self, wins: &[ExclusiveWin]) {
+        let n: u64 = wins.iter().map(|w| w.losers.len() as u64).sum();
+        self.exclusive_losers += n;
+        self.totals.exclusive_losers += n;
+    }
+
+    pub fn record(&mut self, y: &usls::Y) {
+        self.inferences += 1;
+        self.totals.inferences += 1;
+        for hbb in y.hbbs() {
+            let label = match (hbb.name(), hbb.id()) {
+                (Some(name), _) => name.to_string(),
+                (None, Some(id)) => format!("class {id}"),
+                (None, None) => "object".to_string(),
+            };
+            *self.totals.detections.entry(label.clone()).or_default() += 1;
+            if self.interval.is_none() {
+                continue;
+            }
+            self.counts.entry(label).or_insert((hbb.id(), 0)).1 += 1;
+        }
+    }
+
+    /// Counters since startup; `elapsed_s` is taken now.
+    pub fn totals(&mut self) -> &RunTotals {
+        self.totals.elapsed_s = self.started.elapsed().as_secs_f64();
+        &self.totals
+    }
+
+    pub fn maybe_report(&mut self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_report);
+        if elapsed < interval {
+            return;
+        }
+
+        let fps = self.frames as f64 / elapsed.as_secs_f64().max(1e-6);
+        let infer_hz = self.inferences as f64 / elapsed.as_secs_f64().max(1e-6);
+        let inferences = self.inferences.max(1) as f64;
+        let mut parts = Vec::with_capacity(self.counts.len());
+        for (label, (class_id, count)) in &self.counts {
+            let avg = *count as f64 / inferences;
+            if self.color {
+                let color = label_color(*class_id);
+                let bar = "\u{2588}".repeat((avg.round() as usize).clamp(1, BAR_MAX));
+                parts.push(format!(
+                    "\x1b[1;{color}m{label}\x1b[0m \x1b[{color}m{bar}\x1b[0m {avg:.1}"
+                ));
+            } else {
+                parts.push(format!("{label}={avg:.1}"));
+            }
+        }
+        let reused = if self.reused > 0 {
+            format!(" ({} reused)", self.reused)
+        } else {
+            String::new()
+        };
+        let mut detections = if parts.is_empty() {
+            "no detections".to_string()
+        } else {
+            parts.join("  ")
+        };
+        if self.suppressed > 0 {
+            detections.push_str(&format!(
+                " | {} detections suppressed by zones",
+                self.suppressed
+            ));
+        }
+        if self.deduplicated > 0 {
+            detections.push_str(&format!(" | {} duplicates removed", self.deduplicated));
+        }
+        if self.exclusive_losers > 0 {
+            detections.push_str(&format!(
+                " | {} lost to exclusive groups",
+                self.exclusive_losers
+            ));
+        }
+
+        if self.color {
+            // Clear any in-place progress line before printing.
+            eprintln!(
+                "\r\x1b[2K\x1b[2m[{:>7.1}s]\x1b[0m {fps:5.1} fps, {} inferences ({infer_hz:.1}/s){reused} | {detections}",
+                now.duration_since(self.started).as_secs_f64(),
+                self.inferences
+            );
+        } else {
+            tracing::info!(
+                "Stats: {:.1} fps, {} inferences ({:.1}/s){}, avg per inference: {}",
+                fps,
+                self.inferences,
+                infer_hz,
+                reused,
+                detections
+            );
+        }
+
+        self.last_report = now;
+        self.frames = 0;
+        self.inferences = 0;
+        self.reused = 0;
+        self.suppressed = 0;
+        self.exclusive_losers = 0;
+        self.deduplicated = 0;
+        for (_, count) in self.counts.values_mut() {
+            *count = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::viz::PROMPT_PALETTE;
+
+    #[test]
+    fn label_colors_match_the_annotator() {
+        let [r, g, b] = PROMPT_PALETTE[2];
+        assert_eq!(label_color(Some(2)), format!("38;2;{r};{g};{b}"));
+        let n = PROMPT_PALETTE.len();
+        assert_eq!(label_color(Some(n + 1)), label_color(Some(1)));
+        // Detections without a prompt index are drawn in the first prompt's color.
+        assert_eq!(label_color(None), label_color(Some(0)));
+    }
+
+    #[test]
+    fn counts_keep_the_prompt_index() {
+        let mut stats = DetectionStats::new(Some(60.0), true);
+        let y = usls::Y::default().with_hbbs(&[
+            usls::Hbb::from_xyxy(0.0, 0.0, 1.0, 1.0)
+                .with_name("sock")
+                .with_id(3),
+            usls::Hbb::from_xyxy(0.0, 0.0, 1.0, 1.0)
+                .with_name("shoe")
+                .with_id(1),
+            usls::Hbb::from_xyxy(0.0, 0.0, 1.0, 1.0)
+                .with_name("sock")
+                .with_id(3),
+        ]);
+        stats.record(&y);
+        assert_eq!(stats.counts["sock"], (Some(3), 2));
+        assert_eq!(stats.counts["shoe"], (Some(1), 1));
+        assert_eq!(stats.totals().detections["sock"], 2);
+    }
+
+    #[test]
+    fn removed_duplicates_reach_the_summary() {
+        let mut stats = DetectionStats::new(None, true);
+        stats.record_deduplicated(2);
+        stats.record_deduplicated(0);
+        stats.record_deduplicated(1);
+        assert_eq!(stats.totals().to_json()["suppressed_by_mask_dedup"], 3);
+    }
+}
@@ -0,0 +1,192 @@
+//! `--save-svg`: vector overlays for documentation and paper figures.
+//!
+//! Every inferred frame becomes `frame_NNNNNN.jpg` (the unannotated frame) plus
+//! `frame_NNNNNN.svg`, which references the JPEG as its background and draws boxes, mask
+//! polygons and labels as vector shapes, so text stays crisp at any zoom. Colors follow the
+//! legend's per-prompt palette.
+
+use anyhow::{Context, Result};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use usls::models::Sam3Prompt;
+
+use crate::viz::prompt_color;
+
+/// Fill opacity of mask polygons.
+const MASK_OPACITY: f32 = 0.35;
+
+/// One detection as drawn in the SVG.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SvgShape {
+    pub label: Option<String>,
+    /// Prompt index; picks the palette color.
+    pub class_id: Option<usize>,
+    pub confidence: Option<f32>,
+    /// `[x0, y0, x1, y1]` in frame pixels.
+    pub bbox: Option<[f32; 4]>,
+    pub polygon: Vec<[f32; 2]>,
+}
+
+/// Boxes and mask polygons of `y`; `prompts` resolves labels without a class id to a color.
+pub fn shapes_from_y(y: &usls::Y, prompts: &[Sam3Prompt]) -> Vec<SvgShape> {
+    let class_of = |id: Option<usize>, name: Option<&str>| {
+        id.or_else(|| name.and_then(|n| prompts.iter().position(|p| p.text == n)))
+    };
+    let boxes = y.hbbs().iter().map(|hbb| {
+        let (x0, y0, x1, y1) = hbb.xyxy();
+        SvgShape {
+            label: hbb.name().map(str::to_string),
+            class_id: class_of(hbb.id(), hbb.name()),
+            confidence: hbb.confidence(),
+            bbox: Some([x0, y0, x1, y1]),
+            polygon: Vec::new(),
+        }
+    });
+    let masks = y.masks().iter().filter_map(|m| {
+        Some(SvgShape {
+            label: m.name().map(str::to_string),
+            class_id: class_of(m.id(), m.name()),
+            confidence: None,
+            bbox: None,
+            polygon: m.polygon()?.points(),
+        })
+    });
+    let polygons = y.polygons().iter().map(|p| SvgShape {
+        label: p.name().map(str::to_string),
+        class_id: class_of(p.id(), p.name()),
+        confidence: p.confidence(),
+        bbox: None,
+        polygon: p.points(),
+    });
+    // Fills first so outlines and labels are drawn on top.
+    masks.chain(polygons).chain(boxes).collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// SVG document for a `width` x `height` frame with `background` (a path relative to the SVG)
+/// underneath.
+pub fn render_svg(width: u32, height: u32, background: &str, shapes: &[SvgShape]) -> String {
+    let font = (height as f32 / 40.0).max(10.0);
+    let stroke = (height as f32 / 400.0).max(1.5);
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\">\n  <image href=\"{}\" x=\"0\" y=\"0\" \
+         width=\"{width}\" height=\"{height}\"/>\n",
+        escape(background)
+    );
+    for (i, shape) in shapes.iter().enumerate() {
+        let [r, g, b] = prompt_color(shape.class_id);
+        let color = format!("rgb({r},{g},{b})");
+        let class = shape.label.as_deref().map(escape).unwrap_or_default();
+        let _ = writeln!(out, "  <g id=\"det-{i}\" class=\"{class}\">");
+        if !shape.polygon.is_empty() {
+            let points: Vec<String> = shape
+                .polygon
+                .iter()
+                .map(|[x, y]| format!("{x:.1},{y:.1}"))
+                .collect();
+            let _ = writeln!(
+                out,
+                "    <polygon points=\"{}\" fill=\"{color}\" fill-opacity=\"{MASK_OPACITY}\" \
+                 stroke=\"{color}\" stroke-width=\"{stroke:.1}\"/>",
+                points.join(" ")
+            );
+        }
+        if let Some([x0, y0, x1, y1]) = shape.bbox {
+            let _ = writeln!(
+                out,
+                "    <rect x=\"{x0:.1}\" y=\"{y0:.1}\" width=\"{:.1}\" height=\"{:.1}\" \
+                 fill=\"none\" stroke=\"{color}\" stroke-width=\"{stroke:.1}\"/>",
+                x1 - x0,
+                y1 - y0
+            );
+            let text = match (&shape.label, shape.confidence) {
+                (Some(label), Some(conf)) => format!("{label} {conf:.2}"),
+                (Some(label), None) => label.clone(),
+                (None, Some(conf)) => format!("{conf:.2}"),
+                (None, None) => String::new(),
+            };
+            if !text.is_empty() {
+                // Inside the box when there is no room above it.
+                let ty = if y0 >= font * 1.3 {
+                    y0 - font * 0.3
+                } else {
+                    y0 + font
+                };
+                let _ = writeln!(
+                    out,
+                    "    <text x=\"{x0:.1}\" y=\"{ty:.1}\" font-family=\"sans-serif\" \
+                     font-size=\"{font:.1}\" fill=\"white\" stroke=\"{color}\" \
+                     stroke-width=\"{:.1}\" paint-order=\"stroke\">{}</text>",
+                    font / 5.0,
+                    escape(&text)
+                );
+            }
+        }
+        out.push_str("  </g>\n");
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Writes the per-frame background JPEG and SVG into `--save-svg <dir>`.
+pub struct SvgWriter {
+    dir: PathBuf,
+}
+
+impl SvgWriter {
+    pub fn new(dir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create SVG directory: {}", dir.display()))?;
+        tracing::info!("Writing SVG overlays to: {}", dir.display());
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    pub fn write(&self, frame_idx: u64, img: &usls::Image, shapes: &[SvgShape]) -> Result<()> {
+        let background = format!("frame_{frame_idx:06}.jpg");
+        img.save(self.dir.join(&background))?;
+        let path = self.dir.join(format!("frame_{frame_idx:06}.svg"));
+        std::fs::write(
+            &path,
+            render_svg(img.width(), img.height(), &background, shapes),
+        )
+        .with_context(|| format!("failed to write SVG: {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_instances_with_prompt_colors() {
+        let shapes = vec![
+            SvgShape {
+                class_id: Some(1),
+                polygon: vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]],
+                ..Default::default()
+            },
+            SvgShape {
+                label: Some("<card>".to_string()),
+                class_id: Some(1),
+                confidence: Some(0.875),
+                bbox: Some([5.0, 100.0, 45.0, 180.0]),
+                ..Default::default()
+            },
+        ];
+        let svg = render_svg(640, 480, "frame_000001.jpg", &shapes);
+        assert!(svg.contains("<image href=\"frame_000001.jpg\""));
+        assert!(svg.contains("points=\"0.0,0.0 10.0,0.0 10.0,10.0\" fill=\"rgb(255,127,14)\""));
+        assert!(svg.contains("<rect x=\"5.0\" y=\"100.0\" width=\"40.0\" height=\"80.0\""));
+        assert!(svg.contains(">&lt;card&gt; 0.88</text>"));
+        assert_eq!(svg.matches("<g id=").count(), 2);
+    }
+}
@@ -0,0 +1,336 @@
+//! `--tile-size`/`--tile-overlap` (`video-sam3`): tiled inference for frames too large to run in
+//! one pass (e.g. a 50 MP microscopy image, given as a one-frame input).
+//!
+//! [`tile_image`] cuts the image into `tile` x `tile` crops that overlap by `overlap` pixels, the
+//! last row/column flush with the image edge. Polygons found in a tile are moved back to image
+//! coordinates with [`untile_polygons`]; an object cut by a tile border then shows up in two tiles,
+//! and [`deduplicate_polygons_iou`] keeps the more confident one.
+
+use anyhow::Result;
+use usls::models::{SAM3, Sam3Prompt};
+
+use crate::geometry::{self, Rect};
+use crate::viz::to_rgb;
+
+/// IoU above which two same-label polygons are treated as one object.
+pub const DEFAULT_DEDUP_IOU: f32 = 0.5;
+/// `--tile-overlap` default, pixels.
+pub const DEFAULT_TILE_OVERLAP: u32 = 64;
+
+/// One crop of the full image and where its top-left corner sits.
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub image: usls::Image,
+}
+
+/// Tile start offsets along an axis of `len` pixels.
+fn tile_origins(len: u32, tile: u32, overlap: u32) -> Vec<u32> {
+    if len <= tile {
+        return vec![0];
+    }
+    let stride = tile - overlap;
+    let mut origins: Vec<u32> = (0..len - tile).step_by(stride as usize).collect();
+    origins.push(len - tile);
+    origins
+}
+
+/// Split `img` into overlapping `tile` x `tile` crops (smaller when the image is).
+pub fn tile_image(img: &usls::Image, tile: u32, overlap: u32) -> Result<Vec<Tile>> {
+    if tile == 0 || overlap >= tile {
+        anyhow::bail!("--tile-overlap ({overlap}) must be smaller than --tile-size ({tile})");
+    }
+    let frame = to_rgb(img)?;
+    let (w, h) = frame.dimensions();
+    let mut tiles = Vec::new();
+    for y in tile_origins(h, tile, overlap) {
+        for x in tile_origins(w, tile, overlap) {
+            let crop = image::imageops::crop_imm(&frame, x, y, tile.min(w), tile.min(h)).to_image();
+            tiles.push(Tile {
+                x,
+                y,
+                image: usls::Image::from(crop),
+            });
+        }
+    }
+    Ok(tiles)
+}
+
+/// `polygons` of the tile at `(x, y)` in full-image coordinates, keeping label, id and confidence.
+pub fn untile_polygons(polygons: &[usls::Polygon], x: u32, y: u32) -> Vec<usls::Polygon> {
+    let (dx, dy) = (x as f32, y as f32);
+    polygons
+        .iter()
+        .map(|p| {
+            let points: Vec<[f32; 2]> = p
+                .points()
+                .iter()
+                .map(|[px, py]| [px + dx, py + dy])
+                .collect();
+            let mut moved = usls::Polygon::from_xys(&points);
+            if let Some(name) = p.name() {
+                moved = moved.with_name(name);
+            }
+            if let Some(id) = p.id() {
+                moved = moved.with_id(id);
+            }
+            if let Some(conf) = p.confidence() {
+                moved = moved.with_confidence(conf);
+            }
+            moved
+        })
+        .collect()
+}
+
+/// Shoelace area of a closed polygon.
+fn area(points: &[[f32; 2]]) -> f32 {
+    let n = points.len();
+    (0..n)
+        .map(|i| {
+            let ([x0, y0], [x1, y1]) = (points[i], points[(i + 1) % n]);
+            x0 * y1 - x1 * y0
+        })
+        .sum::<f32>()
+        .abs()
+        / 2.0
+}
+
+/// Even-odd point-in-polygon test.
+fn contains(points: &[[f32; 2]], x: f32, y: f32) -> bool {
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for (i, &[xi, yi]) in points.iter().enumerate() {
+        let [xj, yj] = points[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn bounds(points: &[[f32; 2]]) -> Rect {
+    points.iter().fold(
+        (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+        |(x0, y0, x1, y1), [x, y]| (x0.min(*x), y0.min(*y), x1.max(*x), y1.max(*y)),
+    )
+}
+
+/// IoU of two polygons; the intersection is counted on a 1-pixel grid over their common bounds.
+fn polygon_iou(a: &[[f32; 2]], b: &[[f32; 2]]) -> f32 {
+    if a.len() < 3 || b.len() < 3 {
+        return 0.0;
+    }
+    let (ax0, ay0, ax1, ay1) = bounds(a);
+    let (bx0, by0, bx1, by1) = bounds(b);
+    let (x0, y0) = (ax0.max(bx0).floor() as i64, ay0.max(by0).floor() as i64);
+    let (x1, y1) = (ax1.min(bx1).ceil() as i64, ay1.min(by1).ceil() as i64);
+    let mut inter = 0u64;
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+            if contains(a, cx, cy) && contains(b, cx, cy) {
+                inter += 1;
+            }
+        }
+    }
+    let union = area(a) + area(b) - inter as f32;
+    if union <= 0.0 {
+        return 0.0;
+    }
+    (inter as f32 / union).clamp(0.0, 1.0)
+}
+
+/// Points, confidence and label of one polygon.
+type Shape<'a> = (&'a [[f32; 2]], Option<f32>, Option<&'a str>);
+
+/// Upper bound of the IoU of two polygons with areas `a` and `b` and bounding boxes `ra` and `rb`:
+/// their intersection is at most the boxes' overlap and the smaller area.
+fn iou_bound(a: f32, ra: Rect, b: f32, rb: Rect) -> f32 {
+    let inter = geometry::intersection(ra, rb).min(a).min(b);
+    let union = a + b - inter;
+    if union > 0.0 { inter / union } else { 0.0 }
+}
+
+/// Indices to keep: most confident first, dropping any shape whose IoU with an already kept shape
+/// of the same label exceeds `iou`. Pairs whose bounding boxes rule that out skip the per-pixel
+/// [`polygon_iou`].
+fn keep_by_iou(shapes: &[Shape], iou: f32) -> Vec<usize> {
+    let extents: Vec<(f32, Rect)> = shapes
+        .iter()
+        .map(|(points, ..)| (area(points), bounds(points)))
+        .collect();
+    let mut order: Vec<usize> = (0..shapes.len()).collect();
+    order.sort_by(|&i, &j| {
+        let conf = |k: usize| shapes[k].1.unwrap_or(0.0);
+        conf(j).total_cmp(&conf(i))
+    });
+    let mut kept: Vec<usize> = Vec::new();
+    for i in order {
+        let (points, _, label) = shapes[i];
+        let (a, ra) = extents[i];
+        let duplicate = kept.iter().any(|&k| {
+            let (other, _, other_label) = shapes[k];
+            let (b, rb) = extents[k];
+            other_label == label
+                && iou_bound(a, ra, b, rb) > iou
+                && polygon_iou(points, other) > iou
+        });
+        if !duplicate {
+            kept.push(i);
+        }
+    }
+    kept.sort_unstable();
+    kept
+}
+
+/// Merge the polygons of all tiles: of two same-label polygons with IoU above `iou`, the less
+/// confident one is removed.
+pub fn deduplicate_polygons_iou(polygons: Vec<usls::Polygon>, iou: f32) -> Vec<usls::Polygon> {
+    let points: Vec<Vec<[f32; 2]>> = polygons.iter().map(|p| p.points()).collect();
+    let shapes: Vec<_> = polygons
+        .iter()
+        .zip(&points)
+        .map(|(p, pts)| (pts.as_slice(), p.confidence(), p.name()))
+        .collect();
+    let kept = keep_by_iou(&shapes, iou);
+    let dropped = polygons.len() - kept.len();
+    if dropped > 0 {
+        tracing::debug!("Tiling: merged {dropped} duplicate polygon(s) across tile borders");
+    }
+    kept.into_iter().map(|i| polygons[i].clone()).collect()
+}
+
+/// Run `model` on every tile of `img` and return the merged mask polygons in image coordinates,
+/// each with a box around it (same label, id and confidence) for the box-based outputs.
+pub fn forward_tiled(
+    model: &mut SAM3,
+    img: &usls::Image,
+    prompts: &[Sam3Prompt],
+    tile: u32,
+    overlap: u32,
+) -> Result<usls::Y> {
+    let tiles = tile_image(img, tile, overlap)?;
+    tracing::info!(
+        "Tiling {}x{} into {} tile(s) of {tile}px ({overlap}px overlap)",
+        img.width(),
+        img.height(),
+        tiles.len()
+    );
+    let mut polygons = Vec::new();
+    for t in &tiles {
+        for y in model.forward(std::slice::from_ref(&t.image), prompts)? {
+            let found: Vec<usls::Polygon> = y
+                .masks()
+                .iter()
+                .filter_map(|m| {
+                    let mut p = m.polygon()?;
+                    if let Some(name) = m.name() {
+                        p = p.with_name(name);
+                    }
+                    if let Some(id) = m.id() {
+                        p = p.with_id(id);
+                    }
+                    if let Some(conf) = m.confidence() {
+                        p = p.with_confidence(conf);
+                    }
+                    Some(p)
+                })
+                .chain(y.polygons().iter().cloned())
+                .collect();
+            polygons.extend(untile_polygons(&found, t.x, t.y));
+        }
+    }
+    Ok(with_boxes(deduplicate_polygons_iou(
+        polygons,
+        DEFAULT_DEDUP_IOU,
+    )))
+}
+
+/// `polygons` and a box around each, carrying the polygon's label, id and confidence.
+fn with_boxes(polygons: Vec<usls::Polygon>) -> usls::Y {
+    let hbbs: Vec<usls::Hbb> = polygons
+        .iter()
+        .map(|p| {
+            let (x0, y0, x1, y1) = bounds(&p.points());
+            let mut hbb = usls::Hbb::from_xyxy(x0, y0, x1, y1);
+            if let Some(name) = p.name() {
+                hbb = hbb.with_name(name);
+            }
+            if let Some(id) = p.id() {
+                hbb = hbb.with_id(id);
+            }
+            if let Some(conf) = p.confidence() {
+                hbb = hbb.with_confidence(conf);
+            }
+            hbb
+        })
+        .collect();
+    usls::Y::default().with_hbbs(&hbbs).with_polygons(&polygons)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f32, y: f32, size: f32) -> Vec<[f32; 2]> {
+        vec![[x, y], [x + size, y], [x + size, y + size], [x, y + size]]
+    }
+
+    #[test]
+    fn tiles_overlap_and_end_flush_with_the_edge() {
+        assert_eq!(tile_origins(1000, 400, 100), [0, 300, 600]);
+        assert_eq!(tile_origins(1001, 400, 100), [0, 300, 600, 601]);
+        assert_eq!(tile_origins(300, 400, 100), [0]);
+    }
+
+    #[test]
+    fn duplicates_across_tiles_keep_the_more_confident_polygon() {
+        let a = square(0.0, 0.0, 10.0);
+        let b = square(1.0, 0.0, 10.0);
+        let c = square(50.0, 50.0, 10.0);
+        assert!((polygon_iou(&a, &b) - 90.0 / 110.0).abs() < 1e-3);
+        assert_eq!(polygon_iou(&a, &c), 0.0);
+        let shapes = [
+            (a.as_slice(), Some(0.6), Some("cell")),
+            (b.as_slice(), Some(0.9), Some("cell")),
+            (c.as_slice(), Some(0.5), Some("cell")),
+            (a.as_slice(), Some(0.4), Some("nucleus")),
+        ];
+        assert_eq!(keep_by_iou(&shapes, DEFAULT_DEDUP_IOU), [1, 2, 3]);
+    }
+
+    #[test]
+    fn box_bound_never_rules_out_a_duplicate() {
+        // Same square, one with a long thin spike: the boxes barely overlap in IoU terms, but the
+        // polygons are nearly the same shape.
+        let a = square(0.0, 0.0, 10.0);
+        let spiked = vec![
+            [0.0, 0.0],
+            [10.0, 0.0],
+            [10.0, 4.9],
+            [100.0, 5.0],
+            [10.0, 5.1],
+            [10.0, 10.0],
+            [0.0, 10.0],
+        ];
+        let (ra, rb) = (bounds(&a), bounds(&spiked));
+        assert!(geometry::iou(ra, rb) < DEFAULT_DEDUP_IOU);
+        let exact = polygon_iou(&a, &spiked);
+        assert!(exact > DEFAULT_DEDUP_IOU);
+        assert!(iou_bound(area(&a), ra, area(&spiked), rb) >= exact);
+        let far = square(50.0, 0.0, 10.0);
+        assert_eq!(iou_bound(area(&a), ra, area(&far), bounds(&far)), 0.0);
+
+        let y = with_boxes(vec![
+            usls::Polygon::from_xys(&spiked)
+                .with_name("cell")
+                .with_id(1),
+        ]);
+        assert_eq!(y.hbbs()[0].xyxy(), (0.0, 0.0, 100.0, 10.0));
+        assert_eq!(
+            (y.hbbs()[0].name(), y.hbbs()[0].id()),
+            (Some("cell"), Some(1))
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! `--kalman`: a constant-velocity Kalman filter over a track's box, so a track has a position
+//! between inferences (`--infer-every` gaps) and through short occlusions, and jitter in the
+//! detected boxes is smoothed out.
+//!
+//! The state is the box center, width and height with their velocities; the detector measures the
+//! four positions. Those coordinates do not interact in a constant-velocity model, so the 8-state
+//! filter is run as four independent (position, velocity) filters. Noise scales with the box height:
+//! a detection is trusted to [`MEASUREMENT_STD`] of it, and motion may change by [`ACCEL_STD`]
+//! heights/s².
+
+use crate::geometry::Rect;
+
+/// Detected box position noise, as a fraction of the box height.
+pub const MEASUREMENT_STD: f32 = 0.05;
+/// Unmodeled acceleration, box heights per s².
+pub const ACCEL_STD: f32 = 0.5;
+/// Velocity uncertainty of a new track, box heights per s.
+const INITIAL_VELOCITY_STD: f32 = 1.0;
+
+/// One coordinate and its rate of change, with their covariance.
+#[derive(Clone, Copy, Debug)]
+struct Axis {
+    x: f32,
+    v: f32,
+    /// Covariance `[[p00, p01], [p01, p11]]`.
+    p00: f32,
+    p01: f32,
+    p11: f32,
+}
+
+impl Axis {
+    fn new(x: f32, pos_std: f32, vel_std: f32) -> Self {
+        Self {
+            x,
+            v: 0.0,
+            p00: pos_std * pos_std,
+            p01: 0.0,
+            p11: vel_std * vel_std,
+        }
+    }
+
+    /// Advance by `dt` seconds with acceleration variance `q`.
+    fn predict(&mut self, dt: f32, q: f32) {
+        let (dt2, dt3, dt4) = (dt * dt, dt * dt * dt, dt * dt * dt * dt);
+        self.x += self.v * dt;
+        self.p00 += 2.0 * dt * self.p01 + dt2 * self.p11 + q * dt4 / 4.0;
+        self.p01 += dt * self.p11 + q * dt3 / 2.0;
+        self.p11 += q * dt2;
+    }
+
+    /// Fold in a measurement `z` of the position with variance `r`.
+    fn correct(&mut self, z: f32, r: f32) {
+        let s = self.p00 + r;
+        let (k0, k1) = (self.p00 / s, self.p01 / s);
+        let innovation = z - self.x;
+        self.x += k0 * innovation;
+        self.v += k1 * innovation;
+        let (p00, p01, p11) = (self.p00, self.p01, self.p11);
+        self.p00 = (1.0 - k0) * p00;
+        self.p01 = (1.0 - k0) * p01;
+        self.p11 = p11 - k1 * p01;
+    }
+}
+
+/// Constant-velocity filter over one track's box.
+#[derive(Clone, Debug)]
+pub struct KalmanBox {
+    /// Center x, center y, width, height.
+    axes: [Axis; 4],
+}
+
+impl KalmanBox {
+    /// A filter starting at the detected box `rect`, at rest.
+    pub fn new(rect: Rect) -> Self {
+        let z = measurement(rect);
+        let h = z[3].max(1.0);
+        let (pos_std, vel_std) = (2.0 * MEASUREMENT_STD * h, INITIAL_VELOCITY_STD * h);
+        Self {
+            axes: z.map(|x| Axis::new(x, pos_std, vel_std)),
+        }
+    }
+
+    fn height(&self) -> f32 {
+        self.axes[3].x.max(1.0)
+    }
+
+    /// Move the estimate `dt` seconds ahead.
+    pub fn predict(&mut self, dt: f32) {
+        if dt <= 0.0 {
+            return;
+        }
+        let accel = ACCEL_STD * self.height();
+        for axis in &mut self.axes {
+            axis.predict(dt, accel * accel);
+        }
+    }
+
+    /// Update the estimate with the detected box `rect`.
+    pub fn correct(&mut self, rect: Rect) {
+        let noise = MEASUREMENT_STD * self.height();
+        for (axis, z) in self.axes.iter_mut().zip(measurement(rect)) {
+            axis.correct(z, noise * noise);
+        }
+    }
+
+    /// The estimated box.
+    pub fn rect(&self) -> Rect {
+        let [cx, cy, w, h] = self.axes.map(|a| a.x);
+        let (w, h) = (w.max(1.0), h.max(1.0));
+        (cx - w / 2.0, cy - h / 2.0, cx + w / 2.0, cy + h / 2.0)
+    }
+
+    /// Estimated velocity of the box center, pixels per second.
+    pub fn velocity(&self) -> (f32, f32) {
+        (self.axes[0].v, self.axes[1].v)
+    }
+}
+
+/// `rect` as center x, center y, width, height.
+fn measurement((x0, y0, x1, y1): Rect) -> [f32; 4] {
+    [(x0 + x1) / 2.0, (y0 + y1) / 2.0, x1 - x0, y1 - y0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 40x80 box moving at (120, -30) px/s from center (100, 300), seen at `t` seconds.
+    fn truth(t: f32) -> (f32, f32) {
+        (100.0 + 120.0 * t, 300.0 - 30.0 * t)
+    }
+
+    fn rect_at((cx, cy): (f32, f32)) -> Rect {
+        (cx - 20.0, cy - 40.0, cx + 20.0, cy + 40.0)
+    }
+
+    fn center(r: Rect) -> (f32, f32) {
+        ((r.0 + r.2) / 2.0, (r.1 + r.3) / 2.0)
+    }
+
+    fn dist(a: (f32, f32), b: (f32, f32)) -> f32 {
+        ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+    }
+
+    #[test]
+    fn tracks_a_noisy_constant_velocity_trajectory() {
+        // Deterministic +-4 px detection jitter.
+        let mut seed = 12345u32;
+        let mut jitter = || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            ((seed >> 16) % 801) as f32 / 100.0 - 4.0
+        };
+        let dt = 0.1;
+        let mut filter = KalmanBox::new(rect_at(truth(0.0)));
+        let (mut measured_err, mut filtered_err) = (0.0, 0.0);
+        for step in 1..=30 {
+            let t = step as f32 * dt;
+            let (cx, cy) = truth(t);
+            let seen = rect_at((cx + jitter(), cy + jitter()));
+            filter.predict(dt);
+            filter.correct(seen);
+            if step > 20 {
+                measured_err += dist(center(seen), (cx, cy));
+                filtered_err += dist(center(filter.rect()), (cx, cy));
+            }
+        }
+        assert!(
+            filtered_err < measured_err,
+            "filtered {filtered_err} vs measured {measured_err}"
+        );
+        let (vx, vy) = filter.velocity();
+        assert!(
+            (vx - 120.0).abs() < 15.0 && (vy + 30.0).abs() < 15.0,
+            "{vx} {vy}"
+        );
+
+        // Half a second without detections: the prediction follows the motion.
+        for _ in 0..5 {
+            filter.predict(dt);
+        }
+        let predicted = center(filter.rect());
+        assert!(dist(predicted, truth(3.5)) < 10.0, "{predicted:?}");
+        let r = filter.rect();
+        assert!((r.2 - r.0 - 40.0).abs() < 2.0 && (r.3 - r.1 - 80.0).abs() < 2.0);
+    }
+
+    #[test]
+    fn starts_at_rest_on_the_first_box() {
+        let mut filter = KalmanBox::new((10.0, 20.0, 50.0, 100.0));
+        assert_eq!(filter.rect(), (10.0, 20.0, 50.0, 100.0));
+        filter.predict(1.0);
+        assert_eq!(filter.rect(), (10.0, 20.0, 50.0, 100.0));
+        filter.predict(0.0);
+        filter.correct((12.0, 20.0, 52.0, 100.0));
+        let (x0, ..) = filter.rect();
+        assert!(x0 > 10.0 && x0 <= 12.0, "{x0}");
+        assert!(filter.velocity().0 > 0.0);
+    }
+}
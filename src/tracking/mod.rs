@@ -0,0 +1,3 @@
+//! Motion models for tracks of detections.
+
+pub mod kalman;
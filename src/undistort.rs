@@ -0,0 +1,266 @@
+//! `--calibration`: lens undistortion from an OpenCV calibration file.
+//!
+//! The file is what `cv::FileStorage` writes after `calibrateCamera` (`camera_matrix` and
+//! `distortion_coefficients` as `!!opencv-matrix` nodes, optionally `image_width`/`image_height`).
+//! The remap table is built once for the first frame's size; each frame is then a bilinear
+//! lookup, split across threads by row.
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::path::Path;
+
+/// Fixed-point scale of the bilinear weights.
+const WEIGHT_ONE: u32 = 256;
+
+/// Pinhole camera matrix plus OpenCV `k1, k2, p1, p2, k3` distortion.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    /// `[k1, k2, p1, p2, k3]`; missing trailing coefficients are zero.
+    pub dist: [f64; 5],
+    /// Resolution the calibration was made at, if recorded.
+    pub size: Option<(u32, u32)>,
+}
+
+/// `data: [ ... ]` of the matrix node `key`, which may span several lines.
+fn matrix_data(text: &str, key: &str) -> Option<Vec<f64>> {
+    let start = text
+        .lines()
+        .scan(0, |pos, line| {
+            let at = *pos;
+            *pos += line.len() + 1;
+            Some((at, line))
+        })
+        .find(|(_, line)| line.trim_start().starts_with(&format!("{key}:")))?
+        .0;
+    let rest = &text[start..];
+    let data = rest.find("data:")?;
+    let open = data + rest[data..].find('[')?;
+    let close = open + rest[open..].find(']')?;
+    rest[open + 1..close]
+        .split(',')
+        .map(|v| v.trim().parse().ok())
+        .collect()
+}
+
+fn scalar(text: &str, key: &str) -> Option<u32> {
+    text.lines()
+        .find_map(|l| l.trim_start().strip_prefix(&format!("{key}:")))
+        .and_then(|v| v.trim().parse().ok())
+}
+
+impl Calibration {
+    pub fn parse(text: &str) -> Result<Self> {
+        let k = matrix_data(text, "camera_matrix")
+            .filter(|k| k.len() == 9)
+            .context("missing or invalid camera_matrix (expected a 3x3 !!opencv-matrix)")?;
+        let d = matrix_data(text, "distortion_coefficients")
+            .filter(|d| d.len() >= 4)
+            .context("missing or invalid distortion_coefficients (expected 4 or more values)")?;
+        if k[0] <= 0.0 || k[4] <= 0.0 {
+            anyhow::bail!("camera_matrix focal lengths must be positive");
+        }
+        let mut dist = [0.0; 5];
+        for (dst, src) in dist.iter_mut().zip(&d) {
+            *dst = *src;
+        }
+        if d.len() > 5 && d[5..].iter().any(|v| *v != 0.0) {
+            tracing::warn!("Only k1, k2, p1, p2, k3 are used; higher-order coefficients ignored");
+        }
+        let size = scalar(text, "image_width").zip(scalar(text, "image_height"));
+        Ok(Self {
+            fx: k[0],
+            fy: k[4],
+            cx: k[2],
+            cy: k[5],
+            dist,
+            size,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read calibration file: {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("invalid calibration file: {}", path.display()))
+    }
+
+    /// Where output pixel `(u, v)` samples the distorted input.
+    fn distort(&self, u: f64, v: f64) -> (f64, f64) {
+        let [k1, k2, p1, p2, k3] = self.dist;
+        let x = (u - self.cx) / self.fx;
+        let y = (v - self.cy) / self.fy;
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+        let xd = x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+        let yd = y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+        (xd * self.fx + self.cx, yd * self.fy + self.cy)
+    }
+
+    /// The intrinsics scaled to a `width` x `height` frame.
+    fn scaled_to(&self, width: u32, height: u32) -> Self {
+        let Some((w, h)) = self.size.filter(|s| *s != (width, height)) else {
+            return self.clone();
+        };
+        let (sx, sy) = (width as f64 / w as f64, height as f64 / h as f64);
+        Self {
+            fx: self.fx * sx,
+            fy: self.fy * sy,
+            cx: self.cx * sx,
+            cy: self.cy * sy,
+            dist: self.dist,
+            size: Some((width, height)),
+        }
+    }
+}
+
+/// One output pixel: top-left source index (`None` outside the frame) and fixed-point weights.
+#[derive(Clone, Copy)]
+struct Tap {
+    src: Option<u32>,
+    wx: u16,
+    wy: u16,
+}
+
+/// Precomputed remap table for one frame size.
+pub struct Undistorter {
+    calibration: Calibration,
+    width: u32,
+    height: u32,
+    table: Vec<Tap>,
+    /// Toggled with `U` to compare against the raw frame.
+    pub enabled: bool,
+}
+
+impl Undistorter {
+    pub fn new(calibration: Calibration) -> Self {
+        Self {
+            calibration,
+            width: 0,
+            height: 0,
+            table: Vec::new(),
+            enabled: true,
+        }
+    }
+
+    fn build(&mut self, width: u32, height: u32) {
+        let cal = self.calibration.scaled_to(width, height);
+        let (w, h) = (width as f64, height as f64);
+        self.table = (0..height)
+            .flat_map(|v| (0..width).map(move |u| (u, v)))
+            .map(|(u, v)| {
+                let (sx, sy) = cal.distort(u as f64, v as f64);
+                if !(0.0..=w - 1.0).contains(&sx) || !(0.0..=h - 1.0).contains(&sy) {
+                    return Tap {
+                        src: None,
+                        wx: 0,
+                        wy: 0,
+                    };
+                }
+                // Keep the right/bottom bilinear neighbour inside the frame on the last column/row.
+                let x0 = sx.floor().min(w - 2.0);
+                let y0 = sy.floor().min(h - 2.0);
+                Tap {
+                    src: Some(y0 as u32 * width + x0 as u32),
+                    wx: ((sx - x0) * WEIGHT_ONE as f64) as u16,
+                    wy: ((sy - y0) * WEIGHT_ONE as f64) as u16,
+                }
+            })
+            .collect();
+        self.width = width;
+        self.height = height;
+        tracing::info!("Built undistortion table for {width}x{height}");
+    }
+
+    /// `img` undistorted (or unchanged while disabled). Pixels that map outside the sensor are
+    /// black.
+    pub fn apply(&mut self, img: usls::Image) -> usls::Image {
+        if !self.enabled {
+            return img;
+        }
+        let (width, height) = (img.width(), img.height());
+        if width < 2 || height < 2 {
+            return img;
+        }
+        if (width, height) != (self.width, self.height) {
+            self.build(width, height);
+        }
+        let src = img.as_raw();
+        let stride = width as usize * 3;
+        let mut out = vec![0u8; src.len()];
+        out.par_chunks_mut(stride)
+            .zip(self.table.par_chunks(width as usize))
+            .for_each(|(row, taps)| {
+                for (px, tap) in row.chunks_exact_mut(3).zip(taps) {
+                    let Some(i) = tap.src else {
+                        continue;
+                    };
+                    let i = i as usize * 3;
+                    let (wx, wy) = (tap.wx as u32, tap.wy as u32);
+                    for c in 0..3 {
+                        let top =
+                            src[i + c] as u32 * (WEIGHT_ONE - wx) + src[i + 3 + c] as u32 * wx;
+                        let bottom = src[i + stride + c] as u32 * (WEIGHT_ONE - wx)
+                            + src[i + stride + 3 + c] as u32 * wx;
+                        let v = top * (WEIGHT_ONE - wy) + bottom * wy;
+                        px[c] =
+                            ((v + WEIGHT_ONE * WEIGHT_ONE / 2) / (WEIGHT_ONE * WEIGHT_ONE)) as u8;
+                    }
+                }
+            });
+        let rgb = image::RgbImage::from_raw(width, height, out).expect("same size as the input");
+        usls::Image::from(rgb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALIB: &str = "%YAML:1.0
+---
+image_width: 640
+image_height: 480
+camera_matrix: !!opencv-matrix
+   rows: 3
+   cols: 3
+   dt: d
+   data: [ 500., 0., 320., 0., 500.,
+       240., 0., 0., 1. ]
+distortion_coefficients: !!opencv-matrix
+   rows: 1
+   cols: 5
+   dt: d
+   data: [ -0.2, 0.05, 0., 0., 0. ]
+";
+
+    #[test]
+    fn parses_opencv_yaml_and_scales_intrinsics() {
+        let cal = Calibration::parse(CALIB).unwrap();
+        assert_eq!((cal.fx, cal.cx, cal.cy), (500.0, 320.0, 240.0));
+        assert_eq!(cal.dist, [-0.2, 0.05, 0.0, 0.0, 0.0]);
+        let half = cal.scaled_to(320, 240);
+        assert_eq!((half.fx, half.cx, half.cy), (250.0, 160.0, 120.0));
+        // Barrel distortion pulls samples toward the center; the center itself stays put.
+        assert_eq!(cal.distort(320.0, 240.0), (320.0, 240.0));
+        assert!(cal.distort(620.0, 240.0).0 < 620.0);
+    }
+
+    #[test]
+    fn zero_distortion_is_identity() {
+        let mut cal = Calibration::parse(CALIB).unwrap();
+        cal.dist = [0.0; 5];
+        let rgb =
+            image::RgbImage::from_fn(8, 6, |x, y| image::Rgb([x as u8 * 30, y as u8 * 40, 7]));
+        let out = Undistorter::new(cal).apply(usls::Image::from(rgb.clone()));
+        for (x, y, px) in rgb.enumerate_pixels() {
+            assert_eq!(
+                &out.as_raw()[((y * 8 + x) * 3) as usize..][..3],
+                &px.0,
+                "({x}, {y})"
+            );
+        }
+    }
+}
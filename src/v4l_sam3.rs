@@ -1,10 +1,29 @@
 use anyhow::{Context, Result};
 use argh::FromArgs;
-use std::io::Write;
-use usls::{
-    Annotator, Config, Task, Viewer,
-    models::{SAM3, Sam3Prompt},
+use usls::Viewer;
+
+use crate::build_info::handle_version_flag;
+use crate::common::{
+    AnnotationStyle, Capture, ChannelValues, CheckReport, DEFAULT_CONF, MASK_TO_JSON_PATH,
+    ModelOptions, VideoSource, check_dir_writable, check_mask_to_json, default_save_base,
+    dummy_forward, log_writer, warmup_with_image, warn_prompts_outside,
 };
+use crate::device::{ExecutionProvider, auto_select_device_and_ep, ep_flag};
+use crate::ffmpeg_log::FfmpegLogLevel;
+use crate::frame_loop::{FrameHooks, FrameLoop, LoopArgs, Next, parse_prompts};
+use crate::groups::{GroupStyle, split_group_tags};
+use crate::kafka_sink::{KafkaSink, now_unix_ms};
+use crate::logging::init_logging;
+use crate::notify::NotifyKind;
+use crate::output::{CaptureTime, FrameRecord, MaskEncoding, json_schema};
+use crate::pipeline::Pipeline;
+use crate::postprocess::ExclusiveGroup;
+use crate::preprocess::{OutputSize, ToneAdjust, ToneParams, ToneTarget, temporal_average};
+use crate::prompts::with_mirrored;
+use crate::repro::next_run_dir;
+use crate::session::{SessionArgs, SessionFile};
+use crate::shm_sink::ShmSink;
+use crate::zones::Zone;
 
 #[derive(FromArgs)]
 /// SAM3 webcam inference (text prompts via `usls`).
@@ -13,9 +32,18 @@ pub struct Args {
     #[argh(option, default = "String::from(\"sam3-image\")")]
     task: String,
 
-    /// device (cpu:0, cuda:0, etc.)
-    #[argh(option, default = "String::from(\"cpu:0\")")]
-    device: String,
+    /// device (cpu:0, cuda:0, etc., or auto to pick one for this machine; default: cpu:0)
+    #[argh(option)]
+    device: Option<String>,
+
+    /// execution provider: cpu, cuda, tensorrt, directml, coreml or openvino (default: from
+    /// --device)
+    #[argh(option)]
+    execution_provider: Option<ExecutionProvider>,
+
+    /// short for --execution-provider; errors if that provider is not compiled in
+    #[argh(option)]
+    ep: Option<ExecutionProvider>,
 
     /// dtype (q4f16, fp16, fp32, etc.)
     #[argh(option, default = "String::from(\"q4f16\")")]
@@ -25,6 +53,14 @@ pub struct Args {
     #[argh(option, default = "0")]
     camera: usize,
 
+    /// camera backend: v4l (Linux) or opencv (needs `--features opencv`; default: v4l where built)
+    #[argh(option, default = "CameraBackend::default()")]
+    backend: CameraBackend,
+
+    /// exit with code 75 when no frame has been captured for this many seconds (for supervisors)
+    #[argh(option)]
+    watchdog_secs: Option<f32>,
+
     /// capture width (best-effort; may be overridden by the driver)
     #[argh(option, default = "640")]
     width: u32,
@@ -33,21 +69,184 @@ pub struct Args {
     #[argh(option, default = "480")]
     height: u32,
 
+    /// request this pixel format (e.g. MJPG) instead of negotiating from YUYV, MJPG, NV12, BGR3
+    #[argh(option)]
+    force_fourcc: Option<String>,
+
+    /// debug: write frames that fail to decode to this directory (as captured) before exiting,
+    /// to attach to bug reports
+    #[argh(option)]
+    dump_bad_frames: Option<String>,
+
+    /// V4L2 kernel capture buffers, 2-32 (default: 4); more absorbs inference stalls, fewer saves
+    /// memory on small boards
+    #[argh(option, default = "4")]
+    v4l_buf_count: u32,
+
+    /// V4L2 streaming I/O: mmap (default) or userptr (buffers allocated by this process)
+    #[argh(option, default = "V4lIoMethod::default()")]
+    v4l_io_method: V4lIoMethod,
+
     /// prompts (repeatable): `-p shoe` or `-p \"pos:480,290,110,360\"`
     #[argh(option, short = 'p')]
     prompt: Vec<String>,
 
+    /// also prompt with the left-right mirror image of every box prompt (`pos:`/`neg:`), for
+    /// symmetric parts
+    #[argh(switch)]
+    mirror_prompt: bool,
+
+    /// run the multi-stage detection pipeline in this YAML file instead of -p prompts
+    #[argh(option)]
+    pipeline: Option<String>,
+
+    /// average each frame with the previous N-1 (odd N; default 1 = off) to smooth sensor noise;
+    /// moving objects ghost, stationary defects stay sharp
+    #[argh(option, default = "1")]
+    motion_blur_kernel: u32,
+
+    /// rescale every captured frame to WxH before inference/display (prompts use these coordinates)
+    #[argh(option)]
+    output_size: Option<OutputSize>,
+
+    /// with --output-size: keep aspect ratio and pad with black bars instead of stretching
+    #[argh(switch)]
+    letterbox: bool,
+
     /// confidence threshold (default: 0.5)
-    #[argh(option, default = "0.5")]
-    conf: f32,
+    #[argh(option)]
+    conf: Option<f32>,
 
     /// show mask
     #[argh(option, default = "false")]
     show_mask: bool,
 
-    /// run inference every N frames (set 0 to disable)
-    #[argh(option, default = "3")]
-    infer_every: u32,
+    /// skip inference and only draw the prompt boxes/points (for positioning prompts)
+    #[argh(switch)]
+    visualize_points_only: bool,
+
+    /// reuse the previous inference when a frame is byte-identical to the last inferred one
+    #[argh(switch)]
+    dedup_frames: bool,
+
+    /// annotation style: minimal, full, none (default: full)
+    #[argh(option, default = "AnnotationStyle::Full")]
+    annotation_style: AnnotationStyle,
+
+    /// style for prompts tagged `-p "text#grp=<name>"`, as
+    /// <name>=thickness:N,color:RRGGBB,masks:on|off (repeatable)
+    #[argh(option)]
+    group_style: Vec<GroupStyle>,
+
+    /// fade the last annotation out between inferences by this much per frame (0 = hold, 1 = instant)
+    #[argh(option, default = "0.0")]
+    cross_fade_alpha: f32,
+
+    /// keep detections missing from the latest inference drawn in the window for up to N ms,
+    /// fading out (0 = off); saved frames and records are not affected
+    #[argh(option, default = "0")]
+    hold_ms: u64,
+
+    /// run inference every N frames (default 3, set 0 to disable)
+    #[argh(option)]
+    infer_every: Option<u32>,
+
+    /// run inference at most this many times per second, timed on the wall clock instead of
+    /// counting frames (exclusive with --infer-every)
+    #[argh(option)]
+    infer_hz: Option<f32>,
+
+    /// show a per-prompt legend (swatch, count, rolling average) in the window; toggle with L
+    #[argh(switch)]
+    legend: bool,
+
+    /// start with the legend hidden even when the --session file has it shown
+    #[argh(switch)]
+    no_legend: bool,
+
+    /// draw the legend on a panel beside the frame instead of over it
+    #[argh(switch)]
+    legend_outside: bool,
+
+    /// suppress detections whose box center falls inside x,y,w,h (output pixels; repeatable)
+    #[argh(option)]
+    exclude_zone: Vec<Zone>,
+
+    /// black out everything outside the polygons in this JSON file before inference
+    /// (`{"polygons": [[[x, y], ...], ...]}`, output pixels); display and saves stay unmasked
+    #[argh(option)]
+    roi_file: Option<String>,
+
+    /// gamma applied to each frame before inference, as a lookup table; above 1 lifts dark
+    /// frames (default: 1)
+    #[argh(option, default = "1.0")]
+    gamma: f32,
+
+    /// brightness offset applied with --gamma, -1 to 1 of full scale (default: 0)
+    #[argh(option, default = "0.0")]
+    brightness: f32,
+
+    /// contrast factor around mid-gray applied with --gamma (default: 1)
+    #[argh(option, default = "1.0")]
+    contrast: f32,
+
+    /// stretch each frame's levels (0.5th to 99.5th percentile) to the full range before
+    /// --gamma/--contrast/--brightness
+    #[argh(switch)]
+    auto_levels: bool,
+
+    /// apply --gamma/--brightness/--contrast/--auto-levels only to what is shown and saved; the
+    /// model sees the raw frame
+    #[argh(switch)]
+    preprocess_display_only: bool,
+
+    /// apply --gamma/--brightness/--contrast/--auto-levels only to the model input; display and
+    /// saves stay raw
+    #[argh(switch)]
+    preprocess_infer_only: bool,
+
+    /// with --exclude-zone: suppress when at least this fraction of the box is covered instead
+    #[argh(option)]
+    zone_overlap: Option<f32>,
+
+    /// after a prompt is detected, drop its detections for the next N frames (default: 0 = off)
+    #[argh(option, default = "0")]
+    prompt_cooldown_frames: u64,
+
+    /// of two detections whose boxes overlap with IoU above this, drop the less confident one
+    /// (default: 0 = off)
+    #[argh(option, default = "0.0")]
+    deduplicate_masks_iou: f32,
+
+    /// mutually exclusive prompts, `<name>=<prompt>,<prompt>,...` (repeatable): of overlapping
+    /// detections from different prompts of a group only the most confident is kept
+    #[argh(option)]
+    exclusive_group: Vec<ExclusiveGroup>,
+
+    /// with --exclusive-group: box IoU above which detections compete (default: 0.5)
+    #[argh(option, default = "0.5")]
+    exclusive_iou: f32,
+
+    /// load exclude zones from this file (one x,y,w,h per line); runtime edits are saved back
+    #[argh(option)]
+    zones_file: Option<String>,
+
+    /// draw the exclude zones as hatched rectangles in the window
+    #[argh(switch)]
+    show_zones: bool,
+
+    /// log per-label detection stats every N seconds
+    #[argh(option)]
+    stats_interval: Option<f32>,
+
+    /// restore prompts, conf, annotation preset, legend and zones from this JSON file; W and the
+    /// end of the run save them back (flags given on the command line win)
+    #[argh(option)]
+    session: Option<String>,
+
+    /// disable ANSI colors in terminal output
+    #[argh(switch)]
+    no_color: bool,
 
     /// window scale (1.0 = native resolution)
     #[argh(option, default = "1.0")]
@@ -65,227 +264,891 @@ pub struct Args {
     #[argh(option, default = "true")]
     trt_timing_cache: bool,
 
+    /// before opening the input, run the model on this image to warm up ONNX Runtime
+    #[argh(option)]
+    warmup_image: Option<String>,
+
+    /// with --warmup-image: number of warm-up forwards (default: 3)
+    #[argh(option, default = "3")]
+    warmup_runs: u32,
+
+    /// retry model init N times with backoff when the weight download fails (default: 0)
+    #[argh(option, default = "0")]
+    retry_init: u32,
+
+    /// model input size (square side; multiple of 14 in 336..=1008, default 1008). Lower is faster but less accurate
+    #[argh(option)]
+    imgsz: Option<u32>,
+
+    /// per-channel normalization mean as R,G,B (default: model preset)
+    #[argh(option)]
+    image_mean: Option<ChannelValues>,
+
+    /// per-channel normalization std as R,G,B (default: model preset)
+    #[argh(option)]
+    image_std: Option<ChannelValues>,
+
     /// save directory (default: ./runs/<model-spec>/)
     #[argh(option)]
     save_dir: Option<String>,
+
+    /// save into a new numbered <save-dir>/run-NNNN/ and name saved frames by frame index
+    #[argh(switch)]
+    sequential_names: bool,
+
+    /// on every inference also save one annotated frame per prompt with detections, showing only
+    /// that prompt's results, under <save-dir>/<prompt>/
+    #[argh(switch)]
+    save_dir_per_prompt: bool,
+
+    /// seed recorded in the run summary; nothing in the crate is random, so it only labels the run
+    #[argh(option)]
+    seed: Option<u64>,
+
+    /// write a run summary (configuration, versions, model hashes, metrics) as JSON to path
+    #[argh(option)]
+    summary_json: Option<String>,
+
+    /// at exit, diff this run's summary against a --summary-json file from another run
+    #[argh(option)]
+    compare: Option<String>,
+
+    /// save one output frame to the save directory every N seconds (wall time)
+    #[argh(option)]
+    snapshot_interval: Option<f32>,
+
+    /// save a frame to <save-dir>/interval/ every N seconds (wall time), detections or not
+    #[argh(option)]
+    save_on_interval_secs: Option<f32>,
+
+    /// with --save-on-interval-secs: save the annotated frame (true) or the raw frame (false)
+    #[argh(option, default = "true")]
+    save_on_interval_annotated: bool,
+
+    /// notify when a prompt label enters: bell, desktop or command
+    #[argh(option)]
+    notify: Option<NotifyKind>,
+
+    /// publish every output frame as a JPEG message to these Kafka brokers (host:port,...; needs
+    /// a build with --features kafka)
+    #[argh(option)]
+    kafka_brokers: Option<String>,
+
+    /// with --kafka-brokers: topic to publish frames to
+    #[argh(option)]
+    kafka_topic: Option<String>,
+
+    /// with --kafka-brokers: JPEG quality of published frames, 1-100 (default: 70)
+    #[argh(option, default = "70")]
+    kafka_jpeg_quality: u8,
+
+    /// publish every output frame, uncompressed, to the shared-memory ring buffer
+    /// /dev/shm/<name> for a local reader (Linux)
+    #[argh(option)]
+    shm: Option<String>,
+
+    /// with --shm: remove an existing /dev/shm/<name> (e.g. left by a crashed run) instead of
+    /// failing
+    #[argh(switch)]
+    shm_replace: bool,
+
+    /// with --notify command: shell command to run; gets SAM3_PROMPT, SAM3_CONFIDENCE and
+    /// SAM3_SNAPSHOT in its environment
+    #[argh(option)]
+    notify_command: Option<String>,
+
+    /// with --notify: seconds before the same label can notify again (default: 30)
+    #[argh(option, default = "30.0")]
+    notify_cooldown_secs: f32,
+
+    /// with --notify command: commands allowed to run at once; further enters are skipped
+    /// (default: 2)
+    #[argh(option, default = "2")]
+    notify_max_running: usize,
+
+    /// with --notify command: kill a command after this many seconds (default: 10)
+    #[argh(option, default = "10.0")]
+    notify_timeout_secs: f32,
+
+    /// write a clip around every detection event (prompt enters .. leaves) into this directory
+    #[argh(option)]
+    save_event_clips: Option<String>,
+
+    /// ffmpeg log level (quiet, error, warning, info, verbose, ...; default error); ffmpeg's
+    /// messages are logged as they arrive
+    #[argh(option, default = "FfmpegLogLevel::Error")]
+    ffmpeg_loglevel: FfmpegLogLevel,
+
+    /// with --save-event-clips: seconds of pre-roll kept before an event (default: 5)
+    #[argh(option, default = "5.0")]
+    clip_pre_seconds: f32,
+
+    /// with --save-event-clips: seconds recorded after the last leave (default: 5)
+    #[argh(option, default = "5.0")]
+    clip_post_seconds: f32,
+
+    /// assemble the interval snapshots into a time-lapse video at path on exit
+    #[argh(option)]
+    timelapse_video: Option<String>,
+
+    /// write per-frame detections (with capture timestamps) as JSON Lines to path
+    #[argh(option)]
+    json_out: Option<String>,
+
+    /// with --json-out: skip a frame's record when its detections match the last written record
+    /// (same labels, box IoU >= this, confidence within 0.05); the file then has gaps
+    #[argh(option)]
+    jsonl_dedup: Option<f32>,
+
+    /// geometry only: no window, JSON Lines to stdout (as --json-out), no annotation; logs go to
+    /// stderr
+    #[argh(switch)]
+    mask_to_json: bool,
+
+    /// log as JSON Lines (one object per event, with the current frame_idx)
+    #[argh(switch)]
+    log_json: bool,
+
+    /// masks in --json-out records: polygon, rle (uncompressed COCO RLE) or none (default)
+    #[argh(option, default = "MaskEncoding::None")]
+    mask_encoding: MaskEncoding,
+
+    /// dump a reconstruction of the vision encoder input of every inference as
+    /// `<frame>_vision-encoder-approx-input.npy` into this directory
+    #[argh(option)]
+    dump_tensors_dir: Option<String>,
+
+    /// before each inference, save every prompt's boxes (red) and points (green) drawn on the
+    /// frame as `<frame>_prompt_<i>.jpg` into this directory
+    #[argh(option)]
+    export_prompt_vis_dir: Option<String>,
+
+    /// file that prompts entered with P are appended to and recalled from (`!!`, `!N`, `!text`;
+    /// H lists it); default: ~/.cache/sam3/prompt_history
+    #[argh(option)]
+    prompt_history: Option<String>,
+
+    /// write every inferred frame as an SVG overlay (boxes, polygons, labels) over a JPEG
+    /// background into this directory, for documentation figures
+    #[argh(option)]
+    save_svg: Option<String>,
+
+    /// at exit, write the top --best-k annotated frames per prompt (by confidence) here
+    #[argh(option)]
+    save_best: Option<String>,
+
+    /// with --save-best: frames to keep per prompt (default: 5)
+    #[argh(option, default = "5")]
+    best_k: usize,
+
+    /// save every detected instance as its own crop (plus crops.jsonl) into this directory
+    #[argh(option)]
+    save_crops: Option<String>,
+
+    /// with --save-crops: grow each crop by N pixels on every side (default: 0)
+    #[argh(option, default = "0")]
+    crop_pad: u32,
+
+    /// with --save-crops: put crops in one subdirectory per prompt label
+    #[argh(switch)]
+    split_by_prompt: bool,
+
+    /// unix socket for runtime commands (prompt, conf, infer-every, snapshot, ping)
+    #[argh(option)]
+    control_socket: Option<String>,
+
+    /// validate camera, model and save paths, then exit without running
+    #[argh(switch)]
+    check: bool,
+
+    /// print the JSON Schema of --json-out records (detections, COCO RLE masks) to stdout and
+    /// exit
+    #[argh(switch)]
+    print_json_schema: bool,
 }
 
-fn parse_prompts(raw: &[String]) -> Result<Vec<Sam3Prompt>> {
-    if raw.is_empty() {
-        anyhow::bail!("No prompt. Use -p \"text\" or -p \"visual;pos:x,y,w,h\"");
-    }
-    raw.iter()
-        .map(|s| s.parse())
-        .collect::<std::result::Result<Vec<_>, _>>()
-        .map_err(|e| anyhow::anyhow!("{}", e))
+#[cfg(not(any(feature = "v4l", feature = "opencv")))]
+compile_error!("v4l-sam3 needs a camera backend: enable the `v4l` (Linux) or `opencv` feature");
+
+/// Where `v4l-sam3` gets its frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CameraBackend {
+    /// V4L2 mmap streaming (Linux, `v4l` feature).
+    V4l,
+    /// OpenCV `VideoCapture` (any platform, `opencv` feature).
+    OpenCv,
 }
 
-fn prompt_update_loop() -> Result<Option<Vec<Sam3Prompt>>> {
-    eprint!("New prompt(s) (split with `|`, empty keeps current): ");
-    std::io::stderr().flush().ok();
-    let mut line = String::new();
-    std::io::stdin()
-        .read_line(&mut line)
-        .context("failed to read prompt from stdin")?;
-    let line = line.trim();
-    if line.is_empty() {
-        return Ok(None);
+impl Default for CameraBackend {
+    fn default() -> Self {
+        if cfg!(all(target_os = "linux", feature = "v4l")) {
+            Self::V4l
+        } else {
+            Self::OpenCv
+        }
     }
-    let parts: Vec<String> = line
-        .split('|')
-        .map(str::trim)
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_string())
-        .collect();
-    Ok(Some(parse_prompts(&parts)?))
 }
 
-#[cfg(not(target_os = "linux"))]
-pub fn run() -> Result<()> {
-    anyhow::bail!("`v4l_sam3` currently supports only Linux (V4L2).")
+impl std::str::FromStr for CameraBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "v4l" | "v4l2" => Ok(Self::V4l),
+            "opencv" => Ok(Self::OpenCv),
+            other => Err(format!(
+                "invalid camera backend `{other}` (expected v4l or opencv)"
+            )),
+        }
+    }
 }
 
-#[cfg(target_os = "linux")]
-pub fn run() -> Result<()> {
-    use v4l::io::traits::CaptureStream;
-    use v4l::video::Capture;
-    use v4l::{Device, FourCC, buffer::Type, prelude::*};
+/// `--v4l-io-method`: how V4L2 capture buffers are shared with the driver.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum V4lIoMethod {
+    /// Driver-allocated buffers mapped into this process.
+    #[default]
+    Mmap,
+    /// Buffers allocated here and handed to the driver; some drivers only support one of the two.
+    Userptr,
+}
 
-    fn clamp_u8(x: i32) -> u8 {
-        x.clamp(0, 255) as u8
-    }
+impl std::str::FromStr for V4lIoMethod {
+    type Err = String;
 
-    fn yuyv_to_rgb8(width: u32, height: u32, yuyv: &[u8]) -> Result<image::RgbImage> {
-        let expected_len = width
-            .checked_mul(height)
-            .and_then(|px| px.checked_mul(2))
-            .context("width*height overflow")? as usize;
-        if yuyv.len() < expected_len {
-            anyhow::bail!(
-                "YUYV buffer too small: got {}, expected {}",
-                yuyv.len(),
-                expected_len
-            );
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "mmap" => Ok(Self::Mmap),
+            "userptr" => Ok(Self::Userptr),
+            other => Err(format!(
+                "invalid V4L2 I/O method `{other}` (expected mmap or userptr)"
+            )),
         }
+    }
+}
 
-        let mut rgb = vec![0u8; (width as usize) * (height as usize) * 3];
-        let mut di = 0usize;
+/// A live camera; V4L2 also reports the driver timestamp of the last frame.
+trait Camera: VideoSource {
+    fn last_capture(&self) -> Option<CaptureTime> {
+        None
+    }
+}
 
-        for si in (0..expected_len).step_by(4) {
-            let y0 = yuyv[si] as i32;
-            let u = yuyv[si + 1] as i32;
-            let y1 = yuyv[si + 2] as i32;
-            let v = yuyv[si + 3] as i32;
+#[cfg(feature = "opencv")]
+impl Camera for crate::opencv_camera::OpenCvCamera {}
 
-            for y in [y0, y1] {
-                let c = y - 16;
-                let d = u - 128;
-                let e = v - 128;
+/// CLOCK_MONOTONIC, the clock V4L2 drivers stamp buffers with.
+#[cfg(target_os = "linux")]
+fn monotonic_now_s() -> f64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid, writable timespec.
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as f64 + ts.tv_nsec as f64 * 1e-9
+}
 
-                let r = (298 * c + 409 * e + 128) >> 8;
-                let g = (298 * c - 100 * d - 208 * e + 128) >> 8;
-                let b = (298 * c + 516 * d + 128) >> 8;
+/// Only V4L2 frames carry capture times, so there is nothing to compare against elsewhere.
+#[cfg(not(target_os = "linux"))]
+fn monotonic_now_s() -> f64 {
+    f64::NAN
+}
 
-                rgb[di] = clamp_u8(r);
-                rgb[di + 1] = clamp_u8(g);
-                rgb[di + 2] = clamp_u8(b);
-                di += 3;
-            }
-        }
+#[cfg(all(target_os = "linux", feature = "v4l"))]
+mod v4l_camera {
+    use anyhow::{Context, Result};
+    use std::path::{Path, PathBuf};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use v4l::io::traits::CaptureStream;
+    use v4l::video::Capture as _;
+    use v4l::{Device, FourCC, buffer::Type, prelude::*};
 
-        image::RgbImage::from_raw(width, height, rgb).context("failed to construct RgbImage")
-    }
+    use super::{Args, Camera, V4lIoMethod, monotonic_now_s};
+    use crate::common::{CheckReport, VideoSource};
+    use crate::mjpeg::decode_mjpeg;
+    use crate::output::CaptureTime;
+    use crate::v4l_util::{
+        DEFAULT_FOURCCS, bgr3_to_rgb8, effective_stride, negotiate_fourcc, nv12_to_rgb8,
+        packed_line_bytes, parse_fourcc, yuyv_to_rgb8,
+    };
 
     fn decode_frame_to_rgb8(
         width: u32,
         height: u32,
+        stride: u32,
         fourcc: FourCC,
         bytes: &[u8],
     ) -> Result<image::RgbImage> {
         if fourcc == FourCC::new(b"YUYV") {
-            return yuyv_to_rgb8(width, height, bytes);
+            return yuyv_to_rgb8(width, height, stride, bytes);
+        }
+
+        if is_jpeg(fourcc) {
+            return decode_mjpeg(bytes);
         }
 
-        if fourcc == FourCC::new(b"MJPG") || fourcc == FourCC::new(b"JPEG") {
-            let img = image::load_from_memory(bytes).context("failed to decode MJPEG frame")?;
-            return Ok(img.to_rgb8());
+        if fourcc == FourCC::new(b"NV12") {
+            return nv12_to_rgb8(width, height, stride, bytes);
+        }
+
+        if fourcc == FourCC::new(b"BGR3") {
+            return bgr3_to_rgb8(width, height, stride, bytes);
         }
 
         anyhow::bail!(
-            "Unsupported camera pixel format: {:?} (expected YUYV or MJPG)",
+            "Unsupported camera pixel format: {:?} (expected YUYV, MJPG, NV12 or BGR3)",
             fourcc
         );
     }
 
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_timer(tracing_subscriber::fmt::time::ChronoLocal::rfc_3339())
-        .init();
-
-    let args: Args = argh::from_env();
-    let mut prompts = parse_prompts(&args.prompt)?;
-
-    let config = match args.task.parse()? {
-        Task::Sam3Image => Config::sam3_image(),
-        Task::Sam3Tracker => Config::sam3_tracker(),
-        _ => anyhow::bail!(
-            "Sam3 Task now only support: {}, {}",
-            Task::Sam3Image,
-            Task::Sam3Tracker
-        ),
+    fn is_jpeg(fourcc: FourCC) -> bool {
+        fourcc == FourCC::new(b"MJPG") || fourcc == FourCC::new(b"JPEG")
     }
-    .with_tensorrt_fp16_all(args.trt_fp16)
-    .with_tensorrt_engine_cache_all(args.trt_engine_cache)
-    .with_tensorrt_timing_cache_all(args.trt_timing_cache)
-    .with_dtype_all(args.dtype.parse()?)
-    .with_class_confs(&[args.conf])
-    .with_device_all(args.device.parse()?)
-    .commit()?;
-
-    let mut model = SAM3::new(config)?;
-    let annotator = Annotator::default()
-        .with_mask_style(
-            usls::MaskStyle::default()
-                .with_visible(args.show_mask)
-                .with_cutout(true)
-                .with_draw_polygon_largest(true),
-        )
-        .with_polygon_style(usls::PolygonStyle::default().with_thickness(2));
-
-    let mut viewer = Viewer::new("sam3-v4l").with_window_scale(args.window_scale);
-
-    let dev = Device::new(args.camera).context("failed to open camera device")?;
-    let mut fmt = dev.format().context("failed to read camera format")?;
-    fmt.width = args.width;
-    fmt.height = args.height;
-    fmt.fourcc = FourCC::new(b"YUYV");
-    let fmt = dev
-        .set_format(&fmt)
-        .context("failed to set camera format")?;
-    tracing::info!(
-        "Camera format: {}x{} {:?}",
-        fmt.width,
-        fmt.height,
-        fmt.fourcc
-    );
-
-    let mut stream =
-        MmapStream::with_buffers(&dev, Type::VideoCapture, 4).context("failed to start stream")?;
 
-    let save_base = match args.save_dir {
-        Some(dir) => std::path::PathBuf::from(dir),
-        None => usls::Dir::Current.base_dir_with_subs(&["runs", model.spec()])?,
-    };
+    /// `--dump-bad-frames`: save the raw bytes of an undecodable frame, named by its driver
+    /// sequence number, size and format.
+    fn dump_bad_frame(dir: &Path, sequence: u32, fmt: (u32, u32, FourCC), bytes: &[u8]) {
+        let (width, height, fourcc) = fmt;
+        let ext = if is_jpeg(fourcc) {
+            "jpg".to_string()
+        } else {
+            format!(
+                "{}.raw",
+                String::from_utf8_lossy(&fourcc.repr)
+                    .trim()
+                    .to_ascii_lowercase()
+            )
+        };
+        let path = dir.join(format!("bad-{sequence:06}-{width}x{height}.{ext}"));
+        match std::fs::write(&path, bytes) {
+            Ok(()) => tracing::warn!("Wrote undecodable frame to {}", path.display()),
+            Err(e) => tracing::warn!("Failed to write {}: {e}", path.display()),
+        }
+    }
 
-    tracing::info!("Controls: ESC/Q quit, P update prompt, S save frame");
+    /// The mmap or userptr capture stream as a [`VideoSource`]; keeps the driver timestamp of the last frame.
+    struct V4lStream<S> {
+        stream: S,
+        width: u32,
+        height: u32,
+        stride: u32,
+        fourcc: FourCC,
+        wall_minus_mono_s: f64,
+        warned_zero_ts: bool,
+        last_capture: Option<CaptureTime>,
+        bad_frame_dir: Option<PathBuf>,
+    }
 
-    let mut last_displayed: Option<usls::Image> = None;
-    let mut frame_idx: u64 = 0;
-    loop {
-        if viewer.is_window_exist_and_closed() {
-            break;
+    impl<S> V4lStream<S> {
+        fn new(stream: S, fmt: &v4l::Format, stride: u32, wall_minus_mono_s: f64) -> Self {
+            Self {
+                stream,
+                width: fmt.width,
+                height: fmt.height,
+                stride,
+                fourcc: fmt.fourcc,
+                wall_minus_mono_s,
+                warned_zero_ts: false,
+                last_capture: None,
+                bad_frame_dir: None,
+            }
+        }
+
+        fn with_bad_frame_dir(mut self, dir: Option<PathBuf>) -> Self {
+            self.bad_frame_dir = dir;
+            self
         }
+    }
 
-        let (data, meta) = stream.next().context("failed to capture frame")?;
-        let bytes_used = (meta.bytesused as usize).min(data.len());
-        let rgb8 = decode_frame_to_rgb8(fmt.width, fmt.height, fmt.fourcc, &data[..bytes_used])?;
-        let img = usls::Image::from(rgb8);
-
-        frame_idx += 1;
-        let run_infer = args.infer_every > 0 && frame_idx.is_multiple_of(args.infer_every as u64);
-        let display = if run_infer {
-            let batch = vec![img.clone()];
-            let ys = model.forward(&batch, &prompts)?;
-
-            let mut annotated = annotator.annotate(&img, &ys[0])?;
-            for prompt in &prompts {
-                annotated = annotator.annotate(&annotated, &prompt.boxes)?;
-                annotated = annotator.annotate(&annotated, &prompt.points)?;
+    impl<S> Camera for V4lStream<S>
+    where
+        S: for<'b> CaptureStream<'b, Item = [u8]>,
+    {
+        fn last_capture(&self) -> Option<CaptureTime> {
+            self.last_capture
+        }
+    }
+
+    impl<S> VideoSource for V4lStream<S>
+    where
+        S: for<'b> CaptureStream<'b, Item = [u8]>,
+    {
+        fn next_frame(&mut self) -> Result<Option<usls::Image>> {
+            let (data, meta) = self.stream.next().context("failed to capture frame")?;
+            let bytes_used = (meta.bytesused as usize).min(data.len());
+            self.last_capture = if meta.timestamp.sec == 0 && meta.timestamp.usec == 0 {
+                if !self.warned_zero_ts {
+                    tracing::warn!("Driver reports zero buffer timestamps; capture times are unavailable");
+                    self.warned_zero_ts = true;
+                }
+                None
+            } else {
+                let monotonic_s = meta.timestamp.sec as f64 + meta.timestamp.usec as f64 * 1e-6;
+                Some(CaptureTime {
+                    monotonic_s,
+                    wall_unix_ms: (monotonic_s + self.wall_minus_mono_s) * 1000.0,
+                })
+            };
+            let bytes = &data[..bytes_used];
+            let rgb8 = match decode_frame_to_rgb8(
+                self.width,
+                self.height,
+                self.stride,
+                self.fourcc,
+                bytes,
+            ) {
+                Ok(rgb8) => rgb8,
+                Err(e) => {
+                    if let Some(dir) = &self.bad_frame_dir {
+                        let fmt = (self.width, self.height, self.fourcc);
+                        dump_bad_frame(dir, meta.sequence, fmt, bytes);
+                    }
+                    return Err(e);
+                }
+            };
+            Ok(Some(usls::Image::from(rgb8)))
+        }
+
+        fn size(&self) -> (u32, u32) {
+            (self.width, self.height)
+        }
+
+        /// The negotiated frame interval is not queried.
+        fn fps(&self) -> Option<f32> {
+            None
+        }
+    }
+
+    /// Open `--camera`, negotiate the pixel format and start streaming.
+    pub(super) fn open(args: &Args, report: &mut CheckReport) -> Result<Box<dyn Camera>> {
+        if !(2..=32).contains(&args.v4l_buf_count) {
+            anyhow::bail!(
+                "--v4l-buf-count must be between 2 and 32, got {}",
+                args.v4l_buf_count
+            );
+        }
+        let dev = report.record(
+            "camera",
+            Device::new(args.camera).context("failed to open camera device"),
+        )?;
+        let fourcc = match &args.force_fourcc {
+            Some(name) => FourCC::new(&parse_fourcc(name)?),
+            None => {
+                let preferred: Vec<FourCC> = DEFAULT_FOURCCS.iter().map(FourCC::new).collect();
+                let fourcc = report.record("pixel format", negotiate_fourcc(&dev, &preferred))?;
+                tracing::info!("Negotiated pixel format: {fourcc:?}");
+                fourcc
             }
-            last_displayed = Some(annotated.clone());
-            annotated
-        } else {
-            last_displayed.clone().unwrap_or(img)
         };
+        let fmt = report.record(
+            "format",
+            dev.format()
+                .context("failed to read camera format")
+                .and_then(|mut fmt| {
+                    fmt.width = args.width;
+                    fmt.height = args.height;
+                    fmt.fourcc = fourcc;
+                    dev.set_format(&fmt).context("failed to set camera format")
+                }),
+        )?;
+        if fmt.fourcc != fourcc {
+            tracing::warn!("Requested {fourcc:?} but the driver chose {:?}", fmt.fourcc);
+        }
+        report.detail(format!("{}x{} {}", fmt.width, fmt.height, fmt.fourcc));
+        tracing::info!(
+            "Camera format: {}x{} {:?}",
+            fmt.width,
+            fmt.height,
+            fmt.fourcc
+        );
+        let stride = effective_stride(&fmt.fourcc.repr, fmt.width, fmt.stride);
+        if let Some(packed) = packed_line_bytes(&fmt.fourcc.repr, fmt.width)
+            && stride > packed
+        {
+            tracing::info!("Driver pads rows to {stride} bytes (packed: {packed}); de-striding");
+        }
+
+        tracing::debug!("V4L2 kernel buffer count: {}", args.v4l_buf_count);
+        // Calibrated once: driver timestamps are monotonic, exports also want wall-clock time.
+        let wall_minus_mono_s = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("system clock before unix epoch")?
+            .as_secs_f64()
+            - monotonic_now_s();
+        let bad_frame_dir = match &args.dump_bad_frames {
+            Some(dir) => {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create --dump-bad-frames dir: {dir}"))?;
+                Some(PathBuf::from(dir))
+            }
+            None => None,
+        };
+        let count = args.v4l_buf_count;
+        Ok(match args.v4l_io_method {
+            V4lIoMethod::Mmap => {
+                let stream = report.record(
+                    "stream",
+                    MmapStream::with_buffers(&dev, Type::VideoCapture, count)
+                        .context("failed to start mmap stream"),
+                )?;
+                Box::new(
+                    V4lStream::new(stream, &fmt, stride, wall_minus_mono_s)
+                        .with_bad_frame_dir(bad_frame_dir),
+                )
+            }
+            V4lIoMethod::Userptr => {
+                let stream = report.record(
+                    "stream",
+                    UserptrStream::with_buffers(&dev, Type::VideoCapture, count)
+                        .context("failed to start userptr stream (driver may not support it)"),
+                )?;
+                Box::new(
+                    V4lStream::new(stream, &fmt, stride, wall_minus_mono_s)
+                        .with_bad_frame_dir(bad_frame_dir),
+                )
+            }
+        })
+    }
+}
+
+/// Where `v4l-sam3` sends finished frames, stamped with the capture time of the frame.
+struct CameraSinks {
+    kafka: Option<KafkaSink>,
+    shm: Option<ShmSink>,
+    /// Driver timestamp of the current frame.
+    capture: Option<CaptureTime>,
+}
 
-        viewer.imshow(&display)?;
+impl FrameHooks<Box<dyn Camera>> for CameraSinks {
+    fn next_frame(&mut self, frames: &mut Capture<Box<dyn Camera>>) -> Result<Next> {
+        let Some((frame_idx, img)) = frames.next_frame()? else {
+            return Ok(Next::End);
+        };
+        self.capture = frames.source().last_capture();
+        Ok(Next::Frame(frame_idx, img))
+    }
 
-        if viewer.is_key_pressed(usls::Key::Escape) || viewer.is_key_pressed(usls::Key::Q) {
-            break;
+    fn record(&mut self, record: &mut FrameRecord) {
+        record.capture = self.capture;
+        if record.inferred {
+            record.latency_ms = self
+                .capture
+                .map(|c| (monotonic_now_s() - c.monotonic_s) * 1000.0);
         }
+    }
 
-        if viewer.is_key_pressed(usls::Key::S) && let Some(img) = &last_displayed {
-            let path = save_base.join(format!("{}.jpg", usls::timestamp(None)));
-            img.save(&path)?;
-            tracing::info!("Saved: {}", path.display());
+    fn write(&mut self, frame_idx: u64, display: &usls::Image) -> Result<()> {
+        let ts_ms = self
+            .capture
+            .map_or_else(now_unix_ms, |c| c.wall_unix_ms as u64);
+        if let Some(kafka) = self.kafka.as_mut() {
+            kafka.send_frame(display, frame_idx, ts_ms)?;
+        }
+        if let Some(shm) = self.shm.as_mut() {
+            shm.write_frame(display, frame_idx, ts_ms)?;
         }
+        Ok(())
+    }
 
-        if viewer.is_key_pressed(usls::Key::P) && let Some(new_prompts) = prompt_update_loop()? {
-            prompts = new_prompts;
-            tracing::info!("Updated prompts: {:?}", prompts);
+    fn finish(
+        self,
+        _frames: Capture<Box<dyn Camera>>,
+        _stopped: bool,
+    ) -> Result<Vec<(&'static str, serde_json::Value)>> {
+        if let Some(kafka) = self.kafka {
+            kafka.finish();
         }
+        Ok(Vec::new())
     }
+}
 
-    usls::perf(false);
-    Ok(())
+#[cfg(not(any(all(target_os = "linux", feature = "v4l"), feature = "opencv")))]
+pub fn run() -> Result<()> {
+    anyhow::bail!("`v4l_sam3` needs Linux (V4L2) or a build with `--features opencv`.")
+}
+
+#[cfg(any(all(target_os = "linux", feature = "v4l"), feature = "opencv"))]
+pub fn run() -> Result<()> {
+    if handle_version_flag("v4l-sam3") {
+        return Ok(());
+    }
+    let mut args: Args = argh::from_env();
+    if args.print_json_schema {
+        println!("{}", serde_json::to_string_pretty(&json_schema())?);
+        return Ok(());
+    }
+    init_logging(args.log_json, log_writer(args.mask_to_json));
+    let session = SessionFile::open(args.session.as_deref())?;
+    session.restored().restore_args(SessionArgs {
+        prompt: &mut args.prompt,
+        conf: &mut args.conf,
+        legend: &mut args.legend,
+        no_legend: args.no_legend,
+        exclude_zone: &mut args.exclude_zone,
+        zones_file: args.zones_file.is_some(),
+        pipeline: args.pipeline.is_some(),
+    })?;
+    // As given, `#grp=` tags included, for the session file.
+    let session_prompts = if args.pipeline.is_some() {
+        // The prompts come from the pipeline file; keep the ones saved in the session.
+        session.restored().prompts.clone()
+    } else {
+        args.prompt.clone()
+    };
+    let (prompt, mut group_tags) = split_group_tags(&args.prompt);
+    args.prompt = prompt;
+
+    check_mask_to_json(
+        args.mask_to_json,
+        &[
+            ("--json-out", args.json_out.is_some()),
+            ("--visualize-points-only", args.visualize_points_only),
+        ],
+    )?;
+    let mut report = CheckReport::new(args.check);
+    let pipeline = match &args.pipeline {
+        Some(path) => Some(report.record("pipeline", Pipeline::load(std::path::Path::new(path)))?),
+        None => None,
+    };
+    let mut prompts = match &pipeline {
+        Some(_) if !args.prompt.is_empty() => {
+            anyhow::bail!("--pipeline takes its prompts from the file; drop -p")
+        }
+        Some(_) if args.mask_encoding != MaskEncoding::None => {
+            anyhow::bail!(
+                "--mask-encoding does not apply to --pipeline (its stages keep polygons, not masks)"
+            )
+        }
+        Some(pipeline) => pipeline.prompts(),
+        None => report.record("prompts", parse_prompts(&args.prompt))?,
+    };
+    report.detail(format!("{} prompt(s)", prompts.len()));
+
+    let (device, _) = auto_select_device_and_ep(
+        args.device.as_deref(),
+        ep_flag(args.ep, args.execution_provider)?,
+    )?;
+    let model_opts = ModelOptions {
+        task: args.task.clone(),
+        device,
+        dtype: args.dtype.clone(),
+        conf: args.conf.unwrap_or(DEFAULT_CONF),
+        trt_fp16: args.trt_fp16,
+        trt_engine_cache: args.trt_engine_cache,
+        trt_timing_cache: args.trt_timing_cache,
+        imgsz: args.imgsz,
+        image_mean: args.image_mean,
+        image_std: args.image_std,
+        retry_init: args.retry_init,
+    };
+    let config = report.record("config", model_opts.build_config())?;
+    report.detail(format!(
+        "{} on {} ({})",
+        args.task, model_opts.device, args.dtype
+    ));
+
+    let mut model = report.record("model", model_opts.load_model(config))?;
+    report.detail(model.spec().to_string());
+    if let Some(path) = &args.warmup_image
+        && !args.check
+    {
+        warmup_with_image(
+            &mut model,
+            std::path::Path::new(path),
+            &prompts,
+            args.warmup_runs,
+        )?;
+    }
+    let viewer =
+        (!args.mask_to_json).then(|| Viewer::new("sam3-v4l").with_window_scale(args.window_scale));
+
+    let mut source: Box<dyn Camera> = match args.backend {
+        #[cfg(all(target_os = "linux", feature = "v4l"))]
+        CameraBackend::V4l => v4l_camera::open(&args, &mut report)?,
+        #[cfg(feature = "opencv")]
+        CameraBackend::OpenCv => Box::new(report.record(
+            "camera",
+            crate::opencv_camera::OpenCvCamera::open(args.camera, args.width, args.height),
+        )?),
+        #[allow(unreachable_patterns)]
+        other => anyhow::bail!(
+            "--backend {other:?} is not built in (see the `v4l` and `opencv` features)"
+        ),
+    };
+    let (width, height) = source.size();
+    let mirror_width = args
+        .mirror_prompt
+        .then(|| args.output_size.map_or(width, |size| size.width));
+    if mirror_width.is_some() {
+        anyhow::ensure!(
+            pipeline.is_none(),
+            "--mirror-prompt is not supported with --pipeline"
+        );
+        let (raw, tags) = (
+            std::mem::take(&mut args.prompt),
+            std::mem::take(&mut group_tags),
+        );
+        (args.prompt, group_tags, prompts) = with_mirrored(mirror_width, raw, tags, prompts);
+        tracing::info!(
+            "--mirror-prompt: {} prompt(s) with the mirrored boxes",
+            prompts.len()
+        );
+    }
+
+    if report.enabled() {
+        let grabbed = source
+            .next_frame()
+            .and_then(|frame| frame.context("camera stream ended"));
+        report.record("capture", grabbed)?;
+        drop(source);
+
+        report.record("forward", dummy_forward(&mut model, &prompts, width, height))?;
+
+        let save_base = match &args.save_dir {
+            Some(dir) => std::path::PathBuf::from(dir),
+            None => default_save_base(model.spec(), false)?,
+        };
+        report.record("save-dir", check_dir_writable(&save_base))?;
+        report.detail(save_base.display().to_string());
+
+        report.print();
+        return Ok(());
+    }
+
+    let save_base = match args.save_dir {
+        Some(dir) => std::path::PathBuf::from(dir),
+        None => default_save_base(model.spec(), true)?,
+    };
+    let save_base = if args.sequential_names {
+        next_run_dir(&save_base)?
+    } else {
+        save_base
+    };
+    if let Some(size) = args.output_size {
+        tracing::info!(
+            "Output size: {}x{} ({})",
+            size.width,
+            size.height,
+            if args.letterbox {
+                "letterbox"
+            } else {
+                "stretch"
+            }
+        );
+        warn_prompts_outside(&prompts, size.width, size.height);
+    }
+    let kafka = KafkaSink::from_args(
+        args.kafka_brokers.as_deref(),
+        args.kafka_topic.as_deref(),
+        args.kafka_jpeg_quality,
+    )?;
+    let shm = args
+        .shm
+        .as_deref()
+        .map(|name| ShmSink::new(name, args.shm_replace))
+        .transpose()?;
+
+    if viewer.is_some() {
+        tracing::info!(
+            "Controls: ESC/Q quit, P update prompt, S save frame, L legend, M cycle annotation style, H prompt history, W save session"
+        );
+    }
+
+    let json_out_path = if args.mask_to_json {
+        Some(MASK_TO_JSON_PATH)
+    } else {
+        args.json_out.as_deref()
+    };
+    let frames = Capture::new(source)
+        .with_output_size(args.output_size, args.letterbox)
+        .with_temporal_average(temporal_average(args.motion_blur_kernel)?)
+        .with_tone(ToneAdjust::new(
+            ToneParams {
+                gamma: args.gamma,
+                brightness: args.brightness,
+                contrast: args.contrast,
+                auto_levels: args.auto_levels,
+            },
+            ToneTarget::from_flags(args.preprocess_display_only, args.preprocess_infer_only)?,
+        )?);
+    let frame_loop = FrameLoop::new(LoopArgs {
+        model,
+        model_opts,
+        pipeline,
+        prompts,
+        session_prompts,
+        labels: args.prompt.clone(),
+        group_tags,
+        mirror_width,
+        session,
+        viewer,
+        frame_size: frames.size(),
+        save_base,
+        json_out: json_out_path.map(std::path::Path::new),
+        task: &args.task,
+        mask_to_json: args.mask_to_json,
+        annotation_style: args.annotation_style,
+        show_mask: args.show_mask,
+        group_style: &args.group_style,
+        legend: args.legend,
+        legend_outside: args.legend_outside,
+        hold_ms: args.hold_ms,
+        prompt_history: args.prompt_history.as_deref(),
+        visualize_points_only: args.visualize_points_only,
+        cross_fade_alpha: args.cross_fade_alpha,
+        show_zones: args.show_zones,
+        sequential_names: args.sequential_names,
+        jsonl_dedup: args.jsonl_dedup,
+        mask_encoding: args.mask_encoding,
+        dedup_frames: args.dedup_frames,
+        infer_every: args.infer_every,
+        infer_hz: args.infer_hz,
+        roi_file: args.roi_file.as_deref(),
+        export_prompt_vis_dir: args.export_prompt_vis_dir.as_deref(),
+        dump_tensors_dir: args.dump_tensors_dir.as_deref(),
+        control_socket: args.control_socket.as_deref(),
+        exclude_zone: args.exclude_zone.clone(),
+        zone_overlap: args.zone_overlap,
+        zones_file: args.zones_file.as_deref(),
+        deduplicate_masks_iou: args.deduplicate_masks_iou,
+        exclusive_group: args.exclusive_group.clone(),
+        exclusive_iou: args.exclusive_iou,
+        prompt_cooldown_frames: args.prompt_cooldown_frames,
+        stats_interval: args.stats_interval,
+        no_color: args.no_color,
+        save_best: args.save_best.as_deref(),
+        best_k: args.best_k,
+        save_crops: args.save_crops.as_deref(),
+        crop_pad: args.crop_pad,
+        split_by_prompt: args.split_by_prompt,
+        save_svg: args.save_svg.as_deref(),
+        save_dir_per_prompt: args.save_dir_per_prompt,
+        sync_annotate: false,
+        snapshot_interval: args.snapshot_interval,
+        timelapse_video: args.timelapse_video.as_deref(),
+        save_on_interval_secs: args.save_on_interval_secs,
+        save_on_interval_annotated: args.save_on_interval_annotated,
+        save_event_clips: args.save_event_clips.as_deref(),
+        clip_pre_seconds: args.clip_pre_seconds,
+        clip_post_seconds: args.clip_post_seconds,
+        ffmpeg_loglevel: args.ffmpeg_loglevel,
+        notify: args.notify,
+        notify_command: args.notify_command.as_deref(),
+        notify_cooldown_secs: args.notify_cooldown_secs,
+        notify_max_running: args.notify_max_running,
+        notify_timeout_secs: args.notify_timeout_secs,
+        watchdog_secs: args.watchdog_secs,
+        summary_json: args.summary_json.as_deref(),
+        compare: args.compare.as_deref(),
+        seed: args.seed,
+    })?;
+    frame_loop.run(
+        frames,
+        CameraSinks {
+            kafka,
+            shm,
+            capture: None,
+        },
+    )
 }
@@ -0,0 +1,255 @@
+//! V4L2 frame helpers: pixel format negotiation, pixel conversion and row strides.
+
+use anyhow::{Context, Result};
+
+/// Pixel formats tried, in order, when `--force-fourcc` is not given.
+pub const DEFAULT_FOURCCS: [[u8; 4]; 4] = [*b"YUYV", *b"MJPG", *b"NV12", *b"BGR3"];
+
+/// Parse a `--force-fourcc` name such as `MJPG` (shorter names are space-padded, as in V4L2).
+pub fn parse_fourcc(name: &str) -> Result<[u8; 4]> {
+    let bytes = name.as_bytes();
+    if bytes.is_empty() || bytes.len() > 4 || !name.is_ascii() {
+        anyhow::bail!("invalid FourCC `{name}` (expected up to 4 ASCII characters, e.g. MJPG)");
+    }
+    let mut repr = [b' '; 4];
+    repr[..bytes.len()].copy_from_slice(bytes);
+    Ok(repr)
+}
+
+/// First of `preferred` that appears in `supported`.
+pub fn pick_fourcc(supported: &[[u8; 4]], preferred: &[[u8; 4]]) -> Option<[u8; 4]> {
+    preferred.iter().find(|f| supported.contains(f)).copied()
+}
+
+/// First of `preferred` the camera lists in `enum_formats()`.
+#[cfg(all(target_os = "linux", feature = "v4l"))]
+pub fn negotiate_fourcc(dev: &v4l::Device, preferred: &[v4l::FourCC]) -> Result<v4l::FourCC> {
+    use v4l::video::Capture as _;
+
+    let supported: Vec<[u8; 4]> = dev
+        .enum_formats()
+        .context("failed to enumerate camera formats")?
+        .into_iter()
+        .map(|d| d.fourcc.repr)
+        .collect();
+    let preferred: Vec<[u8; 4]> = preferred.iter().map(|f| f.repr).collect();
+    let name = |f: &[u8; 4]| String::from_utf8_lossy(f).into_owned();
+    match pick_fourcc(&supported, &preferred) {
+        Some(fourcc) => Ok(v4l::FourCC::new(&fourcc)),
+        None => anyhow::bail!(
+            "camera supports none of {} (it offers {}); pick one with --force-fourcc",
+            preferred.iter().map(name).collect::<Vec<_>>().join(", "),
+            supported.iter().map(name).collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+fn clamp_u8(x: i32) -> u8 {
+    x.clamp(0, 255) as u8
+}
+
+/// BT.601 limited-range YUV to RGB.
+fn yuv_to_rgb(y: i32, u: i32, v: i32) -> [u8; 3] {
+    let c = y - 16;
+    let d = u - 128;
+    let e = v - 128;
+    [
+        clamp_u8((298 * c + 409 * e + 128) >> 8),
+        clamp_u8((298 * c - 100 * d - 208 * e + 128) >> 8),
+        clamp_u8((298 * c + 516 * d + 128) >> 8),
+    ]
+}
+
+/// Bytes per row of a tightly packed `width`-pixel line, for uncompressed formats.
+///
+/// `None` for compressed formats (MJPG/JPEG), where the driver's `bytesperline` has no meaning
+/// and must not trigger stride handling or warnings.
+pub fn packed_line_bytes(fourcc: &[u8; 4], width: u32) -> Option<u32> {
+    let bytes_per_px = match fourcc {
+        b"YUYV" | b"UYVY" => 2,
+        // NV12: the luma plane; the interleaved chroma plane uses the same stride.
+        b"GREY" | b"NV12" => 1,
+        b"RGB3" | b"BGR3" => 3,
+        _ => return None,
+    };
+    width.checked_mul(bytes_per_px)
+}
+
+/// Row stride to use for `fourcc`: the driver's `bytesperline` if it is at least the packed line
+/// size, else the packed size (some drivers report 0).
+pub fn effective_stride(fourcc: &[u8; 4], width: u32, bytes_per_line: u32) -> u32 {
+    match packed_line_bytes(fourcc, width) {
+        Some(packed) => bytes_per_line.max(packed),
+        None => 0,
+    }
+}
+
+/// Convert a YUYV (YUY2) frame whose rows are `stride` bytes apart into RGB8.
+///
+/// Padding past `width * 2` bytes at the end of each row is skipped.
+pub fn yuyv_to_rgb8(width: u32, height: u32, stride: u32, yuyv: &[u8]) -> Result<image::RgbImage> {
+    let line = width.checked_mul(2).context("width overflow")? as usize;
+    let stride = (stride as usize).max(line);
+    let expected_len = match height {
+        0 => 0,
+        h => stride
+            .checked_mul(h as usize - 1)
+            .and_then(|n| n.checked_add(line))
+            .context("width*height overflow")?,
+    };
+    if yuyv.len() < expected_len {
+        anyhow::bail!(
+            "YUYV buffer too small: got {}, expected {} (stride {stride})",
+            yuyv.len(),
+            expected_len
+        );
+    }
+
+    let mut rgb = vec![0u8; (width as usize) * (height as usize) * 3];
+    let mut di = 0usize;
+
+    for row in yuyv.chunks(stride).take(height as usize) {
+        for px in row[..line].chunks_exact(4) {
+            let y0 = px[0] as i32;
+            let u = px[1] as i32;
+            let y1 = px[2] as i32;
+            let v = px[3] as i32;
+
+            for y in [y0, y1] {
+                rgb[di..di + 3].copy_from_slice(&yuv_to_rgb(y, u, v));
+                di += 3;
+            }
+        }
+    }
+
+    image::RgbImage::from_raw(width, height, rgb).context("failed to construct RgbImage")
+}
+
+/// Convert an NV12 frame (luma plane, then interleaved half-resolution UV) into RGB8. Both planes
+/// use `stride` bytes per row.
+pub fn nv12_to_rgb8(width: u32, height: u32, stride: u32, nv12: &[u8]) -> Result<image::RgbImage> {
+    if !width.is_multiple_of(2) || !height.is_multiple_of(2) {
+        anyhow::bail!("NV12 needs even dimensions, got {width}x{height}");
+    }
+    let (w, h) = (width as usize, height as usize);
+    let stride = (stride as usize).max(w);
+    let expected_len = stride
+        .checked_mul(h + h / 2)
+        .context("width*height overflow")?;
+    if nv12.len() < expected_len {
+        anyhow::bail!(
+            "NV12 buffer too small: got {}, expected {expected_len} (stride {stride})",
+            nv12.len()
+        );
+    }
+    let (luma, chroma) = nv12.split_at(stride * h);
+    let mut rgb = vec![0u8; w * h * 3];
+    for (row, out) in rgb.chunks_exact_mut(w * 3).enumerate() {
+        let y_row = &luma[row * stride..][..w];
+        let uv_row = &chroma[(row / 2) * stride..][..w];
+        for (x, px) in out.chunks_exact_mut(3).enumerate() {
+            let uv = x & !1;
+            px.copy_from_slice(&yuv_to_rgb(
+                y_row[x] as i32,
+                uv_row[uv] as i32,
+                uv_row[uv + 1] as i32,
+            ));
+        }
+    }
+    image::RgbImage::from_raw(width, height, rgb).context("failed to construct RgbImage")
+}
+
+/// Convert a BGR3 (packed B, G, R) frame whose rows are `stride` bytes apart into RGB8.
+pub fn bgr3_to_rgb8(width: u32, height: u32, stride: u32, bgr: &[u8]) -> Result<image::RgbImage> {
+    let line = width.checked_mul(3).context("width overflow")? as usize;
+    let stride = (stride as usize).max(line);
+    let expected_len = match height {
+        0 => 0,
+        h => stride
+            .checked_mul(h as usize - 1)
+            .and_then(|n| n.checked_add(line))
+            .context("width*height overflow")?,
+    };
+    if bgr.len() < expected_len {
+        anyhow::bail!(
+            "BGR3 buffer too small: got {}, expected {expected_len} (stride {stride})",
+            bgr.len()
+        );
+    }
+    let mut rgb = Vec::with_capacity(line * height as usize);
+    for row in bgr.chunks(stride).take(height as usize) {
+        for px in row[..line].chunks_exact(3) {
+            rgb.extend_from_slice(&[px[2], px[1], px[0]]);
+        }
+    }
+    image::RgbImage::from_raw(width, height, rgb).context("failed to construct RgbImage")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: u32 = 6;
+    const H: u32 = 4;
+
+    fn packed_yuyv() -> Vec<u8> {
+        (0..W * H * 2).map(|i| (i * 37 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn strided_yuyv_matches_packed() {
+        let packed = packed_yuyv();
+        let stride = 32u32;
+        let mut strided = vec![0xAAu8; (stride * H) as usize];
+        for (row, src) in packed.chunks((W * 2) as usize).enumerate() {
+            let start = row * stride as usize;
+            strided[start..start + src.len()].copy_from_slice(src);
+        }
+
+        let expected = yuyv_to_rgb8(W, H, W * 2, &packed).unwrap();
+        let actual = yuyv_to_rgb8(W, H, stride, &strided).unwrap();
+        assert_eq!(actual.as_raw(), expected.as_raw());
+    }
+
+    #[test]
+    fn last_row_needs_no_padding() {
+        let stride = 16u32;
+        let buf = vec![128u8; (stride * (H - 1) + W * 2) as usize];
+        assert!(yuyv_to_rgb8(W, H, stride, &buf).is_ok());
+        assert!(yuyv_to_rgb8(W, H, stride, &buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn negotiation_follows_preference_order() {
+        let supported = [*b"MJPG", *b"NV12"];
+        assert_eq!(pick_fourcc(&supported, &DEFAULT_FOURCCS), Some(*b"MJPG"));
+        assert_eq!(pick_fourcc(&supported, &[*b"YUYV"]), None);
+        assert_eq!(parse_fourcc("GREY").unwrap(), *b"GREY");
+        assert_eq!(parse_fourcc("Y8").unwrap(), *b"Y8  ");
+        assert!(parse_fourcc("YUYV2").is_err());
+    }
+
+    #[test]
+    fn nv12_and_bgr3_decode() {
+        // 2x2 NV12, mid-gray luma with neutral chroma, padded to a 4-byte stride.
+        let nv12 = [128, 128, 0, 0, 128, 128, 0, 0, 128, 128, 0, 0];
+        let gray = nv12_to_rgb8(2, 2, 4, &nv12).unwrap();
+        assert!(gray.pixels().all(|p| p.0 == [130, 130, 130]));
+        assert!(nv12_to_rgb8(2, 2, 4, &nv12[..11]).is_err());
+
+        let bgr = [1, 2, 3, 4, 5, 6];
+        assert_eq!(
+            bgr3_to_rgb8(2, 1, 6, &bgr).unwrap().as_raw(),
+            &[3, 2, 1, 6, 5, 4]
+        );
+    }
+
+    #[test]
+    fn stride_ignored_for_compressed_formats() {
+        assert_eq!(packed_line_bytes(b"MJPG", 640), None);
+        assert_eq!(effective_stride(b"MJPG", 640, 0), 0);
+        assert_eq!(effective_stride(b"YUYV", 640, 0), 1280);
+        assert_eq!(effective_stride(b"YUYV", 640, 1344), 1344);
+        assert_eq!(effective_stride(b"GREY", 640, 704), 704);
+    }
+}
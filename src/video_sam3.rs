@@ -1,28 +1,81 @@
 use anyhow::{Context, Result};
 use argh::FromArgs;
-use std::io::{IsTerminal, Read, Write};
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, Stdio};
 use std::time::{Duration, Instant};
-use usls::{
-    Annotator, Config, Task, Viewer,
-    models::{SAM3, Sam3Prompt},
+use usls::{Annotator, Viewer, models::Sam3Prompt};
+
+use crate::annotate_only::{DetectionLog, Overlay, y_from_detections};
+use crate::benchmark::{self, parse_list, run_matrix};
+use crate::build_info::handle_version_flag;
+use crate::capture::{FfmpegRawRgb24Prefetch, read_raw_frame};
+use crate::color::{OutColor, SourceColor};
+use crate::common::{
+    AnnotationStyle, Capture, ChannelValues, CheckReport, DEFAULT_CONF, MASK_TO_JSON_PATH,
+    ModelOptions, SceneCutDetector, VideoSource, build_annotator, check_dir_writable,
+    check_mask_to_json, default_save_base, dummy_forward, log_writer, warmup_with_image,
 };
+use crate::debug::{differing_pixels, frame_checksum};
+use crate::device::{ExecutionProvider, auto_select_device_and_ep, ep_flag};
+use crate::dwell::{DwellTracker, NamedZone, ZoneEvent};
+use crate::ffmpeg_log::{FfmpegLogLevel, StderrDrain, loglevel_args};
+use crate::frame_loop::{FrameHooks, FrameLoop, LoopArgs, Next, parse_prompts};
+use crate::groups::{GroupStyle, PromptGroups, split_group_tags};
+use crate::kafka_sink::{KafkaSink, now_unix_ms};
+use crate::logging::{HeartbeatLogger, fmt_hms, init_logging};
+use crate::matte::{MatteMode, composite_rgb, composite_rgba, feather, union_mask};
+use crate::notify::NotifyKind;
+use crate::output::{
+    Detection, FfmpegSubtitleWriter, FfmpegVideoWriter, FrameDecimator, FrameRecord, GstSinkWriter,
+    MaskEncoding, SubtitleFormat, SubtitleWriter, json_schema, mask_polygons,
+};
+use crate::pipeline::Pipeline;
+use crate::postprocess::ExclusiveGroup;
+use crate::preprocess::{ToneAdjust, ToneParams, ToneTarget};
+use crate::prompts::with_mirrored;
+use crate::quality::{FrameQuality, QualityGate, QualityIssue, QualityThresholds};
+use crate::redact::{InputUrl, ScrubbingMakeWriter, scrub_credentials};
+use crate::repro::next_run_dir;
+use crate::session::{SessionArgs, SessionFile};
+use crate::shm_sink::ShmSink;
+use crate::tiling::DEFAULT_TILE_OVERLAP;
+use crate::viz::{Stack, draw_prompt_state, side_by_side, to_rgb};
+use crate::zones::Zone;
 
 #[derive(FromArgs)]
 /// SAM3 video-file inference (text prompts via `usls`).
 pub struct Args {
-    /// input video path (mp4, mov, etc.; decoded via `ffmpeg`)
+    /// input video paths or URLs (mp4, mov, rtsp://..., etc.; decoded via `ffmpeg`), processed
+    /// back-to-back as one stream. `${VAR}` is expanded from the environment, so credentials can
+    /// stay out of the process list
     #[argh(positional)]
-    input: String,
+    input: Vec<String>,
+
+    /// comma-separated inputs, appended after the positional ones
+    #[argh(option)]
+    inputs: Option<String>,
+
+    /// with several inputs and --task sam3-tracker: reload the model at each file boundary to clear the tracker state
+    #[argh(switch)]
+    reset_per_input: bool,
 
     /// task (sam3-image, sam3-tracker)
     #[argh(option, default = "String::from(\"sam3-image\")")]
     task: String,
 
-    /// device (cpu:0, cuda:0, etc.)
-    #[argh(option, default = "String::from(\"cpu:0\")")]
-    device: String,
+    /// device (cpu:0, cuda:0, etc., or auto to pick one for this machine; default: cpu:0)
+    #[argh(option)]
+    device: Option<String>,
+
+    /// execution provider: cpu, cuda, tensorrt, directml, coreml or openvino (default: from
+    /// --device)
+    #[argh(option)]
+    execution_provider: Option<ExecutionProvider>,
+
+    /// short for --execution-provider; errors if that provider is not compiled in
+    #[argh(option)]
+    ep: Option<ExecutionProvider>,
 
     /// dtype (q4f16, fp16, fp32, etc.)
     #[argh(option, default = "String::from(\"q4f16\")")]
@@ -36,25 +89,234 @@ pub struct Args {
     #[argh(option)]
     height: Option<u32>,
 
+    /// file with `user:password` (first line) added to the input URL right before ffmpeg runs
+    #[argh(option)]
+    input_credentials_file: Option<String>,
+
     /// override playback FPS (default: probed from input, fallback 30)
     #[argh(option)]
     fps: Option<f32>,
 
+    /// have ffmpeg decode only this many frames per second of input (fps filter); frame
+    /// indices, progress and timestamps then count decoded frames
+    #[argh(option)]
+    decode_fps: Option<f32>,
+
+    /// decode up to N frames ahead on a reader thread so the loop rarely waits on ffmpeg
+    /// (default: 4, 0 reads inline)
+    #[argh(option)]
+    prefetch_depth: Option<usize>,
+
+    /// old name of --prefetch-depth
+    #[argh(option)]
+    prefetch: Option<usize>,
+
+    /// annotate frames for --save-video/--save-frames/--gst-sink/--shm and
+    /// --save-dir-per-prompt on the inference loop instead of on worker threads
+    #[argh(switch)]
+    sync_annotate: bool,
+
     /// prompts (repeatable): `-p shoe` or `-p \"pos:480,290,110,360\"`
     #[argh(option, short = 'p')]
     prompt: Vec<String>,
 
+    /// also prompt with the left-right mirror image of every box prompt (`pos:`/`neg:`), for
+    /// symmetric parts
+    #[argh(switch)]
+    mirror_prompt: bool,
+
+    /// run the multi-stage detection pipeline in this YAML file instead of -p prompts
+    #[argh(option)]
+    pipeline: Option<String>,
+
+    /// infer on overlapping <n> x <n> tiles of each frame and merge their mask polygons, for
+    /// frames too large for one pass (e.g. a 50 MP still image)
+    #[argh(option)]
+    tile_size: Option<u32>,
+
+    /// with --tile-size: pixels adjacent tiles overlap (default: 64)
+    #[argh(option)]
+    tile_overlap: Option<u32>,
+
     /// confidence threshold (default: 0.5)
-    #[argh(option, default = "0.5")]
-    conf: f32,
+    #[argh(option)]
+    conf: Option<f32>,
 
     /// show mask
     #[argh(option, default = "false")]
     show_mask: bool,
 
-    /// run inference every N frames (set 0 to disable)
+    /// skip inference and only draw the prompt boxes/points (for positioning prompts)
+    #[argh(switch)]
+    visualize_points_only: bool,
+
+    /// sam3-tracker: reset tracker state when the luma-histogram distance (0..1) between inference
+    /// frames exceeds this, e.g. 0.4
+    #[argh(option)]
+    detect_scene_cut: Option<f32>,
+
+    /// reuse the previous inference when a frame is byte-identical to the last inferred one
+    #[argh(switch)]
+    dedup_frames: bool,
+
+    /// skip the frame-quality gate (black, flat and decode-error frames are then inferred too)
+    #[argh(switch)]
+    no_quality_gate: bool,
+
+    /// quality gate: a frame with every channel at or below this is black (default: 16)
+    #[argh(option, default = "16")]
+    quality_black_level: u8,
+
+    /// quality gate: a frame whose channel values span at most this is uniform (default: 4)
+    #[argh(option, default = "4")]
+    quality_uniform_range: u8,
+
+    /// quality gate: frames with a lower luma standard deviation are suspect (default: 2.0)
+    #[argh(option, default = "2.0")]
+    quality_min_stddev: f32,
+
+    /// quality gate: frames flagged after each ffmpeg decode error (default: 3)
     #[argh(option, default = "3")]
-    infer_every: u32,
+    quality_decode_window: u64,
+
+    /// annotation style: minimal, full, none (default: full)
+    #[argh(option, default = "AnnotationStyle::Full")]
+    annotation_style: AnnotationStyle,
+
+    /// style for prompts tagged `-p "text#grp=<name>"`, as
+    /// <name>=thickness:N,color:RRGGBB,masks:on|off (repeatable)
+    #[argh(option)]
+    group_style: Vec<GroupStyle>,
+
+    /// fade the last annotation out between inferences by this much per frame (0 = hold, 1 = instant)
+    #[argh(option, default = "0.0")]
+    cross_fade_alpha: f32,
+
+    /// keep detections missing from the latest inference drawn in the window for up to N ms,
+    /// fading out (0 = off); saved frames and records are not affected
+    #[argh(option, default = "0")]
+    hold_ms: u64,
+
+    /// run inference every N frames (default 3, set 0 to disable)
+    #[argh(option)]
+    infer_every: Option<u32>,
+
+    /// run inference at most this many times per second, timed on the wall clock instead of
+    /// counting frames (exclusive with --infer-every)
+    #[argh(option)]
+    infer_hz: Option<f32>,
+
+    /// show a per-prompt legend (swatch, count, rolling average) in the window; toggle with L
+    #[argh(switch)]
+    legend: bool,
+
+    /// start with the legend hidden even when the --session file has it shown
+    #[argh(switch)]
+    no_legend: bool,
+
+    /// draw the legend on a panel beside the frame instead of over it
+    #[argh(switch)]
+    legend_outside: bool,
+
+    /// suppress detections whose box center falls inside x,y,w,h (output pixels; repeatable)
+    #[argh(option)]
+    exclude_zone: Vec<Zone>,
+
+    /// black out everything outside the polygons in this JSON file before inference
+    /// (`{"polygons": [[[x, y], ...], ...]}`, output pixels); display and saves stay unmasked
+    #[argh(option)]
+    roi_file: Option<String>,
+
+    /// gamma applied to each frame before inference, as a lookup table; above 1 lifts dark
+    /// frames (default: 1)
+    #[argh(option, default = "1.0")]
+    gamma: f32,
+
+    /// brightness offset applied with --gamma, -1 to 1 of full scale (default: 0)
+    #[argh(option, default = "0.0")]
+    brightness: f32,
+
+    /// contrast factor around mid-gray applied with --gamma (default: 1)
+    #[argh(option, default = "1.0")]
+    contrast: f32,
+
+    /// stretch each frame's levels (0.5th to 99.5th percentile) to the full range before
+    /// --gamma/--contrast/--brightness
+    #[argh(switch)]
+    auto_levels: bool,
+
+    /// apply --gamma/--brightness/--contrast/--auto-levels only to what is shown and saved; the
+    /// model sees the raw frame
+    #[argh(switch)]
+    preprocess_display_only: bool,
+
+    /// apply --gamma/--brightness/--contrast/--auto-levels only to the model input; display and
+    /// saves stay raw
+    #[argh(switch)]
+    preprocess_infer_only: bool,
+
+    /// with --exclude-zone: suppress when at least this fraction of the box is covered instead
+    #[argh(option)]
+    zone_overlap: Option<f32>,
+
+    /// named zone for per-track dwell time, name=x,y,w,h in output pixels (repeatable); writes
+    /// tracks.csv to the save dir
+    #[argh(option)]
+    zone: Vec<NamedZone>,
+
+    /// with --zone: a track is inside when at least this fraction of its box is covered, instead
+    /// of by its box center
+    #[argh(option)]
+    dwell_overlap: Option<f32>,
+
+    /// with --zone: smooth each track's box with a constant-velocity Kalman filter and move it
+    /// along between inferences (--infer-every), so zone crossings are timed on every frame
+    #[argh(switch)]
+    kalman: bool,
+
+    /// after a prompt is detected, drop its detections for the next N frames (default: 0 = off)
+    #[argh(option, default = "0")]
+    prompt_cooldown_frames: u64,
+
+    /// of two detections whose boxes overlap with IoU above this, drop the less confident one
+    /// (default: 0 = off)
+    #[argh(option, default = "0.0")]
+    deduplicate_masks_iou: f32,
+
+    /// mutually exclusive prompts, `<name>=<prompt>,<prompt>,...` (repeatable): of overlapping
+    /// detections from different prompts of a group only the most confident is kept
+    #[argh(option)]
+    exclusive_group: Vec<ExclusiveGroup>,
+
+    /// with --exclusive-group: box IoU above which detections compete (default: 0.5)
+    #[argh(option, default = "0.5")]
+    exclusive_iou: f32,
+
+    /// load exclude zones from this file (one x,y,w,h per line); runtime edits are saved back
+    #[argh(option)]
+    zones_file: Option<String>,
+
+    /// draw the exclude zones as hatched rectangles in the window
+    #[argh(switch)]
+    show_zones: bool,
+
+    /// log per-label detection stats every N seconds
+    #[argh(option)]
+    stats_interval: Option<f32>,
+
+    /// restore prompts, conf, annotation preset, legend and zones from this JSON file; W and the
+    /// end of the run save them back (flags given on the command line win)
+    #[argh(option)]
+    session: Option<String>,
+
+    /// log a heartbeat line (frame, position, speed, memory) every N seconds, even with the
+    /// progress display off (default: 60; 0 = off)
+    #[argh(option, default = "60.0")]
+    heartbeat_secs: f64,
+
+    /// disable ANSI colors in terminal output
+    #[argh(switch)]
+    no_color: bool,
 
     /// window scale (1.0 = native resolution)
     #[argh(option, default = "1.0")]
@@ -72,43 +334,620 @@ pub struct Args {
     #[argh(option, default = "true")]
     trt_timing_cache: bool,
 
+    /// before opening the input, run the model on this image to warm up ONNX Runtime
+    #[argh(option)]
+    warmup_image: Option<String>,
+
+    /// with --warmup-image: number of warm-up forwards (default: 3)
+    #[argh(option, default = "3")]
+    warmup_runs: u32,
+
+    /// retry model init N times with backoff when the weight download fails (default: 0)
+    #[argh(option, default = "0")]
+    retry_init: u32,
+
+    /// model input size (square side; multiple of 14 in 336..=1008, default 1008). Lower is faster but less accurate
+    #[argh(option)]
+    imgsz: Option<u32>,
+
+    /// per-channel normalization mean as R,G,B (default: model preset)
+    #[argh(option)]
+    image_mean: Option<ChannelValues>,
+
+    /// per-channel normalization std as R,G,B (default: model preset)
+    #[argh(option)]
+    image_std: Option<ChannelValues>,
+
     /// save directory (default: ./runs/<model-spec>/)
     #[argh(option)]
     save_dir: Option<String>,
 
+    /// save into a new numbered <save-dir>/run-NNNN/ and name saved frames by frame index
+    #[argh(switch)]
+    sequential_names: bool,
+
+    /// on every inference also save one annotated frame per prompt with detections, showing only
+    /// that prompt's results, under <save-dir>/<prompt>/
+    #[argh(switch)]
+    save_dir_per_prompt: bool,
+
+    /// seed recorded in the run summary; nothing in the crate is random, so it only labels the run
+    #[argh(option)]
+    seed: Option<u64>,
+
+    /// write a run summary (configuration, versions, model hashes, metrics) as JSON to path
+    #[argh(option)]
+    summary_json: Option<String>,
+
+    /// at exit, diff this run's summary against a --summary-json file from another run
+    #[argh(option)]
+    compare: Option<String>,
+
+    /// save one output frame to the save directory every N seconds (media time)
+    #[argh(option)]
+    snapshot_interval: Option<f32>,
+
+    /// save a frame to <save-dir>/interval/ every N seconds (wall time), detections or not
+    #[argh(option)]
+    save_on_interval_secs: Option<f32>,
+
+    /// with --save-on-interval-secs: save the annotated frame (true) or the raw frame (false)
+    #[argh(option, default = "true")]
+    save_on_interval_annotated: bool,
+
+    /// notify when a prompt label enters: bell, desktop or command
+    #[argh(option)]
+    notify: Option<NotifyKind>,
+
+    /// with --notify command: shell command to run; gets SAM3_PROMPT, SAM3_CONFIDENCE and
+    /// SAM3_SNAPSHOT in its environment
+    #[argh(option)]
+    notify_command: Option<String>,
+
+    /// with --notify: seconds before the same label can notify again (default: 30)
+    #[argh(option, default = "30.0")]
+    notify_cooldown_secs: f32,
+
+    /// with --notify command: commands allowed to run at once; further enters are skipped
+    /// (default: 2)
+    #[argh(option, default = "2")]
+    notify_max_running: usize,
+
+    /// with --notify command: kill a command after this many seconds (default: 10)
+    #[argh(option, default = "10.0")]
+    notify_timeout_secs: f32,
+
+    /// write a clip around every detection event (prompt enters .. leaves) into this directory
+    #[argh(option)]
+    save_event_clips: Option<String>,
+
+    /// ffmpeg log level (quiet, error, warning, info, verbose, ...; default error); ffmpeg's
+    /// messages are logged as they arrive
+    #[argh(option, default = "FfmpegLogLevel::Error")]
+    ffmpeg_loglevel: FfmpegLogLevel,
+
+    /// with --save-event-clips: seconds of pre-roll kept before an event (default: 5)
+    #[argh(option, default = "5.0")]
+    clip_pre_seconds: f32,
+
+    /// with --save-event-clips: seconds recorded after the last leave (default: 5)
+    #[argh(option, default = "5.0")]
+    clip_post_seconds: f32,
+
+    /// assemble the interval snapshots into a time-lapse video at path on exit
+    #[argh(option)]
+    timelapse_video: Option<String>,
+
     /// save annotated video to path (disables display window)
     #[argh(option)]
     save_video: Option<String>,
+
+    /// redraw the detections of a --json-out file over the input with the current annotation
+    /// settings into --save-video, without loading the model
+    #[argh(option)]
+    annotate_only: Option<String>,
+
+    /// show the original frame next to the annotated one (window, --save-video, --gst-sink,
+    /// saved frames)
+    #[argh(switch)]
+    side_by_side: bool,
+
+    /// with --side-by-side: horizontal (default, double width) or vertical (double height)
+    #[argh(option, default = "Stack::Horizontal")]
+    stack: Stack,
+
+    /// also pipe annotated frames into this GStreamer pipeline, e.g. "videoconvert ! autovideosink"
+    #[argh(option)]
+    gst_sink: Option<String>,
+
+    /// publish every output frame as a JPEG message to these Kafka brokers (host:port,...; needs
+    /// a build with --features kafka)
+    #[argh(option)]
+    kafka_brokers: Option<String>,
+
+    /// with --kafka-brokers: topic to publish frames to
+    #[argh(option)]
+    kafka_topic: Option<String>,
+
+    /// with --kafka-brokers: JPEG quality of published frames, 1-100 (default: 70)
+    #[argh(option, default = "70")]
+    kafka_jpeg_quality: u8,
+
+    /// publish every output frame, uncompressed, to the shared-memory ring buffer
+    /// /dev/shm/<name> for a local reader (Linux)
+    #[argh(option)]
+    shm: Option<String>,
+
+    /// with --shm: remove an existing /dev/shm/<name> (e.g. left by a crashed run) instead of
+    /// failing
+    #[argh(switch)]
+    shm_replace: bool,
+
+    /// with --save-video: encode at this rate by dropping frames evenly (default: input rate)
+    #[argh(option)]
+    output_fps: Option<f32>,
+
+    /// burn the active prompts and conf into the top-left corner of every output frame
+    #[argh(switch)]
+    annotate_prompt_state: bool,
+
+    /// write per-inference detection summaries as an SRT subtitle file
+    #[argh(option)]
+    save_srt: Option<String>,
+
+    /// write detection summaries and boxes as an ASS subtitle file
+    #[argh(option)]
+    save_ass: Option<String>,
+
+    /// with --save-video: stream-copy the input into an .mkv with a WebVTT detection track (no re-encode)
+    #[argh(switch)]
+    codec_copy: bool,
+
+    /// color matrix of --save-video: bt601, bt709 or auto (the input's; default)
+    #[argh(option, default = "OutColor::Auto")]
+    out_color: OutColor,
+
+    /// with --save-video: mux the source's audio track into the annotated video
+    #[argh(switch)]
+    preserve_audio: bool,
+
+    /// write per-frame detections as JSON Lines to path
+    #[argh(option)]
+    json_out: Option<String>,
+
+    /// with --json-out: skip a frame's record when its detections match the last written record
+    /// (same labels, box IoU >= this, confidence within 0.05); the file then has gaps
+    #[argh(option)]
+    jsonl_dedup: Option<f32>,
+
+    /// geometry only: implies --no-display and --json-out /dev/stdout, and skips annotation
+    /// entirely (logs go to stderr)
+    #[argh(switch)]
+    mask_to_json: bool,
+
+    /// log as JSON Lines (one object per event, with the current frame_idx)
+    #[argh(switch)]
+    log_json: bool,
+
+    /// debug: check that seeking with `ffmpeg -ss` to --start-frame yields the same frame as
+    /// decoding up to it, then exit
+    #[argh(switch)]
+    verify_seek_accuracy: bool,
+
+    /// with --verify-seek-accuracy: 0-based frame to seek to (default: 0)
+    #[argh(option, default = "0")]
+    start_frame: u64,
+
+    /// masks in --json-out records: polygon, rle (uncompressed COCO RLE) or none (default)
+    #[argh(option, default = "MaskEncoding::None")]
+    mask_encoding: MaskEncoding,
+
+    /// run headless (no display window or keyboard controls)
+    #[argh(switch)]
+    no_display: bool,
+
+    /// matte output instead of annotation: transparent, blur or color:#rrggbb
+    #[argh(option)]
+    matte: Option<MatteMode>,
+
+    /// feather matte edges by N pixels (default: 0)
+    #[argh(option, default = "0")]
+    matte_feather: u32,
+
+    /// save every output frame as PNG into this directory (RGBA with `--matte transparent`)
+    #[argh(option)]
+    save_frames: Option<String>,
+
+    /// dump a reconstruction of the vision encoder input of every inference as
+    /// `<frame>_vision-encoder-approx-input.npy` into this directory
+    #[argh(option)]
+    dump_tensors_dir: Option<String>,
+
+    /// before each inference, save every prompt's boxes (red) and points (green) drawn on the
+    /// frame as `<frame>_prompt_<i>.jpg` into this directory
+    #[argh(option)]
+    export_prompt_vis_dir: Option<String>,
+
+    /// file that prompts entered with P are appended to and recalled from (`!!`, `!N`, `!text`;
+    /// H lists it); default: ~/.cache/sam3/prompt_history
+    #[argh(option)]
+    prompt_history: Option<String>,
+
+    /// write every inferred frame as an SVG overlay (boxes, polygons, labels) over a JPEG
+    /// background into this directory, for documentation figures
+    #[argh(option)]
+    save_svg: Option<String>,
+
+    /// at exit, write the top --best-k annotated frames per prompt (by confidence) here
+    #[argh(option)]
+    save_best: Option<String>,
+
+    /// with --save-best: frames to keep per prompt (default: 5)
+    #[argh(option, default = "5")]
+    best_k: usize,
+
+    /// save every detected instance as its own crop (plus crops.jsonl) into this directory
+    #[argh(option)]
+    save_crops: Option<String>,
+
+    /// with --save-crops: grow each crop by N pixels on every side (default: 0)
+    #[argh(option, default = "0")]
+    crop_pad: u32,
+
+    /// with --save-crops: put crops in one subdirectory per prompt label
+    #[argh(switch)]
+    split_by_prompt: bool,
+
+    /// unix socket for runtime commands (prompt, conf, infer-every, snapshot, ping)
+    #[argh(option)]
+    control_socket: Option<String>,
+
+    /// validate ffmpeg, input, model and save paths, then exit without running
+    #[argh(switch)]
+    check: bool,
+
+    /// print the JSON Schema of --json-out records (detections, COCO RLE masks) to stdout and
+    /// exit
+    #[argh(switch)]
+    print_json_schema: bool,
+
+    /// benchmark these dtypes (e.g. q4f16,fp16,fp32) on the first frames, print a table and exit
+    #[argh(option)]
+    benchmark_matrix: Option<String>,
+
+    /// with --benchmark-matrix: devices to benchmark, comma-separated (default: --device)
+    #[argh(option)]
+    benchmark_devices: Option<String>,
+
+    /// with --benchmark-matrix: frames decoded and run per combination (default: 50)
+    #[argh(option, default = "50")]
+    benchmark_frames: u32,
+
+    /// with --benchmark-matrix: also write the table to path (.csv for CSV, else markdown)
+    #[argh(option)]
+    benchmark_out: Option<String>,
 }
 
-fn parse_prompts(raw: &[String]) -> Result<Vec<Sam3Prompt>> {
-    if raw.is_empty() {
-        anyhow::bail!("No prompt. Use -p \"text\" or -p \"visual;pos:x,y,w,h\"");
+/// Annotated frames queued for the worker threads before the loop waits for them.
+const ANNOTATE_QUEUE: usize = 8;
+
+/// `--prefetch-depth` when not given.
+const DEFAULT_PREFETCH_DEPTH: usize = 4;
+
+/// The sinks that take every output frame in order.
+struct StreamSinks {
+    /// `--save-frames`, unless a transparent matte writes RGBA frames there itself.
+    frames_dir: Option<PathBuf>,
+    encoder: Option<FfmpegVideoWriter>,
+    decimator: Option<FrameDecimator>,
+    gst: Option<GstSinkWriter>,
+    kafka: Option<KafkaSink>,
+    shm: Option<ShmSink>,
+}
+
+impl StreamSinks {
+    fn is_empty(&self) -> bool {
+        self.frames_dir.is_none()
+            && self.encoder.is_none()
+            && self.gst.is_none()
+            && self.kafka.is_none()
+            && self.shm.is_none()
+    }
+
+    fn write(&mut self, frame_idx: u64, display: &usls::Image) -> Result<()> {
+        if let Some(dir) = &self.frames_dir {
+            display.save(dir.join(format!("{frame_idx:08}.png")))?;
+        }
+        if let Some(encoder) = self.encoder.as_mut()
+            && self.decimator.as_mut().is_none_or(|d| d.keep(frame_idx))
+        {
+            encoder.write_frame(display)?;
+        }
+        if let Some(sink) = self.gst.as_mut() {
+            sink.write_frame(display)?;
+        }
+        if let Some(kafka) = self.kafka.as_mut() {
+            kafka.send_frame(display, frame_idx, now_unix_ms())?;
+        }
+        if let Some(shm) = self.shm.as_mut() {
+            shm.write_frame(display, frame_idx, now_unix_ms())?;
+        }
+        Ok(())
     }
-    raw.iter()
-        .map(|s| s.parse())
-        .collect::<std::result::Result<Vec<_>, _>>()
-        .map_err(|e| anyhow::anyhow!("{}", e))
 }
 
-fn prompt_update_loop() -> Result<Option<Vec<Sam3Prompt>>> {
-    eprint!("New prompt(s) (split with `|`, empty keeps current): ");
-    std::io::stderr().flush().ok();
-    let mut line = String::new();
-    std::io::stdin()
-        .read_line(&mut line)
-        .context("failed to read prompt from stdin")?;
-    let line = line.trim();
-    if line.is_empty() {
-        return Ok(None);
+/// The inputs `video-sam3` plays back to back and what it makes of them beyond the shared
+/// outputs: the stream sinks, subtitles, dwell tracks, the matte and the quality gate.
+struct VideoOutputs<'a> {
+    args: &'a Args,
+    inputs: &'a [(InputUrl, VideoInfo)],
+    input_idx: usize,
+    /// Decoder settings for the next input.
+    out_size: (u32, u32),
+    fps: f32,
+    scale: bool,
+    prefetch_depth: usize,
+    /// The last frame read.
+    frame_idx: u64,
+    total_frames: Option<u64>,
+    progress: Progress,
+    heartbeat: HeartbeatLogger,
+    quality_gate: Option<QualityGate>,
+    quality_issue: Option<QualityIssue>,
+    dwell: Option<DwellTracker>,
+    zone_events: Vec<ZoneEvent>,
+    /// The last inferred mask; between inferences it is reused so the matte doesn't strobe.
+    matte_mask: Option<image::GrayImage>,
+    save_frames_dir: Option<PathBuf>,
+    sinks: StreamSinks,
+    subtitles: Vec<SubtitleWriter>,
+    detection_track: Option<FfmpegSubtitleWriter>,
+    save_video_path: Option<PathBuf>,
+    save_base: PathBuf,
+}
+
+impl FrameHooks<FfmpegRawRgb24> for VideoOutputs<'_> {
+    fn next_frame(&mut self, frames: &mut Capture<FfmpegRawRgb24>) -> Result<Next> {
+        let Some((frame_idx, img)) = frames.next_frame()? else {
+            if self.input_idx + 1 == self.inputs.len() {
+                return Ok(Next::End);
+            }
+            self.input_idx += 1;
+            let (next, info) = &self.inputs[self.input_idx];
+            tracing::info!(
+                "Input {}/{} at frame {}: {next}",
+                self.input_idx + 1,
+                self.inputs.len(),
+                self.frame_idx + 1
+            );
+            let (out_w, out_h) = self.out_size;
+            let next_scale = self.scale || (info.width, info.height) != (out_w, out_h);
+            let next = FfmpegRawRgb24::spawn(
+                next,
+                out_w,
+                out_h,
+                self.fps,
+                next_scale,
+                info.color,
+                self.args.decode_fps,
+                self.args.ffmpeg_loglevel,
+            )?
+            .with_prefetch(self.prefetch_depth)?;
+            std::mem::replace(frames.source_mut(), next).finish()?;
+            if let Some(gate) = self.quality_gate.as_mut() {
+                gate.source_changed();
+            }
+            return Ok(if self.args.reset_per_input {
+                Next::Restart
+            } else {
+                Next::Retry
+            });
+        };
+        self.frame_idx = frame_idx;
+        self.progress.maybe_update(frame_idx);
+        self.heartbeat.poll(frame_idx, self.total_frames, self.fps);
+        Ok(Next::Frame(frame_idx, img))
+    }
+
+    /// Decode damage is judged on the decoded frame, before any tone adjustment.
+    fn inferable(
+        &mut self,
+        frames: &mut Capture<FfmpegRawRgb24>,
+        frame_idx: u64,
+        img: &usls::Image,
+    ) -> bool {
+        self.quality_issue = match self.quality_gate.as_mut() {
+            Some(gate) => {
+                let decode_errors = frames.source_mut().decode_errors();
+                gate.check(frame_idx, img, decode_errors)
+            }
+            None => None,
+        };
+        self.quality_issue.is_none()
+    }
+
+    fn result(&mut self, frame_idx: u64, t: f64, y: Option<&usls::Y>) -> Result<()> {
+        let Some(y) = y else {
+            self.zone_events = match self.dwell.as_mut() {
+                Some(dwell) => dwell.predict(t),
+                None => Vec::new(),
+            };
+            return Ok(());
+        };
+        self.zone_events = match self.dwell.as_mut() {
+            Some(dwell) => dwell.update(t, y),
+            None => Vec::new(),
+        };
+        if self.args.matte.is_some() {
+            let (out_w, out_h) = self.out_size;
+            self.matte_mask = Some(feather(
+                union_mask(y, out_w, out_h),
+                self.args.matte_feather,
+            ));
+        }
+        for subs in self.subtitles.iter_mut() {
+            subs.record(frame_idx, Detection::from_y(y))?;
+        }
+        if let Some(track) = self.detection_track.as_mut() {
+            let cue = serde_json::json!({
+                "frame_idx": frame_idx,
+                "detections": Detection::from_y(y),
+                "polygons": mask_polygons(y),
+            });
+            track.record(frame_idx, cue.to_string())?;
+        }
+        Ok(())
     }
-    let parts: Vec<String> = line
-        .split('|')
-        .map(str::trim)
-        .filter(|s| !s.is_empty())
-        .map(|s| s.to_string())
+
+    fn record(&mut self, record: &mut FrameRecord) {
+        record.quality = self.quality_issue.map(|_| FrameQuality::Suspect);
+        record.quality_issue = self.quality_issue;
+        record.zone_events = std::mem::take(&mut self.zone_events);
+    }
+
+    fn compose(
+        &mut self,
+        frame_idx: u64,
+        img: &usls::Image,
+        display: &usls::Image,
+        labels: &[String],
+        conf: f32,
+    ) -> Result<Option<usls::Image>> {
+        let matted = match self.args.matte {
+            Some(mode) => {
+                let raw = to_rgb(img)?;
+                let (out_w, out_h) = self.out_size;
+                let mask = self
+                    .matte_mask
+                    .get_or_insert_with(|| image::GrayImage::new(out_w, out_h));
+                if let Some(dir) = &self.save_frames_dir
+                    && mode.is_transparent()
+                {
+                    let path = dir.join(format!("{frame_idx:08}.png"));
+                    composite_rgba(&raw, mask)
+                        .save(&path)
+                        .with_context(|| format!("failed to save frame: {}", path.display()))?;
+                }
+                Some(usls::Image::from(composite_rgb(&raw, mask, mode)))
+            }
+            None => None,
+        };
+        let display = matted.as_ref().unwrap_or(display);
+        let with_state = if self.args.annotate_prompt_state {
+            Some(draw_prompt_state(display, labels, conf)?)
+        } else {
+            None
+        };
+        let display = with_state.as_ref().unwrap_or(display);
+        let split = if self.args.side_by_side {
+            Some(side_by_side(img, display, self.args.stack)?)
+        } else {
+            None
+        };
+        Ok(split.or(with_state).or(matted))
+    }
+
+    fn write(&mut self, frame_idx: u64, display: &usls::Image) -> Result<()> {
+        self.sinks.write(frame_idx, display)
+    }
+
+    fn finish(
+        mut self,
+        frames: Capture<FfmpegRawRgb24>,
+        stopped: bool,
+    ) -> Result<Vec<(&'static str, serde_json::Value)>> {
+        if let Some(encoder) = self.sinks.encoder {
+            encoder.finish()?;
+        }
+        if let Some(sink) = self.sinks.gst {
+            sink.finish()?;
+        }
+        for subs in self.subtitles {
+            subs.finish(self.frame_idx)?;
+        }
+        if let (Some(track), Some(path)) = (self.detection_track, &self.save_video_path) {
+            let (input, _) = &self.inputs[0];
+            track.finish(input, path, self.frame_idx)?;
+        }
+        self.progress.finish(self.frame_idx);
+        if let Some(kafka) = self.sinks.kafka {
+            kafka.finish();
+        }
+        if stopped {
+            drop(frames);
+        } else {
+            frames.into_source().finish()?;
+        }
+
+        let mut extra = Vec::new();
+        if let Some(gate) = &self.quality_gate {
+            if gate.suspect_frames() > 0 {
+                tracing::info!(
+                    "Quality gate skipped {} suspect frame(s)",
+                    gate.suspect_frames()
+                );
+            }
+            extra.push(("suspect_frames", gate.suspect_frames().into()));
+        }
+        if let Some(dwell) = self.dwell {
+            extra.push(("tracks", dwell.finish(&self.save_base.join("tracks.csv"))));
+        }
+        Ok(extra)
+    }
+}
+
+/// Why the run annotates on the inference loop without `--sync-annotate`: these draw over, show
+/// or save the annotated frame outside of the ordered output.
+fn sync_annotate_reason(args: &Args, viewer: bool) -> Option<&'static str> {
+    [
+        (viewer, "the display window"),
+        (args.visualize_points_only, "--visualize-points-only"),
+        (args.matte.is_some(), "--matte"),
+        (args.cross_fade_alpha > 0.0, "--cross-fade-alpha"),
+        (args.annotate_prompt_state, "--annotate-prompt-state"),
+        (args.side_by_side, "--side-by-side"),
+        (args.snapshot_interval.is_some(), "--snapshot-interval"),
+        (
+            args.save_on_interval_secs.is_some(),
+            "--save-on-interval-secs",
+        ),
+        (args.save_best.is_some(), "--save-best"),
+        (args.control_socket.is_some(), "--control-socket"),
+    ]
+    .into_iter()
+    .find_map(|(on, flag)| on.then_some(flag))
+}
+
+/// How to get the FFmpeg command-line tools on common platforms.
+const FFMPEG_INSTALL_HINT: &str = "install FFmpeg with one of:
+  Debian/Ubuntu:  sudo apt install ffmpeg
+  Fedora:         sudo dnf install ffmpeg   (RPM Fusion)
+  Arch:           sudo pacman -S ffmpeg
+  Alpine:         sudo apk add ffmpeg
+  macOS:          brew install ffmpeg
+  Windows:        winget install ffmpeg
+or rebuild with `--features ffmpeg-static` to decode without system FFmpeg";
+
+/// Probe `ffmpeg` and `ffprobe` once and report every missing one in a single error.
+fn check_ffmpeg_tools() -> Result<()> {
+    let missing: Vec<&str> = ["ffmpeg", "ffprobe"]
+        .into_iter()
+        .filter(|tool| {
+            !Command::new(tool)
+                .arg("-version")
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|s| s.success())
+        })
         .collect();
-    Ok(Some(parse_prompts(&parts)?))
+    if missing.is_empty() {
+        return Ok(());
+    }
+    anyhow::bail!(
+        "video-sam3 needs `ffmpeg` and `ffprobe` in PATH; not found or not runnable: {}\n{FFMPEG_INSTALL_HINT}",
+        missing.join(", ")
+    )
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -116,14 +955,16 @@ struct VideoInfo {
     width: u32,
     height: u32,
     fps: f32,
+    color: SourceColor,
 }
 
-fn ffprobe_single_value(args: &[&str], input: &str) -> Result<Option<String>> {
+#[cfg(not(feature = "ffmpeg-static"))]
+fn ffprobe_single_value(args: &[&str], input: &InputUrl) -> Result<Option<String>> {
     let output = Command::new("ffprobe")
         .args(["-v", "error"])
         .args(args)
         .args(["-of", "default=noprint_wrappers=1:nokey=1"])
-        .arg(input)
+        .args(input.ffmpeg_args()?)
         .output()
         .with_context(|| "failed to run `ffprobe` (is FFmpeg installed?)")?;
 
@@ -134,7 +975,8 @@ fn ffprobe_single_value(args: &[&str], input: &str) -> Result<Option<String>> {
     Ok(text.lines().map(str::trim).find(|l| !l.is_empty()).map(|s| s.to_string()))
 }
 
-fn ffprobe_duration_seconds(input: &str) -> Result<Option<f64>> {
+#[cfg(not(feature = "ffmpeg-static"))]
+fn ffprobe_duration_seconds(input: &InputUrl) -> Result<Option<f64>> {
     let Some(v) = ffprobe_single_value(&["-show_entries", "format=duration"], input)? else {
         return Ok(None);
     };
@@ -145,7 +987,8 @@ fn ffprobe_duration_seconds(input: &str) -> Result<Option<f64>> {
     Ok(v.parse::<f64>().ok().filter(|d| d.is_finite() && *d > 0.0))
 }
 
-fn ffprobe_nb_frames(input: &str) -> Result<Option<u64>> {
+#[cfg(not(feature = "ffmpeg-static"))]
+fn ffprobe_nb_frames(input: &InputUrl) -> Result<Option<u64>> {
     let Some(v) = ffprobe_single_value(&["-select_streams", "v:0", "-show_entries", "stream=nb_frames"], input)?
     else {
         return Ok(None);
@@ -157,6 +1000,7 @@ fn ffprobe_nb_frames(input: &str) -> Result<Option<u64>> {
     Ok(v.parse::<u64>().ok().filter(|n| *n > 0))
 }
 
+#[cfg(not(feature = "ffmpeg-static"))]
 fn parse_rate(s: &str) -> Option<f32> {
     let s = s.trim();
     if s.is_empty() {
@@ -173,7 +1017,20 @@ fn parse_rate(s: &str) -> Option<f32> {
     s.parse().ok()
 }
 
-fn ffprobe_video_info(input: &str) -> Result<VideoInfo> {
+#[cfg(feature = "ffmpeg-static")]
+fn ffprobe_video_info(input: &InputUrl) -> Result<VideoInfo> {
+    let info = crate::libav::probe(input.resolved())?;
+    // The in-process decoder converts with swscale's default matrix.
+    Ok(VideoInfo {
+        width: info.width,
+        height: info.height,
+        fps: info.fps,
+        color: SourceColor::default(),
+    })
+}
+
+#[cfg(not(feature = "ffmpeg-static"))]
+fn ffprobe_video_info(input: &InputUrl) -> Result<VideoInfo> {
     let output = Command::new("ffprobe")
         .args([
             "-v",
@@ -181,50 +1038,86 @@ fn ffprobe_video_info(input: &str) -> Result<VideoInfo> {
             "-select_streams",
             "v:0",
             "-show_entries",
-            "stream=width,height,r_frame_rate",
+            "stream=width,height,r_frame_rate,color_space,color_range",
             "-of",
-            "default=noprint_wrappers=1:nokey=1",
-            input,
+            "default=noprint_wrappers=1",
         ])
+        .args(input.ffmpeg_args()?)
         .output()
         .with_context(|| "failed to run `ffprobe` (is FFmpeg installed?)")?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        anyhow::bail!("ffprobe failed: {}", stderr.trim());
+        anyhow::bail!("ffprobe failed: {}", scrub_credentials(stderr.trim()));
     }
+    parse_video_info(&String::from_utf8_lossy(&output.stdout))
+}
 
-    let text = String::from_utf8_lossy(&output.stdout);
-    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
-    let width: u32 = lines
-        .next()
+/// ffprobe's `key=value` lines for the first video stream's size, rate, matrix and range.
+#[cfg(not(feature = "ffmpeg-static"))]
+fn parse_video_info(text: &str) -> Result<VideoInfo> {
+    let value = |key: &str| {
+        text.lines()
+            .filter_map(|l| l.trim().split_once('='))
+            .find_map(|(k, v)| (k == key).then_some(v))
+    };
+    let width: u32 = value("width")
         .context("ffprobe output missing width")?
         .parse()
         .context("failed to parse width from ffprobe")?;
-    let height: u32 = lines
-        .next()
+    let height: u32 = value("height")
         .context("ffprobe output missing height")?
         .parse()
         .context("failed to parse height from ffprobe")?;
-    let fps = lines
-        .next()
+    let fps = value("r_frame_rate")
         .and_then(parse_rate)
         .filter(|v| v.is_finite() && *v > 0.0)
         .unwrap_or(30.0);
+    Ok(VideoInfo {
+        width,
+        height,
+        fps,
+        color: SourceColor::from_ffprobe(text),
+    })
+}
 
-    Ok(VideoInfo { width, height, fps })
+/// Frame count of `input` from `nb_frames`, falling back to duration * `fps`.
+fn estimate_frames(input: &InputUrl, fps: f32, decode_fps: Option<f32>) -> Result<Option<u64>> {
+    #[cfg(feature = "ffmpeg-static")]
+    let (nb_frames, duration) = {
+        let info = crate::libav::probe(input.resolved())?;
+        (info.frames, info.duration_seconds)
+    };
+    #[cfg(not(feature = "ffmpeg-static"))]
+    let (nb_frames, duration) = (ffprobe_nb_frames(input)?, ffprobe_duration_seconds(input)?);
+    // The stream's frame count is meaningless once the decoder drops frames.
+    if decode_fps.is_none()
+        && let Some(n) = nb_frames
+    {
+        return Ok(Some(n));
+    }
+    let rate = decode_fps.unwrap_or(fps);
+    Ok(duration
+        .map(|d| (d * rate as f64).round() as u64)
+        .filter(|n| *n > 0))
 }
 
-fn fmt_hms(seconds: f64) -> String {
-    let seconds = seconds.max(0.0);
-    let total_ms = (seconds * 1000.0).round() as u64;
-    let ms = total_ms % 1000;
-    let total_s = total_ms / 1000;
-    let s = total_s % 60;
-    let total_m = total_s / 60;
-    let m = total_m % 60;
-    let h = total_m / 60;
-    format!("{h:02}:{m:02}:{s:02}.{ms:03}")
+/// Resolve and probe every input; the session runs them back-to-back.
+fn probe_inputs(
+    raw: &[String],
+    credentials_file: Option<&Path>,
+) -> Result<Vec<(InputUrl, VideoInfo)>> {
+    if raw.is_empty() {
+        anyhow::bail!("no input given (pass one or more paths, or --inputs a.mp4,b.mp4)");
+    }
+    raw.iter()
+        .map(|raw| {
+            let input = InputUrl::resolve(raw, credentials_file)?;
+            let info =
+                ffprobe_video_info(&input).with_context(|| format!("failed to probe {input}"))?;
+            Ok((input, info))
+        })
+        .collect()
 }
 
 struct Progress {
@@ -355,316 +1248,1153 @@ impl Progress {
     }
 }
 
-struct FfmpegRawRgb24 {
-    child: Child,
+enum Decoder {
+    /// `ffmpeg` piping raw RGB24 on stdout.
+    Cli(Child, StderrDrain),
+    #[cfg(feature = "ffmpeg-static")]
+    Libav(Box<crate::libav::LibavRgb24>),
+}
+
+impl Decoder {
+    fn cli(&mut self) -> Option<(&mut Child, &mut StderrDrain)> {
+        match self {
+            Decoder::Cli(child, stderr) => Some((child, stderr)),
+            #[cfg(feature = "ffmpeg-static")]
+            Decoder::Libav(_) => None,
+        }
+    }
+}
+
+/// Frames on each side of `--start-frame` searched for the one a bad seek landed on.
+const SEEK_SEARCH_WINDOW: u64 = 5;
+
+/// `--verify-seek-accuracy`: decode up to `start_frame` sequentially, then seek there with `-ss`
+/// and compare the first frame. Passes when at most 1% of the pixels differ by more than 2.
+#[allow(clippy::too_many_arguments)]
+fn verify_seek_accuracy(
+    input: &InputUrl,
     width: u32,
     height: u32,
+    fps: f32,
+    scale: bool,
+    color: SourceColor,
+    start_frame: u64,
+    loglevel: FfmpegLogLevel,
+) -> Result<()> {
+    let first = start_frame.saturating_sub(SEEK_SEARCH_WINDOW);
+    let last = start_frame + SEEK_SEARCH_WINDOW;
+    let mut sequential =
+        FfmpegRawRgb24::spawn_cli(input, width, height, fps, scale, color, None, loglevel)?;
+    let mut window = Vec::new();
+    let mut decoded = 0u64;
+    while decoded <= last {
+        let Some(frame) = sequential.read_frame()? else {
+            break;
+        };
+        if decoded >= first {
+            window.push((decoded, frame));
+        }
+        decoded += 1;
+    }
+    drop(sequential);
+    let Some((_, truth)) = window.iter().find(|(idx, _)| *idx == start_frame) else {
+        anyhow::bail!(
+            "--start-frame {start_frame} is past the end of the input ({decoded} frames)"
+        );
+    };
+
+    let start_s = start_frame as f64 / f64::from(fps);
+    let mut seeking = FfmpegRawRgb24::spawn_cli_from(
+        input,
+        width,
+        height,
+        fps,
+        scale,
+        color,
+        None,
+        Some(start_s),
+        loglevel,
+    )?;
+    let landed = seeking
+        .read_frame()?
+        .context("the seeking decoder returned no frame")?;
+    drop(seeking);
+
+    tracing::info!(
+        "Frame {start_frame} ({start_s:.3}s): sequential checksum {:016x}, seek checksum {:016x}",
+        frame_checksum(truth),
+        frame_checksum(&landed)
+    );
+    let tolerance = u64::from(width) * u64::from(height) / 100;
+    let differing = differing_pixels(truth, &landed, 2);
+    if differing <= tolerance {
+        tracing::info!(
+            "Seek accurate: {differing} pixel(s) differ by more than 2 (tolerance {tolerance})"
+        );
+        return Ok(());
+    }
+    let (best_idx, best_diff) = window
+        .iter()
+        .map(|(idx, frame)| (*idx, differing_pixels(frame, &landed, 2)))
+        .min_by_key(|(_, diff)| *diff)
+        .context("no frames decoded")?;
+    if best_diff <= tolerance {
+        anyhow::bail!(
+            "seek to frame {start_frame} landed on frame {best_idx} ({:+} frames); {differing} pixels differ from the sequential frame",
+            best_idx as i64 - start_frame as i64
+        );
+    }
+    anyhow::bail!(
+        "seek to frame {start_frame} matches no frame within ±{SEEK_SEARCH_WINDOW}: {differing} pixels differ from the sequential frame"
+    );
+}
+
+/// `--annotate-only`: draw the records of `log` over the decoded frames into `writer`.
+fn annotate_only(
+    log: &mut DetectionLog,
+    mut decoder: FfmpegRawRgb24,
+    mut writer: FfmpegVideoWriter,
+    annotator: &Annotator,
+    groups: &PromptGroups,
+    prompts: &[Sam3Prompt],
+) -> Result<()> {
+    let mut frame_idx = 0u64;
+    let mut last_y: Option<usls::Y> = None;
+    let mut last_annotated: Option<usls::Image> = None;
+    while let Some(img) = decoder.next_frame()? {
+        frame_idx += 1;
+        let y = match log.overlay(frame_idx)? {
+            Overlay::Detections(detections) => {
+                let y = y_from_detections(&detections)
+                    .with_context(|| format!("invalid detections for frame {frame_idx}"))?;
+                Some(&*last_y.insert(y))
+            }
+            Overlay::Repeat => None,
+            Overlay::Previous => last_y.as_ref(),
+        };
+        if let Some(y) = y {
+            last_annotated = Some(groups.annotate(annotator, &img, y, prompts)?);
+        }
+        writer.write_frame(last_annotated.as_ref().unwrap_or(&img))?;
+    }
+    let unused = log.remaining()?;
+    if unused > 0 {
+        tracing::warn!(
+            "{unused} record(s) are past the last frame ({frame_idx}); check that the file was \
+             written for this input with the same --decode-fps"
+        );
+    }
+    decoder.finish()?;
+    writer.finish()?;
+    tracing::info!("Annotated {frame_idx} frames");
+    Ok(())
+}
+
+/// Decoded RGB24 frames of one input, from the `ffmpeg` CLI or (with `ffmpeg-static`) libav.
+pub struct FfmpegRawRgb24 {
+    decoder: Decoder,
+    width: u32,
+    height: u32,
+    fps: f32,
+    prefetch: Option<FfmpegRawRgb24Prefetch>,
 }
 
 impl FfmpegRawRgb24 {
-    fn spawn(input: &str, width: u32, height: u32, scale: bool) -> Result<Self> {
+    #[cfg(feature = "ffmpeg-static")]
+    #[allow(clippy::too_many_arguments)]
+    fn spawn(
+        input: &InputUrl,
+        width: u32,
+        height: u32,
+        fps: f32,
+        _scale: bool,
+        _color: SourceColor,
+        decode_fps: Option<f32>,
+        _loglevel: FfmpegLogLevel,
+    ) -> Result<Self> {
+        Self::open_libav(input.resolved(), width, height, fps, decode_fps)
+    }
+
+    #[cfg(not(feature = "ffmpeg-static"))]
+    #[allow(clippy::too_many_arguments)]
+    fn spawn(
+        input: &InputUrl,
+        width: u32,
+        height: u32,
+        fps: f32,
+        scale: bool,
+        color: SourceColor,
+        decode_fps: Option<f32>,
+        loglevel: FfmpegLogLevel,
+    ) -> Result<Self> {
+        Self::spawn_cli(
+            input, width, height, fps, scale, color, decode_fps, loglevel,
+        )
+    }
+
+    /// Decode in-process; `width` x `height` other than the source size rescales.
+    #[cfg(feature = "ffmpeg-static")]
+    pub fn open_libav(
+        input: &str,
+        width: u32,
+        height: u32,
+        fps: f32,
+        decode_fps: Option<f32>,
+    ) -> Result<Self> {
+        let decoder = crate::libav::LibavRgb24::open(input, width, height, decode_fps)?;
+        Ok(Self {
+            decoder: Decoder::Libav(Box::new(decoder)),
+            width,
+            height,
+            fps,
+            prefetch: None,
+        })
+    }
+
+    /// Decode through an `ffmpeg` child process, converting to RGB with `color`'s matrix.
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn_cli(
+        input: &InputUrl,
+        width: u32,
+        height: u32,
+        fps: f32,
+        scale: bool,
+        color: SourceColor,
+        decode_fps: Option<f32>,
+        loglevel: FfmpegLogLevel,
+    ) -> Result<Self> {
+        Self::spawn_cli_from(
+            input, width, height, fps, scale, color, decode_fps, None, loglevel,
+        )
+    }
+
+    /// [`FfmpegRawRgb24::spawn_cli`] starting `start_s` seconds in (input-side `-ss`).
+    #[allow(clippy::too_many_arguments)]
+    fn spawn_cli_from(
+        input: &InputUrl,
+        width: u32,
+        height: u32,
+        fps: f32,
+        scale: bool,
+        color: SourceColor,
+        decode_fps: Option<f32>,
+        start_s: Option<f64>,
+        loglevel: FfmpegLogLevel,
+    ) -> Result<Self> {
         let mut cmd = Command::new("ffmpeg");
-        cmd.args(["-hide_banner", "-loglevel", "error"]);
-        cmd.args(["-i", input]);
+        cmd.arg("-hide_banner").args(loglevel_args(loglevel));
+        if let Some(start) = start_s {
+            cmd.args(["-ss", &format!("{start:.6}")]);
+        }
+        cmd.args(input.ffmpeg_args()?);
         cmd.args(["-map", "0:v:0", "-an", "-sn", "-dn"]);
 
-        if scale {
-            cmd.args(["-vf", &format!("scale={width}:{height}")]);
+        // Drop frames before scaling so the dropped ones are never resized.
+        let mut filters = Vec::new();
+        if let Some(rate) = decode_fps {
+            filters.push(format!("fps={rate}"));
         }
+        filters.push(color.decode_filter(scale.then_some((width, height))));
+        cmd.args(["-vf", &filters.join(",")]);
 
         cmd.args(["-vsync", "0"]);
         cmd.args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"]);
 
-        let child = cmd
+        let mut child = cmd
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
             .with_context(|| "failed to run `ffmpeg` (is FFmpeg installed?)")?;
+        let stderr = StderrDrain::spawn(
+            child.stderr.take().context("ffmpeg stderr missing")?,
+            "ffmpeg (decoder)",
+        );
 
         Ok(Self {
-            child,
+            decoder: Decoder::Cli(child, stderr),
             width,
             height,
+            fps,
+            prefetch: None,
         })
     }
 
-    fn frame_size(&self) -> Result<usize> {
-        let size = self
-            .width
-            .checked_mul(self.height)
-            .and_then(|px| px.checked_mul(3))
-            .context("width*height overflow")?;
-        Ok(size as usize)
+    /// `--prefetch-depth <depth>`: read up to `depth` frames ahead on a thread; 0 reads inline.
+    /// Only the `ffmpeg` CLI decoder is prefetched.
+    pub fn with_prefetch(mut self, depth: usize) -> Result<Self> {
+        if depth == 0 {
+            return Ok(self);
+        }
+        let Some(stdout) = self.decoder.cli().and_then(|(c, _)| c.stdout.take()) else {
+            tracing::debug!("--prefetch-depth only applies to the ffmpeg CLI decoder");
+            return Ok(self);
+        };
+        self.prefetch = Some(FfmpegRawRgb24Prefetch::spawn(
+            stdout,
+            self.width,
+            self.height,
+            depth,
+        )?);
+        Ok(self)
     }
 
-    fn read_frame(&mut self) -> Result<Option<image::RgbImage>> {
-        let frame_size = self.frame_size()?;
-        let Some(stdout) = self.child.stdout.as_mut() else {
+    pub fn read_frame(&mut self) -> Result<Option<image::RgbImage>> {
+        if let Some(prefetch) = &mut self.prefetch {
+            return prefetch.next_frame();
+        }
+        #[cfg(feature = "ffmpeg-static")]
+        if let Decoder::Libav(decoder) = &mut self.decoder {
+            return decoder.read_frame();
+        }
+        let Some(stdout) = self.decoder.cli().and_then(|(c, _)| c.stdout.as_mut()) else {
             anyhow::bail!("ffmpeg stdout missing");
         };
+        read_raw_frame(stdout, self.width, self.height)
+    }
 
-        let mut buf = vec![0u8; frame_size];
-        match stdout.read_exact(&mut buf) {
-            Ok(()) => {
-                let img = image::RgbImage::from_raw(self.width, self.height, buf)
-                    .context("failed to construct RgbImage")?;
-                Ok(Some(img))
-            }
-            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
-            Err(e) => Err(e).context("failed to read frame bytes from ffmpeg"),
+    /// Decode errors reported so far (`ffmpeg` stderr lines, or libav packets it rejected).
+    pub fn decode_errors(&mut self) -> u64 {
+        #[cfg(feature = "ffmpeg-static")]
+        if let Decoder::Libav(decoder) = &self.decoder {
+            return decoder.decode_errors();
         }
+        self.decoder.cli().map_or(0, |(_, stderr)| stderr.errors())
     }
 
-    fn finish(mut self) -> Result<()> {
-        let status = self
-            .child
-            .wait()
-            .context("failed to wait for ffmpeg")?;
+    pub fn finish(mut self) -> Result<()> {
+        if let Some(prefetch) = &mut self.prefetch {
+            prefetch.stop(None);
+        }
+        let Some((child, stderr)) = self.decoder.cli() else {
+            return Ok(());
+        };
+        let status = child.wait().context("failed to wait for ffmpeg")?;
+        let err = stderr.join();
         if status.success() {
             return Ok(());
         }
-        let mut err = String::new();
-        if let Some(mut stderr) = self.child.stderr.take() {
-            stderr.read_to_string(&mut err).ok();
-        }
-        anyhow::bail!("ffmpeg exited with {status}: {}", err.trim());
-    }
-}
-
-impl Drop for FfmpegRawRgb24 {
-    fn drop(&mut self) {
-        let _ = self.child.kill();
+        anyhow::bail!(
+            "ffmpeg exited with {status}: {}",
+            scrub_credentials(err.trim())
+        );
     }
 }
 
-struct FfmpegVideoWriter {
-    child: Child,
-}
-
-impl FfmpegVideoWriter {
-    fn spawn(output: &Path, width: u32, height: u32, fps: f32) -> Result<Self> {
-        if let Some(parent) = output.parent() {
-            if !parent.as_os_str().is_empty() {
-                std::fs::create_dir_all(parent)
-                    .with_context(|| format!("failed to create output directory: {}", parent.display()))?;
-            }
-        }
-
-        let mut cmd = Command::new("ffmpeg");
-        cmd.args(["-hide_banner", "-loglevel", "error", "-y"]);
-        cmd.args(["-f", "rawvideo", "-pix_fmt", "rgb24"]);
-        cmd.args(["-video_size", &format!("{width}x{height}")]);
-        cmd.args(["-framerate", &format!("{fps:.3}")]);
-        cmd.args(["-i", "-"]);
-        cmd.args(["-an", "-sn", "-dn"]);
-        cmd.args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "23"]);
-        cmd.args(["-pix_fmt", "yuv420p"]);
-        cmd.arg(output);
-
-        let child = cmd
-            .stdin(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .with_context(|| "failed to run `ffmpeg` for encoding (is FFmpeg installed?)")?;
-
-        Ok(Self { child })
+impl VideoSource for FfmpegRawRgb24 {
+    fn next_frame(&mut self) -> Result<Option<usls::Image>> {
+        Ok(self.read_frame()?.map(usls::Image::from))
     }
 
-    fn write_frame(&mut self, img: &usls::Image) -> Result<()> {
-        let Some(stdin) = self.child.stdin.as_mut() else {
-            anyhow::bail!("ffmpeg stdin missing");
-        };
-        stdin
-            .write_all(img.as_raw())
-            .context("failed to write frame bytes to ffmpeg")?;
-        Ok(())
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
     }
 
-    fn finish(mut self) -> Result<()> {
-        drop(self.child.stdin.take());
-        let status = self
-            .child
-            .wait()
-            .context("failed to wait for ffmpeg (encoder)")?;
-        if status.success() {
-            return Ok(());
-        }
-        let mut err = String::new();
-        if let Some(mut stderr) = self.child.stderr.take() {
-            stderr.read_to_string(&mut err).ok();
-        }
-        anyhow::bail!(
-            "ffmpeg (encoder) exited with {status}: {}",
-            err.trim()
-        );
+    fn fps(&self) -> Option<f32> {
+        Some(self.fps)
     }
 }
 
-impl Drop for FfmpegVideoWriter {
+impl Drop for FfmpegRawRgb24 {
     fn drop(&mut self) {
-        let _ = self.child.kill();
+        let mut prefetch = self.prefetch.take();
+        let child = self.decoder.cli().map(|(child, _)| child);
+        match prefetch.as_mut() {
+            Some(prefetch) => prefetch.stop(child),
+            None => {
+                if let Some(child) = child {
+                    let _ = child.kill();
+                }
+            }
+        }
     }
 }
 
 pub fn run() -> Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .with_timer(tracing_subscriber::fmt::time::ChronoLocal::rfc_3339())
-        .init();
+    if handle_version_flag("video-sam3") {
+        return Ok(());
+    }
+    let mut args: Args = argh::from_env();
+    if args.print_json_schema {
+        println!("{}", serde_json::to_string_pretty(&json_schema())?);
+        return Ok(());
+    }
+    init_logging(
+        args.log_json,
+        ScrubbingMakeWriter(log_writer(args.mask_to_json)),
+    );
+    let prefetch_depth = match (args.prefetch_depth, args.prefetch) {
+        (Some(_), Some(_)) => {
+            anyhow::bail!("--prefetch is the old name of --prefetch-depth; give only one")
+        }
+        (depth, old) => depth.or(old).unwrap_or(DEFAULT_PREFETCH_DEPTH),
+    };
+    let session = SessionFile::open(args.session.as_deref())?;
+    session.restored().restore_args(SessionArgs {
+        prompt: &mut args.prompt,
+        conf: &mut args.conf,
+        legend: &mut args.legend,
+        no_legend: args.no_legend,
+        exclude_zone: &mut args.exclude_zone,
+        zones_file: args.zones_file.is_some(),
+        pipeline: args.pipeline.is_some(),
+    })?;
+    // As given, `#grp=` tags included, for the session file.
+    let session_prompts = if args.pipeline.is_some() {
+        // The prompts come from the pipeline file; keep the ones saved in the session.
+        session.restored().prompts.clone()
+    } else {
+        args.prompt.clone()
+    };
+    let (prompt, mut group_tags) = split_group_tags(&args.prompt);
+    args.prompt = prompt;
+
+    check_mask_to_json(
+        args.mask_to_json,
+        &[
+            ("--json-out", args.json_out.is_some()),
+            ("--save-video", args.save_video.is_some()),
+            ("--gst-sink", args.gst_sink.is_some()),
+            ("--side-by-side", args.side_by_side),
+            ("--save-frames", args.save_frames.is_some()),
+            ("--visualize-points-only", args.visualize_points_only),
+            ("--matte", args.matte.is_some()),
+        ],
+    )?;
+    if args.annotate_only.is_some()
+        && let Some((flag, _)) = [
+            ("--output-fps", args.output_fps.is_some()),
+            ("--side-by-side", args.side_by_side),
+            ("--matte", args.matte.is_some()),
+        ]
+        .into_iter()
+        .find(|(_, set)| *set)
+    {
+        anyhow::bail!("--annotate-only cannot be combined with {flag}");
+    }
+    let mut report = CheckReport::new(args.check);
+    let pipeline = match &args.pipeline {
+        Some(path) => Some(report.record("pipeline", Pipeline::load(Path::new(path)))?),
+        None => None,
+    };
+    let mut prompts = match &pipeline {
+        Some(_) if !args.prompt.is_empty() => {
+            anyhow::bail!("--pipeline takes its prompts from the file; drop -p")
+        }
+        Some(_) if args.mask_encoding != MaskEncoding::None => {
+            anyhow::bail!(
+                "--mask-encoding does not apply to --pipeline (its stages keep polygons, not masks)"
+            )
+        }
+        Some(pipeline) => pipeline.prompts(),
+        // Redrawing needs prompts only to draw them; the detections come from the file.
+        None if args.annotate_only.is_some() && args.prompt.is_empty() => Vec::new(),
+        None => report.record("prompts", parse_prompts(&args.prompt))?,
+    };
+    let mut prompt_texts = args.prompt.clone();
+    report.detail(format!("{} prompt(s)", prompts.len()));
+    let tiling = match (args.tile_size, args.tile_overlap) {
+        (None, Some(_)) => anyhow::bail!("--tile-overlap needs --tile-size"),
+        (Some(_), _) if pipeline.is_some() => {
+            anyhow::bail!("--tile-size does not apply to --pipeline stages")
+        }
+        (Some(_), _) if args.mask_encoding != MaskEncoding::None => {
+            anyhow::bail!(
+                "--mask-encoding does not apply to --tile-size (tiles are merged as polygons, not masks)"
+            )
+        }
+        (Some(size), overlap) => {
+            let overlap = overlap.unwrap_or(DEFAULT_TILE_OVERLAP);
+            if size == 0 || overlap >= size {
+                anyhow::bail!(
+                    "--tile-overlap ({overlap}) must be smaller than --tile-size ({size})"
+                );
+            }
+            Some((size, overlap))
+        }
+        (None, None) => None,
+    };
 
-    let args: Args = argh::from_env();
-    let mut prompts = parse_prompts(&args.prompt)?;
+    // Fail before loading anything when the FFmpeg tools are missing. libav only replaces the
+    // decoder: video outputs are still encoded by the CLI.
+    let encoding: Vec<&str> = [
+        ("--save-video", args.save_video.is_some()),
+        ("--annotate-only", args.annotate_only.is_some()),
+        ("--save-event-clips", args.save_event_clips.is_some()),
+        ("--timelapse-video", args.timelapse_video.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(flag, set)| set.then_some(flag))
+    .collect();
+    if !cfg!(feature = "ffmpeg-static") {
+        report.record("ffmpeg", check_ffmpeg_tools())?;
+    } else if !encoding.is_empty() {
+        report.record(
+            "ffmpeg",
+            check_ffmpeg_tools().with_context(|| {
+                format!(
+                    "{} encode through the ffmpeg CLI, also in the ffmpeg-static build",
+                    encoding.join(", ")
+                )
+            }),
+        )?;
+    }
 
-    let probed = ffprobe_video_info(&args.input)?;
+    let mut raw_inputs = args.input.clone();
+    if let Some(list) = &args.inputs {
+        raw_inputs.extend(
+            list.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_string),
+        );
+    }
+    let inputs = report.record(
+        "input",
+        probe_inputs(
+            &raw_inputs,
+            args.input_credentials_file.as_deref().map(Path::new),
+        ),
+    )?;
+    let (input, probed) = &inputs[0];
+    report.detail(format!(
+        "{}x{} @ {:.3} fps{}",
+        probed.width,
+        probed.height,
+        probed.fps,
+        if inputs.len() > 1 {
+            format!(" (+{} more input(s))", inputs.len() - 1)
+        } else {
+            String::new()
+        }
+    ));
     let (out_w, out_h, scale) = match (args.width, args.height) {
         (None, None) => (probed.width, probed.height, false),
         (Some(w), Some(h)) => (w, h, true),
         _ => anyhow::bail!("Specify both --width and --height (or neither)."),
     };
-    let fps = args.fps.unwrap_or(probed.fps).max(0.1);
+    let mirror_width = args.mirror_prompt.then_some(out_w);
+    if mirror_width.is_some() {
+        anyhow::ensure!(
+            pipeline.is_none(),
+            "--mirror-prompt is not supported with --pipeline"
+        );
+        let (raw, tags) = (
+            std::mem::take(&mut args.prompt),
+            std::mem::take(&mut group_tags),
+        );
+        (args.prompt, group_tags, prompts) = with_mirrored(mirror_width, raw, tags, prompts);
+        prompt_texts.clone_from(&args.prompt);
+        tracing::info!(
+            "--mirror-prompt: {} prompt(s) with the mirrored boxes",
+            prompts.len()
+        );
+    }
+    // Rate of the frames the decoder actually emits; every timestamp is based on it.
+    let stream_fps = match args.decode_fps {
+        Some(f) if !(f.is_finite() && f > 0.0) => {
+            anyhow::bail!("--decode-fps must be > 0 (got {f})")
+        }
+        Some(f) if f > probed.fps => anyhow::bail!(
+            "--decode-fps {f} exceeds the input rate {:.3}; frames are only dropped, never duplicated",
+            probed.fps
+        ),
+        Some(f) => {
+            tracing::info!("Decoding at {f} fps (input {:.3} fps)", probed.fps);
+            f
+        }
+        None => probed.fps,
+    };
+    let fps = args.fps.unwrap_or(stream_fps).max(0.1);
     let delay_ms: u64 = ((1000.0 / fps).round() as u64).clamp(1, 1000);
 
-    tracing::info!(
-        "Video: {} ({}x{}, {:.3} fps)",
-        args.input,
-        out_w,
-        out_h,
-        fps
-    );
+    tracing::info!("Video: {} ({}x{}, {:.3} fps)", input, out_w, out_h, fps);
+    for (i, (other, info)) in inputs.iter().enumerate().skip(1) {
+        tracing::info!(
+            "Input {}/{}: {} ({}x{}, {:.3} fps)",
+            i + 1,
+            inputs.len(),
+            other,
+            info.width,
+            info.height,
+            info.fps
+        );
+        if !scale && (info.width, info.height) != (out_w, out_h) {
+            tracing::warn!(
+                "{other} is {}x{}; scaling to {out_w}x{out_h}",
+                info.width,
+                info.height
+            );
+        }
+        if (info.fps - probed.fps).abs() > 0.01 {
+            tracing::warn!(
+                "{other} runs at {:.3} fps; timestamps follow the first input ({:.3} fps)",
+                info.fps,
+                probed.fps
+            );
+        }
+    }
 
-    let nb_frames = ffprobe_nb_frames(&args.input)?;
-    let duration_s = ffprobe_duration_seconds(&args.input)?;
-    let total_frames = nb_frames.or_else(|| duration_s.map(|d| (d * fps as f64).round() as u64).filter(|n| *n > 0));
+    // The total is only known if every input reports a frame count.
+    let mut total_frames = Some(0u64);
+    for (input, _) in &inputs {
+        total_frames = match (total_frames, estimate_frames(input, fps, args.decode_fps)?) {
+            (Some(total), Some(n)) => Some(total + n),
+            _ => None,
+        };
+    }
     if let Some(total) = total_frames {
         tracing::info!("Frames: ~{total}");
     }
+    if args.verify_seek_accuracy {
+        if args.decode_fps.is_some() {
+            anyhow::bail!("--verify-seek-accuracy checks the full-rate stream; drop --decode-fps");
+        }
+        return verify_seek_accuracy(
+            input,
+            out_w,
+            out_h,
+            probed.fps,
+            scale,
+            probed.color,
+            args.start_frame,
+            args.ffmpeg_loglevel,
+        );
+    }
 
-    let config = match args.task.parse()? {
-        Task::Sam3Image => Config::sam3_image(),
-        Task::Sam3Tracker => Config::sam3_tracker(),
-        _ => anyhow::bail!(
-            "Sam3 Task now only support: {}, {}",
-            Task::Sam3Image,
-            Task::Sam3Tracker
-        ),
+    if let Some(path) = &args.annotate_only {
+        anyhow::ensure!(inputs.len() == 1, "--annotate-only redraws a single input");
+        let out = args
+            .save_video
+            .as_deref()
+            .context("--annotate-only requires --save-video")?;
+        let annotator = build_annotator(args.annotation_style, args.show_mask)
+            .context("--annotate-only has nothing to draw with --annotation-style none")?;
+        let decoder = FfmpegRawRgb24::spawn(
+            input,
+            out_w,
+            out_h,
+            fps,
+            scale,
+            probed.color,
+            args.decode_fps,
+            args.ffmpeg_loglevel,
+        )?;
+        let writer = FfmpegVideoWriter::spawn(
+            Path::new(out),
+            out_w,
+            out_h,
+            fps,
+            args.out_color.resolve(probed.color),
+            args.ffmpeg_loglevel,
+        )?;
+        tracing::info!("Redrawing {path} into {out}");
+        return annotate_only(
+            &mut DetectionLog::open(Path::new(path))?,
+            decoder,
+            writer,
+            &annotator,
+            &PromptGroups::new(&args.group_style, &group_tags),
+            &prompts,
+        );
+    }
+
+    let (device, _) = auto_select_device_and_ep(
+        args.device.as_deref(),
+        ep_flag(args.ep, args.execution_provider)?,
+    )?;
+    let model_opts = ModelOptions {
+        task: args.task.clone(),
+        device,
+        dtype: args.dtype.clone(),
+        conf: args.conf.unwrap_or(DEFAULT_CONF),
+        trt_fp16: args.trt_fp16,
+        trt_engine_cache: args.trt_engine_cache,
+        trt_timing_cache: args.trt_timing_cache,
+        imgsz: args.imgsz,
+        image_mean: args.image_mean,
+        image_std: args.image_std,
+        retry_init: args.retry_init,
+    };
+    if let Some(dtypes) = &args.benchmark_matrix {
+        let dtypes = parse_list(dtypes);
+        let devices = parse_list(
+            args.benchmark_devices
+                .as_deref()
+                .unwrap_or(&model_opts.device),
+        );
+        if dtypes.is_empty() || devices.is_empty() {
+            anyhow::bail!("--benchmark-matrix needs at least one dtype and one device");
+        }
+        let mut decoder = FfmpegRawRgb24::spawn(
+            input,
+            out_w,
+            out_h,
+            fps,
+            scale,
+            probed.color,
+            args.decode_fps,
+            args.ffmpeg_loglevel,
+        )?;
+        let mut frames = Vec::new();
+        while frames.len() < args.benchmark_frames as usize
+            && let Some(img) = decoder.next_frame()?
+        {
+            frames.push(img);
+        }
+        drop(decoder);
+        if frames.is_empty() {
+            anyhow::bail!("input contains no video frames");
+        }
+        tracing::info!(
+            "Benchmarking {} device(s) x {} dtype(s) on {} frame(s)",
+            devices.len(),
+            dtypes.len(),
+            frames.len()
+        );
+        let results = run_matrix(&model_opts, &devices, &dtypes, &frames, &prompts);
+        return benchmark::report(&results, args.benchmark_out.as_deref().map(Path::new));
+    }
+
+    let config = report.record("config", model_opts.build_config())?;
+    report.detail(format!(
+        "{} on {} ({})",
+        args.task, model_opts.device, args.dtype
+    ));
+
+    let mut model = report.record("model", model_opts.load_model(config))?;
+    report.detail(model.spec().to_string());
+    if let Some(path) = &args.warmup_image
+        && !args.check
+    {
+        warmup_with_image(
+            &mut model,
+            std::path::Path::new(path),
+            &prompts,
+            args.warmup_runs,
+        )?;
+    }
+    let quality_gate = (!args.no_quality_gate).then(|| {
+        QualityGate::new(
+            QualityThresholds {
+                black_level: args.quality_black_level,
+                uniform_range: args.quality_uniform_range,
+                min_stddev: args.quality_min_stddev,
+            },
+            args.quality_decode_window,
+        )
+    });
+    let scene_cut = match args.detect_scene_cut {
+        Some(_) if !model_opts.is_tracker() => {
+            tracing::warn!("--detect-scene-cut only applies to --task sam3-tracker; ignoring");
+            None
+        }
+        Some(threshold) => Some(SceneCutDetector::new(threshold)?),
+        None => None,
+    };
+    let save_video_path: Option<PathBuf> = match &args.save_video {
+        // Nothing model-derived to encode: prompt visualization is a live aid only.
+        Some(_) if args.visualize_points_only => {
+            tracing::warn!("--visualize-points-only: ignoring --save-video");
+            None
+        }
+        other => other.as_deref().map(PathBuf::from),
+    };
+    if args.preserve_audio {
+        if save_video_path.is_none() {
+            anyhow::bail!("--preserve-audio requires --save-video");
+        }
+        if args.codec_copy {
+            tracing::warn!("--preserve-audio: --codec-copy already keeps the audio");
+        }
+        if inputs.len() > 1 {
+            anyhow::bail!(
+                "--preserve-audio takes the audio of a single input; it can't join several files"
+            );
+        }
+    }
+    if args.codec_copy {
+        if inputs.len() > 1 {
+            anyhow::bail!("--codec-copy stream-copies a single input; it can't join several files");
+        }
+        match &save_video_path {
+            None => anyhow::bail!("--codec-copy requires --save-video <out.mkv>"),
+            Some(path)
+                if !path
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("mkv")) =>
+            {
+                anyhow::bail!("--codec-copy muxes into Matroska; use a .mkv --save-video path")
+            }
+            Some(_) => {}
+        }
     }
-    .with_tensorrt_fp16_all(args.trt_fp16)
-    .with_tensorrt_engine_cache_all(args.trt_engine_cache)
-    .with_tensorrt_timing_cache_all(args.trt_timing_cache)
-    .with_dtype_all(args.dtype.parse()?)
-    .with_class_confs(&[args.conf])
-    .with_device_all(args.device.parse()?)
-    .commit()?;
-
-    let mut model = SAM3::new(config)?;
-    let annotator = Annotator::default()
-        .with_mask_style(
-            usls::MaskStyle::default()
-                .with_visible(args.show_mask)
-                .with_cutout(true)
-                .with_draw_polygon_largest(true),
+    let json_out_path: Option<PathBuf> = if args.mask_to_json {
+        Some(PathBuf::from(MASK_TO_JSON_PATH))
+    } else {
+        args.json_out.as_deref().map(PathBuf::from)
+    };
+    let headless = args.no_display || args.mask_to_json || save_video_path.is_some();
+
+    if report.enabled() {
+        let first_frame = FfmpegRawRgb24::spawn(
+            input,
+            out_w,
+            out_h,
+            fps,
+            scale,
+            probed.color,
+            args.decode_fps,
+            args.ffmpeg_loglevel,
         )
-        .with_polygon_style(usls::PolygonStyle::default().with_thickness(2));
+        .and_then(|mut d| d.next_frame()?.context("input contains no video frames"));
+        report.record("decode", first_frame)?;
+
+        report.record("forward", dummy_forward(&mut model, &prompts, out_w, out_h))?;
+
+        let save_base = match &args.save_dir {
+            Some(dir) => PathBuf::from(dir),
+            None => default_save_base(model.spec(), false)?,
+        };
+        report.record("save-dir", check_dir_writable(&save_base))?;
+        report.detail(save_base.display().to_string());
+        if let Some(path) = &save_video_path {
+            let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+            report.record("save-video", check_dir_writable(parent.unwrap_or(Path::new("."))))?;
+            report.detail(path.display().to_string());
+        }
+        if let Some(path) = &json_out_path {
+            let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+            report.record("json-out", check_dir_writable(parent.unwrap_or(Path::new("."))))?;
+            report.detail(path.display().to_string());
+        }
+
+        report.print();
+        return Ok(());
+    }
 
-    let save_video_path: Option<PathBuf> = args.save_video.as_deref().map(PathBuf::from);
-    let mut viewer = save_video_path
-        .is_none()
-        .then(|| Viewer::new("sam3-video").with_window_scale(args.window_scale));
+    let viewer =
+        (!headless).then(|| Viewer::new("sam3-video").with_window_scale(args.window_scale));
 
-    let save_base = match args.save_dir {
+    let save_base = match &args.save_dir {
         Some(dir) => std::path::PathBuf::from(dir),
-        None => usls::Dir::Current.base_dir_with_subs(&["runs", model.spec()])?,
+        None => default_save_base(model.spec(), true)?,
+    };
+    let save_base = if args.sequential_names {
+        next_run_dir(&save_base)?
+    } else {
+        save_base
     };
-
     if let Some(path) = &save_video_path {
         tracing::info!("Writing annotated video to: {}", path.display());
-    } else {
-        tracing::info!("Controls: ESC/Q quit, P update prompt, S save frame");
+    }
+    if let Some(path) = &json_out_path {
+        tracing::info!("Writing detections to: {}", path.display());
+    }
+    if !headless {
+        tracing::info!(
+            "Controls: ESC/Q quit, P update prompt, S save frame, L legend, M cycle annotation style, H prompt history, W save session"
+        );
     }
 
-    let mut decoder = FfmpegRawRgb24::spawn(&args.input, out_w, out_h, scale)?;
-    let mut encoder = match &save_video_path {
-        Some(path) => Some(FfmpegVideoWriter::spawn(path, out_w, out_h, fps)?),
+    let frames = Capture::new(
+        FfmpegRawRgb24::spawn(
+            input,
+            out_w,
+            out_h,
+            fps,
+            scale,
+            probed.color,
+            args.decode_fps,
+            args.ffmpeg_loglevel,
+        )?
+        .with_prefetch(prefetch_depth)?,
+    )
+    .with_tone(ToneAdjust::new(
+        ToneParams {
+            gamma: args.gamma,
+            brightness: args.brightness,
+            contrast: args.contrast,
+            auto_levels: args.auto_levels,
+        },
+        ToneTarget::from_flags(args.preprocess_display_only, args.preprocess_infer_only)?,
+    )?);
+    let decimator = match args.output_fps {
+        Some(out_fps) => Some(FrameDecimator::new(fps, out_fps)?),
+        None => None,
+    };
+    // Size of what reaches the window and the encoders.
+    let (shown_w, shown_h) = if args.side_by_side {
+        args.stack.output_size(out_w, out_h)
+    } else {
+        (out_w, out_h)
+    };
+    let encoder = match &save_video_path {
+        Some(path) if !args.codec_copy => {
+            let writer = FfmpegVideoWriter::spawn(
+                path,
+                shown_w,
+                shown_h,
+                args.output_fps.unwrap_or(fps),
+                args.out_color.resolve(probed.color),
+                args.ffmpeg_loglevel,
+            )?;
+            Some(if args.preserve_audio {
+                writer.with_audio_from(input)
+            } else {
+                writer
+            })
+        }
+        _ => None,
+    };
+    let kafka = KafkaSink::from_args(
+        args.kafka_brokers.as_deref(),
+        args.kafka_topic.as_deref(),
+        args.kafka_jpeg_quality,
+    )?;
+    let gst_sink = match &args.gst_sink {
+        Some(pipeline) => Some(GstSinkWriter::spawn(pipeline, shown_w, shown_h, fps)?),
+        None => None,
+    };
+    let detection_track = match &save_video_path {
+        Some(path) if args.codec_copy => Some(FfmpegSubtitleWriter::create(
+            &path.with_extension("vtt"),
+            stream_fps,
+        )?),
+        _ => None,
+    };
+    // Cue times follow the source video (not `--fps`) so the file lines up with the original.
+    let mut subtitles = Vec::new();
+    for (path, format) in [
+        (&args.save_srt, SubtitleFormat::Srt),
+        (&args.save_ass, SubtitleFormat::Ass),
+    ] {
+        if let Some(path) = path {
+            let path = Path::new(path);
+            tracing::info!("Writing subtitles to: {}", path.display());
+            subtitles.push(SubtitleWriter::create(
+                path, format, stream_fps, out_w, out_h,
+            )?);
+        }
+    }
+    let save_frames_dir = match &args.save_frames {
+        Some(dir) => {
+            let dir = PathBuf::from(dir);
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("failed to create frames directory: {}", dir.display()))?;
+            Some(dir)
+        }
         None => None,
     };
+    let sinks = StreamSinks {
+        frames_dir: save_frames_dir
+            .clone()
+            .filter(|_| !args.matte.is_some_and(|m| m.is_transparent())),
+        encoder,
+        decimator,
+        gst: gst_sink,
+        kafka,
+        shm: args
+            .shm
+            .as_deref()
+            .map(|name| ShmSink::new(name, args.shm_replace))
+            .transpose()?,
+    };
+    if args.matte.is_some_and(|m| m.is_transparent()) && save_video_path.is_some() {
+        tracing::warn!("--matte transparent: video output has no alpha, background will be black");
+    }
+    let dwell = if args.zone.is_empty() {
+        if args.kalman {
+            anyhow::bail!("--kalman requires --zone");
+        }
+        None
+    } else {
+        Some(DwellTracker::new(
+            args.zone.clone(),
+            args.dwell_overlap,
+            args.kalman,
+        )?)
+    };
 
-    let mut last_displayed: Option<usls::Image> = None;
-    let mut frame_idx: u64 = 0;
-    let mut stopped_early = false;
-    let mut progress = Progress::new(save_video_path.is_some(), fps, total_frames);
-    loop {
-        let Some(rgb8) = decoder.read_frame()? else {
-            break;
-        };
-        frame_idx += 1;
-        progress.maybe_update(frame_idx);
-        let img = usls::Image::from(rgb8);
-
-        let run_infer = args.infer_every > 0 && frame_idx.is_multiple_of(args.infer_every as u64);
-        if run_infer {
-            let batch = vec![img.clone()];
-            let ys = model.forward(&batch, &prompts)?;
-
-            let mut annotated = annotator.annotate(&img, &ys[0])?;
-            for prompt in &prompts {
-                annotated = annotator.annotate(&annotated, &prompt.boxes)?;
-                annotated = annotator.annotate(&annotated, &prompt.points)?;
+    let mut frame_loop = FrameLoop::new(LoopArgs {
+        model,
+        model_opts,
+        pipeline,
+        prompts,
+        session_prompts,
+        labels: prompt_texts,
+        group_tags,
+        mirror_width,
+        session,
+        viewer,
+        frame_size: frames.size(),
+        save_base: save_base.clone(),
+        json_out: json_out_path.as_deref(),
+        task: &args.task,
+        mask_to_json: args.mask_to_json,
+        annotation_style: args.annotation_style,
+        show_mask: args.show_mask,
+        group_style: &args.group_style,
+        legend: args.legend,
+        legend_outside: args.legend_outside,
+        hold_ms: args.hold_ms,
+        prompt_history: args.prompt_history.as_deref(),
+        visualize_points_only: args.visualize_points_only,
+        cross_fade_alpha: args.cross_fade_alpha,
+        show_zones: args.show_zones,
+        sequential_names: args.sequential_names,
+        jsonl_dedup: args.jsonl_dedup,
+        mask_encoding: args.mask_encoding,
+        dedup_frames: args.dedup_frames,
+        infer_every: args.infer_every,
+        infer_hz: args.infer_hz,
+        roi_file: args.roi_file.as_deref(),
+        export_prompt_vis_dir: args.export_prompt_vis_dir.as_deref(),
+        dump_tensors_dir: args.dump_tensors_dir.as_deref(),
+        control_socket: args.control_socket.as_deref(),
+        exclude_zone: args.exclude_zone.clone(),
+        zone_overlap: args.zone_overlap,
+        zones_file: args.zones_file.as_deref(),
+        deduplicate_masks_iou: args.deduplicate_masks_iou,
+        exclusive_group: args.exclusive_group.clone(),
+        exclusive_iou: args.exclusive_iou,
+        prompt_cooldown_frames: args.prompt_cooldown_frames,
+        stats_interval: args.stats_interval,
+        no_color: args.no_color,
+        save_best: args.save_best.as_deref(),
+        best_k: args.best_k,
+        save_crops: args.save_crops.as_deref(),
+        crop_pad: args.crop_pad,
+        split_by_prompt: args.split_by_prompt,
+        save_svg: args.save_svg.as_deref(),
+        save_dir_per_prompt: args.save_dir_per_prompt,
+        sync_annotate: args.sync_annotate,
+        snapshot_interval: args.snapshot_interval,
+        timelapse_video: args.timelapse_video.as_deref(),
+        save_on_interval_secs: args.save_on_interval_secs,
+        save_on_interval_annotated: args.save_on_interval_annotated,
+        save_event_clips: args.save_event_clips.as_deref(),
+        clip_pre_seconds: args.clip_pre_seconds,
+        clip_post_seconds: args.clip_post_seconds,
+        ffmpeg_loglevel: args.ffmpeg_loglevel,
+        notify: args.notify,
+        notify_command: args.notify_command.as_deref(),
+        notify_cooldown_secs: args.notify_cooldown_secs,
+        notify_max_running: args.notify_max_running,
+        notify_timeout_secs: args.notify_timeout_secs,
+        watchdog_secs: None,
+        summary_json: args.summary_json.as_deref(),
+        compare: args.compare.as_deref(),
+        seed: args.seed,
+    })?
+    .with_tiling(tiling)
+    .with_scene_cut(scene_cut)
+    .with_media_clock(stream_fps)
+    .with_key_wait(delay_ms);
+    if frame_loop.annotates() && !args.sync_annotate && !sinks.is_empty() {
+        match sync_annotate_reason(&args, !headless) {
+            Some(reason) => {
+                tracing::info!("Annotating on the inference loop: {reason} needs it there");
             }
-            last_displayed = Some(annotated);
+            None => frame_loop = frame_loop.with_ordered_output(ANNOTATE_QUEUE)?,
         }
+    }
+    frame_loop.run(
+        frames,
+        VideoOutputs {
+            args: &args,
+            inputs: &inputs,
+            input_idx: 0,
+            out_size: (out_w, out_h),
+            fps,
+            scale,
+            prefetch_depth,
+            frame_idx: 0,
+            total_frames,
+            progress: Progress::new(headless, fps, total_frames),
+            heartbeat: HeartbeatLogger::new(args.heartbeat_secs)?,
+            quality_gate,
+            quality_issue: None,
+            dwell,
+            zone_events: Vec::new(),
+            matte_mask: None,
+            save_frames_dir,
+            sinks,
+            subtitles,
+            detection_track,
+            save_video_path,
+            save_base,
+        },
+    )
+}
 
-        let display = match &last_displayed {
-            Some(img) => img,
-            None => &img,
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if let Some(encoder) = encoder.as_mut() {
-            encoder.write_frame(display)?;
-        }
-
-        if let Some(viewer) = viewer.as_mut() {
-            if viewer.is_window_exist_and_closed() {
-                stopped_early = true;
-                break;
-            }
+    #[cfg(not(feature = "ffmpeg-static"))]
+    #[test]
+    fn parses_one_probe_for_size_rate_and_color() {
+        let info = parse_video_info(
+            "width=1920\nheight=1080\nr_frame_rate=30000/1001\ncolor_space=bt709\ncolor_range=pc\n",
+        )
+        .unwrap();
+        assert_eq!((info.width, info.height), (1920, 1080));
+        assert!((info.fps - 29.97).abs() < 0.01);
+        assert_eq!(info.color.matrix, crate::color::ColorMatrix::Bt709);
+        assert!(info.color.full_range);
+
+        let info = parse_video_info("width=64\nheight=48\nr_frame_rate=0/0\n").unwrap();
+        assert_eq!(info.fps, 30.0);
+        assert_eq!(info.color, SourceColor::default());
+        assert!(parse_video_info("height=48\n").is_err());
+    }
 
-            viewer.imshow(display)?;
-            if let Some(key) = viewer.wait_key(delay_ms) {
-                match key {
-                    usls::Key::Escape | usls::Key::Q => {
-                        stopped_early = true;
-                        break;
-                    }
-                    usls::Key::S => {
-                        if let Some(img) = &last_displayed {
-                            let path = save_base.join(format!("{}.jpg", usls::timestamp(None)));
-                            img.save(&path)?;
-                            tracing::info!("Saved: {}", path.display());
-                        }
-                    }
-                    usls::Key::P => {
-                        if let Some(new_prompts) = prompt_update_loop()? {
-                            prompts = new_prompts;
-                            tracing::info!("Updated prompts: {:?}", prompts);
-                        }
-                    }
-                    _ => {}
-                }
-            }
+    /// `cmd` standing in for `ffmpeg`, emitting 8x4 RGB24 frames, prefetched `depth` ahead.
+    fn fake_decoder(cmd: &str, args: &[&str], depth: usize) -> FfmpegRawRgb24 {
+        let mut child = Command::new(cmd)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stderr = StderrDrain::spawn(child.stderr.take().unwrap(), "fake ffmpeg");
+        FfmpegRawRgb24 {
+            decoder: Decoder::Cli(child, stderr),
+            width: 8,
+            height: 4,
+            fps: 30.0,
+            prefetch: None,
         }
+        .with_prefetch(depth)
+        .unwrap()
     }
 
-    if let Some(encoder) = encoder {
-        encoder.finish()?;
+    #[test]
+    fn prefetch_passes_through_end_of_stream() {
+        // Two whole frames and a truncated third.
+        let mut decoder = fake_decoder("head", &["-c", "250", "/dev/zero"], 4);
+        assert!(decoder.read_frame().unwrap().is_some());
+        assert!(decoder.read_frame().unwrap().is_some());
+        assert!(decoder.read_frame().unwrap().is_none());
+        assert!(decoder.read_frame().unwrap().is_none());
+        decoder.finish().unwrap();
     }
 
-    progress.finish(frame_idx);
-
-    if stopped_early {
-        drop(decoder);
-    } else {
-        decoder.finish()?;
+    #[test]
+    fn prefetch_early_quit_does_not_deadlock() {
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            // Never ends: the reader fills the channel and `cat` blocks on the full pipe.
+            let mut decoder = fake_decoder("cat", &["/dev/zero"], 2);
+            for _ in 0..3 {
+                assert!(decoder.read_frame().unwrap().is_some());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+            drop(decoder);
+            done_tx.send(()).unwrap();
+        });
+        done_rx
+            .recv_timeout(Duration::from_secs(10))
+            .expect("dropping a prefetching decoder mid-stream hung");
     }
-    usls::perf(false);
-    Ok(())
 }
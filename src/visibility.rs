@@ -0,0 +1,202 @@
+//! Number keys `1`-`9` in the viewer: hide or show the detections of the Nth prompt, to declutter a
+//! busy scene without changing the prompts.
+//!
+//! Hidden prompts are only left out of the overlay (window, saved and encoded frames); JSON/CSV
+//! records, the legend counts and the other sinks keep every detection. A toggle shows its new
+//! state in the top-left corner of the window for [`NOTICE_SECS`].
+
+use anyhow::Result;
+use std::borrow::Cow;
+use std::time::Instant;
+use usls::Annotator;
+use usls::models::Sam3Prompt;
+
+use crate::groups::PromptGroups;
+use crate::postprocess::{prompt_index, retain_detections};
+use crate::viz::{draw_text_box, text_scale, to_rgb};
+
+/// How long a toggle's notice stays on screen.
+pub const NOTICE_SECS: f32 = 1.5;
+
+/// The keys toggling prompts 1-9.
+const PROMPT_KEYS: [usls::Key; 9] = [
+    usls::Key::Key1,
+    usls::Key::Key2,
+    usls::Key::Key3,
+    usls::Key::Key4,
+    usls::Key::Key5,
+    usls::Key::Key6,
+    usls::Key::Key7,
+    usls::Key::Key8,
+    usls::Key::Key9,
+];
+
+pub struct PromptVisibility {
+    /// One per active prompt.
+    visible: Vec<bool>,
+    notice: Option<(String, Instant)>,
+}
+
+impl PromptVisibility {
+    pub fn new(prompts: usize) -> Self {
+        Self {
+            visible: vec![true; prompts],
+            notice: None,
+        }
+    }
+
+    /// Show every prompt again, e.g. when the prompts change.
+    pub fn reset(&mut self, prompts: usize) {
+        self.visible = vec![true; prompts];
+    }
+
+    /// Flip prompt `idx` (of `prompts`); `false` when there is no such prompt.
+    pub fn toggle(&mut self, idx: usize, prompts: &[Sam3Prompt], now: Instant) -> bool {
+        let Some(visible) = self.visible.get_mut(idx) else {
+            return false;
+        };
+        *visible = !*visible;
+        let text = match prompts.get(idx).map(|p| p.text.trim()) {
+            Some("") | None => "visual",
+            Some(text) => text,
+        };
+        let state = if *visible { "on" } else { "off" };
+        tracing::info!("Prompt {} ({text}): {state}", idx + 1);
+        self.notice = Some((format!("{} {text}: {state}", idx + 1), now));
+        true
+    }
+
+    /// Flip the prompt of the first of the keys `1`-`9` that `pressed` reports; `false` when none
+    /// is pressed or it has no prompt.
+    pub fn toggle_pressed(
+        &mut self,
+        pressed: impl Fn(usls::Key) -> bool,
+        prompts: &[Sam3Prompt],
+        now: Instant,
+    ) -> bool {
+        PROMPT_KEYS
+            .into_iter()
+            .position(pressed)
+            .is_some_and(|idx| self.toggle(idx, prompts, now))
+    }
+
+    pub fn is_visible(&self, idx: usize) -> bool {
+        self.visible.get(idx).copied().unwrap_or(true)
+    }
+
+    /// `y` without the detections of hidden prompts (see [`retain_detections`]); borrowed as is
+    /// while nothing is hidden. Detections that match no prompt are kept.
+    pub fn filter<'a>(&self, y: &'a usls::Y, prompts: &[Sam3Prompt]) -> Cow<'a, usls::Y> {
+        if self.visible.iter().all(|v| *v) {
+            return Cow::Borrowed(y);
+        }
+        Cow::Owned(retain_detections(y.clone(), |c| {
+            prompt_index(c.id, c.name, prompts).is_none_or(|i| self.is_visible(i))
+        }))
+    }
+
+    /// `img` annotated with [`Self::filter`]ed `y`, in the prompts' `groups` styles.
+    pub fn annotate(
+        &self,
+        annotator: &Annotator,
+        groups: &PromptGroups,
+        img: &usls::Image,
+        y: &usls::Y,
+        prompts: &[Sam3Prompt],
+    ) -> Result<usls::Image> {
+        groups.annotate(annotator, img, &self.filter(y, prompts), prompts)
+    }
+
+    /// `last`, the frame and result on screen, annotated again after a toggle or an annotation
+    /// preset switch; `None` without an annotator or a result.
+    pub fn redraw(
+        &self,
+        annotator: Option<&Annotator>,
+        groups: &PromptGroups,
+        last: Option<&(usls::Image, usls::Y)>,
+        prompts: &[Sam3Prompt],
+    ) -> Result<Option<usls::Image>> {
+        let (Some(annotator), Some((img, y))) = (annotator, last) else {
+            return Ok(None);
+        };
+        self.annotate(annotator, groups, img, y, prompts).map(Some)
+    }
+
+    /// The notice of the last toggle, while it lasts.
+    fn notice(&self, now: Instant) -> Option<&str> {
+        self.notice
+            .as_ref()
+            .filter(|(_, at)| now.saturating_duration_since(*at).as_secs_f32() < NOTICE_SECS)
+            .map(|(text, _)| text.as_str())
+    }
+
+    /// `img` with the last toggle's notice drawn over it at `now`; `None` once it has expired.
+    pub fn render(&self, img: &usls::Image, now: Instant) -> Result<Option<usls::Image>> {
+        let Some(notice) = self.notice(now) else {
+            return Ok(None);
+        };
+        let mut frame = to_rgb(img)?;
+        let scale = text_scale(frame.height());
+        draw_text_box(&mut frame, &[notice.to_string()], scale);
+        Ok(Some(usls::Image::from(frame)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn toggles_prompts_by_number() {
+        let prompts: Vec<Sam3Prompt> = ["person", "forklift"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let mut visibility = PromptVisibility::new(prompts.len());
+        let t0 = Instant::now();
+        assert!(!visibility.toggle_pressed(|k| k == usls::Key::Key0, &prompts, t0));
+        assert!(visibility.toggle_pressed(|k| k == usls::Key::Key2, &prompts, t0));
+        assert!(visibility.is_visible(0) && !visibility.is_visible(1));
+        assert!(visibility.notice(t0).unwrap().ends_with(": off"));
+        assert!(
+            visibility
+                .notice(t0 + Duration::from_secs_f32(NOTICE_SECS))
+                .is_none()
+        );
+        // No third prompt.
+        assert!(!visibility.toggle(2, &prompts, t0));
+        visibility.reset(prompts.len());
+        assert!(visibility.is_visible(1));
+    }
+
+    #[test]
+    fn hidden_prompts_lose_boxes_masks_and_polygons() {
+        let prompts: Vec<Sam3Prompt> = ["person", "forklift"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let hbb = |id: usize, x: f32| usls::Hbb::from_xywh(x, 0.0, 10.0, 10.0).with_id(id);
+        let mask = |id: usize, value: u8| usls::Mask::new(&[value], 1, 1).unwrap().with_id(id);
+        let polygon = |id: usize, x: f32| usls::Polygon::from_xys(&[[x, 0.0]]).with_id(id);
+        let y = usls::Y::default()
+            .with_hbbs(&[hbb(0, 0.0), hbb(1, 100.0), hbb(0, 200.0)])
+            .with_masks(&[mask(0, 1), mask(0, 3), mask(1, 2)])
+            .with_polygons(&[polygon(0, 0.0), polygon(1, 100.0), polygon(0, 200.0)]);
+        let mut visibility = PromptVisibility::new(prompts.len());
+        assert!(matches!(visibility.filter(&y, &prompts), Cow::Borrowed(_)));
+
+        assert!(visibility.toggle(1, &prompts, Instant::now()));
+        let shown = visibility.filter(&y, &prompts);
+        assert!(shown.hbbs().iter().all(|h| h.id() == Some(0)));
+        assert_eq!(shown.hbbs().len(), 2);
+        let values: Vec<u8> = shown.masks().iter().map(|m| m.to_vec()[0]).collect();
+        assert_eq!(values, [1, 3]);
+        assert!(shown.polygons().iter().all(|p| p.id() == Some(0)));
+        assert_eq!(shown.polygons().len(), 2);
+
+        assert!(visibility.toggle(0, &prompts, Instant::now()));
+        let shown = visibility.filter(&y, &prompts);
+        assert!(shown.hbbs().is_empty() && shown.masks().is_empty() && shown.polygons().is_empty());
+    }
+}
@@ -0,0 +1,544 @@
+use anyhow::{Context, Result};
+use image::RgbImage;
+use std::collections::VecDeque;
+use std::time::Instant;
+use usls::models::Sam3Prompt;
+
+const GLYPH_W: u32 = 5;
+const GLYPH_H: u32 = 7;
+
+/// 5x7 bitmap font for printable ASCII (0x20..=0x7e); each row uses the low 5 bits, MSB left.
+const FONT_5X7: [[u8; 7]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x00, 0x00, 0x04], // '!'
+    [0x0a, 0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a], // '#'
+    [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03], // '%'
+    [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d], // '&'
+    [0x0c, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00], // "'"
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08], // ')'
+    [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x0c, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00], // '/'
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e], // '0'
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e], // '1'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f], // '2'
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e], // '3'
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02], // '4'
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e], // '5'
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e], // '6'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08], // '7'
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e], // '8'
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02], // '<'
+    [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08], // '>'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04], // '?'
+    [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e], // '@'
+    [0x0e, 0x11, 0x11, 0x11, 0x1f, 0x11, 0x11], // 'A'
+    [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e], // 'B'
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e], // 'C'
+    [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c], // 'D'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f], // 'E'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10], // 'F'
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f], // 'G'
+    [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11], // 'H'
+    [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f], // 'L'
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11], // 'N'
+    [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'O'
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10], // 'P'
+    [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d], // 'Q'
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11], // 'R'
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e], // 'S'
+    [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a], // 'W'
+    [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11], // 'X'
+    [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04], // 'Y'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f], // 'Z'
+    [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00], // '\\'
+    [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e], // ']'
+    [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f], // '_'
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f], // 'a'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e], // 'b'
+    [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e], // 'c'
+    [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f], // 'd'
+    [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e], // 'e'
+    [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08], // 'f'
+    [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e], // 'g'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11], // 'h'
+    [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e], // 'i'
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c], // 'j'
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12], // 'k'
+    [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e], // 'l'
+    [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11], // 'm'
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11], // 'n'
+    [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e], // 'o'
+    [0x00, 0x00, 0x1e, 0x11, 0x1e, 0x10, 0x10], // 'p'
+    [0x00, 0x00, 0x0d, 0x13, 0x0f, 0x01, 0x01], // 'q'
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10], // 'r'
+    [0x00, 0x00, 0x0e, 0x10, 0x0e, 0x01, 0x1e], // 's'
+    [0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06], // 't'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d], // 'u'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04], // 'v'
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a], // 'w'
+    [0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11], // 'x'
+    [0x00, 0x00, 0x11, 0x11, 0x0f, 0x01, 0x0e], // 'y'
+    [0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f], // 'z'
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02], // '{'
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04], // '|'
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08], // '}'
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00], // '~'
+];
+
+fn glyph(c: char) -> &'static [u8; 7] {
+    let idx = match c {
+        ' '..='~' => c as usize - 0x20,
+        _ => '?' as usize - 0x20,
+    };
+    &FONT_5X7[idx]
+}
+
+/// Legend swatch colors, indexed by prompt order.
+pub const PROMPT_PALETTE: [[u8; 3]; 10] = [
+    [31, 119, 180],
+    [255, 127, 14],
+    [44, 160, 44],
+    [214, 39, 40],
+    [148, 103, 189],
+    [140, 86, 75],
+    [227, 119, 194],
+    [127, 127, 127],
+    [188, 189, 34],
+    [23, 190, 207],
+];
+
+/// Color of a detection with `class_id` (its prompt index), as the annotators draw it.
+pub fn prompt_color(class_id: Option<usize>) -> [u8; 3] {
+    PROMPT_PALETTE[class_id.unwrap_or(0) % PROMPT_PALETTE.len()]
+}
+
+/// [`PROMPT_PALETTE`] for the usls styles, so annotated detections match their legend swatch.
+pub fn prompt_palette() -> Vec<usls::Color> {
+    PROMPT_PALETTE
+        .map(|[r, g, b]| usls::Color::from([r, g, b, 255]))
+        .to_vec()
+}
+
+/// Inferences averaged in the legend's rolling count.
+const LEGEND_WINDOW: usize = 30;
+
+/// Prompt labels shown in the window title before the rest collapse into `...`.
+const TITLE_MAX_LABELS: usize = 3;
+
+/// Integer text scale that stays legible at `height` (1 up to 360p, 2 at 720p, 3 at 1080p).
+pub fn text_scale(height: u32) -> u32 {
+    (height / 360).max(1)
+}
+
+/// Pixel size of `text` rendered at `scale` (1 px spacing between glyphs).
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let n = text.chars().count() as u32;
+    let w = if n == 0 { 0 } else { n * (GLYPH_W + 1) - 1 };
+    (w * scale, GLYPH_H * scale)
+}
+
+/// Draw `text` with its top-left corner at (`x`, `y`); pixels outside `img` are clipped.
+pub fn draw_text(img: &mut RgbImage, x: u32, y: u32, text: &str, scale: u32, color: [u8; 3]) {
+    let scale = scale.max(1);
+    for (i, c) in text.chars().enumerate() {
+        let gx = x + i as u32 * (GLYPH_W + 1) * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_W {
+                if bits & (0x10 >> col) == 0 {
+                    continue;
+                }
+                let px = gx + col * scale;
+                let py = y + row as u32 * scale;
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        if px + dx < img.width() && py + dy < img.height() {
+                            img.put_pixel(px + dx, py + dy, image::Rgb(color));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draw `lines` as white text on a darkened box anchored at the top-left corner of `img`.
+pub fn draw_text_box(img: &mut RgbImage, lines: &[String], scale: u32) {
+    let pad = 3 * scale;
+    let line_h = (GLYPH_H + 3) * scale;
+    let text_w = lines
+        .iter()
+        .map(|l| text_size(l, scale).0)
+        .max()
+        .unwrap_or(0);
+    let box_w = (text_w + 2 * pad).min(img.width());
+    let box_h = (lines.len() as u32 * line_h + 2 * pad - 3 * scale).min(img.height());
+    for y in 0..box_h {
+        for x in 0..box_w {
+            let px = img.get_pixel_mut(x, y);
+            for c in 0..3 {
+                px[c] /= 3;
+            }
+        }
+    }
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            img,
+            pad,
+            pad + i as u32 * line_h,
+            line,
+            scale,
+            [255, 255, 255],
+        );
+    }
+}
+
+/// A copy of `img` to draw on.
+pub fn to_rgb(img: &usls::Image) -> Result<RgbImage> {
+    RgbImage::from_raw(img.width(), img.height(), img.as_raw().to_vec())
+        .context("failed to construct RgbImage")
+}
+
+/// Burn the active prompts and confidence threshold into the top-left corner of `img`.
+pub fn draw_prompt_state(img: &usls::Image, prompts: &[String], conf: f32) -> Result<usls::Image> {
+    let mut rgb = to_rgb(img)?;
+    let mut lines = Vec::with_capacity(prompts.len() + 1);
+    lines.push(format!("conf {conf:.2}"));
+    lines.extend(prompts.iter().map(|p| format!("- {p}")));
+    let scale = text_scale(rgb.height());
+    draw_text_box(&mut rgb, &lines, scale);
+    Ok(usls::Image::from(rgb))
+}
+
+/// `--export-prompt-vis-dir`: one copy of `img` per prompt with its boxes (red) and point hints
+/// (green dots) drawn where SAM3 receives them, plus the prompt text in the corner.
+pub fn visualize_prompts(img: &usls::Image, prompts: &[Sam3Prompt]) -> Result<Vec<usls::Image>> {
+    let rgb = to_rgb(img)?;
+    let scale = text_scale(rgb.height());
+    let vis = prompts
+        .iter()
+        .enumerate()
+        .map(|(i, prompt)| {
+            let mut vis = rgb.clone();
+            for hbb in &prompt.boxes {
+                draw_rect_outline(&mut vis, hbb.xyxy(), scale, [255, 0, 0]);
+            }
+            for point in &prompt.points {
+                draw_dot(&mut vis, point.xy(), 3 * scale, [0, 255, 0]);
+            }
+            let text = match prompt.text.trim() {
+                "" => "visual",
+                text => text,
+            };
+            draw_text_box(&mut vis, &[format!("prompt {i}: {text}")], scale);
+            usls::Image::from(vis)
+        })
+        .collect();
+    Ok(vis)
+}
+
+/// `thickness`-pixel rectangle outline inside `(x0, y0, x1, y1)`, clipped to `img`.
+fn draw_rect_outline(
+    img: &mut RgbImage,
+    (x0, y0, x1, y1): (f32, f32, f32, f32),
+    thickness: u32,
+    color: [u8; 3],
+) {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let (x0, y0) = (x0.round() as i64, y0.round() as i64);
+    let (x1, y1) = (x1.round() as i64 - 1, y1.round() as i64 - 1);
+    let t = i64::from(thickness.max(1));
+    for y in y0.max(0)..=y1.min(h - 1) {
+        for x in x0.max(0)..=x1.min(w - 1) {
+            if x - x0 < t || x1 - x < t || y - y0 < t || y1 - y < t {
+                img.put_pixel(x as u32, y as u32, image::Rgb(color));
+            }
+        }
+    }
+}
+
+/// Filled disc of `radius` pixels centered on `(x, y)`, clipped to `img`.
+fn draw_dot(img: &mut RgbImage, (x, y): (f32, f32), radius: u32, color: [u8; 3]) {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let (cx, cy, r) = (x.round() as i64, y.round() as i64, i64::from(radius));
+    for py in (cy - r).max(0)..=(cy + r).min(h - 1) {
+        for px in (cx - r).max(0)..=(cx + r).min(w - 1) {
+            if (px - cx).pow(2) + (py - cy).pow(2) <= r * r {
+                img.put_pixel(px as u32, py as u32, image::Rgb(color));
+            }
+        }
+    }
+}
+
+/// `--stack`: how [`side_by_side`] places the two frames.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Stack {
+    /// Original left, annotated right.
+    #[default]
+    Horizontal,
+    /// Original on top, annotated below.
+    Vertical,
+}
+
+impl Stack {
+    /// Size of the composite of two `width` x `height` frames.
+    pub fn output_size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Stack::Horizontal => (width * 2, height),
+            Stack::Vertical => (width, height * 2),
+        }
+    }
+}
+
+impl std::str::FromStr for Stack {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "horizontal" => Ok(Self::Horizontal),
+            "vertical" => Ok(Self::Vertical),
+            other => Err(format!(
+                "invalid stack `{other}` (expected horizontal or vertical)"
+            )),
+        }
+    }
+}
+
+/// `--side-by-side`: `original` and `annotated` in one frame, for before/after comparisons.
+pub fn side_by_side(
+    original: &usls::Image,
+    annotated: &usls::Image,
+    stack: Stack,
+) -> Result<usls::Image> {
+    let (w, h) = (original.width(), original.height());
+    if (annotated.width(), annotated.height()) != (w, h) {
+        anyhow::bail!(
+            "side-by-side size mismatch: {w}x{h} vs {}x{}",
+            annotated.width(),
+            annotated.height()
+        );
+    }
+    let (out_w, out_h) = stack.output_size(w, h);
+    let mut canvas = RgbImage::new(out_w, out_h);
+    let left = to_rgb(original)?;
+    let right = to_rgb(annotated)?;
+    let (dx, dy) = match stack {
+        Stack::Horizontal => (w as i64, 0),
+        Stack::Vertical => (0, h as i64),
+    };
+    image::imageops::replace(&mut canvas, &left, 0, 0);
+    image::imageops::replace(&mut canvas, &right, dx, dy);
+    Ok(usls::Image::from(canvas))
+}
+
+/// Per-pixel lerp `alpha * annotated + (1 - alpha) * raw` (`alpha` clamped to `[0, 1]`).
+pub fn cross_fade(raw: &usls::Image, annotated: &usls::Image, alpha: f32) -> Result<usls::Image> {
+    if (raw.width(), raw.height()) != (annotated.width(), annotated.height()) {
+        anyhow::bail!(
+            "cross-fade size mismatch: {}x{} vs {}x{}",
+            raw.width(),
+            raw.height(),
+            annotated.width(),
+            annotated.height()
+        );
+    }
+    let a = alpha.clamp(0.0, 1.0);
+    let blended = raw
+        .as_raw()
+        .iter()
+        .zip(annotated.as_raw())
+        .map(|(&r, &o)| (o as f32 * a + r as f32 * (1.0 - a)).round() as u8)
+        .collect();
+    let rgb = RgbImage::from_raw(raw.width(), raw.height(), blended)
+        .context("failed to construct RgbImage")?;
+    Ok(usls::Image::from(rgb))
+}
+
+/// Window title: `SAM3 [task] | label, label, label, ... | 12.3 fps`.
+///
+/// Visual-only prompts (no text) show as `visual`.
+pub fn make_window_title(task: &str, prompts: &[Sam3Prompt], fps: f32) -> String {
+    let mut labels: Vec<&str> = prompts
+        .iter()
+        .take(TITLE_MAX_LABELS)
+        .map(|p| match p.text.trim() {
+            "" => "visual",
+            text => text,
+        })
+        .collect();
+    if prompts.len() > TITLE_MAX_LABELS {
+        labels.push("...");
+    }
+    format!("SAM3 [{task}] | {} | {fps:.1} fps", labels.join(", "))
+}
+
+/// Inference rate for the window title, smoothed over recent inference frames.
+#[derive(Default)]
+pub struct FpsMeter {
+    last: Option<Instant>,
+    fps: f32,
+}
+
+impl FpsMeter {
+    /// Record an inference frame; returns the smoothed rate (0 until two frames were seen).
+    pub fn tick(&mut self) -> f32 {
+        let now = Instant::now();
+        if let Some(last) = self.last.replace(now) {
+            let dt = now.duration_since(last).as_secs_f32();
+            if dt > 0.0 {
+                let fps = 1.0 / dt;
+                self.fps = if self.fps == 0.0 {
+                    fps
+                } else {
+                    self.fps * 0.9 + fps * 0.1
+                };
+            }
+        }
+        self.fps
+    }
+}
+
+/// Per-prompt legend (`--legend`, toggled with `L`): color swatch, current detection count and a
+/// rolling average over the last inferences.
+///
+/// Only drawn on the window image; saved and encoded frames stay untouched.
+pub struct Legend {
+    visible: bool,
+    outside: bool,
+    labels: Vec<String>,
+    history: VecDeque<Vec<usize>>,
+}
+
+impl Legend {
+    pub fn new(visible: bool, outside: bool, labels: Vec<String>) -> Self {
+        Self {
+            visible,
+            outside,
+            labels,
+            history: VecDeque::with_capacity(LEGEND_WINDOW),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Replace the prompt list; counts restart since the old ones no longer apply.
+    pub fn set_labels(&mut self, labels: Vec<String>) {
+        self.labels = labels;
+        self.history.clear();
+    }
+
+    /// Count detections per prompt: by class id (prompt index), falling back to the label text.
+    pub fn record(&mut self, y: &usls::Y) {
+        let mut counts = vec![0; self.labels.len()];
+        for hbb in y.hbbs() {
+            let idx = match hbb.id() {
+                Some(id) if id < counts.len() => Some(id),
+                _ => hbb
+                    .name()
+                    .and_then(|name| self.labels.iter().position(|l| l == name)),
+            };
+            if let Some(idx) = idx {
+                counts[idx] += 1;
+            }
+        }
+        if self.history.len() == LEGEND_WINDOW {
+            self.history.pop_front();
+        }
+        self.history.push_back(counts);
+    }
+
+    /// `img` with the legend drawn in the top-right corner, or on a panel to the right of
+    /// the frame with `outside`; `None` while hidden.
+    pub fn render(&self, img: &usls::Image) -> Result<Option<usls::Image>> {
+        if !self.visible || self.labels.is_empty() {
+            return Ok(None);
+        }
+        let frame = to_rgb(img)?;
+        let scale = text_scale(frame.height());
+        let current = self.history.back();
+        let lines: Vec<String> = self
+            .labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                let now = current.map_or(0, |c| c[i]);
+                let avg = self.history.iter().map(|c| c[i]).sum::<usize>() as f32
+                    / self.history.len().max(1) as f32;
+                format!("{label}  {now}  avg {avg:.1}")
+            })
+            .collect();
+
+        let pad = 3 * scale;
+        let line_h = (GLYPH_H + 3) * scale;
+        let swatch = GLYPH_H * scale;
+        let text_x = pad + swatch + 2 * scale;
+        let panel_w = text_x
+            + lines
+                .iter()
+                .map(|l| text_size(l, scale).0)
+                .max()
+                .unwrap_or(0)
+            + pad;
+        let panel_h = lines.len() as u32 * line_h + 2 * pad - 3 * scale;
+
+        let (mut canvas, x0) = if self.outside {
+            let mut canvas = RgbImage::from_pixel(
+                frame.width() + panel_w,
+                frame.height().max(panel_h),
+                image::Rgb([24, 24, 24]),
+            );
+            image::imageops::replace(&mut canvas, &frame, 0, 0);
+            (canvas, frame.width())
+        } else {
+            let x0 = frame.width().saturating_sub(panel_w);
+            let mut canvas = frame;
+            for y in 0..panel_h.min(canvas.height()) {
+                for x in x0..canvas.width() {
+                    let px = canvas.get_pixel_mut(x, y);
+                    for c in 0..3 {
+                        px[c] /= 3;
+                    }
+                }
+            }
+            (canvas, x0)
+        };
+
+        for (i, line) in lines.iter().enumerate() {
+            let y = pad + i as u32 * line_h;
+            let color = image::Rgb(PROMPT_PALETTE[i % PROMPT_PALETTE.len()]);
+            for sy in y..(y + swatch).min(canvas.height()) {
+                for sx in (x0 + pad)..(x0 + pad + swatch).min(canvas.width()) {
+                    canvas.put_pixel(sx, sy, color);
+                }
+            }
+            draw_text(&mut canvas, x0 + text_x, y, line, scale, [255, 255, 255]);
+        }
+        Ok(Some(usls::Image::from(canvas)))
+    }
+}
@@ -0,0 +1,162 @@
+//! `--watchdog-secs`: exit when the camera stops delivering frames.
+//!
+//! Meant for supervised deployments (systemd `Restart=on-failure`): a background thread watches the
+//! time since the last successfully captured frame and, once it exceeds the limit, logs and sets the
+//! run's stop flag. The capture loop then leaves like on SIGTERM, flushes its outputs and `run()`
+//! returns [`WatchdogFired`], which [`exit_on_fire`] turns into [`WATCHDOG_EXIT_CODE`]. When a capture
+//! call blocks forever the loop never sees the flag; after [`FORCE_EXIT_AFTER`] the thread exits the
+//! process itself.
+
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Exit code when the watchdog fires (`EX_TEMPFAIL`), distinct from the `1` of ordinary errors.
+pub const WATCHDOG_EXIT_CODE: i32 = 75;
+/// How long the capture loop gets to stop after the watchdog fired before the process is exited
+/// without flushing outputs.
+pub const FORCE_EXIT_AFTER: Duration = Duration::from_secs(10);
+
+/// The run ended because the watchdog fired; its outputs were flushed.
+#[derive(Debug)]
+pub struct WatchdogFired;
+
+impl std::fmt::Display for WatchdogFired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "watchdog: no frame captured in time")
+    }
+}
+
+impl std::error::Error for WatchdogFired {}
+
+/// Exit with [`WATCHDOG_EXIT_CODE`] when `result` is [`WatchdogFired`]; for the binaries' `main`.
+pub fn exit_on_fire(result: Result<()>) -> Result<()> {
+    if let Err(e) = &result
+        && e.is::<WatchdogFired>()
+    {
+        std::process::exit(WATCHDOG_EXIT_CODE);
+    }
+    result
+}
+
+pub struct Watchdog {
+    last_frame: Arc<Mutex<Instant>>,
+    fired: Arc<AtomicBool>,
+    done: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Watchdog {
+    /// Start watching; the clock starts now. Firing sets `stop` (e.g.
+    /// [`crate::shutdown::StopSignal::flag`]).
+    pub fn spawn(secs: f32, stop: Arc<AtomicBool>) -> Result<Self> {
+        if !(secs.is_finite() && secs > 0.0) {
+            anyhow::bail!("--watchdog-secs must be > 0");
+        }
+        let timeout = Duration::from_secs_f32(secs);
+        let poll = (timeout / 4).min(Duration::from_secs(1));
+        let last_frame = Arc::new(Mutex::new(Instant::now()));
+        let fired = Arc::new(AtomicBool::new(false));
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new().name("watchdog".to_string()).spawn({
+            let last_frame = last_frame.clone();
+            let fired = fired.clone();
+            let done = done.clone();
+            move || {
+                while !done.load(Ordering::Relaxed) {
+                    let stalled = last_frame.lock().map_or(Duration::ZERO, |t| t.elapsed());
+                    if stalled > timeout {
+                        tracing::error!(
+                            "Watchdog: no frame captured for {:.1}s (limit {secs}s); stopping, exit code {WATCHDOG_EXIT_CODE}",
+                            stalled.as_secs_f32()
+                        );
+                        fired.store(true, Ordering::Relaxed);
+                        stop.store(true, Ordering::Relaxed);
+                        break;
+                    }
+                    std::thread::park_timeout(poll);
+                }
+                let deadline = Instant::now() + FORCE_EXIT_AFTER;
+                while fired.load(Ordering::Relaxed) && !done.load(Ordering::Relaxed) {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        tracing::error!(
+                            "Watchdog: capture loop did not stop within {}s, exiting without flushing",
+                            FORCE_EXIT_AFTER.as_secs()
+                        );
+                        std::process::exit(WATCHDOG_EXIT_CODE);
+                    }
+                    std::thread::park_timeout(left);
+                }
+            }
+        })?;
+        tracing::info!("Watchdog: exiting after {secs}s without a frame");
+        Ok(Self {
+            last_frame,
+            fired,
+            done,
+            thread: Some(thread),
+        })
+    }
+
+    /// Reset the timeout after a successful capture.
+    pub fn kick(&self) {
+        if let Ok(mut t) = self.last_frame.lock() {
+            *t = Instant::now();
+        }
+    }
+
+    /// Stop watching once the capture loop has ended; whether the watchdog ended it.
+    pub fn finish(self) -> bool {
+        self.fired.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_by_setting_the_stop_flag() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let watchdog = Watchdog::spawn(0.05, stop.clone()).unwrap();
+        let started = Instant::now();
+        while !stop.load(Ordering::Relaxed) {
+            assert!(started.elapsed() < Duration::from_secs(5), "never fired");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(watchdog.finish());
+    }
+
+    #[test]
+    fn kicks_keep_it_quiet() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let watchdog = Watchdog::spawn(0.2, stop.clone()).unwrap();
+        for _ in 0..20 {
+            std::thread::sleep(Duration::from_millis(15));
+            watchdog.kick();
+        }
+        assert!(!stop.load(Ordering::Relaxed));
+        assert!(!watchdog.finish());
+    }
+
+    #[test]
+    fn only_its_own_error_maps_to_the_exit_code() {
+        assert!(exit_on_fire(Ok(())).is_ok());
+        assert!(exit_on_fire(Err(anyhow::anyhow!("camera gone"))).is_err());
+        assert!(Watchdog::spawn(0.0, Arc::new(AtomicBool::new(false))).is_err());
+    }
+}
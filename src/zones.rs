@@ -0,0 +1,252 @@
+//! `--exclude-zone`: suppress detections in parts of the frame that keep misfiring (a TV, a
+//! reflective window).
+//!
+//! Zones are in output-frame pixels. A detection is suppressed when its box center falls inside a
+//! zone or, with `--zone-overlap <f>`, when at least that fraction of its box is covered. Filtering
+//! happens right after inference, so annotation, exports, events and stats never see it.
+
+use anyhow::{Context, Result};
+use image::RgbImage;
+use std::path::{Path, PathBuf};
+
+use crate::geometry::{self, Rect};
+use crate::postprocess::retain_detections;
+use crate::viz::to_rgb;
+
+/// Zone outline/hatch color.
+const ZONE_COLOR: [u8; 3] = [255, 64, 64];
+/// Distance between hatch lines, in pixels.
+const HATCH_SPACING: u32 = 12;
+
+/// An axis-aligned rectangle `x,y,w,h`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Zone {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl std::str::FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let err = || format!("invalid zone `{s}` (expected x,y,w,h, e.g. 100,50,320,240)");
+        let vals: Vec<f32> = s
+            .split(',')
+            .map(|v| v.trim().parse::<f32>())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| err())?;
+        match vals[..] {
+            [x, y, w, h] if vals.iter().all(|v| v.is_finite()) && w > 0.0 && h > 0.0 => {
+                Ok(Self { x, y, w, h })
+            }
+            _ => Err(err()),
+        }
+    }
+}
+
+impl std::fmt::Display for Zone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.w, self.h)
+    }
+}
+
+impl Zone {
+    pub(crate) fn contains(&self, px: f32, py: f32) -> bool {
+        (self.x..=self.x + self.w).contains(&px) && (self.y..=self.y + self.h).contains(&py)
+    }
+
+    /// Fraction of the `(x0, y0, x1, y1)` box covered by this zone.
+    pub(crate) fn coverage(&self, rect: Rect) -> f32 {
+        let area = geometry::area(rect);
+        if area <= 0.0 {
+            return 0.0;
+        }
+        let zone = geometry::from_xywh([self.x, self.y, self.w, self.h]);
+        geometry::intersection(rect, zone) / area
+    }
+}
+
+/// Read zones from `path`: one `x,y,w,h` per line; blank lines and `#` comments are skipped.
+pub fn load_zones(path: &Path) -> Result<Vec<Zone>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read zones file: {}", path.display()))?;
+    text.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| {
+            l.parse()
+                .map_err(|e: String| anyhow::anyhow!("{}: {e}", path.display()))
+        })
+        .collect()
+}
+
+/// The active exclude zones, optionally backed by a `--zones-file`.
+pub struct ExcludeZones {
+    zones: Vec<Zone>,
+    min_overlap: Option<f32>,
+    file: Option<PathBuf>,
+    /// Changed at runtime since loading; written back to `file` by [`ExcludeZones::save`].
+    dirty: bool,
+}
+
+impl ExcludeZones {
+    /// `zones` from the command line plus those in `file` (if it exists).
+    pub fn new(zones: Vec<Zone>, min_overlap: Option<f32>, file: Option<&Path>) -> Result<Self> {
+        if let Some(f) = min_overlap
+            && !(f > 0.0 && f <= 1.0)
+        {
+            anyhow::bail!("--zone-overlap must be in (0, 1], got {f}");
+        }
+        let mut all = zones;
+        if let Some(path) = file
+            && path.exists()
+        {
+            all.extend(load_zones(path)?);
+        }
+        if !all.is_empty() {
+            tracing::info!("Exclude zones: {}", all.len());
+        }
+        Ok(Self {
+            zones: all,
+            min_overlap,
+            file: file.map(Path::to_path_buf),
+            dirty: false,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+
+    pub fn add(&mut self, zone: Zone) {
+        self.zones.push(zone);
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.zones.clear();
+        self.dirty = true;
+    }
+
+    /// Whether a detection with box `(x0, y0, x1, y1)` is suppressed.
+    fn suppresses(&self, rect: Rect) -> bool {
+        let (x0, y0, x1, y1) = rect;
+        self.zones.iter().any(|z| match self.min_overlap {
+            Some(min) => z.coverage(rect) >= min,
+            None => z.contains((x0 + x1) / 2.0, (y0 + y1) / 2.0),
+        })
+    }
+
+    /// Drop suppressed detections with their masks and polygons; returns the filtered results and
+    /// how many boxes were dropped. A mask or polygon without a box is kept.
+    pub fn filter(&self, ys: Vec<usls::Y>) -> (Vec<usls::Y>, usize) {
+        if self.zones.is_empty() {
+            return (ys, 0);
+        }
+        let mut suppressed = 0;
+        let ys = ys
+            .into_iter()
+            .map(|y| {
+                let boxes = y.hbbs().len();
+                let y = retain_detections(y, |c| {
+                    c.hbb.is_none_or(|(_, hbb)| !self.suppresses(hbb.xyxy()))
+                });
+                suppressed += boxes - y.hbbs().len();
+                y
+            })
+            .collect();
+        (ys, suppressed)
+    }
+
+    /// Write the zones back to `--zones-file` if they were edited at runtime.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = self.file.as_ref().filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        let mut text = String::from("# exclude zones: x,y,w,h\n");
+        for zone in &self.zones {
+            text.push_str(&format!("{zone}\n"));
+        }
+        std::fs::write(path, text)
+            .with_context(|| format!("failed to write zones file: {}", path.display()))?;
+        tracing::info!(
+            "Saved {} exclude zone(s) to {}",
+            self.zones.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// `img` with every zone drawn as a hatched rectangle (`--show-zones`).
+    pub fn draw(&self, img: &usls::Image) -> Result<usls::Image> {
+        let mut frame = to_rgb(img)?;
+        for zone in &self.zones {
+            draw_hatched(&mut frame, zone);
+        }
+        Ok(usls::Image::from(frame))
+    }
+}
+
+fn draw_hatched(frame: &mut RgbImage, zone: &Zone) {
+    let (fw, fh) = frame.dimensions();
+    let x0 = zone.x.max(0.0) as u32;
+    let y0 = zone.y.max(0.0) as u32;
+    let x1 = ((zone.x + zone.w).max(0.0) as u32).min(fw);
+    let y1 = ((zone.y + zone.h).max(0.0) as u32).min(fh);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let border = x == x0 || y == y0 || x + 1 == x1 || y + 1 == y1;
+            if border || (x + y).is_multiple_of(HATCH_SPACING) {
+                frame.put_pixel(x, y, image::Rgb(ZONE_COLOR));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn zones(min_overlap: Option<f32>) -> ExcludeZones {
+        ExcludeZones::new(vec!["100,100,200,100".parse().unwrap()], min_overlap, None).unwrap()
+    }
+
+    #[test]
+    fn parses_zones() {
+        let zone: Zone = " 10, 20 ,30,40 ".parse().unwrap();
+        assert_eq!(zone.to_string(), "10,20,30,40");
+        assert!("10,20,30".parse::<Zone>().is_err());
+        assert!("10,20,0,40".parse::<Zone>().is_err());
+    }
+
+    #[test]
+    fn center_rule_and_overlap_fraction() {
+        // Box 250..350 x 120..180: center (300, 150) is on the zone's right edge.
+        let half_in = (250.0, 120.0, 350.0, 180.0);
+        assert!(zones(None).suppresses(half_in));
+        assert!(zones(Some(0.5)).suppresses(half_in));
+        assert!(!zones(Some(0.6)).suppresses(half_in));
+        assert!(!zones(None).suppresses((0.0, 0.0, 50.0, 50.0)));
+    }
+
+    #[test]
+    fn runtime_edits_are_saved_to_the_zones_file() {
+        let dir = TempDir::new("zones");
+        let path = dir.join("zones.txt");
+        std::fs::write(&path, "# tv\n0,0,10,10\n").unwrap();
+        let mut zones = ExcludeZones::new(Vec::new(), None, Some(&path)).unwrap();
+        assert_eq!(zones.zones.len(), 1);
+        zones.add("5,5,20,20".parse().unwrap());
+        zones.save().unwrap();
+        let reloaded = load_zones(&path).unwrap();
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(reloaded[1].to_string(), "5,5,20,20");
+    }
+}
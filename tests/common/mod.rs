@@ -0,0 +1,8 @@
+//! Fixtures shared by the integration tests.
+
+use std::path::PathBuf;
+
+/// `name` under the system temp dir, prefixed with the test process id.
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("sam3-it-{}-{name}", std::process::id()))
+}
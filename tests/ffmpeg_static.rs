@@ -0,0 +1,85 @@
+//! The in-process `ffmpeg-static` decoder must yield the same frames as the `ffmpeg` CLI pipe.
+//!
+//! Writes a tiny clip and decodes it both ways, so it needs `ffmpeg` in PATH and is ignored by
+//! default:
+//!
+//! ```bash
+//! cargo test --features ffmpeg-static --test ffmpeg_static -- --ignored
+//! ```
+#![cfg(feature = "ffmpeg-static")]
+
+mod common;
+
+use common::temp_path;
+use sam3_card_detector::color::{ColorMatrix, SourceColor};
+use sam3_card_detector::ffmpeg_log::FfmpegLogLevel;
+use sam3_card_detector::output::FfmpegVideoWriter;
+use sam3_card_detector::video_sam3::FfmpegRawRgb24;
+use std::path::Path;
+
+const FRAMES: u32 = 8;
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+fn write_clip(path: &Path) {
+    let mut writer = FfmpegVideoWriter::spawn(
+        path,
+        WIDTH,
+        HEIGHT,
+        10.0,
+        ColorMatrix::default(),
+        FfmpegLogLevel::default(),
+    )
+    .expect("spawn encoder");
+    for i in 0..FRAMES {
+        let frame = image::RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            image::Rgb([(x * 4) as u8, (y * 5) as u8, (i * 30) as u8])
+        });
+        writer
+            .write_frame(&usls::Image::from(frame))
+            .expect("write frame");
+    }
+    writer.finish().expect("finish encoder");
+}
+
+fn decode_all(mut decoder: FfmpegRawRgb24) -> Vec<image::RgbImage> {
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_frame().expect("decode frame") {
+        frames.push(frame);
+    }
+    decoder.finish().expect("finish decoder");
+    frames
+}
+
+#[test]
+#[ignore = "requires ffmpeg"]
+fn libav_frames_match_the_cli() {
+    let clip = temp_path("clip.mp4");
+    write_clip(&clip);
+    let input = clip.to_str().expect("utf-8 temp path");
+
+    for (w, h, scale) in [(WIDTH, HEIGHT, false), (WIDTH / 2, HEIGHT / 2, true)] {
+        let cli = decode_all(
+            FfmpegRawRgb24::spawn_cli(
+                input,
+                w,
+                h,
+                10.0,
+                scale,
+                SourceColor::default(),
+                None,
+                FfmpegLogLevel::default(),
+            )
+            .expect("spawn ffmpeg"),
+        );
+        let lib =
+            decode_all(FfmpegRawRgb24::open_libav(input, w, h, 10.0, None).expect("open libav"));
+        assert_eq!(cli.len(), FRAMES as usize);
+        assert_eq!(lib.len(), cli.len(), "frame count at {w}x{h}");
+        for (i, (a, b)) in cli.iter().zip(&lib).enumerate() {
+            assert!(a == b, "frame {i} differs at {w}x{h}");
+        }
+    }
+
+    std::fs::remove_file(&clip).ok();
+}
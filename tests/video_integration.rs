@@ -0,0 +1,255 @@
+//! End-to-end run of `video-sam3` on a synthetic clip, `--annotate-only` on a
+//! hand-written detections file, and a color round trip through the encoder and
+//! decoder.
+//!
+//! Needs `ffmpeg`/`ffprobe` in PATH and the SAM3 weights (downloaded on first
+//! use), so it is ignored by default:
+//!
+//! ```bash
+//! cargo test --test video_integration -- --ignored
+//! ```
+
+mod common;
+
+use common::temp_path;
+use sam3_card_detector::color::{ColorMatrix, SourceColor};
+use sam3_card_detector::ffmpeg_log::FfmpegLogLevel;
+use sam3_card_detector::output::FfmpegVideoWriter;
+use sam3_card_detector::redact::InputUrl;
+use sam3_card_detector::video_sam3::FfmpegRawRgb24;
+use std::path::Path;
+use std::process::Command;
+
+const FRAMES: usize = 10;
+const SIZE: u32 = 64;
+
+fn write_synthetic_video(path: &Path) {
+    let mut writer = FfmpegVideoWriter::spawn(
+        path,
+        SIZE,
+        SIZE,
+        10.0,
+        ColorMatrix::default(),
+        FfmpegLogLevel::default(),
+    )
+    .expect("spawn encoder");
+    for _ in 0..FRAMES {
+        let frame = image::RgbImage::from_fn(SIZE, SIZE, |x, y| {
+            if (16..48).contains(&x) && (16..48).contains(&y) {
+                image::Rgb([255, 0, 0])
+            } else {
+                image::Rgb([255, 255, 255])
+            }
+        });
+        writer
+            .write_frame(&usls::Image::from(frame))
+            .expect("write frame");
+    }
+    writer.finish().expect("finish encoder");
+}
+
+/// 75% SMPTE-style bars: white, yellow, cyan, green, magenta, red, blue, black.
+const BARS: [[u8; 3]; 8] = [
+    [191, 191, 191],
+    [191, 191, 0],
+    [0, 191, 191],
+    [0, 191, 0],
+    [191, 0, 191],
+    [191, 0, 0],
+    [0, 0, 191],
+    [0, 0, 0],
+];
+const BAR_WIDTH: u32 = 40;
+const BARS_HEIGHT: u32 = 96;
+
+/// Mean RGB of each bar, leaving out the edges that chroma subsampling blurs.
+fn bar_means(frame: &image::RgbImage) -> Vec<[f64; 3]> {
+    (0..BARS.len() as u32)
+        .map(|bar| {
+            let xs = bar * BAR_WIDTH + 4..(bar + 1) * BAR_WIDTH - 4;
+            let mut sum = [0.0; 3];
+            let mut n = 0.0;
+            for x in xs {
+                for y in 0..frame.height() {
+                    let px = frame.get_pixel(x, y).0;
+                    for c in 0..3 {
+                        sum[c] += f64::from(px[c]);
+                    }
+                    n += 1.0;
+                }
+            }
+            sum.map(|s| s / n)
+        })
+        .collect()
+}
+
+fn max_bar_shift(frame: &image::RgbImage) -> f64 {
+    bar_means(frame)
+        .iter()
+        .zip(BARS)
+        .flat_map(|(mean, bar)| (0..3).map(move |c| (mean[c] - f64::from(bar[c])).abs()))
+        .fold(0.0, f64::max)
+}
+
+#[test]
+#[ignore = "requires ffmpeg"]
+fn bt709_color_bars_round_trip() {
+    let path = temp_path("bars.mp4");
+    let width = BAR_WIDTH * BARS.len() as u32;
+    let bars = image::RgbImage::from_fn(width, BARS_HEIGHT, |x, _| {
+        image::Rgb(BARS[(x / BAR_WIDTH) as usize])
+    });
+    let mut writer = FfmpegVideoWriter::spawn(
+        &path,
+        width,
+        BARS_HEIGHT,
+        10.0,
+        ColorMatrix::Bt709,
+        FfmpegLogLevel::default(),
+    )
+    .expect("spawn encoder");
+    for _ in 0..5 {
+        writer
+            .write_frame(&usls::Image::from(bars.clone()))
+            .expect("write frame");
+    }
+    writer.finish().expect("finish encoder");
+
+    let probe = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=color_space,color_range"])
+        .args(["-of", "default=noprint_wrappers=1"])
+        .arg(&path)
+        .output()
+        .expect("run ffprobe");
+    let color = SourceColor::from_ffprobe(&String::from_utf8_lossy(&probe.stdout));
+    assert_eq!(
+        color.matrix,
+        ColorMatrix::Bt709,
+        "output is not tagged BT.709"
+    );
+
+    let decode = |color: SourceColor| {
+        let input = InputUrl::resolve(path.to_str().unwrap(), None).unwrap();
+        let mut decoder = FfmpegRawRgb24::spawn_cli(
+            &input,
+            width,
+            BARS_HEIGHT,
+            10.0,
+            false,
+            color,
+            None,
+            FfmpegLogLevel::default(),
+        )
+        .expect("spawn decoder");
+        decoder.read_frame().expect("decode").expect("a frame")
+    };
+    let shift = max_bar_shift(&decode(color));
+    assert!(shift < 3.0, "bars shifted by {shift:.1} levels");
+    // Decoding with swscale's default BT.601 is the shift this guards against.
+    let wrong = max_bar_shift(&decode(SourceColor::default()));
+    assert!(
+        wrong > 8.0,
+        "BT.601 decode only shifted by {wrong:.1} levels"
+    );
+
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+#[ignore = "requires ffmpeg and SAM3 model weights"]
+fn video_sam3_end_to_end() {
+    let input = temp_path("input.mp4");
+    let json_out = temp_path("test.json");
+    let video_out = temp_path("test_out.mp4");
+    write_synthetic_video(&input);
+
+    let status = Command::new(env!("CARGO_BIN_EXE_video-sam3"))
+        .arg(&input)
+        .args(["-p", "red square", "--infer-every", "1", "--no-display"])
+        .arg("--json-out")
+        .arg(&json_out)
+        .arg("--save-video")
+        .arg(&video_out)
+        .status()
+        .expect("run video-sam3");
+    assert!(status.success(), "video-sam3 exited with {status}");
+
+    let text = std::fs::read_to_string(&json_out).expect("read JSON output");
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), FRAMES);
+    for (i, line) in lines.iter().enumerate() {
+        let record: serde_json::Value = serde_json::from_str(line).expect("valid JSON line");
+        assert_eq!(record["frame_idx"].as_u64(), Some(i as u64 + 1));
+    }
+
+    let probe = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=codec_type", "-of", "csv=p=0"])
+        .arg(&video_out)
+        .output()
+        .expect("run ffprobe");
+    assert!(probe.status.success(), "ffprobe rejected the output video");
+    assert_eq!(String::from_utf8_lossy(&probe.stdout).trim(), "video");
+
+    for path in [&input, &json_out, &video_out] {
+        std::fs::remove_file(path).ok();
+    }
+}
+
+#[test]
+#[ignore = "requires ffmpeg"]
+fn annotate_only_redraws_a_json_out_file() {
+    let input = temp_path("redraw-input.mp4");
+    let detections = temp_path("redraw.jsonl");
+    let video_out = temp_path("redraw-out.mp4");
+    write_synthetic_video(&input);
+    // A box over the white background on the first frame; the other frames repeat it.
+    let mut lines = vec![
+        r#"{"frame_idx":1,"inferred":true,"detections":[{"label":"square","class_id":0,"confidence":0.9,"bbox":[2,2,12,12]}]}"#.to_string(),
+    ];
+    for i in 2..=FRAMES {
+        lines.push(format!(
+            r#"{{"frame_idx":{i},"inferred":false,"detections":[]}}"#
+        ));
+    }
+    std::fs::write(&detections, lines.join("\n")).expect("write detections");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_video-sam3"))
+        .arg(&input)
+        .arg("--annotate-only")
+        .arg(&detections)
+        .arg("--save-video")
+        .arg(&video_out)
+        .status()
+        .expect("run video-sam3");
+    assert!(status.success(), "video-sam3 exited with {status}");
+
+    let annotated = InputUrl::resolve(video_out.to_str().unwrap(), None).unwrap();
+    let mut decoder = FfmpegRawRgb24::spawn_cli(
+        &annotated,
+        SIZE,
+        SIZE,
+        10.0,
+        false,
+        SourceColor::default(),
+        None,
+        FfmpegLogLevel::default(),
+    )
+    .expect("spawn decoder");
+    let mut frames = 0;
+    while let Some(frame) = decoder.read_frame().expect("decode") {
+        // The box's left edge, on what was white in the input.
+        let px = frame.get_pixel(2, 8).0;
+        assert!(
+            px.iter().any(|&c| c < 200),
+            "no outline in frame {frames}: {px:?}"
+        );
+        frames += 1;
+    }
+    assert_eq!(frames, FRAMES);
+
+    for path in [&input, &detections, &video_out] {
+        std::fs::remove_file(path).ok();
+    }
+}